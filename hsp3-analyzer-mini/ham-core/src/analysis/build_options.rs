@@ -0,0 +1,488 @@
+//! ビルドオプションの解析
+//!
+//! `#packopt`, `#runtime`, `#cmpopt`, `#bootopt` で指定される、
+//! コンパイルや実行ファイルの作成、起動時の動作に関する設定を扱う。
+
+use super::*;
+use crate::parse::*;
+
+/// ビルドオプションを指定するディレクティブ
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BuildDirective {
+    PackOpt,
+    Runtime,
+    CmpOpt,
+    BootOpt,
+}
+
+impl BuildDirective {
+    pub(crate) fn from_keyword(keyword: &str) -> Option<Self> {
+        let it = match keyword {
+            "packopt" => BuildDirective::PackOpt,
+            "runtime" => BuildDirective::Runtime,
+            "cmpopt" => BuildDirective::CmpOpt,
+            "bootopt" => BuildDirective::BootOpt,
+            _ => return None,
+        };
+        Some(it)
+    }
+
+    pub(crate) fn keyword(self) -> &'static str {
+        match self {
+            BuildDirective::PackOpt => "packopt",
+            BuildDirective::Runtime => "runtime",
+            BuildDirective::CmpOpt => "cmpopt",
+            BuildDirective::BootOpt => "bootopt",
+        }
+    }
+
+    /// 指定できるオプションの一覧 (`#runtime` はオプション名をとらない。)
+    pub(crate) fn option_specs(self) -> &'static [BuildOptionSpec] {
+        match self {
+            BuildDirective::PackOpt => PACKOPT_SPECS,
+            BuildDirective::Runtime => &[],
+            BuildDirective::CmpOpt => CMPOPT_SPECS,
+            BuildDirective::BootOpt => BOOTOPT_SPECS,
+        }
+    }
+
+    pub(crate) fn find_spec(self, name: &str) -> Option<&'static BuildOptionSpec> {
+        self.option_specs().iter().find(|spec| spec.name == name)
+    }
+}
+
+/// オプションの値の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BuildOptionValueTy {
+    Str,
+    Int,
+    /// 範囲内の整数 (両端を含む)
+    IntRange(i32, i32),
+}
+
+impl BuildOptionValueTy {
+    fn describe(self) -> String {
+        match self {
+            BuildOptionValueTy::Str => "文字列".to_string(),
+            BuildOptionValueTy::Int => "整数".to_string(),
+            BuildOptionValueTy::IntRange(min, max) => format!("{}〜{}の整数", min, max),
+        }
+    }
+}
+
+pub(crate) struct BuildOptionSpec {
+    pub(crate) name: &'static str,
+    pub(crate) ty: BuildOptionValueTy,
+    pub(crate) detail: &'static str,
+}
+
+const fn spec(name: &'static str, ty: BuildOptionValueTy, detail: &'static str) -> BuildOptionSpec {
+    BuildOptionSpec { name, ty, detail }
+}
+
+const FLAG: BuildOptionValueTy = BuildOptionValueTy::IntRange(0, 1);
+
+static PACKOPT_SPECS: &[BuildOptionSpec] = &[
+    spec(
+        "name",
+        BuildOptionValueTy::Str,
+        "作成する実行ファイルの名前",
+    ),
+    spec(
+        "runtime",
+        BuildOptionValueTy::Str,
+        "使用するランタイムファイル",
+    ),
+    spec(
+        "type",
+        BuildOptionValueTy::IntRange(0, 2),
+        "実行ファイルの種類 (0=EXE, 1=フルスクリーン, 2=スクリーンセーバー)",
+    ),
+    spec("xsize", BuildOptionValueTy::Int, "初期ウィンドウのXサイズ"),
+    spec("ysize", BuildOptionValueTy::Int, "初期ウィンドウのYサイズ"),
+    spec("hide", FLAG, "初期ウィンドウを非表示にする"),
+    spec("orgpath", FLAG, "初期ディレクトリを変更しない"),
+    spec("icon", BuildOptionValueTy::Str, "アイコンファイルの設定"),
+    spec(
+        "version",
+        BuildOptionValueTy::Str,
+        "バージョンリソースの設定ファイル",
+    ),
+    spec(
+        "manifest",
+        BuildOptionValueTy::Str,
+        "マニフェストファイルの設定",
+    ),
+    spec("lang", BuildOptionValueTy::Str, "言語コードの設定"),
+    spec("upx", FLAG, "UPXで圧縮する"),
+];
+
+static CMPOPT_SPECS: &[BuildOptionSpec] = &[
+    spec("ppout", FLAG, "プリプロセッサの結果をファイルに出力する"),
+    spec("optcode", FLAG, "不要なモジュールのコードを出力しない"),
+    spec(
+        "case",
+        FLAG,
+        "ラベル名や変数名などの大文字と小文字を区別する",
+    ),
+    spec("optinfo", FLAG, "最適化の情報をログに出力する"),
+    spec("varname", FLAG, "変数名の情報を出力する"),
+    spec("varinit", FLAG, "未初期化の変数をエラーにする"),
+    spec("optprm", FLAG, "パラメータのコードを最適化する"),
+    spec("skipjpspc", FLAG, "全角スペースを空白として扱う"),
+    spec("utf8", FLAG, "文字列をUTF-8として出力する"),
+];
+
+static BOOTOPT_SPECS: &[BuildOptionSpec] = &[
+    spec("notimer", FLAG, "高精度タイマーを使用しない"),
+    spec("utf8", FLAG, "UTF-8形式の文字列を使用する"),
+    spec("hsp64", FLAG, "64ビット版のランタイムを使用する"),
+];
+
+/// ソースコードに書かれた1つのビルドオプション
+#[derive(Clone, Debug)]
+pub(crate) struct BuildOption {
+    pub(crate) directive: BuildDirective,
+    /// オプションの名前とその位置 (`#runtime` にはない。)
+    pub(crate) name_opt: Option<(RcStr, Loc)>,
+    /// 値とその位置 (文字列は引用符を除く。)
+    pub(crate) value_opt: Option<(BuildOptionValue, Loc)>,
+    /// 文全体の位置
+    pub(crate) loc: Loc,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BuildOptionValue {
+    Str(RcStr),
+    Int(i32),
+    /// 文字列とも整数とも解釈できない値 (書かれたとおりのテキスト)
+    Other(RcStr),
+}
+
+impl BuildOptionValue {
+    fn from_tokens(tokens: &[PToken]) -> Self {
+        if let [token] = tokens {
+            if token.kind() == TokenKind::Str {
+                let text = token.body_text();
+                let text = text.strip_prefix('"').unwrap_or(text);
+                let text = text.strip_suffix('"').unwrap_or(text);
+                return BuildOptionValue::Str(text.into());
+            }
+        }
+
+        let text = tokens.iter().map(|t| t.body_text()).collect::<String>();
+        match text.parse::<i32>() {
+            Ok(value) => BuildOptionValue::Int(value),
+            Err(_) => BuildOptionValue::Other(text.into()),
+        }
+    }
+
+    pub(crate) fn to_text(&self) -> String {
+        match self {
+            BuildOptionValue::Str(s) | BuildOptionValue::Other(s) => s.to_string(),
+            BuildOptionValue::Int(value) => value.to_string(),
+        }
+    }
+}
+
+fn tokens_loc(tokens: &[PToken]) -> Option<Loc> {
+    let first = tokens.first()?;
+    let last = tokens.last()?;
+    Some(first.body.loc.unite(&last.body.loc))
+}
+
+impl BuildOption {
+    fn from_stmt(stmt: &PUnknownPreProcStmt) -> Option<Self> {
+        let directive = BuildDirective::from_keyword(stmt.keyword_text())?;
+        let args = &stmt.tokens[1..];
+
+        let (name_opt, value_tokens) = match directive {
+            BuildDirective::Runtime => (None, args),
+            _ => match args.split_first() {
+                Some((name, rest)) if name.kind() == TokenKind::Ident => {
+                    (Some((name.body_text().into(), name.body.loc)), rest)
+                }
+                _ => (None, args),
+            },
+        };
+
+        let value_opt =
+            tokens_loc(value_tokens).map(|loc| (BuildOptionValue::from_tokens(value_tokens), loc));
+
+        let last = stmt.tokens.last().unwrap_or(&stmt.hash);
+        Some(BuildOption {
+            directive,
+            name_opt,
+            value_opt,
+            loc: stmt.hash.body.loc.unite(&last.body.loc),
+        })
+    }
+
+    /// オプションの名前や値の誤りを探す。
+    pub(crate) fn validate(&self) -> Option<(BuildOptionIssue, Loc)> {
+        if self.directive == BuildDirective::Runtime {
+            return match &self.value_opt {
+                Some((BuildOptionValue::Str(_), _)) => None,
+                Some((_, loc)) => Some((
+                    BuildOptionIssue::InvalidValue {
+                        directive: self.directive,
+                        name: self.directive.keyword().into(),
+                        ty: BuildOptionValueTy::Str,
+                    },
+                    *loc,
+                )),
+                None => Some((
+                    BuildOptionIssue::MissingValue {
+                        directive: self.directive,
+                        name: self.directive.keyword().into(),
+                    },
+                    self.loc,
+                )),
+            };
+        }
+
+        let (name, name_loc) = match &self.name_opt {
+            Some(it) => it,
+            None => {
+                return Some((
+                    BuildOptionIssue::MissingName {
+                        directive: self.directive,
+                    },
+                    self.loc,
+                ))
+            }
+        };
+
+        let spec = match self.directive.find_spec(name) {
+            Some(it) => it,
+            None => {
+                return Some((
+                    BuildOptionIssue::UnknownName {
+                        directive: self.directive,
+                        name: name.clone(),
+                    },
+                    *name_loc,
+                ))
+            }
+        };
+
+        let (value, value_loc) = match &self.value_opt {
+            Some(it) => it,
+            None => {
+                return Some((
+                    BuildOptionIssue::MissingValue {
+                        directive: self.directive,
+                        name: name.clone(),
+                    },
+                    self.loc,
+                ))
+            }
+        };
+
+        let ok = match (spec.ty, value) {
+            (BuildOptionValueTy::Str, BuildOptionValue::Str(_)) => true,
+            (BuildOptionValueTy::Int, BuildOptionValue::Int(_)) => true,
+            (BuildOptionValueTy::IntRange(min, max), &BuildOptionValue::Int(value)) => {
+                min <= value && value <= max
+            }
+            _ => false,
+        };
+        if ok {
+            return None;
+        }
+
+        Some((
+            BuildOptionIssue::InvalidValue {
+                directive: self.directive,
+                name: name.clone(),
+                ty: spec.ty,
+            },
+            *value_loc,
+        ))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BuildOptionIssue {
+    MissingName {
+        directive: BuildDirective,
+    },
+    UnknownName {
+        directive: BuildDirective,
+        name: RcStr,
+    },
+    MissingValue {
+        directive: BuildDirective,
+        name: RcStr,
+    },
+    InvalidValue {
+        directive: BuildDirective,
+        name: RcStr,
+        ty: BuildOptionValueTy,
+    },
+}
+
+impl BuildOptionIssue {
+    pub(crate) fn message(&self) -> String {
+        match self {
+            BuildOptionIssue::MissingName { directive } => {
+                format!("#{} にはオプションの名前が必要です。", directive.keyword())
+            }
+            BuildOptionIssue::UnknownName { directive, name } => format!(
+                "#{} に {} というオプションはありません。",
+                directive.keyword(),
+                name
+            ),
+            BuildOptionIssue::MissingValue { directive, name } => {
+                if *directive == BuildDirective::Runtime {
+                    format!("#{} には値が必要です。", directive.keyword())
+                } else {
+                    format!("#{} {} には値が必要です。", directive.keyword(), name)
+                }
+            }
+            BuildOptionIssue::InvalidValue {
+                directive,
+                name,
+                ty,
+            } => {
+                if *directive == BuildDirective::Runtime {
+                    format!(
+                        "#{} の値は{}で指定してください。",
+                        directive.keyword(),
+                        ty.describe()
+                    )
+                } else {
+                    format!(
+                        "#{} {} の値は{}で指定してください。",
+                        directive.keyword(),
+                        name,
+                        ty.describe()
+                    )
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct V {
+    options: Vec<BuildOption>,
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if let PStmt::UnknownPreProc(stmt) = stmt {
+            if let Some(option) = BuildOption::from_stmt(stmt) {
+                self.options.push(option);
+            }
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// ドキュメントに書かれているビルドオプションを出現順に集める。
+pub(crate) fn collect_build_options(root: &PRoot, options: &mut Vec<BuildOption>) {
+    let mut v = V::default();
+    v.on_root(root);
+    options.extend(v.options);
+}
+
+/// カーソルが `#packopt` などのオプション名を書く位置にあるなら、そのディレクティブを返す。
+pub(crate) fn build_directive_at(pos: Pos16, tokens: &[PToken]) -> Option<BuildDirective> {
+    // カーソル位置の直前のトークンを探す。(カーソルが末尾にある識別子は入力中のオプション名とみなす。)
+    let mut i = match tokens.binary_search_by_key(&pos, |token| token.body_pos16()) {
+        Ok(i) | Err(i) => i.checked_sub(1)?,
+    };
+    let token = &tokens[i];
+    if token.kind() == TokenKind::Ident && Pos16::from(token.body.loc.end()) >= pos {
+        i = i.checked_sub(1)?;
+    }
+
+    let keyword = &tokens[i];
+    let hash = tokens.get(i.checked_sub(1)?)?;
+    if hash.kind() != TokenKind::Hash
+        || keyword.kind() != TokenKind::Ident
+        || Pos16::from(keyword.body.loc.end()) >= pos
+    {
+        return None;
+    }
+
+    match BuildDirective::from_keyword(keyword.body_text())? {
+        BuildDirective::Runtime => None,
+        directive => Some(directive),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse::parse_root, token::tokenize};
+
+    fn parse(text: &str) -> PRoot {
+        let tokens = tokenize(1, text.into());
+        parse_root(PToken::from_tokens(tokens.into()))
+    }
+
+    #[test]
+    fn test_validate() {
+        let root = parse(
+            r#"#packopt name "app"
+#packopt type 3
+#packopt hide 1
+#packopt nosuch 1
+#cmpopt varinit 1
+#bootopt utf8 "1"
+#runtime "hsp3dish"
+#runtime
+#cmpopt case 1
+"#,
+        );
+        let mut options = vec![];
+        collect_build_options(&root, &mut options);
+        assert_eq!(options.len(), 9);
+        assert_eq!(
+            options[0]
+                .value_opt
+                .as_ref()
+                .map(|(value, _)| value.to_text()),
+            Some("app".to_string())
+        );
+
+        let messages = options
+            .iter()
+            .filter_map(|option| option.validate())
+            .map(|(issue, loc)| (issue.message(), loc.start_row()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "#packopt type の値は0〜2の整数で指定してください。".to_string(),
+                    1
+                ),
+                (
+                    "#packopt に nosuch というオプションはありません。".to_string(),
+                    3
+                ),
+                (
+                    "#bootopt utf8 の値は0〜1の整数で指定してください。".to_string(),
+                    5
+                ),
+                ("#runtime には値が必要です。".to_string(), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_directive_at() {
+        let text = "#packopt \n#cmpopt var\n#runtime \n#packopt name ";
+        let tokens = PToken::from_tokens(tokenize(1, text.into()).into());
+        let at = |row, column| build_directive_at(Pos16::new(row, column), &tokens);
+        assert_eq!(at(0, 9), Some(BuildDirective::PackOpt));
+        assert_eq!(at(0, 5), None);
+        assert_eq!(at(1, 11), Some(BuildDirective::CmpOpt));
+        assert_eq!(at(2, 9), None);
+        assert_eq!(at(3, 14), None);
+    }
+}
@@ -9,6 +9,20 @@ pub(crate) fn str_is_ornament_comment(s: &str) -> bool {
     s.chars().all(char_is_ornament_comment)
 }
 
+/// ファイルの先頭のコメントに、指定した指示 (`; ham-data` など) が書かれているか？
+pub(crate) fn has_leading_directive(tokens: &[PToken], directive: &str) -> bool {
+    let first = match tokens.first() {
+        Some(it) => it,
+        None => return false,
+    };
+
+    first
+        .leading
+        .iter()
+        .filter(|t| t.kind == TokenKind::Comment)
+        .any(|t| t.text.trim_start_matches(&[';', '/', '*'][..]).trim() == directive)
+}
+
 fn trim_comment_leader(s: RcStr) -> RcStr {
     for prefix in &["/// ", "///", "// ", "//", "; ", ";"] {
         if s.starts_with(prefix) {
@@ -18,23 +32,140 @@ fn trim_comment_leader(s: RcStr) -> RcStr {
     s
 }
 
+/// 空白で区切られた最初の単語と残りに分ける。
+fn split_first_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+/// ドキュメンテーションの行が属している部分
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Doc,
+    /// `@param` の続き
+    Param,
+    /// `@return` の続き
+    Return,
+    /// `@deprecated` の続き
+    Deprecated,
+    /// `@example` の後
+    Example,
+    /// `%prm` の後
+    Prm,
+}
+
+fn append_line(text: &mut String, line: &str) {
+    if !text.is_empty() {
+        text.push(' ');
+    }
+    text.push_str(line);
+}
+
+/// ドキュメンテーションの行を解釈する。
+///
+/// `@param name desc` などのタグや、hsphelp と同様の `%prm` `%inst` を認識する。
+/// タグの直後の行はそのタグの説明の続きとみなす。(空行で終わる。)
+fn on_doc_line(line: &str, section: &mut Section, details: &mut SymbolDetails) {
+    let t = line.trim();
+
+    if let Some(tag) = t.strip_prefix('@') {
+        let (name, rest) = split_first_word(tag);
+        match name {
+            "param" => {
+                let (param, desc) = split_first_word(rest);
+                details.params.push((param.to_string(), desc.to_string()));
+                *section = Section::Param;
+                return;
+            }
+            "return" | "returns" => {
+                details.returns_opt = Some(rest.to_string());
+                *section = Section::Return;
+                return;
+            }
+            "deprecated" => {
+                details.deprecated_opt = Some(rest.to_string());
+                *section = Section::Deprecated;
+                return;
+            }
+            "example" => {
+                if !rest.is_empty() {
+                    details.examples.push(rest.to_string());
+                }
+                *section = Section::Example;
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    match t {
+        "%prm" => {
+            *section = Section::Prm;
+            return;
+        }
+        "%inst" => {
+            *section = Section::Doc;
+            return;
+        }
+        _ => {}
+    }
+
+    match *section {
+        Section::Doc => details.docs.push(line.to_string()),
+        Section::Example => details.examples.push(line.to_string()),
+        _ if t.is_empty() => *section = Section::Doc,
+        Section::Param => {
+            if let Some((_, desc)) = details.params.last_mut() {
+                append_line(desc, t);
+            }
+        }
+        Section::Return => append_line(details.returns_opt.get_or_insert_with(String::new), t),
+        Section::Deprecated => {
+            append_line(details.deprecated_opt.get_or_insert_with(String::new), t)
+        }
+        Section::Prm => match t.split_once(':') {
+            // `name : desc` の形の行はパラメータの説明
+            Some((name, desc)) => details
+                .params
+                .push((name.trim().to_string(), desc.trim().to_string())),
+            // 最初の行は引数の並び (`p1, p2` など) なので無視する。
+            None => {
+                if let Some((_, desc)) = details.params.last_mut() {
+                    append_line(desc, t);
+                }
+            }
+        },
+    }
+}
+
+fn is_tag_line(line: &str) -> bool {
+    let t = line.trim();
+    t.starts_with('@') || t == "%prm" || t == "%inst"
+}
+
 pub(crate) fn calculate_details(comments: &[RcStr]) -> SymbolDetails {
-    let mut description = None;
-    let mut documentation = vec![];
+    let mut details = SymbolDetails::default();
+    let mut section = Section::Doc;
 
     let mut y = 0;
 
     for comment in comments {
-        y += 1;
-
         // 装飾コメントや空行を無視
         let t = comment.as_str().trim();
         if str_is_ornament_comment(t) {
+            y += 1;
             continue;
         }
 
-        // 最初の行は概要
-        description = Some(trim_comment_leader(comment.clone()));
+        // 最初の行は概要 (タグで始まるときは概要なし)
+        let line = trim_comment_leader(comment.clone());
+        if !is_tag_line(line.as_str()) {
+            details.desc = Some(line);
+            y += 1;
+        }
         break;
     }
 
@@ -52,14 +183,11 @@ pub(crate) fn calculate_details(comments: &[RcStr]) -> SymbolDetails {
     if y < comments.len() {
         for comment in &comments[y..] {
             let comment = trim_comment_leader(comment.clone());
-            documentation.push(comment.as_str().trim_end().to_string());
+            on_doc_line(comment.as_str().trim_end(), &mut section, &mut details);
         }
     }
 
-    SymbolDetails {
-        desc: description,
-        docs: documentation,
-    }
+    details
 }
 
 pub(crate) fn collect_comments(leader: &PToken) -> Vec<RcStr> {
@@ -85,3 +213,45 @@ pub(crate) fn collect_comments(leader: &PToken) -> Vec<RcStr> {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(text: &str) -> SymbolDetails {
+        calculate_details(&text.lines().map(RcStr::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_calculate_details_tags() {
+        let d = details(
+            "; 挨拶する\n;\n; 詳しい説明\n; @param name 名前\n;   (空白を含まない)\n; @param count 回数\n; @return 成功したら 1\n; @deprecated greet2 を使う\n; @example\n;\tgreet \"a\", 1",
+        );
+        assert_eq!(d.desc.as_ref().map(|s| s.as_str()), Some("挨拶する"));
+        assert_eq!(d.docs, vec!["詳しい説明"]);
+        assert_eq!(
+            d.params,
+            vec![
+                ("name".to_string(), "名前 (空白を含まない)".to_string()),
+                ("count".to_string(), "回数".to_string()),
+            ]
+        );
+        assert_eq!(d.returns_opt.as_deref(), Some("成功したら 1"));
+        assert_eq!(d.deprecated_opt.as_deref(), Some("greet2 を使う"));
+        assert_eq!(d.examples, vec!["\tgreet \"a\", 1"]);
+    }
+
+    #[test]
+    fn test_calculate_details_hsphelp_style() {
+        let d = details("; %prm\n; p1, p2\n; p1 : 幅\n; p2 : 高さ\n; %inst\n; 大きさを変える");
+        assert_eq!(d.desc, None);
+        assert_eq!(
+            d.params,
+            vec![
+                ("p1".to_string(), "幅".to_string()),
+                ("p2".to_string(), "高さ".to_string()),
+            ]
+        );
+        assert_eq!(d.docs, vec!["大きさを変える"]);
+    }
+}
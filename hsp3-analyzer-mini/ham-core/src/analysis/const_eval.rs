@@ -0,0 +1,333 @@
+//! 定数式の評価
+//!
+//! `#const` や `#enum` の値を計算する。
+
+use crate::{parse::*, token::TokenKind};
+use std::fmt::{self, Display, Formatter};
+
+/// 定数の値
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ConstValue {
+    Int {
+        value: i32,
+        /// 16進数で表示するか (式に16進数のリテラルが含まれるとき)
+        hex: bool,
+    },
+    Double(f64),
+}
+
+impl ConstValue {
+    fn int(value: i32) -> Self {
+        ConstValue::Int { value, hex: false }
+    }
+
+    fn to_int(self) -> i32 {
+        match self {
+            ConstValue::Int { value, .. } => value,
+            ConstValue::Double(value) => value as i32,
+        }
+    }
+
+    fn to_double(self) -> f64 {
+        match self {
+            ConstValue::Int { value, .. } => value as f64,
+            ConstValue::Double(value) => value,
+        }
+    }
+
+    fn is_hex(self) -> bool {
+        matches!(self, ConstValue::Int { hex: true, .. })
+    }
+
+    /// `#const double` のように型を指定されたときの値
+    pub(crate) fn convert(self, ty: PConstTy) -> Self {
+        match ty {
+            PConstTy::Double => ConstValue::Double(self.to_double()),
+            PConstTy::Int => ConstValue::Int {
+                value: self.to_int(),
+                hex: self.is_hex(),
+            },
+        }
+    }
+
+    /// `#enum` の次の値
+    pub(crate) fn next(self) -> Self {
+        ConstValue::Int {
+            value: self.to_int().wrapping_add(1),
+            hex: self.is_hex(),
+        }
+    }
+}
+
+impl Default for ConstValue {
+    fn default() -> Self {
+        ConstValue::int(0)
+    }
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            ConstValue::Int { value, hex: true } => write!(f, "0x{:X}", value as u32),
+            ConstValue::Int { value, hex: false } => write!(f, "{}", value),
+            ConstValue::Double(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+fn parse_int(digits: &str, radix: u32) -> Option<i32> {
+    // HSP の整数は32ビットなので、あふれた分は切り捨てる。
+    u32::from_str_radix(digits, radix).ok().map(|n| n as i32)
+}
+
+fn eval_number(text: &str) -> Option<ConstValue> {
+    let hex = |value| ConstValue::Int { value, hex: true };
+
+    if let Some(digits) = text.strip_prefix('$') {
+        return parse_int(digits, 16).map(hex);
+    }
+    if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return parse_int(digits, 16).map(hex);
+    }
+    if let Some(digits) = text
+        .strip_prefix('%')
+        .or_else(|| text.strip_prefix("0b"))
+        .or_else(|| text.strip_prefix("0B"))
+    {
+        return parse_int(digits, 2).map(ConstValue::int);
+    }
+
+    if text.contains(['.', 'e', 'E']) {
+        return text.parse::<f64>().ok().map(ConstValue::Double);
+    }
+    parse_int(text, 10).map(ConstValue::int)
+}
+
+fn eval_char(text: &str) -> Option<ConstValue> {
+    let inner = text.strip_prefix('\'')?;
+    let inner = inner.strip_suffix('\'').unwrap_or(inner);
+    let mut chars = inner.chars();
+    let c = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            c => c,
+        },
+        c => c,
+    };
+    Some(ConstValue::int(c as i32))
+}
+
+fn eval_prefix(prefix: &PToken, arg: ConstValue) -> Option<ConstValue> {
+    let value = match (prefix.kind(), arg) {
+        (TokenKind::Minus, ConstValue::Int { value, hex }) => ConstValue::Int {
+            value: value.wrapping_neg(),
+            hex,
+        },
+        (TokenKind::Minus, ConstValue::Double(value)) => ConstValue::Double(-value),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn eval_infix(op: TokenKind, left: ConstValue, right: ConstValue) -> Option<ConstValue> {
+    let bool_value = |b: bool| ConstValue::int(b as i32);
+
+    // 二項演算の結果は左辺の型になる。
+    let value = match left {
+        ConstValue::Int { value: l, hex } => {
+            let r = right.to_int();
+            let int_value = |value| ConstValue::Int {
+                value,
+                hex: hex || right.is_hex(),
+            };
+            match op {
+                TokenKind::Plus => int_value(l.wrapping_add(r)),
+                TokenKind::Minus => int_value(l.wrapping_sub(r)),
+                TokenKind::Star => int_value(l.wrapping_mul(r)),
+                TokenKind::Slash => int_value(l.checked_div(r)?),
+                TokenKind::Backslash => int_value(l.checked_rem(r)?),
+                TokenKind::And => int_value(l & r),
+                TokenKind::Pipe => int_value(l | r),
+                TokenKind::Hat => int_value(l ^ r),
+                TokenKind::LeftShift => int_value(l.wrapping_shl(r as u32)),
+                TokenKind::RightShift => int_value(l.wrapping_shr(r as u32)),
+                TokenKind::AndAnd => bool_value(l != 0 && r != 0),
+                TokenKind::PipePipe => bool_value(l != 0 || r != 0),
+                TokenKind::Equal | TokenKind::EqualEqual => bool_value(l == r),
+                TokenKind::Bang | TokenKind::BangEqual => bool_value(l != r),
+                TokenKind::LeftAngle => bool_value(l < r),
+                TokenKind::RightAngle => bool_value(l > r),
+                TokenKind::LeftEqual => bool_value(l <= r),
+                TokenKind::RightEqual => bool_value(l >= r),
+                _ => return None,
+            }
+        }
+        ConstValue::Double(l) => {
+            let r = right.to_double();
+            match op {
+                TokenKind::Plus => ConstValue::Double(l + r),
+                TokenKind::Minus => ConstValue::Double(l - r),
+                TokenKind::Star => ConstValue::Double(l * r),
+                TokenKind::Slash if r != 0.0 => ConstValue::Double(l / r),
+                TokenKind::Backslash if r != 0.0 => ConstValue::Double(l % r),
+                TokenKind::Equal | TokenKind::EqualEqual => bool_value(l == r),
+                TokenKind::Bang | TokenKind::BangEqual => bool_value(l != r),
+                TokenKind::LeftAngle => bool_value(l < r),
+                TokenKind::RightAngle => bool_value(l > r),
+                TokenKind::LeftEqual => bool_value(l <= r),
+                TokenKind::RightEqual => bool_value(l >= r),
+                _ => return None,
+            }
+        }
+    };
+    Some(value)
+}
+
+/// 二項演算子の優先順位 (大きいほど先に計算する)
+fn precedence(op: TokenKind) -> u8 {
+    match op {
+        TokenKind::Star | TokenKind::Slash | TokenKind::Backslash => 4,
+        TokenKind::Plus | TokenKind::Minus => 3,
+        TokenKind::LeftShift | TokenKind::RightShift => 2,
+        TokenKind::Equal
+        | TokenKind::EqualEqual
+        | TokenKind::Bang
+        | TokenKind::BangEqual
+        | TokenKind::LeftAngle
+        | TokenKind::RightAngle
+        | TokenKind::LeftEqual
+        | TokenKind::RightEqual => 1,
+        _ => 0,
+    }
+}
+
+/// 二項演算の列 `a + b * c` を、被演算子と演算子の列に分解する。
+///
+/// (構文木は演算子の優先順位を考慮せずに左から順に結合しているため。)
+fn flatten_infix<'a>(
+    expr: &'a PExpr,
+    operands: &mut Vec<&'a PExpr>,
+    ops: &mut Vec<TokenKind>,
+) -> Option<()> {
+    match expr {
+        PExpr::Infix(PInfixExpr {
+            left,
+            infix,
+            right_opt,
+        }) => {
+            flatten_infix(left, operands, ops)?;
+            ops.push(infix.kind());
+            operands.push(right_opt.as_deref()?);
+        }
+        _ => operands.push(expr),
+    }
+    Some(())
+}
+
+fn eval_infix_chain(
+    expr: &PExpr,
+    resolve: &mut dyn FnMut(&str) -> Option<ConstValue>,
+) -> Option<ConstValue> {
+    let mut operands = vec![];
+    let mut ops = vec![];
+    flatten_infix(expr, &mut operands, &mut ops)?;
+
+    let mut values = vec![eval_const_expr(operands[0], resolve)?];
+    let mut op_stack: Vec<TokenKind> = vec![];
+    for (&op, &operand) in ops.iter().zip(&operands[1..]) {
+        while let Some(&top) = op_stack.last() {
+            if precedence(top) < precedence(op) {
+                break;
+            }
+            op_stack.pop();
+            let right = values.pop()?;
+            let left = values.pop()?;
+            values.push(eval_infix(top, left, right)?);
+        }
+        op_stack.push(op);
+        values.push(eval_const_expr(operand, resolve)?);
+    }
+    while let Some(op) = op_stack.pop() {
+        let right = values.pop()?;
+        let left = values.pop()?;
+        values.push(eval_infix(op, left, right)?);
+    }
+    values.pop()
+}
+
+/// 定数式を評価する。評価できなければ None
+///
+/// `resolve` は式に含まれる名前から、その定数の値を探す関数。
+pub(crate) fn eval_const_expr(
+    expr: &PExpr,
+    resolve: &mut dyn FnMut(&str) -> Option<ConstValue>,
+) -> Option<ConstValue> {
+    match expr {
+        PExpr::Literal(token) => match token.kind() {
+            TokenKind::Number => eval_number(token.body_text()),
+            TokenKind::Char => eval_char(token.body_text()),
+            _ => None,
+        },
+        PExpr::Label(_) => None,
+        PExpr::Compound(PCompound::Name(name)) => resolve(name.body_text()),
+        // 型変換関数の呼び出し
+        PExpr::Compound(PCompound::Paren(np)) => {
+            let ty = PConstTy::parse(np.name.body_text())?;
+            let arg = match &np.args[..] {
+                [arg] => arg.expr_opt.as_ref()?,
+                _ => return None,
+            };
+            Some(eval_const_expr(arg, resolve)?.convert(ty))
+        }
+        PExpr::Compound(PCompound::Dots(_)) => None,
+        PExpr::Paren(PParenExpr { body_opt, .. }) => eval_const_expr(body_opt.as_deref()?, resolve),
+        PExpr::Prefix(PPrefixExpr { prefix, arg_opt }) => {
+            let arg = eval_const_expr(arg_opt.as_deref()?, resolve)?;
+            eval_prefix(prefix, arg)
+        }
+        PExpr::Infix(_) => eval_infix_chain(expr, resolve),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source::DocId, token::tokenize};
+
+    const NO_DOC: DocId = 1;
+
+    fn eval(text: &str) -> Option<String> {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens);
+        let expr = match root.stmts.first()? {
+            PStmt::Assign(stmt) => stmt.args.first()?.expr_opt.as_ref()?,
+            _ => return None,
+        };
+        let value = eval_const_expr(expr, &mut |name| match name {
+            "ten" => Some(ConstValue::int(10)),
+            _ => None,
+        })?;
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn test_eval_const_expr() {
+        assert_eq!(eval("a = 1 + 2 * 3\n").as_deref(), Some("7"));
+        assert_eq!(eval("a = (1 + 2) * -3\n").as_deref(), Some("-9"));
+        assert_eq!(eval("a = $10 | 0x20\n").as_deref(), Some("0x30"));
+        assert_eq!(eval("a = 1 << 4\n").as_deref(), Some("16"));
+        assert_eq!(eval("a = 1 + 2 * 3 - 4 / 2\n").as_deref(), Some("5"));
+        assert_eq!(eval("a = 1 << 2 + 1 | 1\n").as_deref(), Some("9"));
+        assert_eq!(eval("a = ten \\ 3\n").as_deref(), Some("1"));
+        assert_eq!(eval("a = 1.5 * 2\n").as_deref(), Some("3.0"));
+        assert_eq!(eval("a = 7 / 2.0\n").as_deref(), Some("3"));
+        assert_eq!(eval("a = double(1) / 4\n").as_deref(), Some("0.25"));
+        assert_eq!(eval("a = 'A'\n").as_deref(), Some("65"));
+        assert_eq!(eval("a = ten > 5\n").as_deref(), Some("1"));
+        assert_eq!(eval("a = 1 / 0\n"), None);
+        assert_eq!(eval("a = unknown + 1\n"), None);
+        assert_eq!(eval("a = \"s\"\n"), None);
+    }
+}
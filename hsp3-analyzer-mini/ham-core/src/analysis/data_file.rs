@@ -0,0 +1,59 @@
+//! データファイルの判定
+//!
+//! 配列の確保や代入だけが並んでいるファイル (テーブルなど) は、他のファイルから
+//! `#include` されてデータとして使われる。
+//! このようなファイルはシンボルの収集だけ行い、リントや到達可能性の解析の対象から外す。
+
+use crate::parse::*;
+
+/// データファイルであることを明示するコメント (`; ham-data` など)
+const DATA_FILE_DIRECTIVE: &str = "ham-data";
+
+/// 配列を確保する命令
+static DIM_LIKE_COMMANDS: &[&str] = &["dim", "sdim", "ddim", "ldim", "dimtype", "newlab"];
+
+/// データの定義とみなせる文か？
+fn stmt_is_data_like(stmt: &PStmt) -> bool {
+    match stmt {
+        PStmt::Assign(_) | PStmt::Const(_) | PStmt::Define(_) | PStmt::Enum(_) => true,
+        PStmt::Command(stmt) => DIM_LIKE_COMMANDS.contains(&stmt.command.body_text()),
+        _ => false,
+    }
+}
+
+/// データファイルか判定する。
+///
+/// 先頭のコメントで指定されているか、すべての文が配列の確保や代入などであるとき、データファイルとみなす。
+pub(crate) fn is_data_file(tokens: &[PToken], root: &PRoot) -> bool {
+    if crate::analysis::comment::has_leading_directive(tokens, DATA_FILE_DIRECTIVE) {
+        return true;
+    }
+
+    let has_dim = root.stmts.iter().any(|stmt| match stmt {
+        PStmt::Command(stmt) => DIM_LIKE_COMMANDS.contains(&stmt.command.body_text()),
+        _ => false,
+    });
+    has_dim && root.stmts.iter().all(stmt_is_data_like)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source::DocId, token::tokenize};
+
+    const NO_DOC: DocId = 1;
+
+    fn is_data(text: &str) -> bool {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens.clone());
+        is_data_file(&tokens, &root)
+    }
+
+    #[test]
+    fn test_is_data_file() {
+        assert!(is_data("; ham-data\nmes \"hello\"\n"));
+        assert!(is_data("dim table, 3\ntable(0) = 1\ntable(1) = 2\n"));
+        assert!(!is_data("dim table, 3\nmes table(0)\n"));
+        assert!(!is_data("a = 1\n"));
+    }
+}
@@ -1,10 +1,19 @@
 use super::*;
 
+/// エントリーポイントであることを明示するコメント
+const ENTRY_POINT_DIRECTIVE: &str = "ham-entry";
+
 #[derive(Default)]
 pub(crate) struct DocAnalysis {
     // 構文:
     pub(crate) tokens: RcSlice<PToken>,
     pub(crate) tree_opt: Option<PRoot>,
+    /// データファイル (リントなどの対象外) か？
+    pub(crate) data_file: bool,
+    /// 大きすぎるため、シンボルの収集だけを行うか？ (データファイルとしても扱う。)
+    pub(crate) symbols_only: bool,
+    /// `; ham-entry` コメントでエントリーポイントに指定されているか？
+    pub(crate) entry_point: bool,
 
     // プリプロセス:
     pub(crate) include_guard: Option<IncludeGuard>,
@@ -22,6 +31,9 @@ impl DocAnalysis {
     pub(crate) fn invalidate(&mut self) {
         self.tokens = [].into();
         self.tree_opt = None;
+        self.data_file = false;
+        self.symbols_only = false;
+        self.entry_point = false;
         self.includes.clear();
         self.module_map.clear();
         self.deffunc_map.clear();
@@ -31,6 +43,9 @@ impl DocAnalysis {
     }
 
     pub(crate) fn set_syntax(&mut self, tokens: RcSlice<PToken>, tree: PRoot) {
+        self.data_file = crate::analysis::data_file::is_data_file(&tokens, &tree);
+        self.entry_point =
+            crate::analysis::comment::has_leading_directive(&tokens, ENTRY_POINT_DIRECTIVE);
         self.tokens = tokens;
         self.tree_opt = Some(tree);
     }
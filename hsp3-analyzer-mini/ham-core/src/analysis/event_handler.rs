@@ -0,0 +1,41 @@
+//! イベントハンドラの解析
+//!
+//! `onclick *label` などの命令で登録されるラベルを、イベントハンドラとして扱う。
+
+use super::*;
+use crate::parse::*;
+
+/// イベントハンドラを登録する命令
+static EVENT_HANDLER_COMMANDS: &[&str] = &["onclick", "oncmd", "onerror", "onexit", "onkey"];
+
+/// イベントハンドラとして登録されるラベルの名前の位置を集めるビジター
+#[derive(Default)]
+struct V {
+    sites: Vec<Loc>,
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if let PStmt::Command(PCommandStmt { command, args, .. }) = stmt {
+            if EVENT_HANDLER_COMMANDS.contains(&command.body_text()) {
+                // ラベルは最初の引数に書かれる。(`onkey 0` のように、ラベルを書かない形もある。)
+                if let Some(PExpr::Label(PLabel {
+                    name_opt: Some(name),
+                    ..
+                })) = args.first().and_then(|arg| arg.expr_opt.as_ref())
+                {
+                    self.sites.push(name.body.loc);
+                }
+            }
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// イベントハンドラとして登録されるラベルの、使用箇所の位置を集める。
+pub(crate) fn collect_event_handler_sites(root: &PRoot, sites: &mut Vec<Loc>) {
+    let mut v = V::default();
+    v.on_root(root);
+    sites.extend(v.sites);
+}
@@ -0,0 +1,262 @@
+//! includeの関係の解析
+
+use super::*;
+use std::collections::VecDeque;
+
+/// 解決されたincludeからなるグラフ
+#[derive(Default)]
+pub(crate) struct IncludeGraph {
+    /// エントリーポイント (グラフの根)
+    pub(crate) entrypoints: Vec<DocId>,
+    /// ドキュメントごとの、それが書いているinclude (`#include` 文の位置, includeされるドキュメント) のリスト (出現順)
+    includes: HashMap<DocId, Vec<(Loc, DocId)>>,
+    /// ドキュメントごとの、それをincludeしている `#include` 文の位置のリスト (位置の順)
+    included_by: HashMap<DocId, Vec<Loc>>,
+}
+
+impl IncludeGraph {
+    /// `edges` は解決されたinclude (`#include` 文の位置, includeされるドキュメント) のリスト。重複してもいい。
+    ///
+    /// 同じ `#include` 文がプロジェクトによって別のドキュメントに解決されたときは、そのすべてを辺とする。
+    pub(crate) fn new(entrypoints: Vec<DocId>, edges: &[(Loc, DocId)]) -> Self {
+        let mut edges = edges.to_vec();
+        edges.sort();
+        edges.dedup();
+
+        let mut includes: HashMap<DocId, Vec<(Loc, DocId)>> = HashMap::new();
+        let mut included_by: HashMap<DocId, Vec<Loc>> = HashMap::new();
+        for &(loc, dest) in &edges {
+            includes.entry(loc.doc).or_default().push((loc, dest));
+            included_by.entry(dest).or_default().push(loc);
+        }
+
+        IncludeGraph {
+            entrypoints,
+            includes,
+            included_by,
+        }
+    }
+
+    /// ドキュメントがincludeしているドキュメントと、その `#include` 文の位置
+    pub(crate) fn includes(&self, doc: DocId) -> &[(Loc, DocId)] {
+        self.includes.get(&doc).map_or(&[], |v| v.as_slice())
+    }
+
+    /// ドキュメントをincludeしている `#include` 文の位置
+    pub(crate) fn included_by(&self, doc: DocId) -> &[Loc] {
+        self.included_by.get(&doc).map_or(&[], |v| v.as_slice())
+    }
+
+    /// 指定したドキュメントを直接または推移的にincludeしている `#include` 文の位置と、
+    /// そこから指定したドキュメントまでのincludeの段数を集める。
+    ///
+    /// 段数が小さいもの (直接includeしているものは1) から順に並ぶ。
+    pub(crate) fn includers(&self, doc: DocId) -> Vec<(Loc, usize)> {
+        let mut output = vec![];
+        let mut done = HashSet::new();
+        done.insert(doc);
+        let mut queue = VecDeque::new();
+        queue.push_back((doc, 0));
+        while let Some((d, depth)) = queue.pop_front() {
+            for &loc in self.included_by(d) {
+                if loc.doc == doc {
+                    continue;
+                }
+                output.push((loc, depth + 1));
+                if done.insert(loc.doc) {
+                    queue.push_back((loc.doc, depth + 1));
+                }
+            }
+        }
+        output.sort_by_key(|&(loc, depth)| (depth, loc));
+        output.dedup_by_key(|&mut (loc, _)| loc);
+        output
+    }
+
+    /// エントリーポイントから、最も遠くにあるドキュメントまでのincludeの段数を計算する。
+    ///
+    /// 各ドキュメントへの段数は、最も少ない段数でたどったものとする。
+    pub(crate) fn max_depth(&self) -> usize {
+        let mut done = self.entrypoints.iter().cloned().collect::<HashSet<_>>();
+        let mut queue = self
+            .entrypoints
+            .iter()
+            .map(|&doc| (doc, 0))
+            .collect::<VecDeque<_>>();
+        let mut max_depth = 0;
+        while let Some((doc, depth)) = queue.pop_front() {
+            max_depth = max_depth.max(depth);
+            for &(_, d) in self.includes(doc) {
+                if done.insert(d) {
+                    queue.push_back((d, depth + 1));
+                }
+            }
+        }
+        max_depth
+    }
+
+    /// 指定したドキュメントから推移的にincludeされるドキュメントを集める。(指定したものを含む。)
+    pub(crate) fn reachable_from(&self, docs: impl IntoIterator<Item = DocId>) -> HashSet<DocId> {
+        let mut done = HashSet::new();
+        let mut stack = docs.into_iter().collect::<Vec<_>>();
+        while let Some(doc) = stack.pop() {
+            if !done.insert(doc) {
+                continue;
+            }
+            stack.extend(self.includes(doc).iter().map(|&(_, d)| d));
+        }
+        done
+    }
+}
+
+/// 循環しているincludeを探す。
+///
+/// `edges` は解決されたinclude (`#include` 文の位置, includeされるドキュメント) のリスト。
+/// 循環に含まれる `#include` 文ごとに、その文から始まって同じドキュメントに戻ってくるまでのincludeの列を返す。
+/// (列は最短のものを選ぶ。)
+pub(crate) fn find_include_cycles(edges: &[(Loc, DocId)]) -> Vec<Vec<(Loc, DocId)>> {
+    let mut edges = edges.to_vec();
+    edges.sort();
+    edges.dedup();
+
+    let mut children: HashMap<DocId, Vec<(Loc, DocId)>> = HashMap::new();
+    for &(loc, dest) in &edges {
+        children.entry(loc.doc).or_default().push((loc, dest));
+    }
+
+    let mut cycles = vec![];
+    for &(loc, dest) in &edges {
+        let src = loc.doc;
+
+        // dest から src に戻る最短の経路を幅優先探索で探す。
+        let mut parent: HashMap<DocId, (Loc, DocId)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(dest);
+        let mut found = dest == src;
+        while let Some(doc) = queue.pop_front() {
+            if found {
+                break;
+            }
+
+            for &(l, d) in children.get(&doc).map_or(&[][..], |v| v.as_slice()) {
+                if d == dest || parent.contains_key(&d) {
+                    continue;
+                }
+                parent.insert(d, (l, d));
+                if d == src {
+                    found = true;
+                    break;
+                }
+                queue.push_back(d);
+            }
+        }
+        if !found {
+            continue;
+        }
+
+        // 経路を逆にたどって並べる。
+        let mut path = vec![];
+        let mut doc = src;
+        while doc != dest {
+            let (l, d) = parent[&doc];
+            path.push((l, d));
+            doc = l.doc;
+        }
+        path.push((loc, dest));
+        path.reverse();
+        cycles.push(path);
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(src: DocId, row: usize, dest: DocId) -> (Loc, DocId) {
+        let start = Pos::from("\n".repeat(row).as_str());
+        (Loc::new3(src, start, start), dest)
+    }
+
+    fn docs(cycle: &[(Loc, DocId)]) -> Vec<(DocId, DocId)> {
+        cycle.iter().map(|&(loc, dest)| (loc.doc, dest)).collect()
+    }
+
+    #[test]
+    fn test_include_graph() {
+        // 1 -> 2 -> 3, 1 -> 3, 4 -> 1
+        let graph = IncludeGraph::new(
+            vec![1, 4],
+            &[edge(1, 1, 3), edge(1, 0, 2), edge(2, 0, 3), edge(4, 0, 1)],
+        );
+        assert_eq!(
+            graph
+                .includes(1)
+                .iter()
+                .map(|&(_, d)| d)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(
+            graph
+                .included_by(3)
+                .iter()
+                .map(|l| l.doc)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(graph.includes(3), &[]);
+
+        let mut reachable = graph.reachable_from([2]).into_iter().collect::<Vec<_>>();
+        reachable.sort();
+        assert_eq!(reachable, vec![2, 3]);
+
+        assert_eq!(
+            graph
+                .includers(3)
+                .iter()
+                .map(|&(loc, depth)| (loc.doc, depth))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (1, 2), (4, 2)]
+        );
+
+        assert_eq!(graph.max_depth(), 1);
+        let graph = IncludeGraph::new(
+            vec![4],
+            &[edge(1, 1, 3), edge(1, 0, 2), edge(2, 0, 3), edge(4, 0, 1)],
+        );
+        assert_eq!(graph.max_depth(), 2);
+    }
+
+    #[test]
+    fn test_include_resolved_differently() {
+        // 1行目の `#include` が、プロジェクトによって 2 と 3 に解決された。
+        let graph = IncludeGraph::new(vec![1], &[edge(1, 0, 2), edge(1, 0, 3), edge(1, 0, 2)]);
+        assert_eq!(
+            graph
+                .includes(1)
+                .iter()
+                .map(|&(_, d)| d)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(graph.included_by(2).len(), 1);
+        assert_eq!(graph.included_by(3).len(), 1);
+    }
+
+    #[test]
+    fn test_find_include_cycles() {
+        // 1 -> 2 -> 3 -> 2, 3 -> 3, 1 -> 4
+        let cycles = find_include_cycles(&[
+            edge(1, 0, 2),
+            edge(1, 1, 4),
+            edge(2, 0, 3),
+            edge(3, 0, 2),
+            edge(3, 1, 3),
+        ]);
+        assert_eq!(
+            cycles.iter().map(|c| docs(c)).collect::<Vec<_>>(),
+            vec![vec![(2, 3), (3, 2)], vec![(3, 2), (2, 3)], vec![(3, 3)],]
+        );
+    }
+}
@@ -97,4 +97,31 @@ mod tests {
             assert_eq!(actual.as_deref(), expected_map[name], "name={}", name);
         }
     }
+
+    #[test]
+    fn test_entry_point_comment() {
+        let mut wa = WorkspaceAnalysis::default();
+        wa.initialize(WorkspaceHost::default());
+
+        let main_doc: DocId = 1;
+        let sub_doc: DocId = 2;
+        wa.update_doc(
+            main_doc,
+            Lang::Hsp3,
+            "; ham-entry\n#include \"not_exist.hsp\"\n".into(),
+        );
+        wa.update_doc(sub_doc, Lang::Hsp3, "#include \"not_exist.hsp\"\n".into());
+
+        // エントリーポイントからincludeを解決できないことが報告される。
+        let mut includes = vec![];
+        wa.collect_unresolved_includes(&mut includes);
+        let docs = includes.iter().map(|(_, loc)| loc.doc).collect::<Vec<_>>();
+        assert_eq!(docs, vec![main_doc]);
+
+        // コメントを消すとエントリーポイントではなくなる。
+        wa.update_doc(main_doc, Lang::Hsp3, "#include \"not_exist.hsp\"\n".into());
+        let mut includes = vec![];
+        wa.collect_unresolved_includes(&mut includes);
+        assert_eq!(includes.len(), 0);
+    }
 }
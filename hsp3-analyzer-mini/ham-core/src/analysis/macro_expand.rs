@@ -0,0 +1,425 @@
+//! `#define` で定義されたマクロの展開
+//!
+//! マクロの本体にある `%1`, `%2`, ... を引数で置き換え、本体に含まれるマクロの呼び出しも展開する。
+//! マクロの本体に書かれた名前は展開された場所で解決されるので、その解決に使う。
+
+use super::*;
+use crate::parse::*;
+
+/// マクロの展開が入れ子になる深さの上限 (自分自身を呼び出すマクロで止まるため。)
+const MAX_MACRO_EXPANSION_DEPTH: usize = 16;
+
+/// 展開結果のトークン数の上限 (展開のたびに倍々に増えるマクロで止まるため。)
+const MAX_MACRO_EXPANSION_TOKENS: usize = 10000;
+
+/// マクロの定義
+pub(crate) struct MacroDef {
+    pub(crate) ctype: bool,
+    /// パラメータの既定値 (`%1 = 0` の `0` の部分。省略されていたら空)
+    pub(crate) params: Vec<Vec<PToken>>,
+    pub(crate) body: Vec<PToken>,
+}
+
+impl MacroDef {
+    pub(crate) fn new(stmt: &PDefineStmt) -> Self {
+        MacroDef {
+            ctype: stmt.ctype_opt.is_some(),
+            params: stmt.params.iter().map(|param| param.init.clone()).collect(),
+            body: stmt.tokens.clone(),
+        }
+    }
+
+    /// 置換テキスト (パラメータは `%1` などのまま)
+    pub(crate) fn body_to_string(&self) -> String {
+        tokens_to_string(&self.body)
+    }
+}
+
+/// 展開されたトークン
+#[derive(Clone)]
+pub(crate) struct MacroToken {
+    pub(crate) token: PToken,
+    /// マクロの本体 (またはパラメータの既定値) に由来するか。(呼び出し側の引数に由来するなら false)
+    pub(crate) from_body: bool,
+    /// 展開結果を文字列にするとき、直前に空白を置くか
+    pub(crate) space_before: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct MacroExpansion {
+    pub(crate) tokens: Vec<MacroToken>,
+    /// 展開の途中で呼び出されたマクロの名前
+    pub(crate) macro_names: Vec<MacroToken>,
+}
+
+/// 2つのトークンの間に空白がないか
+fn is_adjacent(first: &PToken, second: &PToken) -> bool {
+    first.body.loc.doc == second.body.loc.doc && first.body.loc.end() == second.body.loc.start()
+}
+
+/// トークン列を展開されたトークンにする。空白の有無は元の位置関係から決める。
+fn to_macro_tokens(tokens: &[PToken], from_body: bool) -> Vec<MacroToken> {
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| MacroToken {
+            token: token.clone(),
+            from_body,
+            space_before: i >= 1 && !is_adjacent(&tokens[i - 1], token),
+        })
+        .collect()
+}
+
+/// 置き換えたトークン列の先頭の空白を、置き換えられたトークンに合わせる。
+fn extend_replaced(output: &mut Vec<MacroToken>, tokens: &[MacroToken], space_before: bool) {
+    let start = output.len();
+    output.extend(tokens.iter().cloned());
+    if let Some(first) = output.get_mut(start) {
+        first.space_before = space_before;
+    }
+}
+
+/// 本体のパラメータ (`%n`) を引数で置き換える。引数が省略されていたら既定値を使う。
+fn substitute(def: &MacroDef, args: &[Vec<MacroToken>]) -> Vec<MacroToken> {
+    let body = to_macro_tokens(&def.body, true);
+    let mut output = vec![];
+    let mut i = 0;
+    while i < def.body.len() {
+        let token = &def.body[i];
+
+        let index_opt = match def.body.get(i + 1) {
+            Some(number)
+                if token.kind() == TokenKind::Percent
+                    && number.kind() == TokenKind::Number
+                    && is_adjacent(token, number) =>
+            {
+                number.body_text().parse::<usize>().ok()
+            }
+            _ => None,
+        };
+        let index = match index_opt {
+            Some(n) if n >= 1 => n - 1,
+            _ => {
+                output.push(body[i].clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let space_before = body[i].space_before;
+        match args.get(index) {
+            Some(arg) if !arg.is_empty() => extend_replaced(&mut output, arg, space_before),
+            _ => {
+                let init = def
+                    .params
+                    .get(index)
+                    .map_or(&[][..], |init| init.as_slice());
+                extend_replaced(&mut output, &to_macro_tokens(init, true), space_before);
+            }
+        }
+        i += 2;
+    }
+    output
+}
+
+/// `(` の直後から対応する `)` までのトークンを、トップレベルのカンマで区切って引数のリストにする。
+///
+/// 対応する `)` の次の位置を返す。
+fn split_args(tokens: &[MacroToken], start: usize) -> Option<(Vec<Vec<MacroToken>>, usize)> {
+    let mut args = vec![vec![]];
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(start) {
+        match t.token.kind() {
+            TokenKind::LeftParen => depth += 1,
+            TokenKind::RightParen if depth == 0 => return Some((args, i + 1)),
+            TokenKind::RightParen => depth -= 1,
+            TokenKind::Comma if depth == 0 => {
+                args.push(vec![]);
+                continue;
+            }
+            _ => {}
+        }
+        args.last_mut().unwrap().push(t.clone());
+    }
+    None
+}
+
+struct Expander<'a> {
+    resolve: &'a mut dyn FnMut(&str) -> Option<Rc<MacroDef>>,
+    output: MacroExpansion,
+}
+
+impl Expander<'_> {
+    fn on_tokens(&mut self, tokens: &[MacroToken], depth: usize) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let t = &tokens[i];
+            i += 1;
+
+            let def_opt = if t.token.kind() == TokenKind::Ident
+                && depth < MAX_MACRO_EXPANSION_DEPTH
+                && self.output.tokens.len() < MAX_MACRO_EXPANSION_TOKENS
+            {
+                (self.resolve)(t.token.body_text())
+            } else {
+                None
+            };
+            let def = match def_opt {
+                Some(it) => it,
+                None => {
+                    self.output.tokens.push(t.clone());
+                    continue;
+                }
+            };
+
+            // ctype のマクロは `(...)` を引数とする。
+            let args = if def.ctype {
+                let args_opt = match tokens.get(i) {
+                    Some(paren) if paren.token.kind() == TokenKind::LeftParen => {
+                        split_args(tokens, i + 1)
+                    }
+                    _ => None,
+                };
+                match args_opt {
+                    Some((args, end)) => {
+                        i = end;
+                        args
+                    }
+                    None => {
+                        self.output.tokens.push(t.clone());
+                        continue;
+                    }
+                }
+            } else {
+                vec![]
+            };
+
+            self.output.macro_names.push(t.clone());
+            let mut body = substitute(&def, &args);
+            if let Some(first) = body.first_mut() {
+                first.space_before = t.space_before;
+            }
+            self.on_tokens(&body, depth + 1);
+        }
+    }
+}
+
+/// マクロを展開する。
+///
+/// `args` は呼び出し側の引数のトークン列。
+/// `resolve` はマクロの名前からその定義を探す関数で、展開結果に含まれるマクロの呼び出しを展開するのに使う。
+pub(crate) fn expand_macro(
+    def: &MacroDef,
+    args: &[Vec<PToken>],
+    resolve: &mut dyn FnMut(&str) -> Option<Rc<MacroDef>>,
+) -> MacroExpansion {
+    let mut expander = Expander {
+        resolve,
+        output: MacroExpansion::default(),
+    };
+    let body = expand_macro_once(def, args);
+    expander.on_tokens(&body, 1);
+    expander.output
+}
+
+/// マクロを1段階だけ展開する。(本体に含まれるマクロの呼び出しは展開しない。)
+fn expand_macro_once(def: &MacroDef, args: &[Vec<PToken>]) -> Vec<MacroToken> {
+    let args = args
+        .iter()
+        .map(|arg| to_macro_tokens(arg, false))
+        .collect::<Vec<_>>();
+    substitute(def, &args)
+}
+
+/// トークン列を文字列にする。トークンの間の空白は1つにまとめる。
+pub(crate) fn tokens_to_string(tokens: &[PToken]) -> String {
+    macro_tokens_to_string(&to_macro_tokens(tokens, true))
+}
+
+/// 展開されたトークン列を文字列にする。
+fn macro_tokens_to_string(tokens: &[MacroToken]) -> String {
+    let mut s = String::new();
+    for (i, t) in tokens.iter().enumerate() {
+        if i >= 1 && t.space_before {
+            s += " ";
+        }
+        s += t.token.body_text();
+    }
+    s
+}
+
+#[derive(Default)]
+struct TokenCollector {
+    tokens: Vec<PToken>,
+}
+
+impl PVisitor for TokenCollector {
+    fn on_token(&mut self, token: &PToken) {
+        self.tokens.push(token.clone());
+    }
+}
+
+/// 呼び出し側の引数をトークン列にする。
+pub(crate) fn macro_args_to_tokens(args: &[PArg]) -> Vec<Vec<PToken>> {
+    args.iter()
+        .map(|arg| {
+            let mut v = TokenCollector::default();
+            v.on_expr_opt(arg.expr_opt.as_ref());
+            v.tokens
+        })
+        .collect()
+}
+
+/// マクロの呼び出し
+struct MacroCall {
+    /// 呼び出し側の引数
+    args: Vec<Vec<PToken>>,
+    /// 引数が `(...)` で囲まれているか
+    paren: bool,
+}
+
+struct CallFinder {
+    pos: Pos16,
+    out: Option<MacroCall>,
+}
+
+impl CallFinder {
+    fn try_find(&mut self, callee: &PToken, args: &[PArg], paren: bool) {
+        if self.out.is_none() && callee.body.loc.range.contains_inclusive(self.pos) {
+            self.out = Some(MacroCall {
+                args: macro_args_to_tokens(args),
+                paren,
+            });
+        }
+    }
+}
+
+impl PVisitor for CallFinder {
+    fn on_compound(&mut self, compound: &PCompound) {
+        if self.out.is_some() || !compound.compute_range().contains_inclusive(self.pos) {
+            return;
+        }
+
+        match compound {
+            PCompound::Name(name) => self.try_find(name, &[], false),
+            PCompound::Paren(np) => self.try_find(&np.name, &np.args, true),
+            PCompound::Dots(_) => {}
+        }
+        self.on_compound_default(compound);
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if self.out.is_some() || !stmt.compute_range().contains_inclusive(self.pos) {
+            return;
+        }
+
+        if let PStmt::Command(stmt) = stmt {
+            self.try_find(&stmt.command, &stmt.args, false);
+        }
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// 指定した位置にある名前を呼び出し先とする呼び出しを探す。
+fn find_macro_call(root: &PRoot, pos: Pos16) -> Option<MacroCall> {
+    let mut v = CallFinder { pos, out: None };
+    v.on_root(root);
+    v.out
+}
+
+/// 指定した位置にあるマクロの呼び出しを1段階だけ展開した結果を文字列にする。
+pub(crate) fn expand_macro_call_at(root: &PRoot, pos: Pos16, def: &MacroDef) -> Option<String> {
+    let call = find_macro_call(root, pos)?;
+    // ctype のマクロは `(...)` がなければ展開されない。
+    if call.paren != def.ctype {
+        return None;
+    }
+
+    Some(macro_tokens_to_string(&expand_macro_once(def, &call.args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source::DocId, token::tokenize};
+    use std::collections::HashMap;
+
+    const NO_DOC: DocId = 1;
+
+    /// スクリプトの `#define` を集めて、最後の文の代入の右辺にあるマクロを展開する。
+    fn expand(text: &str) -> (String, Vec<String>) {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens);
+
+        let mut defs = HashMap::new();
+        for stmt in &root.stmts {
+            if let PStmt::Define(stmt) = stmt {
+                let name = stmt.name_opt.as_ref().unwrap().body_text().to_string();
+                defs.insert(name, Rc::new(MacroDef::new(stmt)));
+            }
+        }
+
+        let (name, args) = match root.stmts.last() {
+            Some(PStmt::Assign(stmt)) => match stmt.args[0].expr_opt.as_ref() {
+                Some(PExpr::Compound(PCompound::Paren(np))) => (&np.name, &np.args[..]),
+                Some(PExpr::Compound(PCompound::Name(name))) => (name, &[][..]),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let def = defs[name.body_text()].clone();
+        let expansion = expand_macro(&def, &macro_args_to_tokens(args), &mut |name| {
+            defs.get(name).cloned()
+        });
+
+        let text = expansion
+            .tokens
+            .iter()
+            .map(|t| {
+                if t.from_body {
+                    t.token.body_text().to_string()
+                } else {
+                    format!("<{}>", t.token.body_text())
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let macro_names = expansion
+            .macro_names
+            .iter()
+            .map(|t| t.token.body_text().to_string())
+            .collect();
+        (text, macro_names)
+    }
+
+    #[test]
+    fn test_expand_macro() {
+        // パラメータを引数で置き換える。
+        assert_eq!(
+            expand("#define ctype add(%1, %2) (%1 + %2 + offset)\nx = add(a, 2)\n").0,
+            "( <a> + <2> + offset )"
+        );
+
+        // 省略された引数は既定値になる。
+        assert_eq!(
+            expand("#define ctype f(%1, %2 = 10) (%1 * %2)\nx = f(1)\n").0,
+            "( <1> * 10 )"
+        );
+
+        // 入れ子のマクロも展開する。
+        assert_eq!(
+            expand(
+                "#define LIMIT (max_count * 2)\n#define ctype sq(%1) ((%1) * (%1))\n#define ctype g(%1) sq(%1 + LIMIT)\nx = g(y)\n"
+            ),
+            (
+                "( ( <y> + ( max_count * 2 ) ) * ( <y> + ( max_count * 2 ) ) )".to_string(),
+                vec!["sq".to_string(), "LIMIT".to_string(), "LIMIT".to_string()]
+            )
+        );
+
+        // 自分自身を呼び出すマクロは、一定の深さで展開をやめる。
+        let (text, macro_names) = expand("#define ctype r(%1) r(%1)\nx = r(1)\n");
+        assert_eq!(text, "r ( <1> )");
+        assert_eq!(macro_names.len(), MAX_MACRO_EXPANSION_DEPTH - 1);
+    }
+}
@@ -0,0 +1,126 @@
+//! モジュールの可視性の検査
+//!
+//! モジュールの外から `@モジュール名` をつけて参照されているローカルなシンボルや、
+//! モジュールの外から参照されないグローバルなシンボルをみつける。
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ModuleVisibilityHint {
+    /// モジュールの外から参照されているローカルなシンボル
+    UsedOutside,
+    /// モジュールの外から参照されていないグローバルなシンボル
+    UnusedOutside,
+}
+
+pub(crate) struct ModuleVisibilityIssue {
+    pub(crate) hint: ModuleVisibilityHint,
+    pub(crate) symbol: SymbolRc,
+    pub(crate) module_name_opt: Option<RcStr>,
+    /// モジュールの表示用の名前 (名前のないモジュールは `module#N`)
+    pub(crate) module_label: RcStr,
+    pub(crate) def_loc: Loc,
+    /// モジュールの外にある使用箇所
+    pub(crate) external_uses: Vec<Loc>,
+}
+
+impl ModuleVisibilityIssue {
+    pub(crate) fn message(&self) -> String {
+        let module_name = &self.module_label;
+        match self.hint {
+            ModuleVisibilityHint::UsedOutside => format!(
+                "{} はモジュール {} の外から参照されています。グローバルに定義することを検討してください。",
+                self.symbol.name(),
+                module_name
+            ),
+            ModuleVisibilityHint::UnusedOutside => format!(
+                "{} はモジュール {} の外から参照されていません。ローカルに定義することを検討してください。",
+                self.symbol.name(),
+                module_name
+            ),
+        }
+    }
+}
+
+/// モジュールの外から参照されうる種類のシンボルか？
+fn kind_is_target(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::StaticVar
+        | HspSymbolKind::Const
+        | HspSymbolKind::Enum
+        | HspSymbolKind::Macro { .. }
+        | HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc => true,
+        _ => false,
+    }
+}
+
+/// 指定した位置を含むモジュールをみつける。
+fn enclosing_module(loc: Loc, module_map: &ModuleMap) -> Option<ModuleKey> {
+    module_map.iter().find_map(|(&key, module)| {
+        let content = module.content_loc;
+        if content.doc == loc.doc && content.start() <= loc.start() && loc.end() <= content.end() {
+            Some(key)
+        } else {
+            None
+        }
+    })
+}
+
+pub(crate) fn analyze_module_visibility(
+    def_sites: &[(SymbolRc, Loc)],
+    use_sites: &[(SymbolRc, Loc)],
+    module_map: &ModuleMap,
+    issues: &mut Vec<ModuleVisibilityIssue>,
+) {
+    let mut use_map: HashMap<&SymbolRc, Vec<Loc>> = HashMap::new();
+    for (symbol, loc) in use_sites {
+        use_map.entry(symbol).or_default().push(*loc);
+    }
+
+    let mut done = HashSet::new();
+
+    for (symbol, def_loc) in def_sites {
+        if !kind_is_target(symbol.kind) || !done.insert(symbol) {
+            continue;
+        }
+
+        let module = match (&symbol.scope_opt, enclosing_module(*def_loc, module_map)) {
+            (Some(Scope::Local(scope)), Some(module))
+                if scope.module_opt == Some(module) && scope.deffunc_opt.is_none() =>
+            {
+                module
+            }
+            (Some(Scope::Global), Some(module)) => module,
+            _ => continue,
+        };
+
+        let uses = use_map.get(symbol).map_or(&[][..], |uses| uses.as_slice());
+        let external_uses = uses
+            .iter()
+            .filter(|&&loc| enclosing_module(loc, module_map) != Some(module))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let hint = match symbol.scope_opt {
+            Some(Scope::Local(_)) if !external_uses.is_empty() => ModuleVisibilityHint::UsedOutside,
+            // モジュールの内部で使われていて、外部から使われていないものだけを報告する。
+            // (どこからも使われていないものは、外部に公開するために定義されている可能性がある。)
+            Some(Scope::Global) if !uses.is_empty() && external_uses.is_empty() => {
+                ModuleVisibilityHint::UnusedOutside
+            }
+            _ => continue,
+        };
+
+        issues.push(ModuleVisibilityIssue {
+            hint,
+            symbol: symbol.clone(),
+            module_name_opt: module_map[&module].name_opt.clone(),
+            module_label: module_map[&module].label.clone(),
+            def_loc: *def_loc,
+            external_uses,
+        });
+    }
+}
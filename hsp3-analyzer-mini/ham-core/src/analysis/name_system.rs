@@ -58,6 +58,22 @@ impl SymbolEnv {
         self.map.insert(name, symbol);
     }
 
+    /// 名前が指すシンボルを置き換えて (None なら取り除いて)、元のシンボルを返す。
+    pub(crate) fn replace(
+        &mut self,
+        name: RcStr,
+        symbol_opt: Option<SymbolRc>,
+    ) -> Option<SymbolRc> {
+        match symbol_opt {
+            Some(symbol) => self.map.insert(name, symbol),
+            None => self.map.remove(&name),
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&RcStr, &SymbolRc)> {
+        self.map.iter()
+    }
+
     pub(crate) fn clear(&mut self) {
         self.map.clear();
     }
@@ -259,7 +275,12 @@ pub(crate) fn resolve_implicit_symbol(
     }
 
     if let Some(ns) = &ns_opt {
-        if let it @ Some(_) = ns_env.get(ns).and_then(|env| env.get(&basename)) {
+        // モジュールのフィールドは名前空間の修飾によって外から参照できない。
+        if let it @ Some(_) = ns_env
+            .get(ns)
+            .and_then(|env| env.get(&basename))
+            .filter(|symbol| symbol.kind != HspSymbolKind::Field)
+        {
             return it;
         }
     }
@@ -0,0 +1,74 @@
+//! `newmod` 命令とモジュールの解析
+//!
+//! `newmod 変数, モジュール名` の形の文から、変数とモジュールの対応をみつける。
+//! また、モジュールの初期化・終了処理などの位置をみつける。
+
+use super::*;
+use crate::parse::*;
+
+#[derive(Default)]
+struct V {
+    sites: Vec<(Loc, Loc)>,
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if let PStmt::Command(PCommandStmt { command, args, .. }) = stmt {
+            if command.body_text().eq_ignore_ascii_case("newmod") {
+                let var_opt = match args.first().and_then(|arg| arg.expr_opt.as_ref()) {
+                    Some(PExpr::Compound(compound)) => Some(compound.name()),
+                    _ => None,
+                };
+                let module_opt = match args.get(1).and_then(|arg| arg.expr_opt.as_ref()) {
+                    Some(PExpr::Compound(PCompound::Name(name))) => Some(name),
+                    _ => None,
+                };
+
+                if let (Some(var), Some(module)) = (var_opt, module_opt) {
+                    self.sites.push((var.body.loc, module.body.loc));
+                }
+            }
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// `newmod` 文の (変数名の位置, モジュール名の位置) を列挙する。
+pub(crate) fn collect_newmod_sites(root: &PRoot, sites: &mut Vec<(Loc, Loc)>) {
+    let mut v = V::default();
+    v.on_root(root);
+    sites.extend(v.sites);
+}
+
+/// モジュールの中にある、指定した種類の `#deffunc` 類の位置を列挙する。
+///
+/// `#modinit` などの名前のないものはキーワードの位置とする。
+pub(crate) fn collect_module_deffuncs(
+    root: &PRoot,
+    module_name_loc: Loc,
+    kind: PDefFuncKind,
+    locs: &mut Vec<Loc>,
+) {
+    let module_opt = root.stmts.iter().find_map(|stmt| match stmt {
+        PStmt::Module(stmt)
+            if stmt.name_opt.as_ref().map(|name| name.body.loc) == Some(module_name_loc) =>
+        {
+            Some(stmt)
+        }
+        _ => None,
+    });
+    let module = match module_opt {
+        Some(it) => it,
+        None => return,
+    };
+
+    for stmt in &module.stmts {
+        if let PStmt::DefFunc(stmt) = stmt {
+            if stmt.kind == kind {
+                let token = stmt.name_opt.as_ref().unwrap_or(&stmt.keyword);
+                locs.push(token.body.loc);
+            }
+        }
+    }
+}
@@ -8,6 +8,26 @@ pub(crate) struct IncludeGuard {
     pub(crate) loc: Loc,
 }
 
+/// `#if` 系の命令の条件が「`name` が定義されていない」という意味か判定する。
+///
+/// `#ifndef name`、`#if !defined(name)`、`#if defined(name) == 0` の形に対応する。
+fn is_not_defined_cond(pp_if_stmt: &PUnknownPreProcStmt, name: &str) -> bool {
+    let texts = pp_if_stmt
+        .tokens
+        .iter()
+        .map(|t| t.body_text())
+        .collect::<Vec<_>>();
+
+    match texts.as_slice() {
+        ["ifndef", n] => *n == name,
+        ["if", "!", "defined", "(", n, ")"] => *n == name,
+        ["if", "defined", "(", n, ")", "==", "0"] | ["if", "defined", "(", n, ")", "=", "0"] => {
+            *n == name
+        }
+        _ => false,
+    }
+}
+
 pub(crate) fn find_include_guard(root: &PRoot) -> Option<IncludeGuard> {
     let s1 = root.stmts.get(0)?;
     let s2 = root.stmts.get(1)?;
@@ -18,11 +38,7 @@ pub(crate) fn find_include_guard(root: &PRoot) -> Option<IncludeGuard> {
     };
 
     let name = define_stmt.name_opt.as_ref()?.body_text();
-    if !pp_if_stmt
-        .tokens
-        .iter()
-        .any(|t| t.body_text().contains(name))
-    {
+    if !is_not_defined_cond(pp_if_stmt, name) {
         return None;
     }
 
@@ -36,9 +52,68 @@ pub(crate) fn find_include_guard(root: &PRoot) -> Option<IncludeGuard> {
     })
 }
 
+/// 条件つきコンパイルのブロック (`#if` から `#endif` まで) の範囲を集める。
+///
+/// 条件は評価しないので、インクルードガードを除くすべてのブロックが対象になる。
+/// `#endif` がなければドキュメントの末尾までとする。
+pub(crate) fn find_conditional_blocks(root: &PRoot) -> Vec<Loc> {
+    struct V {
+        include_guard_opt: Option<Pos>,
+        /// 開いている `#if` 系の命令の位置 (インクルードガードならNone)
+        stack: Vec<Option<Loc>>,
+        blocks: Vec<Loc>,
+    }
+
+    impl PVisitor for V {
+        fn on_stmt(&mut self, stmt: &PStmt) {
+            if let PStmt::UnknownPreProc(stmt) = stmt {
+                let loc = stmt.hash.body.loc;
+                match stmt.keyword_text() {
+                    "if" | "ifdef" | "ifndef" => {
+                        let is_guard = self.include_guard_opt == Some(loc.start());
+                        self.stack.push(if is_guard { None } else { Some(loc) });
+                    }
+                    "endif" => {
+                        if let Some(Some(start)) = self.stack.pop() {
+                            self.blocks.push(start.unite(&loc));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            self.on_stmt_default(stmt);
+        }
+    }
+
+    let mut v = V {
+        include_guard_opt: find_include_guard(root).map(|guard| guard.loc.start()),
+        stack: vec![],
+        blocks: vec![],
+    };
+    v.on_root(root);
+
+    let eof = root.eof.body.loc;
+    for start in v.stack.into_iter().flatten() {
+        v.blocks.push(start.unite(&eof));
+    }
+    v.blocks
+}
+
+/// シグネチャのパラメータ
+#[derive(Default)]
+pub(crate) struct SignatureParam {
+    pub(crate) ty_opt: Option<PParamTy>,
+    pub(crate) name_opt: Option<RcStr>,
+    /// 説明
+    pub(crate) info_opt: Option<String>,
+    /// 既定値の式のテキスト
+    pub(crate) default_opt: Option<RcStr>,
+}
+
 pub(crate) struct SignatureData {
     pub(crate) name: RcStr,
-    pub(crate) params: Vec<(Option<PParamTy>, Option<RcStr>, Option<String>)>,
+    pub(crate) params: Vec<SignatureParam>,
 }
 
 #[derive(Default)]
@@ -52,6 +127,16 @@ struct Ctx {
     deffunc_map: DefFuncMap,
     module_len: usize,
     deffunc_len: usize,
+    /// このドキュメントで定義された定数の値
+    const_values: HashMap<RcStr, ConstValue>,
+    /// 次の `#enum` の値 (直前の値を計算できなかったときは None)
+    enum_next: Option<ConstValue>,
+    /// 直前の `#usecom` で定義されたインターフェイス
+    com_interface_opt: Option<SymbolRc>,
+    /// 直前の `#uselib` で指定されたDLLの名前
+    lib_opt: Option<RcStr>,
+    /// 直前の `#regcmd` で指定されたプラグインのファイル名
+    plugin_opt: Option<RcStr>,
 }
 
 impl Ctx {
@@ -86,6 +171,16 @@ impl Ctx {
             &mut self.symbols,
         )
     }
+
+    fn eval_const_expr(&self, expr: &PExpr) -> Option<ConstValue> {
+        eval_const_expr(expr, &mut |name| self.const_values.get(name).copied())
+    }
+
+    /// 定数の値を記録する。
+    fn set_const_value(&mut self, symbol: &SymbolRc, name: &PToken, value: ConstValue) {
+        *symbol.const_value_opt.borrow_mut() = Some(value);
+        self.const_values.insert(name.body.text.clone(), value);
+    }
 }
 
 fn add_symbol(
@@ -141,21 +236,35 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
         PStmt::Const(PConstStmt {
             hash,
             privacy_opt,
+            ty_opt,
             name_opt,
+            init_opt,
             ..
         }) => {
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_local(privacy_opt);
-                ctx.add_symbol(HspSymbolKind::Const, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::Const, hash, name, scope);
+
+                let value_opt = init_opt.as_ref().and_then(|init| ctx.eval_const_expr(init));
+                if let Some(mut value) = value_opt {
+                    if let Some((ty, _)) = ty_opt {
+                        value = value.convert(*ty);
+                    }
+                    ctx.set_const_value(&symbol, name, value);
+                }
             }
         }
-        PStmt::Define(PDefineStmt {
-            hash,
-            privacy_opt,
-            ctype_opt,
-            name_opt,
-            ..
-        }) => {
+        PStmt::Define(stmt) => {
+            let PDefineStmt {
+                hash,
+                privacy_opt,
+                ctype_opt,
+                name_opt,
+                left_paren_opt,
+                tokens,
+                ..
+            } = stmt;
+
             if ctx.include_guard.as_ref().map_or(false, |g| {
                 g.loc.is_touched(hash.body.loc.doc, hash.body_pos16())
             }) {
@@ -165,19 +274,41 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_local(privacy_opt);
                 let ctype = ctype_opt.is_some();
-                ctx.add_symbol(HspSymbolKind::Macro { ctype }, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::Macro { ctype }, hash, name, scope);
+                *symbol.macro_opt.borrow_mut() = Some(Rc::new(MacroDef::new(stmt)));
+                if let Some(data) = new_signature_data_for_macro(stmt) {
+                    *symbol.signature_opt.borrow_mut() = Some(Rc::new(data));
+                }
+
+                // `#define print mes` のように、本体が1つの識別子だけなら別名とみなす。
+                if let ([token], None, false) = (tokens.as_slice(), left_paren_opt, ctype) {
+                    if token.kind() == TokenKind::Ident {
+                        *symbol.alias_opt.borrow_mut() = Some(token.body.text.clone());
+                    }
+                }
             }
         }
         PStmt::Enum(PEnumStmt {
             hash,
             privacy_opt,
             name_opt,
+            init_opt,
             ..
         }) => {
+            // `#enum X = n` は値を n にする。省略されたら直前の値の次になる。
+            let value_opt = match init_opt {
+                Some(init) => ctx.eval_const_expr(init),
+                None => ctx.enum_next,
+            };
+
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_local(privacy_opt);
-                ctx.add_symbol(HspSymbolKind::Enum, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::Enum, hash, name, scope);
+                if let Some(value) = value_opt {
+                    ctx.set_const_value(&symbol, name, value);
+                }
             }
+            ctx.enum_next = value_opt.map(|value| value.next());
         }
         PStmt::DefFunc(stmt) => {
             let PDefFuncStmt {
@@ -197,6 +328,7 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
             ctx.deffunc_map.insert(
                 deffunc,
                 DefFuncData {
+                    kind: *kind,
                     content_loc: hash.body.loc.unite(behind),
                 },
             );
@@ -212,7 +344,7 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
                 }
             }
 
-            if let Some(symbol) = symbol_opt {
+            if let Some(symbol) = &symbol_opt {
                 if let Some(data) = new_signature_data_for_deffunc(stmt) {
                     *symbol.signature_opt.borrow_mut() = Some(Rc::new(data));
                 }
@@ -220,25 +352,44 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
 
             let parent_deffunc = replace(&mut ctx.scope.deffunc_opt, Some(deffunc));
 
+            let mut arg_params = vec![];
+            if let HspSymbolKind::ModFunc | HspSymbolKind::ModCFunc = kind {
+                arg_params.push(None);
+            }
+
             for param in params {
-                if let Some(name) = &param.name_opt {
-                    let param_ty = param.param_ty_opt.as_ref().map(|&(t, _)| t);
+                let param_ty = param.param_ty_opt.as_ref().map(|&(t, _)| t);
+                let param_symbol_opt = param.name_opt.as_ref().map(|name| {
                     ctx.add_symbol(
                         HspSymbolKind::Param(param_ty),
                         hash,
                         name,
                         ImportMode::Param,
-                    );
+                    )
+                });
+
+                if param_ty.map_or(true, |ty| ty.take_arg()) {
+                    arg_params.push(param_symbol_opt);
                 }
             }
 
+            if let Some(symbol) = &symbol_opt {
+                *symbol.arg_params.borrow_mut() = arg_params;
+            }
+
             for stmt in stmts {
                 on_stmt(stmt, ctx);
             }
 
             ctx.scope.deffunc_opt = parent_deffunc;
         }
-        PStmt::UseLib(_) => {}
+        PStmt::UseLib(stmt) => {
+            if let Some(file_path) = &stmt.file_path_opt {
+                if file_path.body.kind == TokenKind::Str {
+                    ctx.lib_opt = Some(unquote(file_path.body.text.clone()));
+                }
+            }
+        }
         PStmt::LibFunc(stmt) => {
             let PLibFuncStmt {
                 hash,
@@ -257,6 +408,7 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
             }
 
             if let Some(symbol) = symbol_opt {
+                *symbol.lib_opt.borrow_mut() = ctx.lib_opt.clone();
                 if let Some(signature_data) = new_signature_data_for_lib_func(stmt) {
                     *symbol.signature_opt.borrow_mut() = Some(Rc::new(signature_data));
                 }
@@ -270,30 +422,58 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
         }) => {
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_local(privacy_opt);
-                ctx.add_symbol(HspSymbolKind::ComInterface, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::ComInterface, hash, name, scope);
+                ctx.com_interface_opt = Some(symbol);
             }
         }
-        PStmt::ComFunc(PComFuncStmt {
-            hash,
-            privacy_opt,
-            name_opt,
-            ..
-        }) => {
+        PStmt::ComFunc(stmt) => {
+            let PComFuncStmt {
+                hash,
+                privacy_opt,
+                name_opt,
+                index_opt,
+                ..
+            } = stmt;
+
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_global(privacy_opt);
-                ctx.add_symbol(HspSymbolKind::ComFunc, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::ComFunc, hash, name, scope);
+                *symbol.com_method_opt.borrow_mut() = Some(ComMethodData {
+                    interface_opt: ctx.com_interface_opt.clone(),
+                    index_opt: index_opt.as_ref().map(|index| index.body.text.clone()),
+                });
+                if let Some(data) = new_signature_data_for_com_func(stmt) {
+                    *symbol.signature_opt.borrow_mut() = Some(Rc::new(data));
+                }
             }
         }
-        PStmt::RegCmd(_) => {}
+        PStmt::RegCmd(PRegCmdStmt { args, .. }) => {
+            // `#regcmd "初期化関数", "プラグインのファイル名", 変数型の数`
+            ctx.plugin_opt = match args.get(1).and_then(|arg| arg.expr_opt.as_ref()) {
+                Some(PExpr::Literal(token)) if token.body.kind == TokenKind::Str => {
+                    Some(unquote(token.body.text.clone()))
+                }
+                _ => None,
+            };
+        }
         PStmt::Cmd(PCmdStmt {
             hash,
             privacy_opt,
             name_opt,
+            command_id_opt,
             ..
         }) => {
             if let Some(name) = name_opt {
                 let scope = ctx.privacy_scope_or_local(privacy_opt);
-                ctx.add_symbol(HspSymbolKind::PluginCmd, hash, name, scope);
+                let symbol = ctx.add_symbol(HspSymbolKind::PluginCmd, hash, name, scope);
+                *symbol.plugin_cmd_opt.borrow_mut() = Some(PluginCmdData {
+                    plugin_opt: ctx.plugin_opt.clone(),
+                    command_id_opt: command_id_opt.as_ref().map(|id| id.body.text.clone()),
+                });
+                let last = command_id_opt.as_ref().unwrap_or(name);
+                if let Some(data) = new_signature_data_for_plugin_cmd(hash, name, last) {
+                    *symbol.signature_opt.borrow_mut() = Some(Rc::new(data));
+                }
             }
         }
         PStmt::Module(PModuleStmt {
@@ -311,9 +491,13 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
                 let name_opt = name_opt
                     .as_ref()
                     .and_then(|t| module_name_as_ident(&t.body));
+                let label = name_opt
+                    .clone()
+                    .unwrap_or_else(|| unnamed_module_label(module.index));
                 let content_loc = hash.body.loc.unite(&behind);
                 ModuleRc::new(ModuleData {
                     name_opt,
+                    label,
                     content_loc,
                 })
             };
@@ -345,12 +529,7 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
         PStmt::Include(stmt) => {
             if let Some(file_path) = &stmt.file_path_opt {
                 if file_path.body.kind == TokenKind::Str {
-                    let mut text = file_path.body.text.clone();
-
-                    // クオートを外す。
-                    let l = if text.starts_with("\"") { 1 } else { 0 };
-                    let r = text.len() - (if text.ends_with("\"") { 1 } else { 0 });
-                    text = text.slice(l, r);
+                    let mut text = unquote(file_path.body.text.clone());
 
                     // 標準化する。
                     text = text.replace("\\\\", "/").to_ascii_lowercase().into();
@@ -360,10 +539,17 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
                 }
             }
         }
-        PStmt::UnknownPreProc(_) => {}
+        PStmt::UnknownPreProc(_) | PStmt::Unknown(_) => {}
     }
 }
 
+/// 文字列リテラルのクオートを外す。
+fn unquote(text: RcStr) -> RcStr {
+    let l = if text.starts_with("\"") { 1 } else { 0 };
+    let r = text.len() - (if text.ends_with("\"") { 1 } else { 0 });
+    text.slice(l, r.max(l))
+}
+
 fn to_symbol_kind(kind: PDefFuncKind) -> HspSymbolKind {
     match kind {
         PDefFuncKind::DefFunc => HspSymbolKind::DefFunc,
@@ -388,13 +574,43 @@ fn new_signature_data_for_lib_func(stmt: &PLibFuncStmt) -> Option<SignatureData>
                 _ => None,
             };
             let name_opt = param.name_opt.as_ref().map(|name| name.body.text.clone());
-            Some((ty_opt, name_opt, None))
+            Some(SignatureParam {
+                ty_opt,
+                name_opt,
+                ..SignatureParam::default()
+            })
         })
         .collect::<Vec<_>>();
 
     Some(SignatureData { name, params })
 }
 
+fn new_signature_data_for_com_func(stmt: &PComFuncStmt) -> Option<SignatureData> {
+    let name = stmt.name_opt.as_ref()?.body.text.clone();
+
+    // 最初の引数はCOMオブジェクトの変数
+    let mut params = vec![SignatureParam {
+        name_opt: Some("comobj".into()),
+        ..SignatureParam::default()
+    }];
+
+    for param in &stmt.params {
+        let ty_opt = match param.param_ty_opt {
+            Some((ty, _)) if !ty.take_arg() => continue,
+            Some((ty, _)) => Some(ty),
+            _ => None,
+        };
+        let name_opt = param.name_opt.as_ref().map(|name| name.body.text.clone());
+        params.push(SignatureParam {
+            ty_opt,
+            name_opt,
+            ..SignatureParam::default()
+        });
+    }
+
+    Some(SignatureData { name, params })
+}
+
 fn new_signature_data_for_deffunc(stmt: &PDefFuncStmt) -> Option<SignatureData> {
     let take_modvar = match stmt.kind {
         PDefFuncKind::DefFunc | PDefFuncKind::DefCFunc => false,
@@ -407,7 +623,11 @@ fn new_signature_data_for_deffunc(stmt: &PDefFuncStmt) -> Option<SignatureData>
     let mut params = vec![];
 
     if take_modvar {
-        params.push((Some(PParamTy::Modvar), Some("thismod".into()), None));
+        params.push(SignatureParam {
+            ty_opt: Some(PParamTy::Modvar),
+            name_opt: Some("thismod".into()),
+            ..SignatureParam::default()
+        });
     }
 
     for param in &stmt.params {
@@ -417,13 +637,142 @@ fn new_signature_data_for_deffunc(stmt: &PDefFuncStmt) -> Option<SignatureData>
             _ => None,
         };
         let name_opt = param.name_opt.as_ref().map(|name| name.body.text.clone());
+        let default_opt = param.default_opt.as_ref().map(expr_to_text);
 
-        params.push((ty_opt, name_opt, None));
+        params.push(SignatureParam {
+            ty_opt,
+            name_opt,
+            info_opt: None,
+            default_opt,
+        });
     }
 
     Some(SignatureData { name, params })
 }
 
+/// 式を書かれたとおりのテキストにする。(トークンの間に空白があれば、1つの空白にする。)
+fn expr_to_text(expr: &PExpr) -> RcStr {
+    #[derive(Default)]
+    struct V {
+        text: String,
+        last: Option<Pos>,
+    }
+
+    impl PVisitor for V {
+        fn on_token(&mut self, token: &PToken) {
+            if self
+                .last
+                .map_or(false, |last| last != token.body.loc.start())
+            {
+                self.text += " ";
+            }
+            self.text += token.body_text();
+            self.last = Some(token.body.loc.end());
+        }
+    }
+
+    let mut v = V::default();
+    v.on_expr(expr);
+    v.text.into()
+}
+
+/// 行末のコメントからパラメータ名を取り出す。
+///
+/// コメントの先頭にある `name` は無視して、括弧があればその中を、なければ全体をカンマで区切ったものをパラメータ名とする。
+/// `parens_required` のときは括弧がなければ空にする。行末のコメントがなければNoneを返す。
+fn param_names_from_comment(
+    last: &PToken,
+    name: &str,
+    parens_required: bool,
+) -> Option<Vec<RcStr>> {
+    let comment = last
+        .trailing
+        .iter()
+        .find(|t| t.kind == TokenKind::Comment)?;
+
+    let mut text = comment.text.as_str().trim_start_matches([';', '/']).trim();
+    text = text.strip_prefix(name).unwrap_or(text).trim();
+    match (text.find('('), text.rfind(')')) {
+        (Some(l), Some(r)) if l < r => text = &text[l + 1..r],
+        _ if parens_required => return Some(vec![]),
+        _ => {}
+    }
+    Some(text.split(',').map(|param| param.trim().into()).collect())
+}
+
+/// プラグインのコマンドのシグネチャを作る。
+///
+/// `#cmd` 文は引数の情報を持たないため、`#cmd foo $00 ; foo p1, p2` のように行末のコメントに書かれた名前をパラメータとする。
+/// (コメントの先頭にコマンド名があれば無視する。括弧で囲んでもよい。)
+/// 行末のコメントがなければ、定義の前のコメントにある `@param` の名前を使う。
+fn new_signature_data_for_plugin_cmd(
+    hash: &PToken,
+    name: &PToken,
+    last: &PToken,
+) -> Option<SignatureData> {
+    let names: Vec<RcStr> = match param_names_from_comment(last, name.body_text(), false) {
+        Some(names) => names
+            .into_iter()
+            .filter(|param| !param.is_empty())
+            .collect(),
+        None => {
+            let details = comment::calculate_details(&comment::collect_comments(hash));
+            details
+                .params
+                .into_iter()
+                .map(|(param, _)| RcStr::from(param))
+                .collect()
+        }
+    };
+    if names.is_empty() {
+        return None;
+    }
+
+    Some(SignatureData {
+        name: name.body.text.clone(),
+        params: names
+            .into_iter()
+            .map(|name| SignatureParam {
+                name_opt: Some(name),
+                ..SignatureParam::default()
+            })
+            .collect(),
+    })
+}
+
+fn new_signature_data_for_macro(stmt: &PDefineStmt) -> Option<SignatureData> {
+    if stmt.ctype_opt.is_none() {
+        return None;
+    }
+
+    let name = stmt.name_opt.as_ref()?.body.text.clone();
+
+    // `#define ctype clamp(%1, %2, %3) ... ; clamp(value, low, high)` のように、
+    // 行末のコメントにある括弧の中をパラメータ名とみなす。
+    let last = stmt
+        .tokens
+        .last()
+        .or(stmt.right_paren_opt.as_ref())
+        .or(stmt.name_opt.as_ref())?;
+    let names = param_names_from_comment(last, name.as_str(), true).unwrap_or_default();
+
+    // 名前がなければ `%1`, `%2`, ... とする。
+    let params = (0..stmt.params.len())
+        .map(|i| {
+            let name = match names.get(i) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => format!("%{}", i + 1).into(),
+            };
+            SignatureParam {
+                name_opt: Some(name),
+                ..SignatureParam::default()
+            }
+        })
+        .collect();
+
+    Some(SignatureData { name, params })
+}
+
 pub(crate) struct PreprocAnalysisResult {
     pub(crate) symbols: Vec<SymbolRc>,
     pub(crate) include_guard: Option<IncludeGuard>,
@@ -435,6 +784,7 @@ pub(crate) struct PreprocAnalysisResult {
 pub(crate) fn analyze_preproc(doc: DocId, root: &PRoot) -> PreprocAnalysisResult {
     let mut ctx = Ctx::default();
     ctx.doc = doc;
+    ctx.enum_next = Some(ConstValue::default());
     ctx.include_guard = find_include_guard(root);
 
     for stmt in &root.stmts {
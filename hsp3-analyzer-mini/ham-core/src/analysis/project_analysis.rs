@@ -1,4 +1,5 @@
 use super::*;
+use crate::parse::{PConstStmt, PConstTy, PEnumStmt, PExpr, PParamTy, PStmt};
 
 type DocAnalysisMap = HashMap<DocId, DocAnalysis>;
 
@@ -40,12 +41,42 @@ pub(crate) struct ProjectAnalysis {
     pub(super) doc_symbols_map: HashMap<DocId, Vec<SymbolRc>>,
     pub(super) def_sites: Vec<(SymbolRc, Loc)>,
     pub(super) use_sites: Vec<(SymbolRc, Loc)>,
+    /// シンボルごとの使用回数
+    pub(super) use_counts: HashMap<SymbolRc, usize>,
+    /// 変数ごとの、代入される値の型とその位置
+    pub(super) var_types: HashMap<SymbolRc, Vec<(VarType, Loc)>>,
+    /// 変数ごとの、配列の確保 (位置の順)
+    pub(super) array_decls: HashMap<SymbolRc, Vec<ArrayDecl>>,
+    /// イベントハンドラとして登録されているラベル
+    pub(super) event_handlers: HashSet<SymbolRc>,
+    /// `newmod` でインスタンスが格納される変数ごとの、そのモジュール
+    pub(super) newmod_modules: HashMap<SymbolRc, Vec<SymbolRc>>,
+    /// 他のドキュメントの定数を参照しているため、プロジェクトの中で値を計算した定数
+    pub(super) const_values: HashMap<SymbolRc, ConstValue>,
 
     /// (loc, doc): locにあるincludeがdocに解決されたことを表す。
     pub(super) include_resolution: Vec<(Loc, DocId)>,
+    /// 名前のないモジュールに、コンパイラがつける名前空間の名前 (`M0`, `M1`, ...)
+    pub(super) unnamed_module_names: HashMap<ModuleKey, RcStr>,
 
     diagnosed: bool,
-    pub(super) diagnostics: Vec<(String, Loc)>,
+    /// 解決できなかったinclude (ファイル名, `#include` 文の位置)
+    pub(super) unresolved_includes: Vec<(RcStr, Loc)>,
+}
+
+/// 定数の値の決め方
+enum ConstInit<'a> {
+    /// 初期値の式と型
+    Expr(&'a PExpr, Option<PConstTy>),
+    /// 直前の `#enum` の値の次
+    Next(SymbolRc),
+}
+
+/// 名前のないモジュールを数えるときに、ドキュメントの中でたどるもの
+#[derive(Clone, Copy)]
+enum UnnamedModuleEvent {
+    Module(ModuleKey),
+    Include(DocId),
 }
 
 impl ProjectAnalysis {
@@ -59,10 +90,17 @@ impl ProjectAnalysis {
         self.doc_symbols_map.clear();
         self.def_sites.clear();
         self.use_sites.clear();
+        self.use_counts.clear();
+        self.var_types.clear();
+        self.array_decls.clear();
+        self.event_handlers.clear();
+        self.newmod_modules.clear();
+        self.const_values.clear();
         self.include_resolution.clear();
+        self.unnamed_module_names.clear();
 
         self.diagnosed = false;
-        self.diagnostics.clear();
+        self.unresolved_includes.clear();
     }
 
     pub(crate) fn is_computed(&self) -> bool {
@@ -78,13 +116,19 @@ impl ProjectAnalysis {
         let help_docs = &mut self.help_docs;
         let active_help_docs = &mut self.active_help_docs;
         let include_resolution = &mut self.include_resolution;
-        let diagnostics = &mut self.diagnostics;
+        let unresolved_includes = &mut self.unresolved_includes;
 
         match entrypoints {
             EntryPoints::Docs(entrypoints) => {
                 assert_ne!(entrypoints.len(), 0);
 
                 // エントリーポイントから推移的にincludeされるドキュメントを集める。
+                // (データファイルはエントリーポイントとみなさない。includeされていれば集められる。)
+                let entrypoints = entrypoints
+                    .iter()
+                    .cloned()
+                    .filter(|doc| doc_analysis_map.get(doc).map_or(true, |da| !da.data_file))
+                    .collect::<Vec<_>>();
                 let mut stack = entrypoints
                     .iter()
                     .map(|&doc| (doc, None))
@@ -98,18 +142,15 @@ impl ProjectAnalysis {
                         None => continue,
                     };
 
-                    for &(ref path, loc) in &da.includes {
-                        let path = path.as_str();
+                    for &(ref name, loc) in &da.includes {
+                        let path = name.as_str();
                         let doc_opt = project_docs
                             .find(path, Some(loc.doc))
                             .or_else(|| common_docs.get(path).cloned());
                         let d = match doc_opt {
                             Some(it) => it,
                             None => {
-                                diagnostics.push((
-                                    format!("includeを解決できません: {:?}", path),
-                                    loc.clone(),
-                                ));
+                                unresolved_includes.push((name.clone(), loc));
                                 continue;
                             }
                         };
@@ -131,8 +172,14 @@ impl ProjectAnalysis {
                         continue;
                     }
 
+                    // エントリーポイントがあるときと同じ方法で解決する。
+                    // (ワークスペースにあるファイルに解決されるなら、同じ名前のcommonのファイルは使われない。)
                     for (include, _) in &da.includes {
-                        let doc_opt = self.common_docs.get(include.as_str()).cloned();
+                        let path = include.as_str();
+                        let doc_opt = project_docs
+                            .find(path, Some(doc))
+                            .or_else(|| common_docs.get(path).cloned())
+                            .filter(|d| in_common.contains(d));
                         included_docs.extend(doc_opt);
                     }
                 }
@@ -143,6 +190,21 @@ impl ProjectAnalysis {
                         .cloned()
                         .filter(|doc| !in_common.contains(&doc) || included_docs.contains(&doc)),
                 );
+
+                for &doc in active_docs.iter() {
+                    let da = match doc_analysis_map.get(&doc) {
+                        Some(it) => it,
+                        None => continue,
+                    };
+
+                    for &(ref name, loc) in &da.includes {
+                        let path = name.as_str();
+                        let doc_opt = project_docs
+                            .find(path, Some(doc))
+                            .or_else(|| common_docs.get(path).cloned());
+                        include_resolution.extend(doc_opt.map(|d| (loc, d)));
+                    }
+                }
             }
         }
 
@@ -160,10 +222,103 @@ impl ProjectAnalysis {
         }
     }
 
+    /// 名前のないモジュールに名前空間の名前をつける。
+    ///
+    /// コンパイラは名前のないモジュールに、出現順に `M0`, `M1`, ... という名前をつける。
+    /// 出現順はincludeを展開したソースコードでの順番なので、エントリーポイントからincludeをたどって数える。
+    /// (エントリーポイントがなければ順番が決まらないので、名前をつけない。)
+    fn compute_unnamed_module_names(
+        &mut self,
+        doc_analysis_map: &DocAnalysisMap,
+        module_map: &ModuleMap,
+    ) {
+        let entrypoints = match &self.entrypoints {
+            EntryPoints::Docs(it) => it,
+            EntryPoints::NonCommon => return,
+        };
+
+        // ドキュメントごとに、名前のないモジュールとincludeを出現順に並べる。
+        let mut events: HashMap<DocId, Vec<(Pos, UnnamedModuleEvent)>> = HashMap::new();
+        for (&m, module) in module_map.iter() {
+            if module.name_opt.is_none() && self.active_docs.contains(&m.doc) {
+                events
+                    .entry(m.doc)
+                    .or_default()
+                    .push((module.content_loc.start(), UnnamedModuleEvent::Module(m)));
+            }
+        }
+        for &(loc, doc) in &self.include_resolution {
+            events
+                .entry(loc.doc)
+                .or_default()
+                .push((loc.start(), UnnamedModuleEvent::Include(doc)));
+        }
+        for list in events.values_mut() {
+            list.sort_by_key(|&(pos, _)| pos);
+        }
+
+        // 同じファイルが2回includeされても、2回目は (インクルードガードによって) 空になるとみなす。
+        // (暗黙にincludeされるファイルは、エントリーポイントの後ろにあるとみなす。)
+        let names = &mut self.unnamed_module_names;
+        let mut count = 0;
+        let mut visited = HashSet::new();
+        for &entrypoint in entrypoints {
+            if doc_analysis_map
+                .get(&entrypoint)
+                .map_or(false, |da| da.data_file)
+                || !visited.insert(entrypoint)
+            {
+                continue;
+            }
+
+            let mut stack = vec![events
+                .get(&entrypoint)
+                .map_or(&[][..], |list| list.as_slice())];
+            while let Some(list) = stack.last_mut() {
+                let (event, rest) = match list.split_first() {
+                    Some(it) => it,
+                    None => {
+                        stack.pop();
+                        continue;
+                    }
+                };
+                *list = rest;
+
+                match *event {
+                    (_, UnnamedModuleEvent::Module(m)) => {
+                        names.insert(m, format!("M{}", count).into());
+                        count += 1;
+                    }
+                    (_, UnnamedModuleEvent::Include(doc)) => {
+                        if visited.insert(doc) {
+                            stack.push(events.get(&doc).map_or(&[][..], |list| list.as_slice()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 名前のないモジュールに名前空間の名前をつけたモジュールマップを作る。
+    fn name_unnamed_modules(&self, module_map: &ModuleMap) -> ModuleMap {
+        let mut module_map = module_map.clone();
+        for (m, name) in &self.unnamed_module_names {
+            if let Some(module) = module_map.get_mut(m) {
+                *module = ModuleRc::new(ModuleData {
+                    name_opt: Some(name.clone()),
+                    label: module.label.clone(),
+                    content_loc: module.content_loc,
+                });
+            }
+        }
+        module_map
+    }
+
     fn compute_symbols(&mut self, doc_analysis_map: &DocAnalysisMap, module_map: &ModuleMap) {
         let active_docs = &self.active_docs;
         let public_env = &mut self.public_env;
         let ns_env = &mut self.ns_env;
+        let unnamed_module_names = &self.unnamed_module_names;
         let doc_symbols_map = &mut self.doc_symbols_map;
         let def_sites = &mut self.def_sites;
         let use_sites = &mut self.use_sites;
@@ -175,6 +330,24 @@ impl ProjectAnalysis {
             }
 
             extend_public_env_from_symbols(&da.preproc_symbols, public_env, ns_env);
+
+            // 名前のないモジュールのシンボルは、ドキュメントごとの解析では名前空間に属していない。
+            for symbol in &da.preproc_symbols {
+                let ns = match &symbol.scope_opt {
+                    Some(Scope::Local(LocalScope {
+                        module_opt: Some(m),
+                        deffunc_opt: None,
+                    })) if symbol.ns_opt.is_none() => match unnamed_module_names.get(m) {
+                        Some(it) => it,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                ns_env
+                    .entry(ns.clone())
+                    .or_default()
+                    .insert(symbol.name(), symbol.clone());
+            }
         }
 
         // 変数の定義箇所を決定する。
@@ -197,6 +370,11 @@ impl ProjectAnalysis {
                 Some((symbol.clone(), loc))
             }));
 
+            // 大きすぎるドキュメントは、変数の定義や使用箇所を解析しない。
+            if da.symbols_only {
+                continue;
+            }
+
             crate::analysis::var::analyze_var_def(
                 doc,
                 da.tree_opt.as_ref().unwrap(),
@@ -234,6 +412,284 @@ impl ProjectAnalysis {
         }
     }
 
+    /// ドキュメントごとの解析では値を計算できなかった `#const` や `#enum` の値を、プロジェクト全体の環境で計算する。
+    /// (includeされたファイルの定数を参照しているものなど)
+    fn compute_const_values(&mut self, doc_analysis_map: &DocAnalysisMap, module_map: &ModuleMap) {
+        // (定数, 値の決め方, 名前を解決するスコープ, ドキュメント)
+        let mut pending = vec![];
+        let mut local_envs: HashMap<DocId, HashMap<LocalScope, SymbolEnv>> = HashMap::new();
+        for (&doc, da) in doc_analysis_map.iter() {
+            if !self.active_docs.contains(&doc) {
+                continue;
+            }
+            let root = match &da.tree_opt {
+                Some(it) => it,
+                None => continue,
+            };
+
+            let find_symbol = |name: &PToken| {
+                da.preproc_symbols
+                    .iter()
+                    .find(|symbol| symbol.preproc_def_site_opt == Some(name.body.loc))
+            };
+
+            // 直前の `#enum` で定義された定数
+            let mut last_enum_opt: Option<SymbolRc> = None;
+
+            for stmt in &root.stmts {
+                let (symbol, init) = match stmt {
+                    PStmt::Const(PConstStmt {
+                        name_opt: Some(name),
+                        init_opt: Some(init),
+                        ty_opt,
+                        ..
+                    }) => match find_symbol(name) {
+                        Some(symbol) => (
+                            symbol,
+                            ConstInit::Expr(init, ty_opt.as_ref().map(|&(ty, _)| ty)),
+                        ),
+                        None => continue,
+                    },
+                    PStmt::Enum(PEnumStmt {
+                        name_opt, init_opt, ..
+                    }) => {
+                        let symbol_opt = name_opt.as_ref().and_then(find_symbol);
+                        let last_opt = std::mem::replace(&mut last_enum_opt, symbol_opt.cloned());
+                        let symbol = match symbol_opt {
+                            Some(it) => it,
+                            None => continue,
+                        };
+                        match init_opt {
+                            Some(init) => (symbol, ConstInit::Expr(init, None)),
+                            // `#enum X` は直前の値の次になる。
+                            None => match last_opt {
+                                Some(last) => (symbol, ConstInit::Next(last)),
+                                None => continue,
+                            },
+                        }
+                    }
+                    _ => continue,
+                };
+                if symbol.const_value_opt.borrow().is_some() {
+                    continue;
+                }
+
+                let scope = match &symbol.scope_opt {
+                    Some(Scope::Local(scope)) => scope.clone(),
+                    _ => LocalScope::default(),
+                };
+                pending.push((symbol.clone(), init, scope, doc));
+                local_envs.entry(doc).or_insert_with(|| {
+                    let mut local_env = HashMap::new();
+                    extend_local_env_from_symbols(&da.preproc_symbols, &mut local_env);
+                    local_env
+                });
+            }
+        }
+
+        // 値が決まった定数を参照している定数を、値が決まらなくなるまで繰り返し計算する。
+        let public_env = &self.public_env;
+        let ns_env = &self.ns_env;
+        let const_values = &mut self.const_values;
+        let mut progress = true;
+        while progress && !pending.is_empty() {
+            progress = false;
+            pending.retain(|(symbol, init, scope, doc)| {
+                let local_env = &local_envs[doc];
+                let value_opt = match init {
+                    ConstInit::Expr(init, ty_opt) => {
+                        let value_opt = eval_const_expr(init, &mut |name| {
+                            let target = resolve_implicit_symbol(
+                                &name.into(),
+                                scope,
+                                public_env,
+                                ns_env,
+                                local_env,
+                                module_map,
+                            )?;
+                            let value_opt = *target.const_value_opt.borrow();
+                            value_opt.or_else(|| const_values.get(&target).copied())
+                        });
+                        match ty_opt {
+                            Some(ty) => value_opt.map(|value| value.convert(*ty)),
+                            None => value_opt,
+                        }
+                    }
+                    ConstInit::Next(last) => {
+                        let value_opt = *last.const_value_opt.borrow();
+                        value_opt
+                            .or_else(|| const_values.get(last).copied())
+                            .map(|value| value.next())
+                    }
+                };
+                let value = match value_opt {
+                    Some(it) => it,
+                    None => return true,
+                };
+                const_values.insert(symbol.clone(), value);
+                progress = true;
+                false
+            });
+        }
+    }
+
+    fn compute_event_handlers(&mut self, doc_analysis_map: &DocAnalysisMap) {
+        let mut sites = vec![];
+        for (&doc, da) in doc_analysis_map.iter() {
+            if !self.active_docs.contains(&doc) || da.symbols_only {
+                continue;
+            }
+            if let Some(root) = &da.tree_opt {
+                collect_event_handler_sites(root, &mut sites);
+            }
+        }
+        if sites.is_empty() {
+            return;
+        }
+
+        let sites = sites.into_iter().collect::<HashSet<_>>();
+        self.event_handlers.extend(
+            self.use_sites
+                .iter()
+                .filter(|(symbol, loc)| symbol.kind == HspSymbolKind::Label && sites.contains(loc))
+                .map(|(symbol, _)| symbol.clone()),
+        );
+    }
+
+    fn compute_newmod_modules(&mut self, doc_analysis_map: &DocAnalysisMap) {
+        let mut sites = vec![];
+        for (&doc, da) in doc_analysis_map.iter() {
+            if !self.active_docs.contains(&doc) || da.symbols_only {
+                continue;
+            }
+            if let Some(root) = &da.tree_opt {
+                super::newmod::collect_newmod_sites(root, &mut sites);
+            }
+        }
+        if sites.is_empty() {
+            return;
+        }
+
+        let var_map = self
+            .def_sites
+            .iter()
+            .map(|(symbol, loc)| (*loc, symbol))
+            .collect::<HashMap<_, _>>();
+        let module_map = self
+            .use_sites
+            .iter()
+            .filter(|(symbol, _)| symbol.kind == HspSymbolKind::Module)
+            .map(|(symbol, loc)| (*loc, symbol))
+            .collect::<HashMap<_, _>>();
+
+        for (var_loc, module_loc) in sites {
+            let (var, module) = match (var_map.get(&var_loc), module_map.get(&module_loc)) {
+                (Some(var), Some(module)) => (var, module),
+                _ => continue,
+            };
+
+            let modules = self.newmod_modules.entry((*var).clone()).or_default();
+            if !modules.contains(module) {
+                modules.push((*module).clone());
+            }
+        }
+    }
+
+    fn compute_var_types(&mut self, doc_analysis_map: &DocAnalysisMap) {
+        let site_map = self
+            .def_sites
+            .iter()
+            .chain(&self.use_sites)
+            .map(|(symbol, loc)| (*loc, symbol.clone()))
+            .collect::<HashMap<_, _>>();
+        let is_var = |symbol: &SymbolRc| {
+            matches!(
+                symbol.kind,
+                HspSymbolKind::StaticVar | HspSymbolKind::Param(_)
+            )
+        };
+
+        let mut types = vec![];
+        let mut calls = vec![];
+        let mut arrays = vec![];
+        for (&doc, da) in doc_analysis_map.iter() {
+            if !self.active_docs.contains(&doc) || da.symbols_only {
+                continue;
+            }
+            if let Some(root) = &da.tree_opt {
+                collect_var_type_sites(root, &mut types, &mut calls, &mut arrays);
+            }
+        }
+
+        arrays.sort_by_key(|decl| decl.loc);
+        for decl in arrays {
+            match site_map.get(&decl.loc) {
+                Some(symbol) if symbol.kind == HspSymbolKind::StaticVar => self
+                    .array_decls
+                    .entry(symbol.clone())
+                    .or_default()
+                    .push(decl),
+                _ => {}
+            }
+        }
+
+        let var_types = &mut self.var_types;
+        for (loc, ty) in types {
+            match site_map.get(&loc) {
+                Some(symbol) if is_var(symbol) => {
+                    var_types.entry(symbol.clone()).or_default().push((ty, loc))
+                }
+                _ => {}
+            }
+        }
+
+        // `var` や `array` のパラメータに渡された変数には、命令の中でパラメータに代入された型の値が入る。
+        let mut edges = vec![];
+        for (callee_loc, args) in calls {
+            let callee = match site_map.get(&callee_loc) {
+                Some(it) => it,
+                None => continue,
+            };
+            let arg_params = callee.arg_params.borrow();
+            for (arg_loc_opt, param_opt) in args.iter().zip(arg_params.iter()) {
+                let (arg_loc, param) = match (arg_loc_opt, param_opt) {
+                    (Some(loc), Some(param)) => (*loc, param),
+                    _ => continue,
+                };
+                match param.kind {
+                    HspSymbolKind::Param(Some(PParamTy::Var))
+                    | HspSymbolKind::Param(Some(PParamTy::Array)) => {}
+                    _ => continue,
+                }
+                match site_map.get(&arg_loc) {
+                    Some(arg) if is_var(arg) => edges.push((param.clone(), arg.clone(), arg_loc)),
+                    _ => {}
+                }
+            }
+        }
+
+        // パラメータがさらに別の命令に渡されることもあるので、変化がなくなるまで繰り返す。
+        loop {
+            let mut changed = false;
+            for (param, arg, arg_loc) in &edges {
+                let param_types = match var_types.get(param) {
+                    Some(it) => it.iter().map(|&(ty, _)| ty).collect::<Vec<_>>(),
+                    None => continue,
+                };
+                let sites = var_types.entry(arg.clone()).or_default();
+                for ty in param_types {
+                    if !sites.contains(&(ty, *arg_loc)) {
+                        sites.push((ty, *arg_loc));
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
     pub(crate) fn compute<'a>(
         &'a mut self,
         doc_analysis_map: &'a DocAnalysisMap,
@@ -248,7 +704,20 @@ impl ProjectAnalysis {
         self.computed = true;
 
         self.compute_active_docs(doc_analysis_map);
+        self.compute_unnamed_module_names(doc_analysis_map, module_map);
+
+        // 名前のないモジュールも、名前空間の名前で参照できるようにする。
+        let module_map = &self.name_unnamed_modules(module_map);
         self.compute_symbols(doc_analysis_map, module_map);
+        self.compute_const_values(doc_analysis_map, module_map);
+
+        for (symbol, _) in &self.use_sites {
+            *self.use_counts.entry(symbol.clone()).or_default() += 1;
+        }
+
+        self.compute_var_types(doc_analysis_map);
+        self.compute_event_handlers(doc_analysis_map);
+        self.compute_newmod_modules(doc_analysis_map);
 
         // デバッグ用: 集計を出す。
         let total_symbol_count = self
@@ -276,16 +745,59 @@ impl<'a> ProjectAnalysisRef<'a> {
         Some(self.doc_analysis_map.get(&doc)?.tree_opt.as_ref()?)
     }
 
+    /// 変数に代入される値の型とその位置
+    pub(crate) fn var_type_sites(self, symbol: &SymbolRc) -> &'a [(VarType, Loc)] {
+        self.project
+            .var_types
+            .get(symbol)
+            .map_or(&[][..], |sites| sites.as_slice())
+    }
+
+    /// イベントハンドラとして登録されているラベルか？
+    pub(crate) fn is_event_handler(self, symbol: &SymbolRc) -> bool {
+        self.project.event_handlers.contains(symbol)
+    }
+
+    /// `#const` や `#enum` で定義された定数の値 (計算できたときだけ)
+    pub(crate) fn const_value(self, symbol: &SymbolRc) -> Option<ConstValue> {
+        let value_opt = *symbol.const_value_opt.borrow();
+        value_opt.or_else(|| self.project.const_values.get(symbol).copied())
+    }
+
+    /// 変数の配列としての確保
+    pub(crate) fn array_decls(self, symbol: &SymbolRc) -> &'a [ArrayDecl] {
+        self.project
+            .array_decls
+            .get(symbol)
+            .map_or(&[][..], |decls| decls.as_slice())
+    }
+
+    /// 指定した位置にあるマクロの呼び出しを1段階だけ展開した結果を文字列にする。
+    pub(crate) fn expand_macro_call_at(
+        self,
+        doc: DocId,
+        pos: Pos16,
+        def: &MacroDef,
+    ) -> Option<String> {
+        expand_macro_call_at(self.syntax_tree(doc)?, pos, def)
+    }
+
     pub(crate) fn get_signature_help_context(
         self,
         doc: DocId,
         pos: Pos16,
     ) -> Option<SignatureHelpContext> {
         let tree = self.syntax_tree(doc)?;
+        let use_site_map = self.use_site_map(doc);
 
-        let use_site_map = self
-            .project
-            .use_sites
+        let mut h = SignatureHelpHost { use_site_map };
+        h.process(pos, tree)
+    }
+
+    /// ドキュメント内の定義箇所の位置から、そこで定義されているシンボルへの対応表を作る。
+    pub(crate) fn def_site_map(self, doc: DocId) -> HashMap<Pos, SymbolRc> {
+        self.project
+            .def_sites
             .iter()
             .filter_map(|&(ref symbol, loc)| {
                 if loc.doc == doc {
@@ -294,10 +806,22 @@ impl<'a> ProjectAnalysisRef<'a> {
                     None
                 }
             })
-            .collect::<HashMap<_, _>>();
+            .collect()
+    }
 
-        let mut h = SignatureHelpHost { use_site_map };
-        h.process(pos, tree)
+    /// ドキュメント内の使用箇所の位置から、そこで使われているシンボルへの対応表を作る。
+    pub(crate) fn use_site_map(self, doc: DocId) -> HashMap<Pos, SymbolRc> {
+        self.project
+            .use_sites
+            .iter()
+            .filter_map(|&(ref symbol, loc)| {
+                if loc.doc == doc {
+                    Some((loc.start(), symbol.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
     }
 
     pub(crate) fn locate_symbol(self, doc: DocId, pos: Pos16) -> Option<(SymbolRc, Loc)> {
@@ -328,6 +852,145 @@ impl<'a> ProjectAnalysisRef<'a> {
         }
     }
 
+    /// `newmod` によって変数に格納されるインスタンスのモジュールを列挙する。
+    pub(crate) fn collect_newmod_modules(self, var: &SymbolRc, modules: &mut Vec<SymbolRc>) {
+        if let Some(m) = self.project.newmod_modules.get(var) {
+            modules.extend(m.iter().cloned());
+        }
+    }
+
+    /// `newmod` によってモジュールのインスタンスが格納される変数を列挙する。
+    pub(crate) fn collect_module_instances(self, module_name: &str, vars: &mut Vec<SymbolRc>) {
+        for (var, modules) in &self.project.newmod_modules {
+            if modules.iter().any(|m| m.name().as_str() == module_name) {
+                vars.push(var.clone());
+            }
+        }
+    }
+
+    /// `#modfunc` などが定義されているモジュールの名前
+    pub(crate) fn module_name_of_deffunc(self, deffunc: &SymbolRc) -> Option<RcStr> {
+        let loc = deffunc.def_site_opt()?;
+        let da = self.doc_analysis_map.get(&loc.doc)?;
+        da.module_map.values().find_map(|module_data| {
+            if range_is_touched(&module_data.content_loc.range, loc.start().into()) {
+                module_data.name_opt.clone()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// モジュールの `#modinit` などの位置を列挙する。
+    pub(crate) fn collect_module_deffuncs(
+        self,
+        module: &SymbolRc,
+        kind: PDefFuncKind,
+        locs: &mut Vec<Loc>,
+    ) {
+        let mut module_locs = vec![];
+        self.collect_symbol_defs(module, &mut module_locs);
+
+        for module_loc in module_locs {
+            if let Some(tree) = self.syntax_tree(module_loc.doc) {
+                super::newmod::collect_module_deffuncs(tree, module_loc, kind, locs);
+            }
+        }
+    }
+
+    /// モジュールの名前空間の名前を列挙する。
+    pub(crate) fn collect_module_names(self, names: &mut Vec<RcStr>) {
+        names.extend(
+            self.project
+                .ns_env
+                .keys()
+                .filter(|ns| !ns.is_empty())
+                .cloned(),
+        );
+    }
+
+    /// 名前のないモジュールにつけられた名前空間の名前か？
+    pub(crate) fn is_unnamed_module_name(self, ns: &str) -> bool {
+        self.project
+            .unnamed_module_names
+            .values()
+            .any(|name| name.as_str() == ns)
+    }
+
+    /// 名前空間に属すシンボルを、修飾子を除いた名前とともに列挙する。(ns が空文字列ならトップレベルの名前空間)
+    ///
+    /// モジュールのフィールドは修飾しても外から参照できないので含まない。
+    pub(crate) fn collect_ns_symbols(self, ns: &str, symbols: &mut Vec<(RcStr, SymbolRc)>) {
+        if let Some(env) = self.project.ns_env.get(ns) {
+            symbols.extend(
+                env.iter()
+                    .filter(|(_, symbol)| symbol.kind != HspSymbolKind::Field)
+                    .map(|(name, symbol)| (name.clone(), symbol.clone())),
+            );
+        }
+    }
+
+    /// 別名として定義されたマクロが指す命令のシンボルを探す。
+    pub(crate) fn resolve_alias(self, symbol: &SymbolRc) -> Option<SymbolRc> {
+        let target = symbol.alias_opt.borrow().clone()?;
+
+        let resolved = match target.find('@') {
+            Some(i) => {
+                let (basename, ns) = (&target[..i], &target[i + 1..]);
+                self.project
+                    .ns_env
+                    .get(ns)
+                    .and_then(|env| env.get(basename))
+            }
+            None => self.project.public_env.resolve(&target),
+        };
+
+        // モジュールの中で定義された命令など、公開されていないシンボルは同じファイルから探す。
+        resolved
+            .or_else(|| {
+                let doc = symbol.preproc_def_site_opt?.doc;
+                self.project
+                    .doc_symbols_map
+                    .get(&doc)?
+                    .iter()
+                    .find(|s| s.name == target)
+                    .cloned()
+            })
+            .filter(|s| s != symbol)
+    }
+
+    /// COMのインターフェイスに属すメソッドを、vtable のインデックスの順に集める。
+    pub(crate) fn collect_com_methods(self, interface: &SymbolRc, methods: &mut Vec<SymbolRc>) {
+        let mut items = vec![];
+        for symbols in self.project.doc_symbols_map.values() {
+            for symbol in symbols {
+                let index_opt = match &*symbol.com_method_opt.borrow() {
+                    Some(method) if method.interface_opt.as_ref() == Some(interface) => method
+                        .index_opt
+                        .as_ref()
+                        .and_then(|index| index.parse::<i64>().ok()),
+                    _ => continue,
+                };
+                items.push((index_opt, symbol.clone()));
+            }
+        }
+        items.sort_by_key(|(index_opt, symbol)| (index_opt.is_none(), *index_opt, symbol.name()));
+        methods.extend(items.into_iter().map(|(_, symbol)| symbol));
+    }
+
+    /// 指定したシンボルを指す別名のマクロを集める。
+    pub(crate) fn collect_aliases(self, target: &SymbolRc, aliases: &mut Vec<SymbolRc>) {
+        for symbols in self.project.doc_symbols_map.values() {
+            for symbol in symbols {
+                if symbol.alias_opt.borrow().is_some()
+                    && self.resolve_alias(symbol).as_ref() == Some(target)
+                {
+                    aliases.push(symbol.clone());
+                }
+            }
+        }
+    }
+
     pub(crate) fn collect_symbol_uses(self, symbol: &SymbolRc, locs: &mut Vec<Loc>) {
         for &(ref s, loc) in &self.project.use_sites {
             if s == symbol {
@@ -336,12 +999,33 @@ impl<'a> ProjectAnalysisRef<'a> {
         }
     }
 
+    pub(crate) fn use_count(self, symbol: &SymbolRc) -> usize {
+        self.project.use_counts.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// ドキュメントの中での、シンボルごとの使用回数を数える。
+    pub(crate) fn collect_doc_use_counts(self, doc: DocId, counts: &mut HashMap<SymbolRc, usize>) {
+        for (symbol, loc) in &self.project.use_sites {
+            if loc.doc == doc {
+                *counts.entry(symbol.clone()).or_default() += 1;
+            }
+        }
+    }
+
     pub(crate) fn collect_symbol_occurrences(self, symbols: &mut Vec<(&'a SymbolRc, Loc)>) {
         for (symbol, loc) in self.project.def_sites.iter().chain(&self.project.use_sites) {
             symbols.push((symbol, *loc));
         }
     }
 
+    /// 指定した位置でモジュール変数 (`thismod` やモジュールのフィールド) を使えるか？
+    pub(crate) fn takes_modvar_at(self, doc: DocId, pos: Pos16) -> bool {
+        match self.doc_analysis_map.get(&doc) {
+            Some(da) => deffunc_takes_modvar_at(&da.deffunc_map, pos),
+            None => false,
+        }
+    }
+
     pub(crate) fn collect_completion_items(
         self,
         doc: DocId,
@@ -355,6 +1039,7 @@ impl<'a> ProjectAnalysisRef<'a> {
             Some(da) => resolve_scope_at(&da.module_map, &da.deffunc_map, pos),
             None => LocalScope::default(),
         };
+        let takes_modvar = self.takes_modvar_at(doc, pos);
 
         let doc_symbols = p
             .doc_symbols_map
@@ -368,7 +1053,14 @@ impl<'a> ProjectAnalysisRef<'a> {
             })
             .collect::<Vec<_>>();
 
-        collect_symbols_as_completion_items(doc, scope, &doc_symbols, completion_items);
+        let mut items = vec![];
+        collect_symbols_as_completion_items(doc, scope, &doc_symbols, &mut items);
+
+        // モジュールのフィールドは #modfunc などの中でしか使えない。
+        completion_items.extend(items.into_iter().filter(|item| {
+            let ACompletionItem::Symbol(symbol) = item;
+            takes_modvar || symbol.kind != HspSymbolKind::Field
+        }));
     }
 
     // FIXME: lsp_typesをここで使うべきではない
@@ -384,10 +1076,27 @@ impl<'a> ProjectAnalysisRef<'a> {
                 .iter()
                 .filter(|(&doc, _)| p.active_help_docs.contains(&doc))
                 .flat_map(|(_, symbols)| symbols.iter().filter(|s| !s.label.starts_with("#")))
+                .chain(&p.hsphelp_info.builtin_items)
                 .cloned(),
         );
     }
 
+    // FIXME: lsp_typesをここで使うべきではない
+    pub(crate) fn find_hsphelp_completion_item(
+        self,
+        label: &str,
+    ) -> Option<&'a lsp_types::CompletionItem> {
+        let p = self.project;
+
+        p.hsphelp_info
+            .doc_symbols
+            .iter()
+            .filter(|(&doc, _)| p.active_help_docs.contains(&doc))
+            .flat_map(|(_, symbols)| symbols.iter())
+            .chain(&p.hsphelp_info.builtin_items)
+            .find(|s| s.label == label)
+    }
+
     // FIXME: lsp_typesをここで使うべきではない
     pub(crate) fn collect_preproc_completion_items(
         self,
@@ -430,14 +1139,17 @@ impl<'a> ProjectAnalysisRef<'a> {
     pub(crate) fn collect_all_symbols(self, name_filter: &str, symbols: &mut Vec<(SymbolRc, Loc)>) {
         let p = self.project;
 
+        // 大文字・小文字を区別せずに部分一致で検索する。
         let name_filter = name_filter.trim().to_ascii_lowercase();
+        let is_match = |symbol: &SymbolRc| symbol.name.to_ascii_lowercase().contains(&name_filter);
 
-        let map = p
-            .def_sites
-            .iter()
-            .filter(|(symbol, _)| symbol.name.contains(&name_filter))
-            .map(|(symbol, loc)| (symbol.clone(), *loc))
-            .collect::<HashMap<_, _>>();
+        // 複数の定義箇所があるシンボルは、最初の定義箇所を使う。
+        let mut map = HashMap::new();
+        for (symbol, loc) in p.def_sites.iter().filter(|(symbol, _)| is_match(symbol)) {
+            map.entry(symbol).or_insert(*loc);
+        }
+
+        let mut done = HashSet::new();
 
         for (&doc, doc_symbols) in &p.doc_symbols_map {
             if !p.active_docs.contains(&doc) {
@@ -445,7 +1157,7 @@ impl<'a> ProjectAnalysisRef<'a> {
             }
 
             for symbol in doc_symbols {
-                if !symbol.name.contains(&name_filter) {
+                if !is_match(symbol) || !done.insert(symbol) {
                     continue;
                 }
 
@@ -467,12 +1179,11 @@ impl<'a> ProjectAnalysisRef<'a> {
             None => return,
         };
 
-        let def_site_map = p
-            .def_sites
-            .iter()
-            .filter(|(_, loc)| loc.doc == doc)
-            .cloned()
-            .collect::<HashMap<_, _>>();
+        // 複数の定義箇所があるシンボルは、最初の定義箇所を使う。
+        let mut def_site_map = HashMap::new();
+        for (symbol, loc) in p.def_sites.iter().filter(|(_, loc)| loc.doc == doc) {
+            def_site_map.entry(symbol).or_insert(*loc);
+        }
 
         symbols.extend(doc_symbols.iter().filter_map(|symbol| {
             let loc = def_site_map.get(&symbol)?;
@@ -480,6 +1191,17 @@ impl<'a> ProjectAnalysisRef<'a> {
         }));
     }
 
+    /// ドキュメントにある `#include` の解決結果を、(`#include` 文の位置, includeされるドキュメント) の形で列挙する。
+    pub(crate) fn collect_doc_includes(self, doc: DocId, includes: &mut Vec<(Loc, DocId)>) {
+        includes.extend(
+            self.project
+                .include_resolution
+                .iter()
+                .filter(|(loc, _)| loc.doc == doc)
+                .cloned(),
+        );
+    }
+
     pub(crate) fn find_include_target(self, doc: DocId, pos: Pos16) -> Option<DocId> {
         let p = self.project;
         let (_, dest_doc) = *p
@@ -491,6 +1213,13 @@ impl<'a> ProjectAnalysisRef<'a> {
     }
 }
 
+/// 指定した位置が、モジュール変数を使える `#deffunc` 類 (`#modfunc` など) の中にあるか？
+pub(crate) fn deffunc_takes_modvar_at(deffunc_map: &DefFuncMap, pos: Pos16) -> bool {
+    deffunc_map.values().any(|deffunc_data| {
+        deffunc_data.kind.takes_modvar() && range_is_touched(&deffunc_data.content_loc.range, pos)
+    })
+}
+
 fn resolve_scope_at(module_map: &ModuleMap, deffunc_map: &DefFuncMap, pos: Pos16) -> LocalScope {
     let mut scope = LocalScope::default();
 
@@ -1,9 +1,24 @@
-use super::*;
-use crate::parse::*;
+use super::{preproc::find_include_guard, *};
+use crate::parse::{p_param_ty::PParamCategory, *};
 
 pub(crate) enum Diagnostic {
     Undefined,
+    /// 命令として使われている名前が、どの命令やマクロにも解決されない。
+    UndefinedCommand(RcStr),
     VarRequired,
+    /// ラベルを受け取らないパラメータにラベルが渡されている。
+    LabelNotAllowed(PParamTy),
+    /// `#defcfunc` などで定義された関数が命令として使われている。
+    FunctionUsedAsCommand(RcStr),
+    /// `#deffunc` などで定義された命令が式の中で使われている。
+    CommandUsedInExpr(RcStr),
+    /// 引数の個数がシグネチャに合わない。
+    ArityMismatch {
+        name: RcStr,
+        min: usize,
+        max: usize,
+        found: usize,
+    },
 }
 
 type UseSiteMap = HashMap<(DocId, Pos), SymbolRc>;
@@ -11,65 +26,208 @@ type UseSiteMap = HashMap<(DocId, Pos), SymbolRc>;
 pub(crate) struct Sema {
     pub(crate) use_site_map: UseSiteMap,
     pub(crate) diagnostics: Vec<(Diagnostic, Loc)>,
+    /// インクルードガードの `#if` 系の命令の位置
+    include_guard_opt: Option<Pos>,
+    /// いま解析している文を囲む `#if` 系のブロックのそれぞれについて、その中の文が必ずコンパイルされるか。
+    ///
+    /// 条件は評価しないので、インクルードガード以外のブロックはコンパイルされないかもしれないとみなす。
+    conditions: Vec<bool>,
 }
 
 impl Sema {
-    pub(crate) fn on_root(&mut self, root: &PRoot) {
-        for stmt in &root.stmts {
-            on_stmt(stmt, self)
+    pub(crate) fn new(use_site_map: UseSiteMap) -> Self {
+        Sema {
+            use_site_map,
+            diagnostics: vec![],
+            include_guard_opt: None,
+            conditions: vec![],
+        }
+    }
+
+    /// 条件つきコンパイルによって、コンパイルされないかもしれない位置にいるか？
+    fn in_conditional_block(&self) -> bool {
+        self.conditions.iter().any(|&always| !always)
+    }
+
+    fn on_preproc_cond(&mut self, stmt: &PUnknownPreProcStmt) {
+        match stmt.keyword_text() {
+            "if" | "ifdef" | "ifndef" => {
+                let always = self.include_guard_opt == Some(stmt.hash.body.loc.start());
+                self.conditions.push(always);
+            }
+            "else" => {
+                if let Some(always) = self.conditions.last_mut() {
+                    *always = false;
+                }
+            }
+            "endif" => {
+                self.conditions.pop();
+            }
+            _ => {}
         }
     }
 
     fn symbol(&self, loc: Loc) -> Option<SymbolRc> {
         self.use_site_map.get(&(loc.doc, loc.start())).cloned()
     }
-}
 
-fn on_stmt(stmt: &PStmt, ctx: &mut Sema) {
-    match stmt {
-        PStmt::Label(_) => {}
-        PStmt::Assign(_) => {}
-        PStmt::Command(stmt) => {
-            let loc = stmt.command.body.loc;
-            let symbol = match ctx.symbol(loc) {
+    /// 命令や関数の呼び出しの引数をシグネチャと照らし合わせる。
+    fn check_args(&mut self, symbol: &SymbolRc, args: &[PArg], loc: Loc) {
+        let signature_data = match symbol.signature_opt() {
+            Some(it) => it,
+            None => return,
+        };
+
+        for (arg, param) in args.iter().zip(&signature_data.params) {
+            let param = match param.ty_opt {
                 Some(it) => it,
-                None => {
-                    ctx.diagnostics.push((Diagnostic::Undefined, loc));
-                    return;
-                }
+                None => continue,
+            };
+            let expr = match arg.expr_opt.as_ref() {
+                Some(it) => it,
+                None => continue,
             };
 
-            if let Some(signature_data) = symbol.signature_opt() {
-                for (arg, _) in stmt
-                    .args
-                    .iter()
-                    .zip(&signature_data.params)
-                    .filter(|(_, (param, _, _))| param.map_or(false, |p| p.is_by_ref()))
-                {
-                    if arg_is_definitely_rval(arg, &ctx) {
-                        let range = match arg.expr_opt.as_ref() {
-                            Some(expr) => expr.compute_range(),
-                            None => stmt.command.body.loc.range,
-                        };
-                        let loc = loc.with_range(range);
-                        ctx.diagnostics.push((Diagnostic::VarRequired, loc));
+            if param.is_by_ref() && arg_is_definitely_rval(arg, self) {
+                let loc = loc.with_range(expr.compute_range());
+                self.diagnostics.push((Diagnostic::VarRequired, loc));
+            } else if param.category() == PParamCategory::ByValue
+                && param != PParamTy::Label
+                && expr_is_label(expr)
+            {
+                let loc = loc.with_range(expr.compute_range());
+                self.diagnostics
+                    .push((Diagnostic::LabelNotAllowed(param), loc));
+            }
+        }
+
+        if !symbol_kind_is_user_command(symbol.kind) {
+            return;
+        }
+
+        let (min, max) = match arity(&signature_data) {
+            Some(it) => it,
+            None => return,
+        };
+
+        let found = count_args(args);
+        if found < min || max < found {
+            self.diagnostics.push((
+                Diagnostic::ArityMismatch {
+                    name: symbol.name.clone(),
+                    min,
+                    max,
+                    found,
+                },
+                loc,
+            ));
+        }
+    }
+}
+
+impl PVisitor for Sema {
+    fn on_expr(&mut self, expr: &PExpr) {
+        if let PExpr::Compound(compound) = expr {
+            let loc = compound.name().body.loc;
+            if let Some(symbol) = self.symbol(loc) {
+                match (symbol.kind, compound) {
+                    (HspSymbolKind::DefFunc | HspSymbolKind::ModFunc, _) => {
+                        self.diagnostics
+                            .push((Diagnostic::CommandUsedInExpr(symbol.name.clone()), loc));
                     }
+                    (_, PCompound::Paren(np)) => self.check_args(&symbol, &np.args, loc),
+                    _ => {}
                 }
             }
         }
-        PStmt::DefFunc(stmt) => {
-            for stmt in &stmt.stmts {
-                on_stmt(stmt, ctx);
-            }
+
+        self.on_expr_default(expr);
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        match stmt {
+            PStmt::Command(stmt) => on_command_stmt(stmt, self),
+            PStmt::UnknownPreProc(stmt) => self.on_preproc_cond(stmt),
+            _ => {}
         }
-        PStmt::Module(stmt) => {
-            for stmt in &stmt.stmts {
-                on_stmt(stmt, ctx);
-            }
+
+        self.on_stmt_default(stmt);
+    }
+
+    fn on_root(&mut self, root: &PRoot) {
+        self.include_guard_opt = find_include_guard(root).map(|guard| guard.loc.start());
+        self.conditions.clear();
+
+        self.on_stmts(&root.stmts);
+        self.on_token(&root.eof);
+    }
+}
+
+fn on_command_stmt(stmt: &PCommandStmt, ctx: &mut Sema) {
+    let loc = stmt.command.body.loc;
+    let symbol = match ctx.symbol(loc) {
+        Some(it) => it,
+        None => {
+            ctx.diagnostics.push((Diagnostic::Undefined, loc));
+            return;
+        }
+    };
+
+    if symbol.kind == HspSymbolKind::Unresolved {
+        // 条件つきコンパイルのブロックの中では、ほかの環境向けの命令を使っていることがある。
+        if !ctx.in_conditional_block() {
+            ctx.diagnostics
+                .push((Diagnostic::UndefinedCommand(symbol.name.clone()), loc));
+        }
+        return;
+    }
+
+    if let HspSymbolKind::DefCFunc | HspSymbolKind::ModCFunc = symbol.kind {
+        ctx.diagnostics
+            .push((Diagnostic::FunctionUsedAsCommand(symbol.name.clone()), loc));
+        return;
+    }
+
+    ctx.check_args(&symbol, &stmt.args, loc);
+}
+
+/// 引数の個数を検査する対象のシンボルか？ (ユーザー定義の命令・関数と `#func`)
+fn symbol_kind_is_user_command(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc
+        | HspSymbolKind::LibFunc => true,
+        _ => false,
+    }
+}
+
+/// 省略できないパラメータか？
+///
+/// 値渡しのパラメータは省略すると既定値 (0 や空文字列) になるが、
+/// 参照渡しとラベルのパラメータは、既定値が書かれていなければ省略できない。
+fn param_is_required(param: PParamTy, has_default: bool) -> bool {
+    (param.is_by_ref() || param == PParamTy::Label) && !has_default
+}
+
+/// シグネチャが受け取る引数の個数の範囲 (最小, 最大) を計算する。
+/// 型が不明なパラメータがあるときは None を返す。
+fn arity(signature_data: &SignatureData) -> Option<(usize, usize)> {
+    let mut min = 0;
+    for (i, param) in signature_data.params.iter().enumerate() {
+        if param_is_required(param.ty_opt?, param.default_opt.is_some()) {
+            min = i + 1;
         }
-        // PStmt::If
-        _ => {}
     }
+    Some((min, signature_data.params.len()))
+}
+
+/// 渡された引数の個数を数える。末尾の省略された引数 (`f a, b,` の最後など) は数えない。
+fn count_args(args: &[PArg]) -> usize {
+    args.iter()
+        .rposition(|arg| arg.expr_opt.is_some())
+        .map_or(0, |i| i + 1)
 }
 
 fn symbol_kind_is_definitely_rval(kind: HspSymbolKind) -> bool {
@@ -111,3 +269,15 @@ fn arg_is_definitely_rval(arg: &PArg, ctx: &Sema) -> bool {
     }
     false
 }
+
+fn expr_is_label(expr: &PExpr) -> bool {
+    let mut expr_opt = Some(expr);
+    while let Some(expr) = expr_opt {
+        match expr {
+            PExpr::Label(_) => return true,
+            PExpr::Paren(expr) => expr_opt = expr.body_opt.as_deref(),
+            _ => return false,
+        }
+    }
+    false
+}
@@ -1,6 +1,6 @@
 use super::comment::*;
 use super::*;
-use crate::parse::PParamTy;
+use crate::parse::{PDefFuncKind, PParamTy};
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub(crate) enum HspSymbolKind {
@@ -90,6 +90,12 @@ impl SymbolRc {
         self.0.signature_opt.borrow().clone()
     }
 
+    /// シンボルの定義箇所 (変数など、暗黙に定義されるものは最初に現れた位置)
+    pub(crate) fn def_site_opt(&self) -> Option<Loc> {
+        self.preproc_def_site_opt
+            .or_else(|| Some(self.leader_opt.as_ref()?.body.loc))
+    }
+
     pub(crate) fn compute_details(&self) -> SymbolDetails {
         if let Some(details) = self.details_opt.as_ref() {
             return details.clone();
@@ -108,6 +114,7 @@ impl SymbolRc {
                         _ => None,
                     })
                     .collect(),
+                ..SymbolDetails::default()
             };
         }
 
@@ -193,6 +200,13 @@ impl DefInfo {
 
                 preproc_def_site_opt: None,
                 signature_opt: RefCell::new(signature_opt),
+                alias_opt: Default::default(),
+                macro_opt: Default::default(),
+                const_value_opt: Default::default(),
+                arg_params: Default::default(),
+                com_method_opt: Default::default(),
+                lib_opt: Default::default(),
+                plugin_cmd_opt: Default::default(),
                 linked_symbol_opt: Default::default(),
             },
             DefInfo::Preproc {
@@ -212,6 +226,13 @@ impl DefInfo {
                 details_opt: None,
                 preproc_def_site_opt: Some(loc),
                 signature_opt: Default::default(),
+                alias_opt: Default::default(),
+                macro_opt: Default::default(),
+                const_value_opt: Default::default(),
+                arg_params: Default::default(),
+                com_method_opt: Default::default(),
+                lib_opt: Default::default(),
+                plugin_cmd_opt: Default::default(),
                 linked_symbol_opt: Default::default(),
             },
             DefInfo::Name {
@@ -230,6 +251,13 @@ impl DefInfo {
                 details_opt: None,
                 preproc_def_site_opt: None,
                 signature_opt: Default::default(),
+                alias_opt: Default::default(),
+                macro_opt: Default::default(),
+                const_value_opt: Default::default(),
+                arg_params: Default::default(),
+                com_method_opt: Default::default(),
+                lib_opt: Default::default(),
+                plugin_cmd_opt: Default::default(),
                 linked_symbol_opt: Default::default(),
             },
         };
@@ -249,13 +277,54 @@ pub(crate) struct SymbolData {
 
     // 追加の情報:
     pub(crate) signature_opt: RefCell<Option<Rc<SignatureData>>>,
+    /// 引数をとらない `#define` で他の命令の別名として定義されたマクロなら、その命令の名前
+    pub(crate) alias_opt: RefCell<Option<RcStr>>,
+    /// `#define` で定義されたマクロなら、その定義
+    pub(crate) macro_opt: RefCell<Option<Rc<MacroDef>>>,
+    /// `#const` や `#enum` で定義された定数なら、その値 (ドキュメントの中で計算できたときだけ)
+    pub(crate) const_value_opt: RefCell<Option<ConstValue>>,
+    /// `#deffunc` などで定義された命令・関数なら、呼び出しの引数に対応するパラメータ
+    /// (引数の順に並べる。名前のないパラメータやモジュール変数の引数は None)
+    pub(crate) arg_params: RefCell<Vec<Option<SymbolRc>>>,
+    /// `#comfunc` で定義されたメソッドなら、そのインターフェイスとインデックス
+    pub(crate) com_method_opt: RefCell<Option<ComMethodData>>,
+    /// `#func` で定義された関数なら、直前の `#uselib` で指定されたDLLの名前
+    pub(crate) lib_opt: RefCell<Option<RcStr>>,
+    /// `#cmd` で定義されたプラグインのコマンドなら、そのプラグインとコマンドID
+    pub(crate) plugin_cmd_opt: RefCell<Option<PluginCmdData>>,
     pub(crate) linked_symbol_opt: RefCell<Option<lsp_types::CompletionItem>>,
 }
 
+/// `#comfunc` で定義されたメソッドの情報
+#[derive(Clone)]
+pub(crate) struct ComMethodData {
+    /// メソッドが属すインターフェイス (直前の `#usecom` で定義されたもの)
+    pub(crate) interface_opt: Option<SymbolRc>,
+    /// vtable のインデックス
+    pub(crate) index_opt: Option<RcStr>,
+}
+
+/// `#cmd` で定義されたプラグインのコマンドの情報
+#[derive(Clone)]
+pub(crate) struct PluginCmdData {
+    /// コマンドを登録するプラグインのファイル名 (直前の `#regcmd` で指定されたもの)
+    pub(crate) plugin_opt: Option<RcStr>,
+    /// コマンドID
+    pub(crate) command_id_opt: Option<RcStr>,
+}
+
 #[derive(Clone, Default)]
 pub(crate) struct SymbolDetails {
     pub(crate) desc: Option<RcStr>,
     pub(crate) docs: Vec<String>,
+    /// パラメータの説明 (名前, 説明)。`@param` や `%prm` で書かれたもの
+    pub(crate) params: Vec<(String, String)>,
+    /// 戻り値の説明。`@return` で書かれたもの
+    pub(crate) returns_opt: Option<String>,
+    /// 非推奨である理由 (空文字列もある)。`@deprecated` で書かれたもの
+    pub(crate) deprecated_opt: Option<String>,
+    /// 使用例の行。`@example` の後に書かれたもの
+    pub(crate) examples: Vec<String>,
 }
 
 // -----------------------------------------------
@@ -277,6 +346,7 @@ impl DefFuncKey {
 }
 
 pub(crate) struct DefFuncData {
+    pub(crate) kind: PDefFuncKind,
     pub(crate) content_loc: Loc,
 }
 
@@ -317,9 +387,21 @@ impl Deref for ModuleRc {
 
 pub(crate) struct ModuleData {
     pub(crate) name_opt: Option<RcStr>,
+    /// 表示用の名前。名前のないモジュールは `module#N` になる。(`unnamed_module_label` を参照。)
+    pub(crate) label: RcStr,
     pub(crate) content_loc: Loc,
 }
 
+/// 名前のないモジュールの表示用の名前。
+///
+/// ドキュメントシンボルや警告の中でモジュールを区別するためだけに使う。
+/// 番号はファイルの中で何番目のモジュールか (0から数える) で、
+/// コンパイラがつける名前空間の名前 (`M0` など) とは一致しない。
+/// (名前空間の名前はプロジェクトごとに決まる。`ProjectAnalysis::compute_unnamed_module_names` を参照。)
+pub(crate) fn unnamed_module_label(index: usize) -> RcStr {
+    format!("module#{}", index).into()
+}
+
 /// 文字列リテラルを識別子とみなす。
 fn str_as_module_name_ident(s: &RcStr) -> Option<RcStr> {
     // "..." の形で、引用符の間に1文字以上必要。
@@ -0,0 +1,244 @@
+//! 構文エラーの検出
+//!
+//! 構文解析器はエラーがあっても解析を続けるので、解析結果に残った痕跡 (読み飛ばしたトークンや
+//! 閉じられていない括弧など) から構文エラーを集める。
+
+use super::*;
+use crate::parse::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SyntaxError {
+    /// 解釈できない文字
+    BadChar,
+    /// 閉じられていない文字列
+    UnterminatedStr,
+    /// 閉じられていない丸カッコ
+    MissingRightParen,
+    /// 構文解析器が読み飛ばしたトークン
+    UnexpectedToken,
+    /// `#global` で閉じられていない `#module`
+    ModuleWithoutGlobal,
+    /// `#module` の外にある `#global`
+    GlobalWithoutModule,
+    /// `#module` の外にある `#modfunc` など
+    ModFuncOutsideModule,
+}
+
+impl SyntaxError {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SyntaxError::BadChar => "解釈できない文字です。",
+            SyntaxError::UnterminatedStr => "文字列が閉じられていません。",
+            SyntaxError::MissingRightParen => "対応する ) がありません。",
+            SyntaxError::UnexpectedToken => "予期しないトークンです。",
+            SyntaxError::ModuleWithoutGlobal => "#module に対応する #global がありません。",
+            SyntaxError::GlobalWithoutModule => "#global に対応する #module がありません。",
+            SyntaxError::ModFuncOutsideModule => {
+                "#modfunc, #modcfunc, #modinit, #modterm は #module の中でしか使えません。"
+            }
+        }
+    }
+}
+
+/// 文字列リテラルや文字リテラルが閉じられているか？
+fn is_terminated(text: &str) -> bool {
+    if text.starts_with("{\"") {
+        return text.len() >= 4 && text.ends_with("\"}");
+    }
+
+    let quote = match text.chars().next() {
+        Some(c @ ('"' | '\'')) => c,
+        _ => return true,
+    };
+    if text.len() < 2 || !text.ends_with(quote) {
+        return false;
+    }
+
+    // 末尾の引用符がエスケープされていないか？ (直前のバックスラッシュが偶数個なら閉じている。)
+    let backslashes = text[1..text.len() - 1]
+        .chars()
+        .rev()
+        .take_while(|&c| c == '\\')
+        .count();
+    backslashes % 2 == 0
+}
+
+/// 閉じられていない丸カッコや、対応のとれていない `#module` と `#global` を探すビジター
+struct V<'a> {
+    errors: &'a mut Vec<(SyntaxError, Loc)>,
+    /// `#module` の中か？
+    in_module: bool,
+}
+
+impl PVisitor for V<'_> {
+    fn on_module_stmt(&mut self, stmt: &PModuleStmt) {
+        if stmt.global_opt.is_none() {
+            let last = stmt.name_opt.as_ref().unwrap_or(&stmt.keyword);
+            self.errors.push((
+                SyntaxError::ModuleWithoutGlobal,
+                stmt.hash.body.loc.unite(&last.body.loc),
+            ));
+        }
+
+        let parent = replace(&mut self.in_module, true);
+        for stmt in &stmt.stmts {
+            self.on_stmt(stmt);
+        }
+        self.in_module = parent;
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        match stmt {
+            PStmt::Global(stmt) if !self.in_module => {
+                self.errors.push((
+                    SyntaxError::GlobalWithoutModule,
+                    stmt.hash.body.loc.unite(&stmt.keyword.body.loc),
+                ));
+            }
+            PStmt::DefFunc(stmt) if stmt.kind.takes_modvar() && !self.in_module => {
+                let last = stmt.name_opt.as_ref().unwrap_or(&stmt.keyword);
+                self.errors.push((
+                    SyntaxError::ModFuncOutsideModule,
+                    stmt.hash.body.loc.unite(&last.body.loc),
+                ));
+            }
+            _ => {}
+        }
+
+        self.on_stmt_default(stmt);
+    }
+
+    fn on_compound(&mut self, compound: &PCompound) {
+        if let PCompound::Paren(PNameParen {
+            left_paren,
+            right_paren_opt: None,
+            ..
+        }) = compound
+        {
+            self.errors
+                .push((SyntaxError::MissingRightParen, left_paren.body.loc));
+        }
+
+        self.on_compound_default(compound);
+    }
+
+    fn on_expr(&mut self, expr: &PExpr) {
+        if let PExpr::Paren(PParenExpr {
+            left_paren,
+            right_paren_opt: None,
+            ..
+        }) = expr
+        {
+            self.errors
+                .push((SyntaxError::MissingRightParen, left_paren.body.loc));
+        }
+
+        self.on_expr_default(expr);
+    }
+}
+
+pub(crate) fn collect_syntax_errors(
+    tokens: &[PToken],
+    root: &PRoot,
+    errors: &mut Vec<(SyntaxError, Loc)>,
+) {
+    for token in tokens.iter().flat_map(|token| token.iter()) {
+        match token.kind {
+            TokenKind::Bad => errors.push((SyntaxError::BadChar, token.loc)),
+            TokenKind::Str | TokenKind::Char if !is_terminated(token.text.as_str()) => {
+                errors.push((SyntaxError::UnterminatedStr, token.loc));
+            }
+            _ => {}
+        }
+    }
+
+    // 連続して読み飛ばされたトークンはまとめて報告する。
+    let mut run_opt: Option<Loc> = None;
+    for token in &root.skipped {
+        match token.kind() {
+            // 文の区切りやブロックの括弧は、エラーでなくても読み飛ばされる。
+            TokenKind::Eos | TokenKind::Colon | TokenKind::LeftBrace | TokenKind::RightBrace => {
+                if let Some(loc) = run_opt.take() {
+                    errors.push((SyntaxError::UnexpectedToken, loc));
+                }
+            }
+            _ => {
+                let loc = token.body.loc;
+                run_opt = Some(match run_opt {
+                    Some(run) => run.unite(&loc),
+                    None => loc,
+                });
+            }
+        }
+    }
+    if let Some(loc) = run_opt {
+        errors.push((SyntaxError::UnexpectedToken, loc));
+    }
+
+    V {
+        errors,
+        in_module: false,
+    }
+    .on_root(root);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::tokenize;
+
+    const NO_DOC: DocId = 1;
+
+    fn errors(text: &str) -> Vec<(SyntaxError, u32, u32)> {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens.clone());
+        let mut errors = vec![];
+        collect_syntax_errors(&tokens, &root, &mut errors);
+
+        let mut errors = errors
+            .into_iter()
+            .map(|(e, loc)| {
+                let pos = Pos16::from(loc.start());
+                (e, pos.row, pos.column)
+            })
+            .collect::<Vec<_>>();
+        errors.sort_by_key(|&(_, row, column)| (row, column));
+        errors
+    }
+
+    #[test]
+    fn test_no_errors() {
+        assert_eq!(
+            errors(
+                "mes \"a\\\"b\\\\\"\nx = f(1, (2 + 3))\nif x {\n\tmes 'a'\n} else : mes {\"\n\"}\n"
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_module_balance() {
+        assert_eq!(
+            errors("#global\n#modfunc f\n\treturn\n#module m1\n#modinit\n\treturn\n#module m2\n#global\n#module m3\n"),
+            vec![
+                (SyntaxError::GlobalWithoutModule, 0, 0),
+                (SyntaxError::ModFuncOutsideModule, 1, 0),
+                (SyntaxError::ModuleWithoutGlobal, 3, 0),
+                (SyntaxError::ModuleWithoutGlobal, 8, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(
+            errors("mes \"abc\nx = f(1, (2\nmes 1) 2)\n"),
+            vec![
+                (SyntaxError::UnterminatedStr, 0, 4),
+                (SyntaxError::MissingRightParen, 1, 5),
+                (SyntaxError::MissingRightParen, 1, 9),
+                (SyntaxError::UnexpectedToken, 2, 5),
+            ]
+        );
+    }
+}
@@ -4,12 +4,25 @@ use crate::parse::*;
 #[derive(Clone)]
 pub(crate) enum SyntaxLint {
     ReturnInLoop,
+    /// `#uselib` より前にある `#func`
+    FuncWithoutUseLib,
+    /// 認識できないプリプロセッサ命令や文 (ファイルごとに最初の1つだけ報告する)
+    UnknownSyntax,
+    /// `return` などの後にあって、ラベルがないため実行されない文
+    UnreachableCode,
 }
 
 impl SyntaxLint {
     pub(crate) fn as_str(self) -> &'static str {
         match self {
             SyntaxLint::ReturnInLoop => "repeatループの中ではreturnできません。",
+            SyntaxLint::FuncWithoutUseLib => {
+                "この関数を読み込むDLLを指定する #uselib がありません。"
+            }
+            SyntaxLint::UnknownSyntax => {
+                "認識できない構文です。この行は解析の対象外になります。(このファイルでは最初の1箇所だけを報告しています。)"
+            }
+            SyntaxLint::UnreachableCode => "このコードは実行されません。",
         }
     }
 }
@@ -17,6 +30,8 @@ impl SyntaxLint {
 #[derive(Default)]
 pub(crate) struct SyntaxLinter<'p> {
     loop_stack: Vec<&'p PCommandStmt>,
+    /// `#uselib` が現れたか？
+    uselib_seen: bool,
     lints: Vec<(SyntaxLint, Loc)>,
 }
 
@@ -58,6 +73,18 @@ impl<'p> SyntaxLinter<'p> {
                     self.on_stmt(stmt);
                 }
             }
+            PStmt::UseLib(_) => {
+                self.uselib_seen = true;
+            }
+            PStmt::LibFunc(stmt) => {
+                if !self.uselib_seen {
+                    let last = stmt.name_opt.as_ref().unwrap_or(&stmt.keyword);
+                    self.report(
+                        SyntaxLint::FuncWithoutUseLib,
+                        stmt.hash.body.loc.unite(&last.body.loc),
+                    );
+                }
+            }
             // PStmt::If
             _ => {}
         }
@@ -70,9 +97,178 @@ impl<'p> SyntaxLinter<'p> {
     }
 }
 
+/// 最初にみつかった認識できない構文の位置を探すビジター
+#[derive(Default)]
+struct UnknownSyntaxFinder {
+    first_opt: Option<Loc>,
+}
+
+impl UnknownSyntaxFinder {
+    fn report(&mut self, first: &PToken, last: &PToken) {
+        if self.first_opt.is_none() {
+            self.first_opt = Some(first.body.loc.unite(&last.body.loc));
+        }
+    }
+}
+
+impl PVisitor for UnknownSyntaxFinder {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if self.first_opt.is_some() {
+            return;
+        }
+
+        match stmt {
+            PStmt::UnknownPreProc(stmt) if !stmt.is_known() => {
+                let last = stmt.tokens.last().unwrap_or(&stmt.hash);
+                self.report(&stmt.hash, last);
+            }
+            PStmt::Unknown(stmt) => {
+                if let (Some(first), Some(last)) = (stmt.tokens.first(), stmt.tokens.last()) {
+                    self.report(first, last);
+                }
+            }
+            _ => self.on_stmt_default(stmt),
+        }
+    }
+}
+
+/// 無条件に制御を移す文か？ (`return`, `goto`, `end`, `stop`)
+fn stmt_is_terminator(stmt: &PStmt) -> bool {
+    match stmt {
+        PStmt::Command(stmt) => {
+            matches!(stmt.command.body_text(), "return" | "goto" | "end" | "stop")
+        }
+        _ => false,
+    }
+}
+
+/// 実行されない文の範囲を探す。
+///
+/// 同じ文の並びの中で、無条件に制御を移す文から次のラベルまでの間にある文を報告する。
+/// (`#deffunc` や `#module` などは新たな入り口になるので、そこで区切る。)
+fn find_unreachable_code(stmts: &[PStmt], lints: &mut Vec<(SyntaxLint, Loc)>) {
+    fn flush(dead_opt: &mut Option<(Loc, Option<Loc>)>, lints: &mut Vec<(SyntaxLint, Loc)>) {
+        if let Some((_, Some(loc))) = dead_opt.take() {
+            lints.push((SyntaxLint::UnreachableCode, loc));
+        }
+    }
+
+    // 到達不能な区間に入っているか？ (terminatorの位置, 到達不能な文の範囲)
+    let mut dead_opt: Option<(Loc, Option<Loc>)> = None;
+
+    for stmt in stmts {
+        match stmt {
+            PStmt::Label(_)
+            | PStmt::DefFunc(_)
+            | PStmt::Module(_)
+            | PStmt::Global(_)
+            | PStmt::Include(_) => flush(&mut dead_opt, lints),
+            PStmt::Assign(_) | PStmt::Command(_) | PStmt::Invoke(_) | PStmt::If(_) => {
+                if let Some((terminator, range_opt)) = &mut dead_opt {
+                    let loc = terminator.with_range(stmt.compute_body_range());
+                    *range_opt = Some(match range_opt {
+                        Some(range) => range.unite(&loc),
+                        None => loc,
+                    });
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        match stmt {
+            PStmt::DefFunc(stmt) => find_unreachable_code(&stmt.stmts, lints),
+            PStmt::Module(stmt) => find_unreachable_code(&stmt.stmts, lints),
+            PStmt::If(stmt) => {
+                for block in [&stmt.body, &stmt.alt] {
+                    find_unreachable_code(&block.outer_stmts, lints);
+                    find_unreachable_code(&block.inner_stmts, lints);
+                }
+            }
+            PStmt::Command(command) if stmt_is_terminator(stmt) => {
+                dead_opt = Some((command.command.body.loc, None));
+            }
+            _ => {}
+        }
+    }
+    flush(&mut dead_opt, lints);
+}
+
 pub(crate) fn syntax_lint(root: &PRoot, lints: &mut Vec<(SyntaxLint, Loc)>) {
     let mut linter = SyntaxLinter::default();
     linter.lints = take(lints);
     linter.run(root);
     *lints = take(&mut linter.lints);
+
+    find_unreachable_code(&root.stmts, lints);
+
+    let mut finder = UnknownSyntaxFinder::default();
+    finder.on_root(root);
+    lints.extend(finder.first_opt.map(|loc| (SyntaxLint::UnknownSyntax, loc)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{tokenize, TokenKind};
+
+    const NO_DOC: DocId = 1;
+
+    fn lint(text: &str) -> Vec<(SyntaxLint, Loc)> {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens);
+        let mut lints = vec![];
+        syntax_lint(&root, &mut lints);
+        lints
+    }
+
+    #[test]
+    fn test_unknown_syntax() {
+        let lints =
+            lint("#cmpopt varinit 1\n#newdirective a, b\n#futuresyntax\n1 + 2 mes 3\nmes 4\n");
+        let unknowns = lints
+            .iter()
+            .filter(|(lint, _)| matches!(lint, SyntaxLint::UnknownSyntax))
+            .collect::<Vec<_>>();
+        assert_eq!(unknowns.len(), 1);
+        assert_eq!(unknowns[0].1.start_row(), 1);
+    }
+
+    #[test]
+    fn test_func_without_uselib() {
+        let lints = lint(
+            "#func f \"f\"\n#uselib \"a.dll\"\n#func g \"g\"\n#module m\n#cfunc h \"h\"\n#global\n",
+        );
+        let rows = lints
+            .iter()
+            .filter(|(lint, _)| matches!(lint, SyntaxLint::FuncWithoutUseLib))
+            .map(|(_, loc)| loc.start_row())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![0]);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let lints = lint(
+            "\tmes 1\n\tstop\n\tmes 2\n\tmes 3\n*l_next\n\tif a : return : mes 4\n\tgoto *l_next\n#const X 1\n\tmes 5\n#deffunc f\n\treturn\n",
+        );
+        let rows = lints
+            .iter()
+            .filter(|(lint, _)| matches!(lint, SyntaxLint::UnreachableCode))
+            .map(|(_, loc)| (loc.start_row(), loc.end().row))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(2, 3), (5, 5), (8, 8)]);
+    }
+
+    #[test]
+    fn test_unknown_stmt_recovery() {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, "1 + 2 mes 3\nmes 4\n".into()).into());
+        let root = parse_root(tokens);
+        assert_eq!(root.stmts.len(), 2);
+        match &root.stmts[0] {
+            PStmt::Unknown(stmt) => assert_eq!(stmt.tokens[0].kind(), TokenKind::Number),
+            _ => panic!(),
+        }
+        assert!(matches!(root.stmts[1], PStmt::Command(_)));
+    }
 }
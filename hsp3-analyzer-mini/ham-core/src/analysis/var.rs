@@ -20,11 +20,92 @@ struct Ctx<'a> {
     /// ドキュメント内の環境
     local_env: HashMap<LocalScope, SymbolEnv>,
 
+    /// マクロの本体に書かれた名前の使用箇所 (同じマクロが何度も使われても1回だけ記録するため。)
+    macro_use_sites: HashSet<(SymbolRc, Loc)>,
+
+    /// ドキュメント内の `#define` で定義されたマクロ (定義箇所から引く。)
+    macro_defs: HashMap<Loc, SymbolRc>,
+
+    /// `#define` や `#undef` で書き換えた、他のドキュメントと共有している環境の元の状態
+    /// (解析の後で元に戻す。)
+    env_undo: Vec<(EnvKey, RcStr, Option<SymbolRc>)>,
+
     deffunc_len: usize,
     module_len: usize,
     scope: LocalScope,
 }
 
+/// シンボルが属す環境
+enum EnvKey {
+    Global,
+    Local(LocalScope),
+    Ns(RcStr),
+}
+
+impl EnvKey {
+    fn for_symbol(symbol: &SymbolRc) -> Vec<EnvKey> {
+        let mut keys = vec![];
+        match &symbol.scope_opt {
+            Some(Scope::Global) => keys.push(EnvKey::Global),
+            Some(Scope::Local(scope)) => keys.push(EnvKey::Local(scope.clone())),
+            None => {}
+        }
+        if let Some(ns) = &symbol.ns_opt {
+            keys.push(EnvKey::Ns(ns.clone()));
+        }
+        keys
+    }
+
+    fn env<'a>(&self, ctx: &'a mut Ctx) -> &'a mut SymbolEnv {
+        match self {
+            EnvKey::Global => &mut ctx.public_env.global,
+            EnvKey::Local(scope) => ctx.local_env.entry(scope.clone()).or_default(),
+            EnvKey::Ns(ns) => ctx.ns_env.entry(ns.clone()).or_default(),
+        }
+    }
+}
+
+/// 環境の中でシンボルの名前が指すものを置き換える。(None なら取り除く。)
+fn replace_in_env(symbol: &SymbolRc, symbol_opt: Option<SymbolRc>, ctx: &mut Ctx) {
+    for key in EnvKey::for_symbol(symbol) {
+        let old = key.env(ctx).replace(symbol.name(), symbol_opt.clone());
+
+        // ドキュメント内の環境は解析の後に捨てられる。
+        if !matches!(key, EnvKey::Local(_)) {
+            ctx.env_undo.push((key, symbol.name(), old));
+        }
+    }
+}
+
+/// `#define` の位置で、その名前がこのマクロを指すようにする。
+/// (同じ名前のマクロが `#undef` の後で再定義されることがあるため。)
+fn on_define(stmt: &PDefineStmt, ctx: &mut Ctx) {
+    let symbol = match stmt
+        .name_opt
+        .as_ref()
+        .and_then(|name| ctx.macro_defs.get(&name.body.loc))
+    {
+        Some(it) => it.clone(),
+        None => return,
+    };
+    replace_in_env(&symbol, Some(symbol.clone()), ctx);
+}
+
+/// `#undef` の位置で、マクロを環境から取り除く。
+fn on_undef(stmt: &PUnknownPreProcStmt, ctx: &mut Ctx) {
+    let name = match stmt.tokens.get(1) {
+        Some(it) if it.kind() == TokenKind::Ident => it,
+        _ => return,
+    };
+    let symbol = match resolve_symbol(&name.body.text, ctx) {
+        Some(it) if matches!(it.kind, HspSymbolKind::Macro { .. }) => it,
+        _ => return,
+    };
+
+    ctx.public_use_sites.push((symbol.clone(), name.body.loc));
+    replace_in_env(&symbol, None, ctx);
+}
+
 const DEF_SITE: bool = true;
 const USE_SITE: bool = false;
 
@@ -83,17 +164,23 @@ fn on_symbol_def(name: &PToken, ctx: &mut Ctx) {
     }
 }
 
-fn on_symbol_use(name: &PToken, is_var: bool, ctx: &mut Ctx) {
-    match resolve_implicit_symbol(
-        &name.body.text,
+fn resolve_symbol(name: &RcStr, ctx: &Ctx) -> Option<SymbolRc> {
+    resolve_implicit_symbol(
+        name,
         &ctx.scope,
         &ctx.public_env,
         &ctx.ns_env,
         &ctx.local_env,
         &ctx.module_map,
-    ) {
+    )
+}
+
+/// 名前の使用箇所を記録して、解決されたシンボルを返す。
+fn on_symbol_use(name: &PToken, is_var: bool, ctx: &mut Ctx) -> Option<SymbolRc> {
+    match resolve_symbol(&name.body.text, ctx) {
         Some(symbol) => {
-            ctx.public_use_sites.push((symbol, name.body.loc));
+            ctx.public_use_sites.push((symbol.clone(), name.body.loc));
+            Some(symbol)
         }
         None => {
             let kind = if is_var {
@@ -102,6 +189,46 @@ fn on_symbol_use(name: &PToken, is_var: bool, ctx: &mut Ctx) {
                 HspSymbolKind::Unresolved
             };
             add_symbol(kind, name, USE_SITE, ctx);
+            None
+        }
+    }
+}
+
+/// ラベルの使用箇所を記録する。
+///
+/// 定義されていないラベルは、定義箇所のないラベルとして環境に加える。
+fn on_label_use(name: &PToken, ctx: &mut Ctx) {
+    match resolve_symbol(&name.body.text, ctx) {
+        Some(symbol) => ctx.public_use_sites.push((symbol, name.body.loc)),
+        None => add_symbol(HspSymbolKind::Label, name, USE_SITE, ctx),
+    }
+}
+
+/// マクロの使用箇所でマクロを展開して、本体に書かれた名前をこの場所のスコープで解決する。
+fn on_macro_use(symbol_opt: Option<SymbolRc>, args: &[PArg], ctx: &mut Ctx) {
+    let def = match symbol_opt.and_then(|symbol| symbol.macro_opt.borrow().clone()) {
+        Some(it) => it,
+        None => return,
+    };
+
+    let expansion = expand_macro(&def, &macro_args_to_tokens(args), &mut |name| {
+        resolve_symbol(&name.into(), ctx)?
+            .macro_opt
+            .borrow()
+            .clone()
+    });
+
+    for t in expansion.tokens.iter().chain(&expansion.macro_names) {
+        // 引数は呼び出し側で解析される。
+        if !t.from_body || t.token.kind() != TokenKind::Ident {
+            continue;
+        }
+
+        if let Some(symbol) = resolve_symbol(&t.token.body.text, ctx) {
+            let loc = t.token.body.loc;
+            if ctx.macro_use_sites.insert((symbol.clone(), loc)) {
+                ctx.public_use_sites.push((symbol, loc));
+            }
         }
     }
 }
@@ -128,9 +255,13 @@ fn on_compound_def(compound: &PCompound, ctx: &mut Ctx) {
 
 fn on_compound_use(compound: &PCompound, ctx: &mut Ctx) {
     match compound {
-        PCompound::Name(name) => on_symbol_use(name, true, ctx),
+        PCompound::Name(name) => {
+            let symbol_opt = on_symbol_use(name, true, ctx);
+            on_macro_use(symbol_opt, &[], ctx);
+        }
         PCompound::Paren(PNameParen { name, args, .. }) => {
-            on_symbol_use(name, true, ctx);
+            let symbol_opt = on_symbol_use(name, true, ctx);
+            on_macro_use(symbol_opt, args, ctx);
 
             for arg in args {
                 on_expr_opt(arg.expr_opt.as_ref(), ctx);
@@ -151,7 +282,7 @@ fn on_expr(expr: &PExpr, ctx: &mut Ctx) {
         PExpr::Literal(_) => {}
         PExpr::Label(PLabel { star: _, name_opt }) => {
             if let Some(name) = name_opt {
-                on_symbol_use(name, false, ctx);
+                on_label_use(name, ctx);
             }
         }
         PExpr::Compound(compound) => on_compound_use(compound, ctx),
@@ -182,22 +313,24 @@ fn on_args(args: &[PArg], ctx: &mut Ctx) {
 
 fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
     match stmt {
-        PStmt::Label(PLabel { name_opt, .. }) => {
-            if let Some(name) = name_opt {
-                add_symbol(HspSymbolKind::Label, name, DEF_SITE, ctx);
-            }
-        }
-        PStmt::Assign(PAssignStmt {
-            left,
-            op_opt: _,
-            args,
-        }) => {
-            // FIXME: def/use は演算子の種類による
+        // ラベルはpreprocで定義されている。
+        PStmt::Label(_) => {}
+        PStmt::Assign(PAssignStmt { left, op_opt, args }) => {
             on_compound_def(left, ctx);
+
+            // 複合代入 (`+=` や `++` など) は左辺の変数の値を読んでから書き込む。
+            let is_compound = op_opt
+                .as_ref()
+                .map_or(false, |op| op.kind() != TokenKind::Equal);
+            if is_compound {
+                on_symbol_use(left.name(), true, ctx);
+            }
+
             on_args(args, ctx);
         }
         PStmt::Command(PCommandStmt { command, args, .. }) => {
-            on_symbol_use(command, false, ctx);
+            let symbol_opt = on_symbol_use(command, false, ctx);
+            on_macro_use(symbol_opt, args, ctx);
 
             static COMMANDS: &[&str] = &[
                 "ldim", "sdim", "ddim", "dim", "dimtype", "newlab", "newmod", "dup", "dupptr",
@@ -275,8 +408,9 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
 
             ctx.scope = parent_scope;
         }
+        PStmt::Define(stmt) => on_define(stmt, ctx),
+        PStmt::UnknownPreProc(stmt) if stmt.keyword_text() == "undef" => on_undef(stmt, ctx),
         PStmt::Const(_)
-        | PStmt::Define(_)
         | PStmt::Enum(_)
         | PStmt::UseLib(_)
         | PStmt::LibFunc(_)
@@ -286,7 +420,8 @@ fn on_stmt(stmt: &PStmt, ctx: &mut Ctx) {
         | PStmt::Cmd(_)
         | PStmt::Global(_)
         | PStmt::Include(_)
-        | PStmt::UnknownPreProc(_) => {}
+        | PStmt::UnknownPreProc(_)
+        | PStmt::Unknown(_) => {}
     }
 }
 
@@ -303,6 +438,12 @@ pub(crate) fn analyze_var_def(
     let mut local_env = HashMap::new();
     extend_local_env_from_symbols(&symbols, &mut local_env);
 
+    let macro_defs = symbols
+        .iter()
+        .filter(|symbol| matches!(symbol.kind, HspSymbolKind::Macro { .. }))
+        .filter_map(|symbol| Some((symbol.preproc_def_site_opt?, symbol.clone())))
+        .collect();
+
     let mut ctx = Ctx {
         public_env,
         ns_env,
@@ -312,6 +453,9 @@ pub(crate) fn analyze_var_def(
         doc,
         symbols,
         local_env,
+        macro_use_sites: HashSet::new(),
+        macro_defs,
+        env_undo: vec![],
         deffunc_len: 0,
         module_len: 0,
         scope: LocalScope::default(),
@@ -320,4 +464,9 @@ pub(crate) fn analyze_var_def(
     for stmt in &root.stmts {
         on_stmt(stmt, &mut ctx);
     }
+
+    // 他のドキュメントからは、ドキュメント全体で定義されたシンボルがみえるようにする。
+    while let Some((key, name, symbol_opt)) = ctx.env_undo.pop() {
+        key.env(&mut ctx).replace(name, symbol_opt);
+    }
 }
@@ -0,0 +1,310 @@
+//! 変数の型の推論
+//!
+//! 代入文の右辺や配列を確保する命令から、変数に格納される値の型を推測する。
+
+use super::*;
+use crate::{parse::*, token::TokenKind};
+
+/// 変数に格納される値の型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum VarType {
+    Int,
+    Double,
+    Str,
+    Label,
+    /// モジュール型変数
+    Struct,
+}
+
+impl VarType {
+    pub(crate) fn to_str(self) -> &'static str {
+        match self {
+            VarType::Int => "int",
+            VarType::Double => "double",
+            VarType::Str => "str",
+            VarType::Label => "label",
+            VarType::Struct => "struct",
+        }
+    }
+
+    /// 変数を初期化する命令 (`dim` や `newmod` など) が変数に格納する値の型
+    pub(crate) fn from_dim_command(name: &str) -> Option<VarType> {
+        let ty = match name {
+            "dim" => VarType::Int,
+            "ddim" => VarType::Double,
+            "sdim" => VarType::Str,
+            "ldim" | "newlab" => VarType::Label,
+            "newmod" => VarType::Struct,
+            _ => return None,
+        };
+        Some(ty)
+    }
+}
+
+/// 式の値の型を推測する。わからなければ None
+pub(crate) fn infer_expr_type(expr: &PExpr) -> Option<VarType> {
+    match expr {
+        PExpr::Literal(token) => match token.kind() {
+            TokenKind::Str => Some(VarType::Str),
+            TokenKind::Char => Some(VarType::Int),
+            TokenKind::Number => {
+                let text = token.body_text();
+                let is_double = !text.starts_with('$')
+                    && !text.starts_with("0x")
+                    && !text.starts_with("0b")
+                    && (text.contains('.') || text.contains(['e', 'E']));
+                Some(if is_double {
+                    VarType::Double
+                } else {
+                    VarType::Int
+                })
+            }
+            _ => None,
+        },
+        PExpr::Label(_) => Some(VarType::Label),
+        // 型変換関数の呼び出し
+        PExpr::Compound(PCompound::Paren(np)) => match np.name.body_text() {
+            "int" => Some(VarType::Int),
+            "double" => Some(VarType::Double),
+            "str" => Some(VarType::Str),
+            _ => None,
+        },
+        PExpr::Compound(_) => None,
+        PExpr::Paren(PParenExpr { body_opt, .. }) => infer_expr_type(body_opt.as_deref()?),
+        PExpr::Prefix(PPrefixExpr { arg_opt, .. }) => infer_expr_type(arg_opt.as_deref()?),
+        // 二項演算の結果は左辺の型になる。
+        PExpr::Infix(PInfixExpr { left, .. }) => infer_expr_type(left),
+    }
+}
+
+/// `dim` などの命令による配列の確保
+#[derive(Clone, Debug)]
+pub(crate) struct ArrayDecl {
+    /// 要素の型
+    pub(crate) ty: VarType,
+    /// 要素数などの引数 (書かれたとおりの式)
+    pub(crate) dims: Vec<String>,
+    /// 変数名の位置
+    pub(crate) loc: Loc,
+}
+
+impl ArrayDecl {
+    /// `str buf(64, 16)` のような形式で書き出す。
+    pub(crate) fn describe(&self, name: &str) -> String {
+        if self.dims.is_empty() {
+            format!("{} {}", self.ty.to_str(), name)
+        } else {
+            format!("{} {}({})", self.ty.to_str(), name, self.dims.join(", "))
+        }
+    }
+}
+
+/// `dimtype` の型の引数 (`4` や `vartype("int")`) を解釈する。
+fn var_type_from_type_arg(expr: &PExpr) -> Option<VarType> {
+    let ty = match expr {
+        PExpr::Literal(token) if token.kind() == TokenKind::Number => match token.body_text() {
+            "1" => VarType::Label,
+            "2" => VarType::Str,
+            "3" => VarType::Double,
+            "4" => VarType::Int,
+            "5" => VarType::Struct,
+            _ => return None,
+        },
+        PExpr::Compound(PCompound::Paren(np)) if np.name.body_text() == "vartype" => {
+            match np.args.first().and_then(|arg| arg.expr_opt.as_ref()) {
+                Some(PExpr::Literal(token)) if token.kind() == TokenKind::Str => {
+                    match token.body_text().trim_matches('"') {
+                        "label" => VarType::Label,
+                        "str" => VarType::Str,
+                        "double" => VarType::Double,
+                        "int" => VarType::Int,
+                        "struct" => VarType::Struct,
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(ty)
+}
+
+/// 配列を確保する命令の文から、配列の確保を読み取る。
+fn array_decl_from_command(stmt: &PCommandStmt) -> Option<ArrayDecl> {
+    let var = match stmt.args.first()?.expr_opt.as_ref()? {
+        PExpr::Compound(PCompound::Name(name)) => name,
+        _ => return None,
+    };
+
+    let (ty, dims) = match stmt.command.body_text() {
+        "dimtype" => {
+            let ty = var_type_from_type_arg(stmt.args.get(1)?.expr_opt.as_ref()?)?;
+            (ty, stmt.args.get(2..).unwrap_or(&[]))
+        }
+        "dim" | "sdim" | "ddim" | "ldim" => (
+            VarType::from_dim_command(stmt.command.body_text())?,
+            &stmt.args[1..],
+        ),
+        _ => return None,
+    };
+
+    let dims = macro_args_to_tokens(dims)
+        .iter()
+        .map(|tokens| tokens_to_string(tokens))
+        .collect();
+    Some(ArrayDecl {
+        ty,
+        dims,
+        loc: var.body.loc,
+    })
+}
+
+/// 代入ごとの型を1つにまとめる。すべて同じ型なら、その型を返す。
+pub(crate) fn unify_var_types(sites: &[(VarType, Loc)]) -> Option<VarType> {
+    let (first, _) = *sites.first()?;
+    if sites.iter().all(|&(ty, _)| ty == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+/// 変数の型の説明 (`int` や `不定 (int, str)` など)
+pub(crate) fn describe_var_types(sites: &[(VarType, Loc)]) -> Option<String> {
+    if let Some(ty) = unify_var_types(sites) {
+        return Some(ty.to_str().to_string());
+    }
+
+    let mut types = vec![];
+    for &(ty, _) in sites {
+        if !types.contains(&ty.to_str()) {
+            types.push(ty.to_str());
+        }
+    }
+    if types.is_empty() {
+        return None;
+    }
+    Some(format!("不定 ({})", types.join(", ")))
+}
+
+/// 変数の型がわかる箇所を集めるビジター
+#[derive(Default)]
+struct V {
+    types: Vec<(Loc, VarType)>,
+    calls: Vec<(Loc, Vec<Option<Loc>>)>,
+    arrays: Vec<ArrayDecl>,
+}
+
+impl V {
+    fn on_call(&mut self, callee: &PToken, args: &[PArg]) {
+        // 変数をそのまま渡している引数だけを記録する。
+        let args = args
+            .iter()
+            .map(|arg| match &arg.expr_opt {
+                Some(PExpr::Compound(PCompound::Name(name))) => Some(name.body.loc),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if args.iter().any(|arg| arg.is_some()) {
+            self.calls.push((callee.body.loc, args));
+        }
+    }
+}
+
+impl PVisitor for V {
+    fn on_compound(&mut self, compound: &PCompound) {
+        if let PCompound::Paren(np) = compound {
+            self.on_call(&np.name, &np.args);
+        }
+
+        self.on_compound_default(compound);
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        match stmt {
+            PStmt::Assign(PAssignStmt {
+                left,
+                op_opt: Some(op),
+                args,
+            }) if op.kind() == TokenKind::Equal => {
+                let ty_opt = args
+                    .first()
+                    .and_then(|arg| arg.expr_opt.as_ref())
+                    .and_then(infer_expr_type);
+                if let Some(ty) = ty_opt {
+                    self.types.push((left.name().body.loc, ty));
+                }
+            }
+            PStmt::Command(stmt) => {
+                if let Some(decl) = array_decl_from_command(stmt) {
+                    self.types.push((decl.loc, decl.ty));
+                    self.arrays.push(decl);
+                } else {
+                    let ty_opt = VarType::from_dim_command(stmt.command.body_text());
+                    let var_opt = match stmt.args.first().and_then(|arg| arg.expr_opt.as_ref()) {
+                        Some(PExpr::Compound(compound)) => Some(compound.name()),
+                        _ => None,
+                    };
+                    if let (Some(ty), Some(var)) = (ty_opt, var_opt) {
+                        self.types.push((var.body.loc, ty));
+                    }
+                }
+
+                self.on_call(&stmt.command, &stmt.args);
+            }
+            _ => {}
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// 変数の型がわかる箇所を集める。
+///
+/// `types` には (変数名の位置, 型) を、`calls` には
+/// (呼び出される命令・関数の名前の位置, 変数をそのまま渡している引数の位置のリスト) を、
+/// `arrays` には配列の確保を追加する。
+pub(crate) fn collect_var_type_sites(
+    root: &PRoot,
+    types: &mut Vec<(Loc, VarType)>,
+    calls: &mut Vec<(Loc, Vec<Option<Loc>>)>,
+    arrays: &mut Vec<ArrayDecl>,
+) {
+    let mut v = V::default();
+    v.on_root(root);
+    types.extend(v.types);
+    calls.extend(v.calls);
+    arrays.extend(v.arrays);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source::DocId, token::tokenize};
+
+    const NO_DOC: DocId = 1;
+
+    fn infer(text: &str) -> Option<&'static str> {
+        let tokens = PToken::from_tokens(tokenize(NO_DOC, text.into()).into());
+        let root = parse_root(tokens);
+        match root.stmts.first()? {
+            PStmt::Assign(stmt) => {
+                infer_expr_type(stmt.args.first()?.expr_opt.as_ref()?).map(VarType::to_str)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_infer_expr_type() {
+        assert_eq!(infer("a = 1\n"), Some("int"));
+        assert_eq!(infer("a = 0x1E\n"), Some("int"));
+        assert_eq!(infer("a = 1.5\n"), Some("double"));
+        assert_eq!(infer("a = -(2.0 * 3)\n"), Some("double"));
+        assert_eq!(infer("a = \"x\" + 1\n"), Some("str"));
+        assert_eq!(infer("a = *l\n"), Some("label"));
+        assert_eq!(infer("a = str(1)\n"), Some("str"));
+        assert_eq!(infer("a = f(1)\n"), None);
+    }
+}
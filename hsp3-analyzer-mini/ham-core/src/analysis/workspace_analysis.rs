@@ -1,85 +1,168 @@
 use super::*;
+use crate::{parse::PVisitor, utils::read_file::sjis_encodable};
+use std::time::{Duration, Instant, SystemTime};
 
 type DocAnalysisMap = HashMap<DocId, DocAnalysis>;
 
+/// 解析にかかった時間の統計
+#[derive(Clone, Copy, Default)]
+pub(crate) struct AnalysisTiming {
+    /// 解析した回数
+    pub(crate) count: usize,
+    /// 最後の解析にかかった時間
+    pub(crate) last: Duration,
+    /// 解析にかかった時間の合計
+    pub(crate) total: Duration,
+}
+
 /// ワークスペースの外側のデータ
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub(crate) struct WorkspaceHost {
     pub(crate) builtin_env: Rc<SymbolEnv>,
     pub(crate) common_docs: Rc<HashMap<String, DocId>>,
     pub(crate) hsphelp_info: Rc<HspHelpInfo>,
     pub(crate) entrypoints: Vec<DocId>,
+    /// すべてのプロジェクトで暗黙にincludeされているとみなすドキュメント (ランタイムの命令の定義など)
+    pub(crate) implicit_docs: Vec<DocId>,
 }
 
 #[derive(Default)]
 pub(crate) struct WorkspaceAnalysis {
+    host: WorkspaceHost,
     dirty_docs: HashSet<DocId>,
+    /// `set_project_docs` の後、プロジェクトを解析しなおしていないか
+    project_docs_changed: bool,
     doc_texts: HashMap<DocId, (Lang, RcStr)>,
+    /// ドキュメントが最後に編集された時刻 (最初に読み込まれたときは記録しない)
+    doc_edited_at: HashMap<DocId, SystemTime>,
+    /// シンボルの収集だけを行うドキュメント (大きすぎるもの)
+    symbols_only_docs: HashSet<DocId>,
+    timing: AnalysisTiming,
 
     // すべてのドキュメントの解析結果を使って構築される情報:
     doc_analysis_map: DocAnalysisMap,
     module_map: ModuleMap,
+    /// どのプロジェクトにも属さないドキュメントのためのもの (すべてのドキュメントを対象にする。)
     project1: ProjectAnalysis,
-    project_opt: Option<ProjectAnalysis>,
+    /// プロジェクトのエントリーポイント (`projects` と同じ順番)
+    entrypoints: Vec<DocId>,
+    /// エントリーポイントごとのプロジェクト
+    ///
+    /// エントリーポイントと、そこから推移的にincludeされるドキュメントを1つの単位として解析する。
+    /// (無関係なスクリプトのシンボルが互いに見えないようにするため。)
+    projects: Vec<ProjectAnalysis>,
 }
 
 impl WorkspaceAnalysis {
     pub(crate) fn initialize(&mut self, host: WorkspaceHost) {
+        self.host = host;
+        self.entrypoints.clear();
+        self.projects.clear();
+
         let WorkspaceHost {
             common_docs,
             hsphelp_info,
             builtin_env,
-            entrypoints,
-        } = host;
-
-        self.project_opt = if !entrypoints.is_empty() {
-            let mut p = ProjectAnalysis::default();
-            p.entrypoints = EntryPoints::Docs(entrypoints);
-            p.common_docs = common_docs.clone();
-            p.hsphelp_info = hsphelp_info.clone();
-            p.public_env.builtin = builtin_env.clone();
-            Some(p)
-        } else {
-            None
-        };
-
+            ..
+        } = self.host.clone();
         self.project1.entrypoints = EntryPoints::NonCommon;
         self.project1.common_docs = common_docs;
         self.project1.hsphelp_info = hsphelp_info;
         self.project1.public_env.builtin = builtin_env;
     }
 
+    fn new_project(&self, entrypoint: DocId) -> ProjectAnalysis {
+        let mut entrypoints = vec![entrypoint];
+        entrypoints.extend(self.host.implicit_docs.iter().cloned());
+
+        let mut p = ProjectAnalysis::default();
+        p.entrypoints = EntryPoints::Docs(entrypoints);
+        p.common_docs = self.host.common_docs.clone();
+        p.hsphelp_info = self.host.hsphelp_info.clone();
+        p.public_env.builtin = self.host.builtin_env.clone();
+        p.project_docs = self.project1.project_docs.clone();
+        p
+    }
+
+    /// 設定で指定されたエントリーポイントと `; ham-entry` コメントがついたドキュメントごとに、
+    /// プロジェクトを構成しなおす。
+    fn update_entrypoints(&mut self) {
+        let mut entrypoints = self.host.entrypoints.clone();
+        entrypoints.extend(
+            self.doc_analysis_map
+                .iter()
+                .filter(|(_, da)| da.entry_point)
+                .map(|(&doc, _)| doc),
+        );
+        entrypoints.sort();
+        entrypoints.dedup();
+
+        if self.entrypoints == entrypoints {
+            return;
+        }
+
+        self.projects = entrypoints
+            .iter()
+            .map(|&doc| self.new_project(doc))
+            .collect();
+        self.entrypoints = entrypoints;
+    }
+
     pub(crate) fn update_doc(&mut self, doc: DocId, lang: Lang, text: RcStr) {
         self.dirty_docs.insert(doc);
-        self.doc_texts.insert(doc, (lang, text));
+        if self.doc_texts.insert(doc, (lang, text)).is_some() {
+            self.doc_edited_at.insert(doc, SystemTime::now());
+        }
         self.doc_analysis_map
             .entry(doc)
             .and_modify(|a| a.invalidate());
     }
 
+    /// ドキュメントのシンボルの収集だけを行うか設定する。(次に `update_doc` したときに反映される。)
+    pub(crate) fn set_symbols_only(&mut self, doc: DocId, symbols_only: bool) {
+        if symbols_only {
+            self.symbols_only_docs.insert(doc);
+        } else {
+            self.symbols_only_docs.remove(&doc);
+        }
+    }
+
     pub(crate) fn close_doc(&mut self, doc: DocId) {
         self.dirty_docs.insert(doc);
         self.doc_texts.remove(&doc);
+        self.doc_edited_at.remove(&doc);
+        self.symbols_only_docs.remove(&doc);
         self.doc_analysis_map.remove(&doc);
     }
 
     pub(crate) fn set_project_docs(&mut self, project_docs: Rc<ProjectDocs>) {
-        for p in [Some(&mut self.project1), self.project_opt.as_mut()]
-            .iter_mut()
-            .flatten()
-        {
+        for p in iter::once(&mut self.project1).chain(self.projects.iter_mut()) {
             p.project_docs = project_docs.clone();
         }
+        self.project_docs_changed = true;
     }
 
     fn compute(&mut self) {
-        if self.dirty_docs.is_empty() {
+        if self.dirty_docs.is_empty() && !self.project_docs_changed {
             return;
         }
+        let project_docs_changed = take(&mut self.project_docs_changed);
+        let start = Instant::now();
 
+        // エントリーポイントからincludeをたどって到達できるドキュメント (active_docs) が変更されていなければ、
+        // プロジェクトの解析結果は変わらないので、捨てずに使いまわす。
+        // (解決できないincludeがあるときは、変更されたドキュメントがそれにあたるかもしれないので解析しなおす。)
         self.project1.invalidate();
-        if let Some(p) = self.project_opt.as_mut() {
-            p.invalidate();
+        for p in &mut self.projects {
+            let affected = project_docs_changed
+                || !p.unresolved_includes.is_empty()
+                || self
+                    .dirty_docs
+                    .iter()
+                    .any(|doc| p.active_docs.contains(doc));
+            if affected {
+                p.invalidate();
+            }
         }
 
         let mut doc_analysis_map = take(&mut self.doc_analysis_map);
@@ -107,22 +190,29 @@ impl WorkspaceAnalysis {
             let da = doc_analysis_map.entry(doc).or_default();
             da.set_syntax(p_tokens, root);
             da.set_preproc(preproc);
+            if self.symbols_only_docs.contains(&doc) {
+                da.symbols_only = true;
+                da.data_file = true;
+            }
 
             self.module_map
                 .extend(da.module_map.iter().map(|(&m, rc)| (m, rc.clone())));
         }
 
         self.doc_analysis_map = doc_analysis_map;
+        self.update_entrypoints();
 
         // 以前の解析結果を捨てる:
-        for p in [Some(&mut self.project1), self.project_opt.as_mut()]
-            .iter_mut()
-            .flatten()
-        {
+        for p in iter::once(&mut self.project1).chain(self.projects.iter_mut()) {
             p.compute(&self.doc_analysis_map, &self.module_map);
         }
 
-        assert_eq!(self.project1.diagnostics.len(), 0);
+        assert_eq!(self.project1.unresolved_includes.len(), 0);
+
+        let elapsed = start.elapsed();
+        self.timing.count += 1;
+        self.timing.last = elapsed;
+        self.timing.total += elapsed;
     }
 
     pub(crate) fn in_preproc(&mut self, doc: DocId, pos: Pos16) -> Option<bool> {
@@ -132,6 +222,17 @@ impl WorkspaceAnalysis {
         Some(in_preproc(pos, tokens))
     }
 
+    pub(crate) fn include_path_prefix(
+        &mut self,
+        doc: DocId,
+        pos: Pos16,
+    ) -> Option<(Pos16, String)> {
+        self.compute();
+
+        let tokens = &self.doc_analysis_map.get(&doc)?.tokens;
+        include_path_prefix(pos, tokens)
+    }
+
     pub(crate) fn in_str_or_comment(&mut self, doc: DocId, pos: Pos16) -> Option<bool> {
         self.compute();
 
@@ -147,6 +248,122 @@ impl WorkspaceAnalysis {
             .map_or(false, |da| da.include_guard.is_some())
     }
 
+    /// 複数回includeされていて、インクルードガードがなく、定義が重複してしまうドキュメントを集める。
+    pub(crate) fn collect_docs_missing_include_guard(&mut self, docs: &mut Vec<DocId>) {
+        self.compute();
+
+        // プロジェクトごとに数える。(別々のプロジェクトでincludeされるのは問題ない。)
+        let mut multiple = HashSet::new();
+        for p in &self.projects {
+            let mut include_counts: HashMap<DocId, usize> = HashMap::new();
+            for &(_, doc) in &p.include_resolution {
+                *include_counts.entry(doc).or_default() += 1;
+            }
+            multiple.extend(
+                include_counts
+                    .into_iter()
+                    .filter(|&(_, count)| count >= 2)
+                    .map(|(doc, _)| doc),
+            );
+        }
+
+        let in_common = self
+            .host
+            .common_docs
+            .values()
+            .cloned()
+            .collect::<HashSet<_>>();
+        for doc in multiple {
+            if in_common.contains(&doc) {
+                continue;
+            }
+
+            let da = match self.doc_analysis_map.get(&doc) {
+                Some(it) => it,
+                None => continue,
+            };
+            let has_defs = !da.preproc_symbols.is_empty() || !da.module_map.is_empty();
+            if da.include_guard.is_none() && has_defs {
+                docs.push(doc);
+            }
+        }
+        docs.sort();
+    }
+
+    /// 複数回includeされていて、インクルードガードがないドキュメントについて、それをincludeしている `#include` 文を (位置, includeされるドキュメント) の形で集める。
+    pub(crate) fn collect_includes_missing_include_guard(
+        &mut self,
+        includes: &mut Vec<(Loc, DocId)>,
+    ) {
+        let mut docs = vec![];
+        self.collect_docs_missing_include_guard(&mut docs);
+        if docs.is_empty() {
+            return;
+        }
+
+        // 複数回includeしているプロジェクトの `#include` だけを報告する。
+        for p in &self.projects {
+            let mut include_counts: HashMap<DocId, usize> = HashMap::new();
+            for &(_, doc) in &p.include_resolution {
+                *include_counts.entry(doc).or_default() += 1;
+            }
+            includes.extend(p.include_resolution.iter().filter(|&&(_, doc)| {
+                docs.contains(&doc) && include_counts.get(&doc).map_or(false, |&n| n >= 2)
+            }));
+        }
+        includes.sort();
+        includes.dedup();
+    }
+
+    /// ドキュメント内のモジュールと `#deffunc` 系命令の範囲を集める。
+    pub(crate) fn collect_doc_blocks(&mut self, doc: DocId, blocks: &mut Vec<DocBlock>) {
+        self.compute();
+
+        let da = match self.doc_analysis_map.get(&doc) {
+            Some(it) => it,
+            None => return,
+        };
+
+        blocks.extend(da.module_map.values().map(|module| DocBlock {
+            kind: DocBlockKind::Module(module.label.clone()),
+            loc: module.content_loc,
+        }));
+        blocks.extend(da.deffunc_map.values().map(|deffunc| DocBlock {
+            kind: DocBlockKind::DefFunc,
+            loc: deffunc.content_loc,
+        }));
+    }
+
+    /// 指定した名前のシンボルを公開する形で定義しているドキュメントを集める。
+    pub(crate) fn collect_docs_defining(&mut self, name: &str, docs: &mut Vec<DocId>) {
+        self.compute();
+
+        for (&doc, da) in &self.doc_analysis_map {
+            let defined = da.preproc_symbols.iter().any(|symbol| {
+                symbol.name.as_str() == name
+                    && symbol.scope_opt.as_ref().map_or(false, |s| s.is_public())
+            });
+            if defined {
+                docs.push(doc);
+            }
+        }
+        docs.sort();
+    }
+
+    /// commonディレクトリにあるドキュメントの、commonディレクトリからの相対パスを列挙する。
+    pub(crate) fn collect_common_doc_names(&self, names: &mut Vec<String>) {
+        names.extend(self.host.common_docs.keys().cloned());
+    }
+
+    /// commonディレクトリにあるドキュメントなら、commonディレクトリからの相対パスを返す。
+    pub(crate) fn common_doc_name(&self, doc: DocId) -> Option<&str> {
+        self.host
+            .common_docs
+            .iter()
+            .find(|&(_, &d)| d == doc)
+            .map(|(name, _)| name.as_str())
+    }
+
     pub(crate) fn on_include_guard(&mut self, doc: DocId, pos: Pos16) -> Option<Loc> {
         self.compute();
 
@@ -196,21 +413,46 @@ impl WorkspaceAnalysis {
         Some((token.text.clone(), token.loc))
     }
 
-    pub(crate) fn require_some_project(&mut self) -> ProjectAnalysisRef {
+    /// すべてのプロジェクトのシンボルを集める。(複数のプロジェクトに属するシンボルは1回だけ。)
+    ///
+    /// プロジェクトがなければ、すべてのドキュメントを対象にする。
+    pub(crate) fn collect_all_symbols(
+        &mut self,
+        name_filter: &str,
+        symbols: &mut Vec<(SymbolRc, Loc)>,
+    ) {
         self.compute();
 
-        let p = self.project_opt.as_mut().unwrap_or(&mut self.project1);
-        p.compute(&self.doc_analysis_map, &self.module_map)
+        if self.projects.is_empty() {
+            self.project1
+                .compute(&self.doc_analysis_map, &self.module_map)
+                .collect_all_symbols(name_filter, symbols);
+            return;
+        }
+
+        let mut done = HashSet::new();
+        for p in &mut self.projects {
+            let mut found = vec![];
+            p.compute(&self.doc_analysis_map, &self.module_map)
+                .collect_all_symbols(name_filter, &mut found);
+            symbols.extend(found.into_iter().filter(|&(_, loc)| done.insert(loc)));
+        }
     }
 
+    /// ドキュメントを含むプロジェクトを得る。
+    ///
+    /// 複数のプロジェクトに含まれるときは、エントリーポイントが最初のものを使う。
+    /// どのプロジェクトにも含まれなければ、すべてのドキュメントを対象にしたものを使う。
     pub(crate) fn require_project_for_doc(&mut self, doc: DocId) -> ProjectAnalysisRef {
         self.compute();
 
-        if let Some(p) = self.project_opt.as_mut() {
-            if p.active_docs.contains(&doc) {
-                debug_assert!(p.is_computed());
-                return p.compute(&self.doc_analysis_map, &self.module_map);
-            }
+        if let Some(p) = self
+            .projects
+            .iter_mut()
+            .find(|p| p.active_docs.contains(&doc))
+        {
+            debug_assert!(p.is_computed());
+            return p.compute(&self.doc_analysis_map, &self.module_map);
         }
 
         debug_assert!(self.project1.is_computed());
@@ -218,22 +460,42 @@ impl WorkspaceAnalysis {
             .compute(&self.doc_analysis_map, &self.module_map)
     }
 
-    pub(crate) fn diagnose(&mut self, diagnostics: &mut Vec<(String, Loc)>) {
+    /// いずれかのプロジェクトに含まれるドキュメント
+    fn docs_in_projects(&self) -> HashSet<DocId> {
+        self.projects
+            .iter()
+            .flat_map(|p| p.active_docs.iter().cloned())
+            .collect()
+    }
+
+    /// 意味解析による診断を集める。
+    ///
+    /// 定義が見つからない命令は警告として報告するので、`undefined_commands` に分けて集める。
+    pub(crate) fn diagnose(
+        &mut self,
+        diagnostics: &mut Vec<(String, Loc)>,
+        undefined_commands: &mut Vec<(RcStr, Loc)>,
+    ) {
         self.compute();
 
-        self.diagnose_precisely(diagnostics);
+        self.diagnose_precisely(diagnostics, undefined_commands);
     }
 
     pub(crate) fn diagnose_syntax_lints(&mut self, lints: &mut Vec<(SyntaxLint, Loc)>) {
         self.compute();
 
-        let p = match self.project_opt.as_ref() {
-            Some(it) => it,
-            None => return,
-        };
+        let active_docs = self.docs_in_projects();
+
+        // includeされるだけのファイルにある `#func` は、includeする側の `#uselib` を使うことがあるので報告しない。
+        let included_docs = self
+            .projects
+            .iter()
+            .flat_map(|p| p.include_resolution.iter().map(|&(_, doc)| doc))
+            .filter(|doc| !self.entrypoints.contains(doc))
+            .collect::<HashSet<_>>();
 
         for (&doc, da) in self.doc_analysis_map.iter() {
-            if !p.active_docs.contains(&doc) {
+            if !active_docs.contains(&doc) || da.data_file {
                 continue;
             }
 
@@ -249,56 +511,720 @@ impl WorkspaceAnalysis {
                 Some(it) => it,
                 None => continue,
             };
-            crate::analysis::syntax_linter::syntax_lint(&tree, lints);
+            let mut doc_lints = vec![];
+            crate::analysis::syntax_linter::syntax_lint(&tree, &mut doc_lints);
+            let included = included_docs.contains(&doc);
+            lints.extend(
+                doc_lints.into_iter().filter(|(lint, _)| {
+                    !(included && matches!(lint, SyntaxLint::FuncWithoutUseLib))
+                }),
+            );
+        }
+    }
+
+    /// `#packopt` などのビルドオプションの誤りを集める。
+    pub(crate) fn diagnose_build_options(&mut self, issues: &mut Vec<(BuildOptionIssue, Loc)>) {
+        self.compute();
+
+        let active_docs = self.docs_in_projects();
+        for (&doc, da) in self.doc_analysis_map.iter() {
+            if !active_docs.contains(&doc) || da.data_file {
+                continue;
+            }
+
+            let tree = match &da.tree_opt {
+                Some(it) => it,
+                None => continue,
+            };
+            let mut options = vec![];
+            collect_build_options(tree, &mut options);
+            issues.extend(options.iter().filter_map(|option| option.validate()));
         }
     }
 
-    pub(crate) fn diagnose_precisely(&mut self, diagnostics: &mut Vec<(String, Loc)>) {
+    /// 文字列リテラルとコメントに含まれる、shift_jis で表せない文字とその位置を集める。
+    ///
+    /// `is_sjis` はドキュメントとそのテキストを受け取り、そのドキュメントが shift_jis としてコンパイルされるかを判定する。
+    pub(crate) fn diagnose_sjis_incompatible_chars(
+        &mut self,
+        is_sjis: impl Fn(DocId, &str) -> bool,
+        chars: &mut Vec<(char, Loc)>,
+    ) {
+        self.compute();
+
+        let active_docs = self.docs_in_projects();
+        for (&doc, da) in self.doc_analysis_map.iter() {
+            if !active_docs.contains(&doc) || da.data_file {
+                continue;
+            }
+            match self.doc_texts.get(&doc) {
+                Some((_, text)) if is_sjis(doc, text) => {}
+                _ => continue,
+            }
+
+            for token in da.tokens.iter().flat_map(|t| t.iter()) {
+                if !matches!(token.kind, TokenKind::Str | TokenKind::Comment) {
+                    continue;
+                }
+
+                let text = token.text.as_str();
+                for (i, c) in text.char_indices() {
+                    // 置換文字は読み込めなかったバイト列なので、ここでは報告しない。
+                    if c.is_ascii() || c == char::REPLACEMENT_CHARACTER || sjis_encodable(c) {
+                        continue;
+                    }
+
+                    let start = token.loc.start() + Pos::from(&text[..i]);
+                    let end = start + Pos::from(&text[i..i + c.len_utf8()]);
+                    chars.push((c, Loc::new3(doc, start, end)));
+                }
+            }
+        }
+    }
+
+    /// 読み込めなかったバイト列の位置を探す。
+    ///
+    /// 不正なバイトは置換文字として読み込まれているので、最初の置換文字の位置を返す。
+    /// みつからなければ最初の行を返す。
+    /// ドキュメントの最初の行の範囲を得る。(ドキュメント全体に関する診断を置くためのもの)
+    pub(crate) fn first_line_loc(&mut self, doc: DocId) -> Option<Loc> {
+        self.compute();
+
+        let (_, text) = self.doc_texts.get(&doc)?;
+        let text = text.as_str();
+        let end = text.find('\n').unwrap_or(text.len());
+        Some(Loc::new3(
+            doc,
+            Pos::default(),
+            Pos::from(text[..end].trim_end_matches('\r')),
+        ))
+    }
+
+    pub(crate) fn find_malformed_bytes(&mut self, doc: DocId) -> Option<Loc> {
         self.compute();
 
-        let p = match &self.project_opt {
+        let (_, text) = self.doc_texts.get(&doc)?;
+        let text = text.as_str();
+        let start = match text.find(char::REPLACEMENT_CHARACTER) {
             Some(it) => it,
-            None => return,
+            None => return self.first_line_loc(doc),
         };
+        let end = start + char::REPLACEMENT_CHARACTER.len_utf8();
+        Some(Loc::new3(
+            doc,
+            Pos::from(&text[..start]),
+            Pos::from(&text[..end]),
+        ))
+    }
 
-        // diagnose:
+    /// プロジェクトごとに、エントリーポイントとビルドオプションを集める。
+    ///
+    /// オプションはエントリーポイントに書かれたものを先に、
+    /// それ以外のドキュメントのものをドキュメントの順に並べる。
+    pub(crate) fn collect_build_options(&mut self, projects: &mut Vec<(DocId, Vec<BuildOption>)>) {
+        self.compute();
 
-        let use_site_map = p
-            .use_sites
-            .iter()
-            .map(|(symbol, loc)| ((loc.doc, loc.start()), symbol.clone()))
-            .collect::<HashMap<_, _>>();
+        for (&entrypoint, p) in self.entrypoints.iter().zip(&self.projects) {
+            let mut docs = p.active_docs.iter().cloned().collect::<Vec<_>>();
+            docs.sort_by_key(|&doc| (doc != entrypoint, doc));
 
-        let mut ctx = Sema {
-            use_site_map,
-            diagnostics: vec![],
-        };
+            let mut options = vec![];
+            for doc in docs {
+                let tree = match self.doc_analysis_map.get(&doc) {
+                    Some(DocAnalysis {
+                        data_file: false,
+                        tree_opt: Some(tree),
+                        ..
+                    }) => tree,
+                    _ => continue,
+                };
+                collect_build_options(tree, &mut options);
+            }
+            projects.push((entrypoint, options));
+        }
+    }
 
+    pub(crate) fn build_directive_at(&mut self, doc: DocId, pos: Pos16) -> Option<BuildDirective> {
+        self.compute();
+
+        let tokens = &self.doc_analysis_map.get(&doc)?.tokens;
+        build_directive_at(pos, tokens)
+    }
+
+    pub(crate) fn diagnose_syntax_errors(&mut self, errors: &mut Vec<(SyntaxError, Loc)>) {
+        self.compute();
+
+        let active_docs = self.docs_in_projects();
         for (&doc, da) in self.doc_analysis_map.iter() {
-            if !p.active_docs.contains(&doc) {
+            if !active_docs.contains(&doc) || da.data_file {
                 continue;
             }
 
-            let root = match &da.tree_opt {
+            let tree = match &da.tree_opt {
                 Some(it) => it,
                 None => continue,
             };
+            crate::analysis::syntax_error::collect_syntax_errors(&da.tokens, tree, errors);
+        }
+    }
 
-            ctx.on_root(root);
+    /// モジュールの可視性に関する問題を集める。
+    pub(crate) fn collect_module_visibility_issues(
+        &mut self,
+        issues: &mut Vec<ModuleVisibilityIssue>,
+    ) {
+        self.compute();
+
+        if self.projects.is_empty() {
+            let p = &self.project1;
+            crate::analysis::module_visibility::analyze_module_visibility(
+                &p.def_sites,
+                &p.use_sites,
+                &self.module_map,
+                issues,
+            );
+            return;
         }
 
-        // どのプロジェクトに由来するか覚えておく必要がある
-        diagnostics.extend(ctx.diagnostics.into_iter().map(|(d, loc)| {
-            let msg = match d {
-                Diagnostic::Undefined => "定義が見つかりません",
-                Diagnostic::VarRequired => "変数か配列の要素が必要です。",
+        let mut done = HashSet::new();
+        for p in &self.projects {
+            let mut found = vec![];
+            crate::analysis::module_visibility::analyze_module_visibility(
+                &p.def_sites,
+                &p.use_sites,
+                &self.module_map,
+                &mut found,
+            );
+            issues.extend(found.into_iter().filter(|issue| done.insert(issue.def_loc)));
+        }
+    }
+
+    pub(crate) fn diagnose_module_visibility(&mut self, issues: &mut Vec<ModuleVisibilityIssue>) {
+        self.compute();
+
+        if self.projects.is_empty() {
+            return;
+        }
+        self.collect_module_visibility_issues(issues);
+    }
+
+    /// エントリーポイントと、すべてのプロジェクトで解決されたincludeからなるグラフを作る。
+    pub(crate) fn include_graph(&mut self) -> IncludeGraph {
+        self.compute();
+
+        let edges = self
+            .projects
+            .iter()
+            .flat_map(|p| p.include_resolution.iter().cloned())
+            .collect::<Vec<_>>();
+        IncludeGraph::new(self.entrypoints.clone(), &edges)
+    }
+
+    /// 循環しているincludeを集める。
+    ///
+    /// 循環に含まれる `#include` 文ごとに、そこから同じドキュメントに戻ってくるまでのincludeの列を集める。
+    pub(crate) fn collect_include_cycles(&mut self, cycles: &mut Vec<Vec<(Loc, DocId)>>) {
+        self.compute();
+
+        let edges = self
+            .projects
+            .iter()
+            .flat_map(|p| p.include_resolution.iter().cloned())
+            .collect::<Vec<_>>();
+        cycles.extend(find_include_cycles(&edges));
+    }
+
+    /// 解析済みのドキュメントの `#include` に書かれているファイル名を集める。(標準化されたもの)
+    pub(crate) fn collect_include_names(&mut self, names: &mut Vec<RcStr>) {
+        self.compute();
+
+        for da in self.doc_analysis_map.values() {
+            names.extend(da.includes.iter().map(|(name, _)| name.clone()));
+        }
+    }
+
+    /// 解析済みのドキュメントの `#include` を、(includeするドキュメント, ファイル名) の形で集める。
+    pub(crate) fn collect_doc_include_names(&mut self, includes: &mut Vec<(DocId, RcStr)>) {
+        self.compute();
+
+        for (&doc, da) in &self.doc_analysis_map {
+            includes.extend(da.includes.iter().map(|(name, _)| (doc, name.clone())));
+        }
+    }
+
+    /// 解析にかかった時間の統計
+    pub(crate) fn analysis_timing(&mut self) -> AnalysisTiming {
+        self.compute();
+        self.timing
+    }
+
+    /// モジュールの表示用の名前
+    pub(crate) fn module_label(&mut self, module: ModuleKey) -> Option<RcStr> {
+        self.compute();
+        Some(self.module_map.get(&module)?.label.clone())
+    }
+
+    /// エントリーポイントごとのプロジェクトの数
+    pub(crate) fn project_count(&self) -> usize {
+        self.projects.len()
+    }
+
+    /// スクリプトのドキュメントの数と、それらの行数の合計を数える。
+    pub(crate) fn count_script_lines(&self) -> (usize, usize) {
+        let mut doc_count = 0;
+        let mut line_count = 0;
+        for (lang, text) in self.doc_texts.values() {
+            if *lang != Lang::Hsp3 {
+                continue;
+            }
+            doc_count += 1;
+            line_count += text.lines().count();
+        }
+        (doc_count, line_count)
+    }
+
+    /// シンボルの使用頻度の統計を集める。
+    ///
+    /// 複数のプロジェクトに含まれるドキュメントでの使用は1回と数える。
+    pub(crate) fn collect_symbol_stats(&mut self, stats: &mut Vec<SymbolStat>) {
+        self.compute();
+
+        let doc_edited_at = &self.doc_edited_at;
+        let projects = if self.projects.is_empty() {
+            std::slice::from_ref(&self.project1)
+        } else {
+            &self.projects[..]
+        };
+
+        let mut use_locs: HashMap<&SymbolRc, HashSet<Loc>> = HashMap::new();
+        for p in projects {
+            for (symbol, loc) in &p.use_sites {
+                use_locs.entry(symbol).or_default().insert(*loc);
+            }
+        }
+
+        let mut done = HashSet::new();
+        for p in projects {
+            for (symbol, def_loc) in &p.def_sites {
+                if !done.insert(symbol) {
+                    continue;
+                }
+
+                stats.push(SymbolStat {
+                    symbol: symbol.clone(),
+                    def_loc: *def_loc,
+                    use_count: use_locs.get(symbol).map_or(0, |locs| locs.len()),
+                    last_edited_opt: doc_edited_at.get(&def_loc.doc).copied(),
+                });
+            }
+        }
+    }
+
+    /// 意味解析によって問題を探す。
+    ///
+    /// ドキュメントはそれを含むプロジェクトごとに解析する。同じ箇所の同じ種類の問題は1回だけ報告する。
+    fn run_sema(&mut self) -> Vec<(Diagnostic, Loc)> {
+        self.compute();
+
+        let mut diagnostics = vec![];
+        let mut done = HashSet::new();
+        for p in &self.projects {
+            let use_site_map = p
+                .use_sites
+                .iter()
+                .map(|(symbol, loc)| ((loc.doc, loc.start()), symbol.clone()))
+                .collect::<HashMap<_, _>>();
+
+            let mut ctx = Sema::new(use_site_map);
+
+            for (&doc, da) in self.doc_analysis_map.iter() {
+                if !p.active_docs.contains(&doc) || da.data_file {
+                    continue;
+                }
+
+                let root = match &da.tree_opt {
+                    Some(it) => it,
+                    None => continue,
+                };
+
+                ctx.on_root(root);
             }
-            .to_string();
-            (msg, loc)
+
+            diagnostics.extend(
+                ctx.diagnostics
+                    .into_iter()
+                    .filter(|(d, loc)| done.insert((std::mem::discriminant(d), *loc))),
+            );
+        }
+        diagnostics
+    }
+
+    pub(crate) fn diagnose_precisely(
+        &mut self,
+        diagnostics: &mut Vec<(String, Loc)>,
+        undefined_commands: &mut Vec<(RcStr, Loc)>,
+    ) {
+        let sema_diagnostics = self.run_sema();
+
+        if self.projects.is_empty() {
+            return;
+        }
+
+        diagnostics.extend(sema_diagnostics.into_iter().filter_map(|(d, loc)| {
+            let msg = match d {
+                Diagnostic::Undefined => "定義が見つかりません".to_string(),
+                Diagnostic::VarRequired => "変数か配列の要素が必要です。".to_string(),
+                Diagnostic::LabelNotAllowed(param) => {
+                    format!("{} 型のパラメータにラベルは渡せません。", param.to_str())
+                }
+                Diagnostic::FunctionUsedAsCommand(name) => format!(
+                    "{} は関数なので命令としては使えません。戻り値を変数に代入するなどして、式の中で呼び出してください。",
+                    name
+                ),
+                Diagnostic::CommandUsedInExpr(name) => {
+                    format!("{} は命令なので式の中では使えません。", name)
+                }
+                Diagnostic::ArityMismatch {
+                    name,
+                    min,
+                    max,
+                    found,
+                } => {
+                    let expected = if min == max {
+                        format!("{}個", max)
+                    } else {
+                        format!("{}〜{}個", min, max)
+                    };
+                    format!(
+                        "{} の引数は{}ですが、{}個指定されています。",
+                        name, expected, found
+                    )
+                }
+                // 警告として別に報告する。
+                Diagnostic::UndefinedCommand(name) => {
+                    undefined_commands.push((name, loc));
+                    return None;
+                }
+            };
+            Some((msg, loc))
         }));
+    }
+
+    /// 解決できなかったincludeのファイル名と、`#include` 文の位置を集める。
+    pub(crate) fn collect_unresolved_includes(&mut self, includes: &mut Vec<(RcStr, Loc)>) {
+        self.compute();
 
-        diagnostics.extend(p.diagnostics.clone());
+        let mut done = HashSet::new();
+        for p in &self.projects {
+            includes.extend(
+                (p.unresolved_includes.iter())
+                    .filter(|(_, loc)| done.insert(*loc))
+                    .cloned(),
+            );
+        }
     }
+
+    /// 診断の対象となるドキュメントか？ (共通フォルダのファイルやデータファイルは対象外。)
+    fn is_in_workspace(&self, p: &ProjectAnalysis, doc: DocId) -> bool {
+        p.active_docs.contains(&doc)
+            && self
+                .doc_analysis_map
+                .get(&doc)
+                .map_or(false, |da| !da.data_file)
+            && !p.common_docs.values().any(|&d| d == doc)
+    }
+
+    /// 定義が見つからないラベルの使用箇所を集める。
+    ///
+    /// 複数のプロジェクトに含まれる箇所は、どのプロジェクトでも解決できないときだけ報告する。
+    pub(crate) fn diagnose_unresolved_labels(&mut self, labels: &mut Vec<(RcStr, Loc)>) {
+        self.compute();
+
+        let mut resolved = HashSet::new();
+        let mut unresolved = vec![];
+        for p in &self.projects {
+            let defined = p
+                .def_sites
+                .iter()
+                .map(|(symbol, _)| symbol)
+                .collect::<HashSet<_>>();
+
+            for (symbol, loc) in &p.use_sites {
+                if symbol.kind != HspSymbolKind::Label {
+                    continue;
+                }
+
+                if defined.contains(symbol) {
+                    resolved.insert(*loc);
+                } else if self.is_in_workspace(p, loc.doc) {
+                    unresolved.push((symbol.name(), *loc));
+                }
+            }
+        }
+
+        unresolved.retain(|(_, loc)| !resolved.contains(loc));
+        unresolved.sort_by_key(|&(_, loc)| loc);
+        unresolved.dedup_by_key(|&mut (_, loc)| loc);
+        labels.extend(unresolved);
+    }
+
+    /// 異なるファイルでglobalに定義された同じ名前の命令・関数・モジュールを集める。
+    ///
+    /// (シンボル, 定義箇所, 同じ名前のほかの定義箇所) のリストを返す。
+    pub(crate) fn diagnose_duplicate_globals(&mut self, out: &mut Vec<(SymbolRc, Loc, Vec<Loc>)>) {
+        self.compute();
+
+        // 条件つきコンパイルのブロックの中の定義は数えない。(ランタイムごとに書き分けていることがある。)
+        let conditional_blocks = self
+            .doc_analysis_map
+            .iter()
+            .filter_map(|(&doc, da)| {
+                let root = da.tree_opt.as_ref()?;
+                Some((doc, crate::analysis::preproc::find_conditional_blocks(root)))
+            })
+            .collect::<HashMap<_, _>>();
+        let in_conditional_block = |loc: &Loc| {
+            conditional_blocks.get(&loc.doc).map_or(false, |blocks| {
+                blocks
+                    .iter()
+                    .any(|block| block.start() <= loc.start() && loc.end() <= block.end())
+            })
+        };
+
+        let mut found = vec![];
+        for p in &self.projects {
+            let mut defs: HashMap<RcStr, Vec<(SymbolRc, Loc)>> = HashMap::new();
+            for (doc, symbols) in &p.doc_symbols_map {
+                if !p.active_docs.contains(doc) {
+                    continue;
+                }
+
+                for symbol in symbols {
+                    let is_target = matches!(
+                        symbol.kind,
+                        HspSymbolKind::DefFunc
+                            | HspSymbolKind::DefCFunc
+                            | HspSymbolKind::ModFunc
+                            | HspSymbolKind::ModCFunc
+                            | HspSymbolKind::LibFunc
+                            | HspSymbolKind::ComFunc
+                            | HspSymbolKind::Module
+                    );
+                    if !is_target || !matches!(symbol.scope_opt, Some(Scope::Global)) {
+                        continue;
+                    }
+                    if let Some(loc) = symbol.preproc_def_site_opt {
+                        if in_conditional_block(&loc) {
+                            continue;
+                        }
+                        defs.entry(symbol.name())
+                            .or_default()
+                            .push((symbol.clone(), loc));
+                    }
+                }
+            }
+
+            for sites in defs.values() {
+                for (symbol, loc) in sites {
+                    if !self.is_in_workspace(p, loc.doc) {
+                        continue;
+                    }
+
+                    // 同じファイルの中での重複は数えない。(条件付きコンパイルで書き分けていることがある。)
+                    let others = sites
+                        .iter()
+                        .map(|&(_, other)| other)
+                        .filter(|other| other.doc != loc.doc)
+                        .collect::<Vec<_>>();
+                    if !others.is_empty() {
+                        found.push((symbol.clone(), *loc, others));
+                    }
+                }
+            }
+        }
+
+        found.sort_by_key(|&(_, loc, _)| loc);
+        found.dedup_by_key(|&mut (_, loc, _)| loc);
+        out.extend(found);
+    }
+
+    /// ほかのシンボルを隠している定義を集める。
+    ///
+    /// (シンボル, 定義箇所, 隠されているシンボル) のリストを返す。次の2種類を報告する:
+    ///
+    /// - `#deffunc` などのパラメータと同じ名前の変数が、同じファイルの同じモジュールで使われている。
+    /// - 標準命令などの組み込みのシンボルと同じ名前のシンボルを定義している。
+    pub(crate) fn diagnose_shadowing(&mut self, out: &mut Vec<(SymbolRc, Loc, SymbolRc)>) {
+        self.compute();
+
+        fn module_of(symbol: &SymbolRc) -> Option<ModuleKey> {
+            match &symbol.scope_opt {
+                Some(Scope::Local(local)) => local.module_opt,
+                _ => None,
+            }
+        }
+
+        let mut found = vec![];
+        for p in &self.projects {
+            // (ファイル, モジュール, 名前) ごとの変数
+            let mut vars: HashMap<(DocId, Option<ModuleKey>, RcStr), SymbolRc> = HashMap::new();
+            for (symbol, loc) in p.def_sites.iter().chain(&p.use_sites) {
+                if symbol.kind == HspSymbolKind::StaticVar {
+                    vars.entry((loc.doc, module_of(symbol), symbol.name()))
+                        .or_insert_with(|| symbol.clone());
+                }
+            }
+
+            for (doc, symbols) in &p.doc_symbols_map {
+                if !p.active_docs.contains(doc) || !self.is_in_workspace(p, *doc) {
+                    continue;
+                }
+
+                for symbol in symbols {
+                    let loc = match symbol.preproc_def_site_opt {
+                        Some(it) => it,
+                        None => continue,
+                    };
+
+                    let shadowed_opt = match symbol.kind {
+                        HspSymbolKind::Param(_) => {
+                            vars.get(&(*doc, module_of(symbol), symbol.name())).cloned()
+                        }
+                        HspSymbolKind::Label | HspSymbolKind::Module => None,
+                        _ => self.host.builtin_env.get(&symbol.name),
+                    };
+                    if let Some(shadowed) = shadowed_opt {
+                        found.push((symbol.clone(), loc, shadowed));
+                    }
+                }
+            }
+        }
+
+        found.sort_by_key(|&(_, loc, _)| loc);
+        found.dedup_by_key(|&mut (_, loc, _)| loc);
+        out.extend(found);
+    }
+
+    /// モジュールのフィールドが `#modfunc` などの外で使われている箇所を集める。
+    pub(crate) fn diagnose_field_misuses(&mut self, sites: &mut Vec<(SymbolRc, Loc)>) {
+        self.compute();
+
+        let mut found = vec![];
+        for p in &self.projects {
+            for (symbol, loc) in p.def_sites.iter().chain(&p.use_sites) {
+                if symbol.kind != HspSymbolKind::Field
+                    || symbol.preproc_def_site_opt == Some(*loc)
+                    || !self.is_in_workspace(p, loc.doc)
+                {
+                    continue;
+                }
+
+                let da = match self.doc_analysis_map.get(&loc.doc) {
+                    Some(it) => it,
+                    None => continue,
+                };
+                if !deffunc_takes_modvar_at(&da.deffunc_map, loc.start().into()) {
+                    found.push((symbol.clone(), *loc));
+                }
+            }
+        }
+
+        found.sort_by_key(|&(_, loc)| loc);
+        found.dedup_by_key(|&mut (_, loc)| loc);
+        sites.extend(found);
+    }
+
+    /// ワークスペース全体で一度も使用されていないシンボルと、その定義箇所を集める。
+    ///
+    /// 共通フォルダのファイルやデータファイルで定義されたシンボルは含まない。
+    /// 複数のプロジェクトに含まれるシンボルは、どのプロジェクトでも使用されていないときだけ報告する。
+    pub(crate) fn diagnose_unused_symbols(&mut self, symbols: &mut Vec<(SymbolRc, Loc)>) {
+        self.compute();
+
+        let used = self
+            .projects
+            .iter()
+            .flat_map(|p| p.use_sites.iter().map(|(symbol, _)| symbol))
+            .collect::<HashSet<_>>();
+
+        let mut done = HashSet::new();
+        for p in &self.projects {
+            for (symbol, def_loc) in &p.def_sites {
+                let target = match symbol.kind {
+                    HspSymbolKind::StaticVar
+                    | HspSymbolKind::Const
+                    | HspSymbolKind::Enum
+                    | HspSymbolKind::Macro { .. }
+                    | HspSymbolKind::DefFunc
+                    | HspSymbolKind::DefCFunc
+                    | HspSymbolKind::ModFunc
+                    | HspSymbolKind::ModCFunc
+                    | HspSymbolKind::Label => true,
+                    _ => false,
+                };
+                if !target || !done.insert(symbol) {
+                    continue;
+                }
+
+                if !self.is_in_workspace(p, def_loc.doc) || used.contains(symbol) {
+                    continue;
+                }
+
+                symbols.push((symbol.clone(), *def_loc));
+            }
+        }
+    }
+
+    /// 配列が前と異なる型の配列として確保し直されている箇所を集める。
+    ///
+    /// (変数, 直前の確保, 確保し直している箇所) のリストを返す。
+    /// 共通フォルダのファイルやデータファイルの中の箇所は含まない。
+    pub(crate) fn diagnose_array_type_changes(
+        &mut self,
+        changes: &mut Vec<(SymbolRc, ArrayDecl, ArrayDecl)>,
+    ) {
+        self.compute();
+
+        let mut done = HashSet::new();
+        for p in &self.projects {
+            for (symbol, decls) in &p.array_decls {
+                for pair in decls.windows(2) {
+                    let (prev, decl) = (&pair[0], &pair[1]);
+                    if prev.ty == decl.ty || !done.insert(decl.loc) {
+                        continue;
+                    }
+
+                    if !self.is_in_workspace(p, decl.loc.doc) {
+                        continue;
+                    }
+
+                    changes.push((symbol.clone(), prev.clone(), decl.clone()));
+                }
+            }
+        }
+        changes.sort_by_key(|(_, _, decl)| decl.loc);
+    }
+}
+
+pub(crate) enum DocBlockKind {
+    /// モジュール (表示用の名前)
+    Module(RcStr),
+    DefFunc,
+}
+
+/// モジュールや `#deffunc` 系命令の範囲
+pub(crate) struct DocBlock {
+    pub(crate) kind: DocBlockKind,
+    /// `#` から範囲の終わりまで
+    pub(crate) loc: Loc,
+}
+
+pub(crate) struct SymbolStat {
+    pub(crate) symbol: SymbolRc,
+    pub(crate) def_loc: Loc,
+    pub(crate) use_count: usize,
+    /// 定義しているドキュメントが最後に編集された時刻
+    pub(crate) last_edited_opt: Option<SystemTime>,
 }
 
 pub(crate) struct DocSyntax<'a> {
@@ -1,20 +1,43 @@
+pub(crate) mod build_options;
+pub(crate) mod code_lens;
 pub(crate) mod completion;
 pub(crate) mod definitions;
 pub(crate) mod diagnose;
 pub(crate) mod document_highlight;
+pub(crate) mod document_link;
 pub(crate) mod document_symbol;
+pub(crate) mod dump_symbols;
+pub(crate) mod folding_range;
 pub(crate) mod formatting;
 pub(crate) mod hover;
+pub(crate) mod implementation;
+pub(crate) mod include_graph;
+pub(crate) mod include_tree;
+pub(crate) mod inlay_hint;
+pub(crate) mod linked_editing_range;
+pub(crate) mod module_visibility;
+pub(crate) mod moniker;
+pub(crate) mod on_type_formatting;
 pub(crate) mod references;
 pub(crate) mod rename;
+pub(crate) mod rename_files;
+pub(crate) mod replace_in_strings;
+pub(crate) mod selection_range;
 pub(crate) mod semantic_tokens;
 pub(crate) mod signature_help;
+pub(crate) mod snapshot;
+pub(crate) mod symbol_stats;
+pub(crate) mod type_definition;
+pub(crate) mod workspace_stats;
 pub(crate) mod workspace_symbol;
 
 pub(crate) mod rewrites {
     use super::*;
 
+    pub(crate) mod add_include;
+    pub(crate) mod create_include_file;
     pub(crate) mod flip_comma;
+    pub(crate) mod generate_deffunc;
     pub(crate) mod generate_include_guard;
 }
 
@@ -34,6 +57,37 @@ fn plain_text_to_marked_string(value: String) -> MarkedString {
     })
 }
 
+/// シンボルの説明のうち、タグで書かれた部分 (非推奨、パラメータ、戻り値) をテキストにする。
+fn details_tag_sections(details: &SymbolDetails) -> Vec<String> {
+    let mut sections = vec![];
+
+    if let Some(reason) = &details.deprecated_opt {
+        sections.push(if reason.is_empty() {
+            "非推奨".to_string()
+        } else {
+            format!("非推奨: {}", reason)
+        });
+    }
+
+    if !details.params.is_empty() {
+        let mut s = "パラメータ:".to_string();
+        for (name, desc) in &details.params {
+            if desc.is_empty() {
+                s += &format!("\n- {}", name);
+            } else {
+                s += &format!("\n- {}: {}", name, desc);
+            }
+        }
+        sections.push(s);
+    }
+
+    if let Some(returns) = &details.returns_opt {
+        sections.push(format!("戻り値: {}", returns));
+    }
+
+    sections
+}
+
 fn markdown_marked_string(value: String) -> MarkedString {
     MarkedString::LanguageString(LanguageString {
         language: "markdown".to_string(),
@@ -59,6 +113,23 @@ fn loc_to_location(loc: Loc, docs: &Docs) -> Option<Location> {
     Some(Location { uri, range })
 }
 
+/// 1つのドキュメントに対する編集からなる `WorkspaceEdit` を作る。
+fn new_doc_workspace_edit(
+    uri: Url,
+    version: Option<i32>,
+    edits: Vec<lsp_types::TextEdit>,
+) -> lsp_types::WorkspaceEdit {
+    lsp_types::WorkspaceEdit {
+        document_changes: Some(lsp_types::DocumentChanges::Edits(vec![
+            lsp_types::TextDocumentEdit {
+                text_document: lsp_types::OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(lsp_types::OneOf::Left).collect(),
+            },
+        ])),
+        ..lsp_types::WorkspaceEdit::default()
+    }
+}
+
 fn from_document_position(uri: &Url, position: Position, docs: &Docs) -> Option<(DocId, Pos16)> {
     let uri = CanonicalUri::from_url(uri);
     let doc = docs.find_by_uri(&uri)?;
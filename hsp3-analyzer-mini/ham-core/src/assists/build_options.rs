@@ -0,0 +1,145 @@
+//! プロジェクトごとのビルドオプション (`ham/buildOptions` リクエスト)
+
+use super::*;
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProjectBuildOptions {
+    entry_point: Url,
+    /// `#runtime` で指定されたランタイムの名前 (デバッガーやコンパイラーを起動するときに使う。)
+    ///
+    /// `#runtime` がなければ設定の `target` を使う。
+    runtime: Option<String>,
+    options: Vec<BuildOptionItem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BuildOptionItem {
+    /// `"packopt"`, `"runtime"`, `"cmpopt"`, `"bootopt"` のいずれか
+    directive: &'static str,
+    name: Option<String>,
+    value: Option<String>,
+    location: Location,
+}
+
+pub(crate) fn build_options(
+    target: Option<&str>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Vec<ProjectBuildOptions> {
+    let mut projects = vec![];
+    wa.collect_build_options(&mut projects);
+
+    projects
+        .into_iter()
+        .filter_map(|(entrypoint, options)| {
+            // エントリーポイントに書かれたものを優先する。
+            let runtime = options
+                .iter()
+                .find_map(|option| match option {
+                    BuildOption {
+                        directive: BuildDirective::Runtime,
+                        value_opt: Some((BuildOptionValue::Str(name), _)),
+                        ..
+                    } => Some(name.to_string()),
+                    _ => None,
+                })
+                .or_else(|| target.map(|target| target.to_string()));
+
+            Some(ProjectBuildOptions {
+                entry_point: docs.get_uri(entrypoint)?.clone().into_url(),
+                runtime,
+                options: options
+                    .into_iter()
+                    .filter_map(|option| {
+                        Some(BuildOptionItem {
+                            directive: option.directive.keyword(),
+                            name: option.name_opt.map(|(name, _)| name.to_string()),
+                            value: option.value_opt.map(|(value, _)| value.to_text()),
+                            location: loc_to_location(option.loc, docs)?,
+                        })
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::Url;
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("build_options.hsp");
+        ls.open_doc(
+            dummy_url("build_options_lib.as"),
+            NO_VERSION,
+            "#runtime \"hsp3dish\"\n#cmpopt varinit 1\n".into(),
+        );
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"build_options_lib.as\"\n#packopt name \"app\"\n#runtime \"hsp3cl\"\n"
+                .into(),
+        );
+
+        let projects = ls.build_options();
+        let project = projects
+            .iter()
+            .find(|p| p.entry_point == main_uri)
+            .expect("project");
+        assert_eq!(project.runtime.as_deref(), Some("hsp3cl"));
+
+        let summary = project
+            .options
+            .iter()
+            .map(|item| {
+                (
+                    item.directive,
+                    item.name.as_deref(),
+                    item.value.as_deref(),
+                    item.location.uri == main_uri,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("packopt", Some("name"), Some("app"), true),
+                ("runtime", None, Some("hsp3cl"), true),
+                ("runtime", None, Some("hsp3dish"), false),
+                ("cmpopt", Some("varinit"), Some("1"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_target("hsp3dish".into());
+
+        let main_uri = dummy_url("build_options_target.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#packopt name \"app\"\n".into(),
+        );
+
+        // `#runtime` がなければ設定のランタイムを使う。
+        let projects = ls.build_options();
+        let project = projects
+            .iter()
+            .find(|p| p.entry_point == main_uri)
+            .expect("project");
+        assert_eq!(project.runtime.as_deref(), Some("hsp3dish"));
+    }
+}
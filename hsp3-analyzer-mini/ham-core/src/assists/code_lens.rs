@@ -0,0 +1,139 @@
+//! コードレンズ (定義の上に表示される参照数)
+//!
+//! 参照数の計算は重いので、`codeLens` では位置だけを返し、`codeLens/resolve` で計算する。
+
+use super::*;
+use lsp_types::{CodeLens, Command, Position, Url};
+use serde::{Deserialize, Serialize};
+
+/// 参照の一覧を表示するためのクライアント側のコマンド
+///
+/// 引数は LSP の `Url`, `Position`, `Location[]` で、VSCode 拡張機能 (extension.ts) が
+/// VSCode の型に変換してから `editor.action.showReferences` を呼ぶ。
+const COMMAND_SHOW_REFERENCES: &str = "hsp3-analyzer-mini.showReferences";
+
+#[derive(Serialize, Deserialize)]
+struct CodeLensData {
+    // 参照数を計算するためのデータ:
+    uri: Url,
+    position: Position,
+}
+
+/// 参照数を表示する種類のシンボルか？
+fn kind_is_target(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc
+        | HspSymbolKind::Module => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn code_lens(
+    uri: Url,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<CodeLens>> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+
+    let mut symbols = vec![];
+    wa.require_project_for_doc(doc)
+        .collect_doc_symbols(doc, &mut symbols);
+
+    let mut lenses = symbols
+        .into_iter()
+        .filter(|(symbol, _)| kind_is_target(symbol.kind))
+        .map(|(_, loc)| {
+            let range = loc_to_range(loc);
+            let data = CodeLensData {
+                uri: uri.clone(),
+                position: range.start,
+            };
+            CodeLens {
+                range,
+                command: None,
+                data: Some(serde_json::to_value(&data).unwrap()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    lenses.sort_by_key(|lens| lens.range.start);
+    lenses.dedup_by_key(|lens| lens.range.start);
+    Some(lenses)
+}
+
+pub(crate) fn code_lens_resolve(
+    mut lens: CodeLens,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<CodeLens> {
+    let CodeLensData { uri, position } = lens
+        .data
+        .take()
+        .and_then(|data| serde_json::from_value(data).ok())?;
+
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+    let project = wa.require_project_for_doc(doc);
+    let (symbol, _) = project.locate_symbol(doc, pos)?;
+
+    let mut locs = vec![];
+    project.collect_symbol_uses(&symbol, &mut locs);
+    locs.sort();
+    locs.dedup();
+
+    let locations = locs
+        .into_iter()
+        .filter_map(|loc| loc_to_location(loc, docs))
+        .collect::<Vec<_>>();
+
+    lens.command = Some(Command {
+        title: format!("{} 件の参照", locations.len()),
+        command: COMMAND_SHOW_REFERENCES.to_string(),
+        arguments: Some(vec![
+            serde_json::to_value(&uri).unwrap(),
+            serde_json::to_value(position).unwrap(),
+            serde_json::to_value(&locations).unwrap(),
+        ]),
+    });
+    Some(lens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_code_lens() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("code_lens.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module m\n#deffunc f\n\treturn\n#deffunc g\n\treturn\n#global\n\tf\n\tf\n".into(),
+        );
+
+        let lenses = ls.code_lens(uri);
+        assert_eq!(
+            lenses
+                .iter()
+                .map(|lens| (lens.range.start.line, lens.command.is_none()))
+                .collect::<Vec<_>>(),
+            vec![(0, true), (1, true), (3, true)]
+        );
+
+        let titles = lenses
+            .into_iter()
+            .skip(1)
+            .map(|lens| {
+                let command = ls.code_lens_resolve(lens).command.unwrap();
+                assert_eq!(command.command, "hsp3-analyzer-mini.showReferences");
+                command.title
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["2 件の参照", "0 件の参照"]);
+    }
+}
@@ -1,13 +1,16 @@
 use super::*;
 use crate::{
-    analysis::{HspSymbolKind, LocalScope, Scope, SymbolRc},
+    analysis::{describe_var_types, HspSymbolKind, LocalScope, Scope, SymbolRc},
     assists::from_document_position,
     lang_service::docs::Docs,
     parse::{p_param_ty::PParamCategory, PToken},
     source::*,
     token::TokenKind,
 };
-use lsp_types::{CompletionItem, CompletionItemKind, CompletionList, Documentation, Position, Url};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionItemTag,
+    CompletionList, CompletionTextEdit, Documentation, InsertTextFormat, Position, TextEdit, Url,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashSet;
@@ -52,6 +55,221 @@ pub(crate) fn in_preproc(pos: Pos16, tokens: &[PToken]) -> bool {
     }
 }
 
+/// カーソルが `#include "..."` の引用符の中にあるなら、引用符の直後の位置と、カーソルまでに入力されたパスを返す。
+pub(crate) fn include_path_prefix(pos: Pos16, tokens: &[PToken]) -> Option<(Pos16, String)> {
+    let i = match tokens.binary_search_by_key(&pos, |token| token.body_pos16()) {
+        Ok(i) | Err(i) => i.checked_sub(1)?,
+    };
+
+    let token = &tokens[i];
+    let range = token.body.loc.range;
+    if token.kind() != TokenKind::Str || !(range.start() < pos && pos <= range.end()) {
+        return None;
+    }
+
+    // 直前のトークンが `#include` か？
+    let keyword = tokens.get(i.checked_sub(1)?)?;
+    let hash = tokens.get(i.checked_sub(2)?)?;
+    let is_include = hash.kind() == TokenKind::Hash
+        && keyword.kind() == TokenKind::Ident
+        && ["include", "addition"].contains(&keyword.body_text());
+    if !is_include {
+        return None;
+    }
+
+    // 開き引用符の後ろからカーソルまでの文字列を取り出す。
+    let start = Pos16::from(range.start());
+    let start = Pos16::new(start.row, start.column + 1);
+    if pos.row != start.row || pos.column < start.column {
+        return None;
+    }
+    let len = (pos.column - start.column) as usize;
+
+    let mut prefix = String::new();
+    let mut n = 0;
+    for c in token.body_text().chars().skip(1) {
+        if n >= len {
+            break;
+        }
+        n += c.len_utf16();
+        prefix.push(c);
+    }
+    Some((start, prefix.replace('\\', "/")))
+}
+
+/// カーソル位置にある (カーソルが末尾にあるものを含む) 識別子のトークンを探す。
+fn ident_at(pos: Pos16, tokens: &[PToken]) -> Option<&PToken> {
+    let i = match tokens.binary_search_by_key(&pos, |token| token.body_pos16()) {
+        Ok(i) => i,
+        Err(i) => i.checked_sub(1)?,
+    };
+
+    // カーソルが識別子の末尾にあるときは、次のトークンの先頭にも一致する。
+    tokens[i.saturating_sub(1)..=i].iter().rev().find(|token| {
+        token.kind() == TokenKind::Ident && token.body.loc.range.contains_inclusive(pos)
+    })
+}
+
+/// カーソル位置にある識別子の、カーソルより前の部分を返す。
+fn word_prefix(pos: Pos16, tokens: &[PToken]) -> Option<&str> {
+    let token = ident_at(pos, tokens)?;
+    let range = token.body.loc.range;
+
+    // 識別子は1行に収まっているので、列の差だけを考えればいい。
+    let len = (pos.column - Pos16::from(range.start()).column) as usize;
+    let text = token.body_text();
+    let mut n = 0;
+    let end = text
+        .char_indices()
+        .find(|&(_, c)| {
+            let done = n >= len;
+            n += c.len_utf16();
+            done
+        })
+        .map_or(text.len(), |(i, _)| i);
+    Some(&text[..end])
+}
+
+/// 候補が単語の区切りの直後で始まるか？ (先頭、`_` の直後、小文字から大文字への変わり目)
+fn is_word_start(prev_opt: Option<char>, c: char) -> bool {
+    match prev_opt {
+        None => true,
+        Some(prev) => {
+            (!prev.is_ascii_alphanumeric() && c.is_ascii_alphanumeric())
+                || (prev.is_ascii_lowercase() && c.is_ascii_uppercase())
+        }
+    }
+}
+
+/// パターンの文字が候補の中に (大文字・小文字を区別せず) 順番に現れるなら、一致の良さを表すスコアを返す。
+///
+/// 単語の先頭や連続した文字での一致を高く評価する。例えば `gmsg` は `gui_message` に一致する。
+fn fuzzy_score(pattern: &str, candidate: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut chars = candidate.chars().enumerate().peekable();
+    let mut prev_opt: Option<char> = None;
+    let mut last_match_opt: Option<usize> = None;
+
+    for p in pattern.chars() {
+        loop {
+            let (i, c) = chars.next()?;
+            let word_start = is_word_start(prev_opt, c);
+            prev_opt = Some(c);
+
+            if !c.eq_ignore_ascii_case(&p) {
+                continue;
+            }
+
+            score += 1;
+            if word_start {
+                score += 10;
+            }
+            if last_match_opt.map(|last| last + 1) == Some(i) {
+                score += 5;
+            }
+            last_match_opt = Some(i);
+            break;
+        }
+    }
+
+    // 前方一致は特に高く評価する。
+    if candidate.len() >= pattern.len()
+        && candidate.is_char_boundary(pattern.len())
+        && candidate[..pattern.len()].eq_ignore_ascii_case(pattern)
+    {
+        score += 100;
+    }
+    Some(score)
+}
+
+/// 補完候補の数の上限。これを超えたときは、入力が進んだときに補完をやり直してもらう。
+const MAX_COMPLETION_ITEMS: usize = 200;
+
+/// 入力中の単語にあいまいに一致する候補だけを残し、一致の良い順に並べる。
+///
+/// クライアント側での絞り込みで候補が消えないように、filterText には入力中の単語を設定する。
+/// (そのため、入力が進んだら補完をやり直してもらう必要がある。)
+fn apply_fuzzy_filter(prefix: &str, list: &mut CompletionList) {
+    if prefix.is_empty() {
+        if list.items.len() > MAX_COMPLETION_ITEMS {
+            list.items.sort_by(|l, r| {
+                let key = |item: &CompletionItem| item.sort_text.clone().unwrap_or_default();
+                key(l).cmp(&key(r))
+            });
+            list.items.truncate(MAX_COMPLETION_ITEMS);
+            list.is_incomplete = true;
+        }
+        return;
+    }
+
+    let mut scored = list
+        .items
+        .drain(..)
+        .filter_map(|item| {
+            let text = item.filter_text.as_deref().unwrap_or(&item.label);
+            Some((fuzzy_score(prefix, text)?, item))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|(l_score, l), (r_score, r)| {
+        r_score
+            .cmp(l_score)
+            .then_with(|| l.sort_text.cmp(&r.sort_text))
+            .then_with(|| l.label.cmp(&r.label))
+    });
+    scored.truncate(MAX_COMPLETION_ITEMS);
+
+    list.items = scored
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, mut item))| {
+            let sort_text = item.sort_text.take().unwrap_or_else(|| item.label.clone());
+            item.sort_text = Some(format!("{:04}{}", i, sort_text));
+            item.filter_text = Some(prefix.to_string());
+            item
+        })
+        .collect();
+    list.is_incomplete = true;
+}
+
+/// `@` で修飾された識別子の補完の文脈
+enum QualContext {
+    /// `xxx@` の `@` の後ろ。モジュール名を補完する。
+    Module { range: lsp_types::Range },
+    /// `xxx@m` の `@` の前。名前空間 m のシンボルを補完する。
+    Symbol { ns: RcStr, range: lsp_types::Range },
+}
+
+/// カーソル位置にある識別子が `@` を含むなら、補完の文脈と置き換える範囲を返す。
+fn qual_context(pos: Pos16, tokens: &[PToken]) -> Option<QualContext> {
+    let token = ident_at(pos, tokens)?;
+    let range = token.body.loc.range;
+
+    let text = token.body_text();
+    let at = text.rfind('@')?;
+
+    // 識別子は1行に収まっているので、列の差だけを考えればいい。
+    let lsp_range = to_lsp_range(range);
+    let at_column = lsp_range.start.character + text[..at].encode_utf16().count() as u32;
+
+    let context = if pos.column > at_column {
+        QualContext::Module {
+            range: lsp_types::Range::new(
+                Position::new(lsp_range.start.line, at_column + 1),
+                lsp_range.end,
+            ),
+        }
+    } else {
+        QualContext::Symbol {
+            ns: token.body.text.slice(at + 1, text.len()),
+            range: lsp_types::Range::new(
+                lsp_range.start,
+                Position::new(lsp_range.start.line, at_column),
+            ),
+        }
+    };
+    Some(context)
+}
+
 fn collect_local_completion_items(
     symbols: &[SymbolRc],
     local: &LocalScope,
@@ -136,14 +354,106 @@ fn to_completion_symbol_kind(kind: HspSymbolKind) -> CompletionItemKind {
     }
 }
 
-fn to_lsp_completion_item(symbol: &SymbolRc) -> CompletionItem {
+/// スニペットのプレースホルダーの中で特別な意味を持つ文字をエスケープする。
+fn escape_snippet_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let '$' | '}' | '\\' = c {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// ユーザー定義の命令・関数について、引数のプレースホルダーを含むスニペットを作る。
+///
+/// 例: `mycmd ${1:x}, ${2:y}` や `myfunc(${1:x})`
+fn to_snippet(symbol: &SymbolRc) -> Option<String> {
+    let is_func = match symbol.kind {
+        HspSymbolKind::DefFunc | HspSymbolKind::ModFunc => false,
+        HspSymbolKind::DefCFunc | HspSymbolKind::ModCFunc => true,
+        _ => return None,
+    };
+
+    let signature_data = symbol.signature_opt()?;
+    if signature_data.params.is_empty() {
+        return None;
+    }
+
+    let placeholders = signature_data
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let text = match (&param.name_opt, &param.ty_opt) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(ty)) => ty.to_str().to_string(),
+                (None, None) => format!("p{}", i + 1),
+            };
+            format!("${{{}:{}}}", i + 1, escape_snippet_text(&text))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let name = escape_snippet_text(&symbol.name);
+    Some(if is_func {
+        format!("{}({})", name, placeholders)
+    } else {
+        format!("{} {}", name, placeholders)
+    })
+}
+
+/// 使用回数をソート順に反映するときの上限
+const USE_COUNT_RANK_LIMIT: usize = 9999;
+
+/// シンボルの説明を補完候補に設定する。(重いので、resolveのときに行う。)
+fn apply_symbol_details(item: &mut CompletionItem, symbol: &SymbolRc, project: ProjectAnalysisRef) {
     let details = symbol.compute_details();
-    let detail = details.desc.map(|s| s.to_string());
-    let documentation = if details.docs.is_empty() {
+    let value_opt = project.const_value(symbol);
+    item.detail = match (value_opt, &details.desc) {
+        (Some(value), Some(desc)) => Some(format!("= {} ; {}", value, desc)),
+        (Some(value), None) => Some(format!("= {}", value)),
+        (None, desc_opt) => desc_opt.as_ref().map(|s| s.to_string()),
+    };
+    if let Some(lib) = &*symbol.lib_opt.borrow() {
+        item.detail = Some(match item.detail.take() {
+            Some(detail) => format!("{} ; {}", lib, detail),
+            None => lib.to_string(),
+        });
+    }
+    if details.deprecated_opt.is_some() {
+        item.tags = Some(vec![CompletionItemTag::DEPRECATED]);
+    }
+
+    let mut sections = details_tag_sections(&details);
+    sections.extend(details.docs);
+    if !details.examples.is_empty() {
+        sections.push(format!("使用例:\r\n{}", details.examples.join("\r\n")));
+    }
+    item.documentation = if sections.is_empty() {
         None
     } else {
-        Some(Documentation::String(details.docs.join("\r\n\r\n")))
+        Some(Documentation::String(sections.join("\r\n\r\n")))
     };
+}
+
+/// シンボルの補完候補を作る。
+///
+/// `use_counts` は (補完を行うドキュメントでの使用回数, プロジェクト全体での使用回数)
+fn to_lsp_completion_item(
+    symbol: &SymbolRc,
+    use_counts: (usize, usize),
+    snippet: bool,
+) -> CompletionItem {
+    // COMのメソッドはインターフェイスごと、DLLの関数はDLLごと、プラグインのコマンドはプラグインごとにまとめる。
+    let group_opt = symbol
+        .com_method_opt
+        .borrow()
+        .as_ref()
+        .and_then(|method| Some(method.interface_opt.as_ref()?.name()))
+        .or_else(|| symbol.lib_opt.borrow().clone())
+        .or_else(|| symbol.plugin_cmd_opt.borrow().as_ref()?.plugin_opt.clone());
 
     let sort_text = {
         let sort_prefix = match (&symbol.scope_opt, symbol.kind) {
@@ -157,15 +467,30 @@ fn to_lsp_completion_item(symbol: &SymbolRc) -> CompletionItem {
             (Some(Scope::Global), _) => 'e',
             (None, _) => 'g',
         };
-        Some(format!("{}{}", sort_prefix, symbol.name))
+        // 同じ分類の中では、よく使われているシンボルを先に並べる。
+        // (このドキュメントでの使用回数を優先し、同じならプロジェクト全体での使用回数で比べる。)
+        let rank = |count: usize| USE_COUNT_RANK_LIMIT - count.min(USE_COUNT_RANK_LIMIT);
+        let (doc_use_count, use_count) = use_counts;
+        let rank = format!("{:04}{:04}", rank(doc_use_count), rank(use_count));
+        match &group_opt {
+            Some(group) => Some(format!("{}{}:{}{}", sort_prefix, group, rank, symbol.name)),
+            None => Some(format!("{}{}{}", sort_prefix, rank, symbol.name)),
+        }
     };
 
+    let snippet_opt = if snippet { to_snippet(symbol) } else { None };
+    let insert_text_format = snippet_opt.as_ref().map(|_| InsertTextFormat::SNIPPET);
+
     CompletionItem {
         kind: Some(to_completion_symbol_kind(symbol.kind)),
         label: symbol.name.to_string(),
-        detail,
-        documentation,
+        label_details: group_opt.map(|group| CompletionItemLabelDetails {
+            detail: None,
+            description: Some(group.to_string()),
+        }),
         sort_text,
+        insert_text: snippet_opt,
+        insert_text_format,
         ..CompletionItem::default()
     }
 }
@@ -184,9 +509,180 @@ pub(crate) fn incomplete_completion_list() -> CompletionList {
     }
 }
 
+/// 説明はresolveで計算するので、シンボルを復元するための情報を付与する。
+fn set_symbol_data(item: &mut CompletionItem, symbol: &SymbolRc, uri: &Url, docs: &Docs) {
+    let def_site_opt = symbol
+        .def_site_opt()
+        .and_then(|loc| Some((docs.get_uri(loc.doc)?.clone().into_url(), loc)));
+    if let Some((def_uri, def_loc)) = def_site_opt {
+        let data = CompletionData::Symbol {
+            uri: uri.clone(),
+            def_uri,
+            def_position: loc_to_range(def_loc).start,
+        };
+        item.data = Some(serde_json::to_value(&data).unwrap());
+    }
+}
+
+/// `@` で修飾された識別子の補完を行う。
+///
+/// `@` の後ろではモジュール名を、`@m` の前ではモジュール m の名前空間に属すシンボルを候補にする。
+fn do_qual_completion(
+    uri: &Url,
+    doc: DocId,
+    pos: Pos16,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<CompletionList> {
+    let context = {
+        let DocSyntax { tokens, .. } = wa.get_syntax(doc)?;
+        qual_context(pos, &tokens)?
+    };
+
+    let p = wa.require_project_for_doc(doc);
+    let mut items = vec![];
+    match context {
+        QualContext::Module { range } => {
+            let mut names = vec![];
+            p.collect_module_names(&mut names);
+            names.sort();
+
+            for name in names {
+                // 名前のないモジュールは、コンパイラがつけた名前であることを示す。
+                let label_details = if p.is_unnamed_module_name(&name) {
+                    Some(CompletionItemLabelDetails {
+                        detail: None,
+                        description: Some("名前のないモジュール".to_string()),
+                    })
+                } else {
+                    None
+                };
+                items.push(CompletionItem {
+                    label: name.to_string(),
+                    label_details,
+                    kind: Some(CompletionItemKind::MODULE),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range,
+                        new_text: name.to_string(),
+                    })),
+                    ..CompletionItem::default()
+                });
+            }
+        }
+        QualContext::Symbol { ns, range } => {
+            let mut symbols = vec![];
+            p.collect_ns_symbols(&ns, &mut symbols);
+
+            let mut doc_use_counts = HashMap::new();
+            p.collect_doc_use_counts(doc, &mut doc_use_counts);
+
+            for (name, symbol) in symbols {
+                let use_counts = (
+                    doc_use_counts.get(&symbol).copied().unwrap_or(0),
+                    p.use_count(&symbol),
+                );
+                // 修飾子の前に挿入するので、スニペットは使わない。
+                let mut item = to_lsp_completion_item(&symbol, use_counts, false);
+                item.label = name.to_string();
+                item.text_edit = Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: name.to_string(),
+                }));
+                set_symbol_data(&mut item, &symbol, uri, docs);
+                items.push(item);
+            }
+        }
+    }
+
+    Some(new_completion_list(items))
+}
+
+/// `#include` のファイル名の補完を行う。
+///
+/// ワークスペース内のファイルとcommonディレクトリのファイルを候補にする。
+/// 入力中のパスより深い階層にあるファイルは、ディレクトリ名を候補にする。
+fn do_include_completion(
+    doc: DocId,
+    pos: Pos16,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<CompletionList> {
+    let (start, prefix) = wa.include_path_prefix(doc, pos)?;
+
+    let base_dir = docs
+        .get_uri(doc)
+        .and_then(|uri| uri.to_file_path())
+        .and_then(|path| Some(path.parent()?.to_path_buf()));
+
+    let mut paths = vec![];
+    if let Some(base_dir) = &base_dir {
+        for (d, uri) in docs.iter() {
+            // commonディレクトリのファイルは下で名前によって候補にする。
+            if d == doc || wa.common_doc_name(d).is_some() {
+                continue;
+            }
+            if let Some(path) = uri
+                .to_file_path()
+                .and_then(|path| rewrites::add_include::relative_path(base_dir, &path))
+            {
+                paths.push(path);
+            }
+        }
+    }
+    wa.collect_common_doc_names(&mut paths);
+
+    // 入力中のパスのうち、最後の `/` までがディレクトリ、それ以降がファイル名の一部
+    let dir_len = prefix.rfind('/').map_or(0, |i| i + 1);
+    let (dir, _) = prefix.split_at(dir_len);
+    let name_start = Pos16::new(start.row, start.column + dir.encode_utf16().count() as u32);
+    let range = lsp_types::Range::new(
+        Position::new(name_start.row, name_start.column),
+        Position::new(pos.row, pos.column),
+    );
+
+    let mut items = vec![];
+    let mut done = HashSet::new();
+    for path in &paths {
+        let lower = path.to_ascii_lowercase();
+        if !(lower.ends_with(".as") || lower.ends_with(".hsp")) {
+            continue;
+        }
+
+        let rest = match path.strip_prefix(dir) {
+            Some(it) => it,
+            None => continue,
+        };
+
+        let (label, is_dir) = match rest.find('/') {
+            Some(i) => (&rest[..i], true),
+            None => (rest, false),
+        };
+        if label.is_empty() || !done.insert((label.to_string(), is_dir)) {
+            continue;
+        }
+
+        let (kind, new_text, sort_prefix) = if is_dir {
+            (CompletionItemKind::FOLDER, format!("{}/", label), 'a')
+        } else {
+            (CompletionItemKind::FILE, label.to_string(), 'b')
+        };
+        items.push(CompletionItem {
+            label: label.to_string(),
+            kind: Some(kind),
+            // ディレクトリを先に並べる。
+            sort_text: Some(format!("{}{}", sort_prefix, label)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+            ..CompletionItem::default()
+        });
+    }
+
+    Some(new_completion_list(items))
+}
+
 fn do_completion(
     uri: &Url,
     position: Position,
+    snippet: bool,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
 ) -> Option<CompletionList> {
@@ -194,10 +690,31 @@ fn do_completion(
 
     let (doc, pos) = from_document_position(uri, position, docs)?;
 
+    if let Some(list) = do_include_completion(doc, pos, docs, wa) {
+        return Some(list);
+    }
+
     if wa.in_str_or_comment(doc, pos).unwrap_or(true) {
         return None;
     }
 
+    if let Some(list) = do_qual_completion(uri, doc, pos, docs, wa) {
+        return Some(list);
+    }
+
+    if let Some(directive) = wa.build_directive_at(doc, pos) {
+        // #packopt などのオプション名を補完する。
+        for spec in directive.option_specs() {
+            items.push(CompletionItem {
+                kind: Some(CompletionItemKind::PROPERTY),
+                label: spec.name.to_string(),
+                detail: Some(spec.detail.to_string()),
+                ..CompletionItem::default()
+            });
+        }
+        return Some(new_completion_list(items));
+    }
+
     if wa.in_preproc(doc, pos).unwrap_or(false) {
         wa.require_project_for_doc(doc)
             .collect_preproc_completion_items(&mut items);
@@ -208,6 +725,23 @@ fn do_completion(
     let p = wa.require_project_for_doc(doc);
     p.collect_completion_items(doc, pos, &mut completion_items);
 
+    // #modfunc の最初の引数には、そのモジュールのインスタンスを持つ変数を優先して補完する。
+    let mut instances = vec![];
+    if let Some(context) = p.get_signature_help_context(doc, pos) {
+        if matches!(
+            context.symbol.kind,
+            HspSymbolKind::ModFunc | HspSymbolKind::ModCFunc
+        ) && context.arg_index == 0
+        {
+            if let Some(module_name) = p.module_name_of_deffunc(&context.symbol) {
+                p.collect_module_instances(&module_name, &mut instances);
+            }
+        }
+    }
+
+    let mut doc_use_counts = HashMap::new();
+    p.collect_doc_use_counts(doc, &mut doc_use_counts);
+
     for item in completion_items {
         match item {
             ACompletionItem::Symbol(symbol) => {
@@ -215,13 +749,27 @@ fn do_completion(
                     continue;
                 }
 
-                items.push(to_lsp_completion_item(&symbol));
+                let use_counts = (
+                    doc_use_counts.get(&symbol).copied().unwrap_or(0),
+                    p.use_count(&symbol),
+                );
+                let mut item = to_lsp_completion_item(&symbol, use_counts, snippet);
+                if instances.contains(&symbol) {
+                    item.sort_text = item.sort_text.map(|text| format!("0{}", text));
+                }
+                set_symbol_data(&mut item, &symbol, uri, docs);
+                items.push(item);
             }
         }
     }
 
     p.collect_hsphelp_completion_items(&mut items);
 
+    // thismod は #modfunc などの中でしか使えない。
+    if !p.takes_modvar_at(doc, pos) {
+        items.retain(|item| item.label != "thismod");
+    }
+
     // HACK: 不要な候補を削除する。(__hspdef__ はスクリプトの記述的にインクルードガードとみなされないので有効なシンボルとして登録されてしまう。)
     if let Some(i) = items.iter().position(|item| item.label == "__hspdef__") {
         items.swap_remove(i);
@@ -241,29 +789,46 @@ fn do_completion(
         });
     }
 
-    Some(new_completion_list(items))
+    let mut list = new_completion_list(items);
+    if let Some(DocSyntax { tokens, .. }) = wa.get_syntax(doc) {
+        let prefix = word_prefix(pos, &tokens).unwrap_or_default();
+        apply_fuzzy_filter(prefix, &mut list);
+    }
+    Some(list)
 }
 
+/// resolveで補完候補の情報を復元するためのデータ
 #[derive(Serialize, Deserialize)]
-struct CompletionData {
-    // completionの結果を復元するためのデータ:
-    uri: Url,
-    position: Position,
-
-    // 元の項目のdata
-    data_opt: Option<Value>,
+#[serde(tag = "kind")]
+enum CompletionData {
+    /// ソースコード上のシンボル
+    Symbol {
+        /// 補完を行ったドキュメント
+        uri: Url,
+        // シンボルの定義箇所:
+        def_uri: Url,
+        def_position: Position,
+    },
+    /// ヘルプファイルの項目
+    HspHelp {
+        /// 補完を行ったドキュメント
+        uri: Url,
+        // 元の項目のdata
+        data_opt: Option<Value>,
+    },
 }
 
 pub(crate) fn completion(
     uri: Url,
     position: Position,
+    snippet: bool,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
 ) -> Option<CompletionList> {
-    let mut completion_list = do_completion(&uri, position, docs, wa)?;
+    let mut completion_list = do_completion(&uri, position, snippet, docs, wa)?;
 
     for item in &mut completion_list.items {
-        if item.documentation.is_none() && item.data.is_none() {
+        if item.documentation.is_none() {
             continue;
         }
 
@@ -273,9 +838,8 @@ pub(crate) fn completion(
 
         // resolveリクエストで使うための情報を付与する。
         let data_opt = item.data.take();
-        let data = CompletionData {
+        let data = CompletionData::HspHelp {
             uri: uri.clone(),
-            position,
             data_opt,
         };
         item.data = Some(serde_json::to_value(&data).unwrap());
@@ -301,20 +865,508 @@ pub(crate) fn completion_resolve(
         }
     };
 
-    // completionの計算を再試行して情報を復元する。(重い)
+    match data {
+        CompletionData::Symbol {
+            uri,
+            def_uri,
+            def_position,
+        } => {
+            let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+            let (def_doc, def_pos) = from_document_position(&def_uri, def_position, docs)?;
 
-    let CompletionData {
-        uri,
-        position,
-        data_opt,
-    } = data;
+            let project = wa.require_project_for_doc(doc);
+            let (symbol, _) = project.locate_symbol(def_doc, def_pos)?;
+            apply_symbol_details(&mut resolved_item, &symbol, project);
 
-    let list = do_completion(&uri, position, docs, wa)?;
-    let item = list
-        .items
-        .into_iter()
-        .find(|i| i.label == resolved_item.label)?;
-    resolved_item.documentation = item.documentation;
-    resolved_item.data = data_opt;
+            if symbol.kind == HspSymbolKind::StaticVar {
+                // 配列として確保されている変数は、最後の確保の形を表示する。
+                let ty_opt = match project.array_decls(&symbol).last() {
+                    Some(decl) => Some(decl.describe(&symbol.name)),
+                    None => describe_var_types(project.var_type_sites(&symbol))
+                        .map(|ty| format!("型: {}", ty)),
+                };
+                if let Some(ty) = ty_opt {
+                    resolved_item.detail = Some(match resolved_item.detail.take() {
+                        Some(desc) => format!("{} ; {}", ty, desc),
+                        None => ty,
+                    });
+                }
+            }
+
+            // commonのファイルで定義されたシンボルを、それをincludeしていないファイルで使うときは、includeを追加する。
+            let mut includes = vec![];
+            project.collect_doc_includes(doc, &mut includes);
+            if def_doc != doc && includes.iter().all(|&(_, d)| d != def_doc) {
+                if let Some(name) = wa.common_doc_name(def_doc).map(|name| name.to_string()) {
+                    let DocSyntax { text, root, .. } = wa.get_syntax(doc)?;
+                    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+                    let position = Position::new(rewrites::add_include::insert_row(root), 0);
+                    resolved_item.additional_text_edits = Some(vec![TextEdit {
+                        range: lsp_types::Range::new(position, position),
+                        new_text: format!("#include \"{}\"{}", name, eol),
+                    }]);
+                }
+            }
+        }
+        CompletionData::HspHelp { uri, data_opt } => {
+            let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+            let project = wa.require_project_for_doc(doc);
+            let item = project.find_hsphelp_completion_item(&resolved_item.label)?;
+            resolved_item.documentation = item.documentation.clone();
+            resolved_item.data = data_opt;
+        }
+    }
     Some(resolved_item)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+
+    #[test]
+    fn test_snippet() {
+        let mut ls = LangService::new_standalone();
+        ls.set_completion_snippet(true);
+
+        let uri = dummy_url("snippet.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc mycmd int x, str y\n\treturn\n#defcfunc myfunc int\n\treturn\n#deffunc noargs\n\treturn\n#global\n\t\n".into(),
+        );
+
+        let list = ls.completion(uri, Position::new(8, 1));
+        let insert_text = |label: &str| {
+            let item = list.items.iter().find(|item| item.label == label).unwrap();
+            (item.insert_text.clone(), item.insert_text_format)
+        };
+
+        assert_eq!(
+            insert_text("mycmd"),
+            (
+                Some("mycmd ${1:x}, ${2:y}".to_string()),
+                Some(InsertTextFormat::SNIPPET)
+            )
+        );
+        assert_eq!(
+            insert_text("myfunc"),
+            (
+                Some("myfunc(${1:int})".to_string()),
+                Some(InsertTextFormat::SNIPPET)
+            )
+        );
+        assert_eq!(insert_text("noargs"), (None, None));
+    }
+
+    #[test]
+    fn test_project_isolation() {
+        let mut ls = LangService::new_standalone();
+        let dummy_root = dummy_root();
+        ls.initialize(Some(Url::from_directory_path(&dummy_root).unwrap()));
+
+        let a_uri = dummy_url("isolation_a.hsp");
+        let b_uri = dummy_url("isolation_b.hsp");
+        ls.open_doc(
+            a_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"isolation_lib.as\"\n#deffunc isolation_a\n\treturn\n\t\n"
+                .into(),
+        );
+        ls.open_doc(
+            b_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"isolation_lib.as\"\n#deffunc isolation_b\n\treturn\n\t\n"
+                .into(),
+        );
+        ls.open_doc(
+            dummy_url("isolation_lib.as"),
+            NO_VERSION,
+            "#deffunc isolation_lib\n\treturn\n".into(),
+        );
+
+        // 別のエントリーポイントに属するシンボルは候補に出ない。
+        let labels = |ls: &mut LangService, uri: Url| {
+            let mut labels = ls
+                .completion(uri, Position::new(4, 1))
+                .items
+                .into_iter()
+                .map(|item| item.label)
+                .filter(|label| label.starts_with("isolation_"))
+                .collect::<Vec<_>>();
+            labels.sort();
+            labels
+        };
+        assert_eq!(labels(&mut ls, a_uri), vec!["isolation_a", "isolation_lib"]);
+        assert_eq!(labels(&mut ls, b_uri), vec!["isolation_b", "isolation_lib"]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("completion_resolve.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n; 挨拶する\n#deffunc greet\n\treturn\n#global\n\t\n".into(),
+        );
+
+        let list = ls.completion(uri, Position::new(5, 1));
+        let item = list
+            .items
+            .into_iter()
+            .find(|item| item.label == "greet")
+            .unwrap();
+
+        // 説明は後から計算する。
+        assert_eq!(item.detail, None);
+        assert!(item.data.is_some());
+
+        let item = ls.completion_resolve(item).unwrap();
+        assert_eq!(item.detail.as_deref(), Some("挨拶する"));
+        assert_eq!(item.data, None);
+    }
+
+    #[test]
+    fn test_resolve_var_type() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("completion_var_type.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tddim ratios, 4\n\tratio = 0.5\n\t\n".into(),
+        );
+
+        let items = ls.completion(uri, Position::new(2, 1)).items;
+        let mut detail_of = |label: &str| {
+            let item = items.iter().find(|item| item.label == label).unwrap();
+            ls.completion_resolve(item.clone()).unwrap().detail
+        };
+        assert_eq!(detail_of("ratio").as_deref(), Some("型: double"));
+        // 配列はその確保の形を表示する。
+        assert_eq!(detail_of("ratios").as_deref(), Some("double ratios(4)"));
+    }
+
+    #[test]
+    fn test_com_methods() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("completion_com_methods.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#usecom IFoo \"{0}\"\n#comfunc IFoo_Run 3\n#usecom IBar \"{1}\"\n#comfunc Bar_Stop 4\n#comfunc Bar_Go 3 int\n\t\n"
+                .into(),
+        );
+
+        let mut items = ls
+            .completion(uri, Position::new(5, 1))
+            .items
+            .into_iter()
+            .filter(|item| item.kind == Some(CompletionItemKind::METHOD))
+            .collect::<Vec<_>>();
+        items.sort_by(|l, r| l.sort_text.cmp(&r.sort_text));
+
+        // メソッドはインターフェイスごとにまとまって並ぶ。
+        assert_eq!(
+            items
+                .iter()
+                .map(|item| (
+                    item.label.as_str(),
+                    item.label_details
+                        .as_ref()
+                        .and_then(|d| d.description.as_deref())
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Bar_Go", Some("IBar")),
+                ("Bar_Stop", Some("IBar")),
+                ("IFoo_Run", Some("IFoo")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_const_value() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("completion_const_value.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "; 既定の幅\n#const DEFAULT_WIDTH 640\n#const HALF_WIDTH DEFAULT_WIDTH / 2\n\t\n"
+                .into(),
+        );
+
+        let items = ls.completion(uri, Position::new(3, 1)).items;
+        let mut detail = |label: &str| {
+            let item = items.iter().find(|item| item.label == label).unwrap();
+            ls.completion_resolve(item.clone()).unwrap().detail
+        };
+
+        // 定数の値を表示する。
+        assert_eq!(detail("DEFAULT_WIDTH").as_deref(), Some("= 640 ; 既定の幅"));
+        assert_eq!(detail("HALF_WIDTH").as_deref(), Some("= 320"));
+    }
+
+    #[test]
+    fn test_qualified_name() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("qualified_name.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module m_hoge\n#deffunc local greet\n\tcounter = 1\n#global\n\tgreet@m_hoge\n".into(),
+        );
+
+        let mut labels = |position: Position| {
+            let mut labels = ls
+                .completion(uri.clone(), position)
+                .items
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>();
+            labels.sort();
+            labels
+        };
+
+        // `@` の後ろではモジュール名
+        assert_eq!(labels(Position::new(4, 8)), vec!["m_hoge".to_string()]);
+
+        // `@m_hoge` の前ではモジュールのシンボル
+        assert_eq!(
+            labels(Position::new(4, 3)),
+            vec!["counter".to_string(), "greet".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_unnamed_module_names() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let uri = dummy_url("completion_unnamed_main.hsp");
+        ls.open_doc(
+            dummy_url("completion_unnamed_sub.as"),
+            NO_VERSION,
+            "#module\n\tsub_value = 1\n#global\n".into(),
+        );
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#module\n\tmain_value = 1\n#global\n#include \"completion_unnamed_sub.as\"\n\tmes sub_value@M1\n\tmes main_value@M0\n".into(),
+        );
+
+        let mut items = |position: Position| {
+            let mut items = ls
+                .completion(uri.clone(), position)
+                .items
+                .into_iter()
+                .map(|item| {
+                    let description = item.label_details.and_then(|d| d.description);
+                    (item.label, description)
+                })
+                .collect::<Vec<_>>();
+            items.sort();
+            items
+        };
+
+        // 名前のないモジュールは、includeを展開した順に `M0`, `M1`, ... という名前空間になる。
+        let unnamed = Some("名前のないモジュール".to_string());
+        assert_eq!(
+            items(Position::new(5, 15)),
+            vec![
+                ("M0".to_string(), unnamed.clone()),
+                ("M1".to_string(), unnamed.clone()),
+            ]
+        );
+        assert_eq!(
+            items(Position::new(5, 6)),
+            vec![("sub_value".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn test_module_fields() {
+        let mut ls = LangService::new_standalone();
+        ls.did_initialize();
+        let uri = dummy_url("completion_module_fields.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module m_point px, py\n#modinit\n\tp\n\tth\n\treturn\n#deffunc point_origin\n\tp\n\tth\n\treturn\n#global\n\tp@m_point\n"
+            .into(),
+        );
+
+        let mut has = |position: Position, label: &str| {
+            ls.completion(uri.clone(), position)
+                .items
+                .into_iter()
+                .any(|item| item.label == label)
+        };
+
+        // #modinit の中ではフィールドと thismod が使える。
+        assert!(has(Position::new(2, 2), "px"));
+        assert!(has(Position::new(3, 3), "thismod"));
+
+        // #deffunc の中ではフィールドも thismod も使えない。
+        assert!(!has(Position::new(6, 2), "px"));
+        assert!(!has(Position::new(7, 3), "thismod"));
+
+        // 修飾してもモジュールの外からは使えない。
+        assert!(!has(Position::new(10, 2), "py"));
+    }
+
+    #[test]
+    fn test_module_instances() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("completion_module_instances.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module m_point x\n#modfunc point_move int dx\n\tx += dx\n\treturn\n#global\n\tdim count\n\tnewmod pt, m_point\n\tpoint_move \n"
+                .into(),
+        );
+
+        let mut items = ls
+            .completion(uri, Position::new(7, 12))
+            .items
+            .into_iter()
+            .filter(|item| item.kind == Some(CompletionItemKind::VARIABLE))
+            .collect::<Vec<_>>();
+        items.sort_by(|l, r| l.sort_text.cmp(&r.sort_text));
+
+        // インスタンスを持つ変数が先に並ぶ。
+        assert_eq!(items[0].label, "pt");
+        assert!(items.iter().any(|item| item.label == "count"));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("gmsg", "gui_message").is_some());
+        assert!(fuzzy_score("gmsg", "message").is_none());
+        assert!(fuzzy_score("dm", "drawMap").is_some());
+
+        // 単語の先頭での一致は、途中での一致より高い。
+        assert!(fuzzy_score("gm", "gui_message") > fuzzy_score("gm", "gamma"));
+        // 前方一致はさらに高い。
+        assert!(fuzzy_score("gui", "gui_message") > fuzzy_score("gui", "get_ui_info"));
+    }
+
+    #[test]
+    fn test_fuzzy_completion() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("fuzzy_completion.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tgui_message = 1\n\tmessage = 2\n\tgmsg_x = 3\n\tgmsg\n".into(),
+        );
+
+        let list = ls.completion(uri, Position::new(3, 5));
+        assert!(list.is_incomplete);
+        assert_eq!(
+            list.items
+                .iter()
+                .map(|item| item.label.as_str())
+                .collect::<Vec<_>>(),
+            vec!["gmsg", "gmsg_x", "gui_message"]
+        );
+        assert!(list
+            .items
+            .iter()
+            .all(|item| item.filter_text.as_deref() == Some("gmsg")));
+    }
+
+    #[test]
+    fn test_build_option_names() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("build_option_names.hsp");
+        ls.open_doc(uri.clone(), NO_VERSION, "#cmpopt \n#bootopt u\n".into());
+
+        let mut labels = |position: Position| {
+            let mut labels = ls
+                .completion(uri.clone(), position)
+                .items
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>();
+            labels.sort();
+            labels
+        };
+
+        assert!(labels(Position::new(0, 8)).contains(&"varinit".to_string()));
+        assert_eq!(
+            labels(Position::new(1, 10)),
+            vec!["hsp64", "notimer", "utf8"]
+        );
+    }
+
+    #[test]
+    fn test_use_count_ranking() {
+        let mut ls = LangService::new_standalone();
+        ls.open_doc(
+            dummy_url("ranking_lib.hsp"),
+            NO_VERSION,
+            "#deffunc rank_alpha\n\treturn\n#deffunc rank_beta\n\treturn\n#deffunc rank_gamma\n\treturn\n"
+                .into(),
+        );
+        ls.open_doc(
+            dummy_url("ranking_other.hsp"),
+            NO_VERSION,
+            "\trank_alpha\n\trank_alpha\n\trank_alpha\n".into(),
+        );
+        let uri = dummy_url("ranking_main.hsp");
+        ls.open_doc(uri.clone(), NO_VERSION, "\trank_beta\n\t\n".into());
+
+        // このドキュメントで使われているもの、プロジェクト全体でよく使われているものの順に並ぶ。
+        let mut items = ls
+            .completion(uri, Position::new(1, 1))
+            .items
+            .into_iter()
+            .filter(|item| item.label.starts_with("rank_"))
+            .collect::<Vec<_>>();
+        items.sort_by(|l, r| l.sort_text.cmp(&r.sort_text));
+        let labels = items.into_iter().map(|item| item.label).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["rank_beta", "rank_alpha", "rank_gamma"]);
+    }
+
+    #[test]
+    fn test_include_path() {
+        let mut ls = LangService::new_standalone();
+        let main_uri = dummy_url("main.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "#include \"\"\n#include \"lib/\"\n".into(),
+        );
+        for name in ["a.as", "lib/b.as", "lib/sub/c.hsp", "lib/readme.txt"] {
+            ls.open_doc(dummy_url(name), NO_VERSION, "".into());
+        }
+
+        let mut labels = |position: Position| {
+            let mut labels = ls
+                .completion(main_uri.clone(), position)
+                .items
+                .into_iter()
+                .map(|item| (item.label, item.kind.unwrap()))
+                .collect::<Vec<_>>();
+            labels.sort_by(|l, r| l.0.cmp(&r.0));
+            labels
+        };
+
+        assert_eq!(
+            labels(Position::new(0, 10)),
+            vec![
+                ("a.as".to_string(), CompletionItemKind::FILE),
+                ("lib".to_string(), CompletionItemKind::FOLDER),
+            ]
+        );
+        assert_eq!(
+            labels(Position::new(1, 14)),
+            vec![
+                ("b.as".to_string(), CompletionItemKind::FILE),
+                ("sub".to_string(), CompletionItemKind::FOLDER),
+            ]
+        );
+    }
+}
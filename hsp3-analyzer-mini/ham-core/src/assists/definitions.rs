@@ -11,6 +11,11 @@ fn goto_symbol_definition(
     let project = wa.require_project_for_doc(doc);
     let (symbol, _) = project.locate_symbol(doc, pos)?;
     project.collect_symbol_defs(&symbol, locs);
+
+    // 別名のマクロなら、展開先の命令の定義も候補にする。
+    if let Some(target) = project.resolve_alias(&symbol) {
+        project.collect_symbol_defs(&target, locs);
+    }
     Some(())
 }
 
@@ -48,3 +53,55 @@ pub(crate) fn definitions(
             .collect(),
     )
 }
+
+/// 宣言箇所を探す。別名のマクロについては `#define` の行だけを返す。
+pub(crate) fn declarations(
+    uri: Url,
+    position: Position,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<Location>> {
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    let project = wa.require_project_for_doc(doc);
+    let (symbol, _) = project.locate_symbol(doc, pos)?;
+    let mut locs = vec![];
+    project.collect_symbol_defs(&symbol, &mut locs);
+
+    Some(
+        locs.into_iter()
+            .filter_map(|loc| loc_to_location(loc, docs))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    fn rows(locations: Vec<Location>) -> Vec<u32> {
+        let mut rows = locations
+            .into_iter()
+            .map(|location| location.range.start.line)
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_alias_definition_and_declaration() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("alias.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc greet\n\treturn\n#global\n#define hello greet\n\thello\n".into(),
+        );
+
+        let position = Position::new(5, 2);
+        assert_eq!(rows(ls.definitions(uri.clone(), position)), vec![1, 4]);
+        assert_eq!(rows(ls.declarations(uri, position)), vec![4]);
+    }
+}
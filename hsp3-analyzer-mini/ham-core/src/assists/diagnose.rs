@@ -1,9 +1,22 @@
 use super::*;
 use crate::{
     assists::{loc_to_range, to_lsp_range},
-    lang_service::docs::Docs,
+    lang_service::{docs::Docs, SourceEncoding},
+    utils::read_file::{find_modeline_encoding, has_utf8_marker},
+};
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag,
+    DocumentDiagnosticReport, DocumentDiagnosticReportResult, FullDocumentDiagnosticReport,
+    PreviousResultId, RelatedFullDocumentDiagnosticReport,
+    RelatedUnchangedDocumentDiagnosticReport, UnchangedDocumentDiagnosticReport, Url,
+    WorkspaceDiagnosticReport, WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport,
+    WorkspaceFullDocumentDiagnosticReport, WorkspaceUnchangedDocumentDiagnosticReport,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::PathBuf,
 };
-use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
 
 #[derive(Default)]
 pub(crate) struct DiagnosticsCache {
@@ -11,7 +24,8 @@ pub(crate) struct DiagnosticsCache {
     map2: HashMap<Url, (Option<i32>, String)>,
 }
 
-fn filter_diagnostics(
+/// 前回から変化したdiagnosticsだけを残す。(プッシュ型の通知に使う。)
+pub(crate) fn filter_diagnostics(
     diagnostics: &mut Vec<(Url, Option<i32>, Vec<Diagnostic>)>,
     docs: &Docs,
     cache: &mut DiagnosticsCache,
@@ -19,10 +33,6 @@ fn filter_diagnostics(
     let mut map = take(&mut cache.map1);
     let mut backup = take(&mut cache.map2);
 
-    for (_, _, new) in diagnostics.iter_mut() {
-        new.sort_by_key(|d| (d.range.start, d.range.end));
-    }
-
     diagnostics.retain(|&(ref uri, version, ref new)| {
         let old_opt = map.remove(&uri);
         let new_opt = if !new.is_empty() {
@@ -58,17 +68,164 @@ fn filter_diagnostics(
     cache.map2 = map;
 }
 
-pub(crate) fn diagnose(
+/// モジュールファイル (.as) か？
+fn is_module_file(doc: DocId, docs: &Docs) -> bool {
+    docs.get_uri(doc)
+        .and_then(|uri| uri.to_file_path())
+        .map_or(false, |path| {
+            path.extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("as"))
+        })
+}
+
+/// 使われていなくても報告しないシンボルか？
+///
+/// `global` なシンボルやモジュールファイルで公開されている命令・関数は、
+/// 他のスクリプトから使われるために定義されている可能性がある。
+fn is_exported(symbol: &SymbolRc, def_loc: Loc, docs: &Docs) -> bool {
+    if !matches!(symbol.scope_opt, Some(Scope::Global)) {
+        return false;
+    }
+
+    match symbol.kind {
+        // `#deffunc` などは既定で global になるので、スクリプト本体で定義されたものは報告する。
+        HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc => is_module_file(def_loc.doc, docs),
+        _ => true,
+    }
+}
+
+/// 解決できないincludeのメッセージ。探したディレクトリを列挙する。
+fn unresolved_include_message(
+    name: &str,
+    doc: DocId,
     docs: &Docs,
-    cache: &mut DiagnosticsCache,
+    search_dirs: &[PathBuf],
+) -> String {
+    let base_dir_opt = (docs.get_uri(doc))
+        .and_then(|uri| uri.to_file_path())
+        .and_then(|path| Some(path.parent()?.to_path_buf()));
+
+    let mut message = format!(
+        "includeを解決できません: {:?}\n次のディレクトリを探しました:",
+        name
+    );
+    for dir in base_dir_opt.iter().chain(search_dirs) {
+        message += &format!("\n- {}", dir.display());
+    }
+    message
+}
+
+/// メッセージに表示するドキュメントの名前 (ファイル名)
+fn doc_file_name(doc: DocId, docs: &Docs) -> String {
+    docs.get_uri(doc)
+        .and_then(|uri| uri.to_file_path())
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+        .unwrap_or_else(|| format!("(doc:{})", doc))
+}
+
+/// 循環しているincludeの警告。関連情報として循環の経路を並べる。
+fn include_cycle_diagnostic(cycle: &[(Loc, DocId)], docs: &Docs) -> Option<Diagnostic> {
+    let &(loc, _) = cycle.first()?;
+
+    let mut names = vec![doc_file_name(loc.doc, docs)];
+    names.extend(cycle.iter().map(|&(_, dest)| doc_file_name(dest, docs)));
+
+    let related_information = cycle
+        .iter()
+        .filter_map(|&(l, dest)| {
+            Some(DiagnosticRelatedInformation {
+                location: loc_to_location(l, docs)?,
+                message: format!("{} をincludeしています。", doc_file_name(dest, docs)),
+            })
+        })
+        .collect();
+
+    Some(Diagnostic {
+        message: format!("includeが循環しています: {}", names.join(" → ")),
+        severity: Some(DiagnosticSeverity::WARNING),
+        range: loc_to_range(loc),
+        source: source(),
+        related_information: Some(related_information),
+        ..Default::default()
+    })
+}
+
+/// ワークスペースのdiagnosticsをドキュメントごとに集める。
+///
+/// プッシュ型の通知とプル型のリクエストの両方で使う。
+/// `search_dirs` はincludeするファイルのディレクトリ以外に、includeを探すディレクトリ。
+/// `encoding` は設定された文字コード。(エディタで開かれているドキュメントの文字コードを決めるのに使う。)
+pub(crate) fn collect_diagnostics(
+    docs: &Docs,
+    lint_unused: bool,
+    search_dirs: &[PathBuf],
+    encoding: SourceEncoding,
     wa: &mut WorkspaceAnalysis,
 ) -> Vec<(Url, Option<i32>, Vec<Diagnostic>)> {
     let mut dd = vec![];
-    wa.diagnose(&mut dd);
+    let mut undefined_commands = vec![];
+    wa.diagnose(&mut dd, &mut undefined_commands);
+
+    let mut unresolved_includes = vec![];
+    wa.collect_unresolved_includes(&mut unresolved_includes);
+
+    let mut include_cycles = vec![];
+    wa.collect_include_cycles(&mut include_cycles);
+
+    let mut syntax_errors = vec![];
+    wa.diagnose_syntax_errors(&mut syntax_errors);
 
     let mut lints = vec![];
     wa.diagnose_syntax_lints(&mut lints);
 
+    let mut build_option_issues = vec![];
+    wa.diagnose_build_options(&mut build_option_issues);
+
+    // ファイルから読み込んだドキュメントは読み込んだときの文字コード、
+    // エディタで開かれているものはモードラインか設定で指定された文字コードで保存されるとみなす。
+    // (`#cmpopt utf8` があれば UTF-8 としてコンパイルされる。自動判定なら、それがなければ shift_jis とみなす。)
+    let is_sjis = |doc, text: &str| {
+        let encoding = match docs.get_file_encoding(doc) {
+            Some(file_encoding) => file_encoding.encoding,
+            None if has_utf8_marker(text.as_bytes()) => SourceEncoding::Utf8,
+            None => find_modeline_encoding(text.as_bytes())
+                .and_then(|name| SourceEncoding::from_name(&name))
+                .unwrap_or(encoding),
+        };
+        matches!(encoding, SourceEncoding::Sjis | SourceEncoding::Auto)
+    };
+    let mut sjis_incompatible_chars = vec![];
+    wa.diagnose_sjis_incompatible_chars(is_sjis, &mut sjis_incompatible_chars);
+
+    let mut visibility_issues = vec![];
+    wa.diagnose_module_visibility(&mut visibility_issues);
+
+    let mut missing_guard_includes = vec![];
+    wa.collect_includes_missing_include_guard(&mut missing_guard_includes);
+
+    let mut unresolved_labels = vec![];
+    wa.diagnose_unresolved_labels(&mut unresolved_labels);
+
+    let mut array_type_changes = vec![];
+    wa.diagnose_array_type_changes(&mut array_type_changes);
+
+    let mut field_misuses = vec![];
+    wa.diagnose_field_misuses(&mut field_misuses);
+
+    let mut duplicate_globals = vec![];
+    wa.diagnose_duplicate_globals(&mut duplicate_globals);
+
+    let mut shadowings = vec![];
+    wa.diagnose_shadowing(&mut shadowings);
+
+    let mut unused_symbols = vec![];
+    if lint_unused {
+        wa.diagnose_unused_symbols(&mut unused_symbols);
+    }
+
     let mut map: HashMap<DocId, Vec<Diagnostic>> = HashMap::new();
     for (message, loc) in dd {
         let d = Diagnostic {
@@ -80,9 +237,87 @@ pub(crate) fn diagnose(
         };
         map.entry(loc.doc).or_default().push(d);
     }
+    for (name, loc) in unresolved_includes {
+        let d = Diagnostic {
+            message: unresolved_include_message(name.as_str(), loc.doc, docs, search_dirs),
+            severity: Some(DiagnosticSeverity::ERROR),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+    for cycle in include_cycles {
+        if let Some(d) = include_cycle_diagnostic(&cycle, docs) {
+            map.entry(cycle[0].0.doc).or_default().push(d);
+        }
+    }
+    for (name, loc) in undefined_commands {
+        let d = Diagnostic {
+            message: format!("命令 {} の定義が見つかりません。", name),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+    for (name, loc) in unresolved_labels {
+        let d = Diagnostic {
+            message: format!("ラベル *{} の定義が見つかりません。", name),
+            severity: Some(DiagnosticSeverity::ERROR),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+    for (symbol, loc) in field_misuses {
+        let d = Diagnostic {
+            message: format!(
+                "モジュール変数 {} は #modfunc, #modcfunc, #modinit, #modterm の中でしか使えません。",
+                symbol.name()
+            ),
+            severity: Some(DiagnosticSeverity::ERROR),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+    for (error, loc) in syntax_errors {
+        let d = Diagnostic {
+            message: error.as_str().to_string(),
+            severity: Some(DiagnosticSeverity::ERROR),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
     for (lint, loc) in lints {
+        let severity = match lint {
+            SyntaxLint::UnknownSyntax | SyntaxLint::UnreachableCode => DiagnosticSeverity::HINT,
+            _ => DiagnosticSeverity::WARNING,
+        };
+        let tags = match lint {
+            SyntaxLint::UnreachableCode => Some(vec![DiagnosticTag::UNNECESSARY]),
+            _ => None,
+        };
         let d = Diagnostic {
             message: lint.as_str().to_string(),
+            severity: Some(severity),
+            range: loc_to_range(loc),
+            source: source(),
+            tags,
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (issue, loc) in build_option_issues {
+        let d = Diagnostic {
+            message: issue.message(),
             severity: Some(DiagnosticSeverity::WARNING),
             range: loc_to_range(loc),
             source: source(),
@@ -91,21 +326,1123 @@ pub(crate) fn diagnose(
         map.entry(loc.doc).or_default().push(d);
     }
 
+    for (c, loc) in sjis_incompatible_chars {
+        let d = Diagnostic {
+            message: format!(
+                "文字 {} (U+{:04X}) は shift_jis で表せないため、コンパイルすると ? に置き換わります。",
+                c, c as u32
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for issue in visibility_issues {
+        let loc = issue.def_loc;
+        let d = Diagnostic {
+            message: issue.message(),
+            severity: Some(DiagnosticSeverity::HINT),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (loc, doc) in missing_guard_includes {
+        // モジュールファイル (.as) だけを対象にする。
+        if !is_module_file(doc, docs) {
+            continue;
+        }
+
+        let d = Diagnostic {
+            message: "includeされるファイルは複数回includeされていますが、インクルードガードがないため定義が重複します。".to_string(),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: loc_to_range(loc),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (symbol, prev, decl) in array_type_changes {
+        let related_information = loc_to_location(prev.loc, docs).map(|location| {
+            vec![DiagnosticRelatedInformation {
+                location,
+                message: format!("ここで {} の配列として確保されています。", prev.ty.to_str()),
+            }]
+        });
+        let d = Diagnostic {
+            message: format!(
+                "配列 {} を {} の配列から {} の配列に確保し直しています。",
+                symbol.name(),
+                prev.ty.to_str(),
+                decl.ty.to_str()
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: loc_to_range(decl.loc),
+            source: source(),
+            related_information,
+            ..Default::default()
+        };
+        map.entry(decl.loc.doc).or_default().push(d);
+    }
+
+    for (symbol, loc, others) in duplicate_globals {
+        let related_information = others
+            .into_iter()
+            .filter_map(|other| {
+                Some(DiagnosticRelatedInformation {
+                    location: loc_to_location(other, docs)?,
+                    message: "ここでも定義されています。".to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let d = Diagnostic {
+            message: format!(
+                "{} はほかのファイルでもglobalに定義されています。",
+                symbol.name()
+            ),
+            severity: Some(DiagnosticSeverity::ERROR),
+            range: loc_to_range(loc),
+            source: source(),
+            related_information: Some(related_information),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (symbol, loc, shadowed) in shadowings {
+        let (message, related_information) = match shadowed.kind {
+            HspSymbolKind::StaticVar => (
+                format!(
+                    "パラメータ {} は同じ名前の変数を隠しています。この命令の中では変数を参照できません。",
+                    symbol.name()
+                ),
+                (shadowed.def_site_opt())
+                    .and_then(|def_loc| loc_to_location(def_loc, docs))
+                    .map(|location| {
+                        vec![DiagnosticRelatedInformation {
+                            location,
+                            message: "変数はここで使われています。".to_string(),
+                        }]
+                    }),
+            ),
+            _ => (
+                format!(
+                    "{0} は標準命令などの組み込みの名前と同じです。この定義によって {0} の意味が変わります。",
+                    symbol.name()
+                ),
+                None,
+            ),
+        };
+        let d = Diagnostic {
+            message,
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: loc_to_range(loc),
+            source: source(),
+            related_information,
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (symbol, loc) in unused_symbols {
+        if is_exported(&symbol, loc, docs) {
+            continue;
+        }
+
+        let message = match symbol.kind {
+            HspSymbolKind::Label => format!("ラベル *{} は使用されていません。", symbol.name()),
+            _ => format!("{} は使用されていません。", symbol.name()),
+        };
+        let d = Diagnostic {
+            message,
+            severity: Some(DiagnosticSeverity::HINT),
+            range: loc_to_range(loc),
+            source: source(),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Default::default()
+        };
+        map.entry(loc.doc).or_default().push(d);
+    }
+
+    for (doc, file_encoding) in docs.iter_malformed_files() {
+        let range = wa
+            .find_malformed_bytes(doc)
+            .map_or_else(lsp_types::Range::default, loc_to_range);
+        let d = Diagnostic {
+            message: format!(
+                "このファイルには {} として解釈できないバイト列が含まれています。文字コードを `; ham: encoding=...` で指定できます。",
+                file_encoding.encoding.as_str()
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range,
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(doc).or_default().push(d);
+    }
+
+    for (doc, large) in docs.iter_large_docs() {
+        let d = Diagnostic {
+            message: if large.skipped {
+                format!(
+                    "このファイルは大きい ({} バイト) ため、解析していません。",
+                    large.size
+                )
+            } else {
+                format!(
+                    "このファイルは大きい ({} バイト) ため、シンボルの収集だけを行います。リントや変数の解析は行いません。",
+                    large.size
+                )
+            },
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            range: wa
+                .first_line_loc(doc)
+                .map_or_else(lsp_types::Range::default, loc_to_range),
+            source: source(),
+            ..Default::default()
+        };
+        map.entry(doc).or_default().push(d);
+    }
+
     let mut doc_diagnostics = vec![];
-    for (doc, diagnostics) in map {
+    for (doc, mut diagnostics) in map {
         let uri = match docs.get_uri(doc) {
             Some(it) => it.clone().into_url(),
             None => continue,
         };
         let version = docs.get_version(doc);
 
+        diagnostics.sort_by_key(|d| (d.range.start, d.range.end));
         doc_diagnostics.push((uri, version, diagnostics));
     }
-
-    filter_diagnostics(&mut doc_diagnostics, docs, cache);
     doc_diagnostics
 }
 
+/// diagnosticsの内容から結果IDを計算する。内容が同じなら同じIDになる。
+fn result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// `textDocument/diagnostic` の結果を作る。
+///
+/// 前回の結果IDと変わらなければ、変化なしとして報告する。
+pub(crate) fn document_report(
+    diagnostics: Vec<Diagnostic>,
+    previous_result_id: Option<String>,
+) -> DocumentDiagnosticReportResult {
+    let id = result_id(&diagnostics);
+
+    let report = if previous_result_id.as_deref() == Some(id.as_str()) {
+        DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                result_id: id,
+            },
+        })
+    } else {
+        DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: Some(id),
+                items: diagnostics,
+            },
+        })
+    };
+    DocumentDiagnosticReportResult::Report(report)
+}
+
+/// `workspace/diagnostic` の結果を作る。
+///
+/// 前回の結果があるのにdiagnosticsがなくなったドキュメントには、空の結果を報告してクリアさせる。
+pub(crate) fn workspace_report(
+    diagnostics: Vec<(Url, Option<i32>, Vec<Diagnostic>)>,
+    previous_result_ids: Vec<PreviousResultId>,
+) -> WorkspaceDiagnosticReportResult {
+    let mut previous = previous_result_ids
+        .into_iter()
+        .map(|p| (p.uri, p.value))
+        .collect::<HashMap<_, _>>();
+
+    let mut items = vec![];
+    for (uri, version, diagnostics) in diagnostics {
+        let id = result_id(&diagnostics);
+        let version = version.map(i64::from);
+
+        if previous.remove(&uri).as_deref() == Some(id.as_str()) {
+            items.push(WorkspaceDocumentDiagnosticReport::Unchanged(
+                WorkspaceUnchangedDocumentDiagnosticReport {
+                    uri,
+                    version,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: id,
+                    },
+                },
+            ));
+        } else {
+            items.push(WorkspaceDocumentDiagnosticReport::Full(
+                WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: Some(id),
+                        items: diagnostics,
+                    },
+                },
+            ));
+        }
+    }
+
+    let empty_id = result_id(&[]);
+    for (uri, value) in previous {
+        if value == empty_id {
+            continue;
+        }
+
+        items.push(WorkspaceDocumentDiagnosticReport::Full(
+            WorkspaceFullDocumentDiagnosticReport {
+                uri,
+                version: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(empty_id.clone()),
+                    items: vec![],
+                },
+            },
+        ));
+    }
+
+    WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport { items })
+}
+
 fn source() -> Option<String> {
     Some(env!("CARGO_PKG_NAME").to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_root;
+
+    #[test]
+    fn test_undefined_command() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("undefined_command.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module
+#deffunc hello
+#global
+#define greet hello
+	hello
+	greet
+	helo
+	if 1 {
+		gret
+	}
+	x = 1
+"#
+            .into(),
+        );
+
+        let warnings = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.severity == Some(DiagnosticSeverity::WARNING))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (7, "命令 helo の定義が見つかりません。".to_string()),
+                (9, "命令 gret の定義が見つかりません。".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_command_in_conditional_block() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+        ls.did_initialize();
+
+        let uri = Url::from_file_path(dummy_root().join("undefined_command_cond.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#ifndef included_undefined_command_cond
+#define included_undefined_command_cond
+#ifdef __hsp64__
+	only_on_64bit
+#else
+	only_on_32bit
+#endif
+	gradf 0, 0, 1, 1, 0, 0, 0
+	helo
+#endif
+"#
+            .into(),
+        );
+
+        // インクルードガードの中は調べるが、ほかの条件つきコンパイルのブロックの中は調べない。
+        let warnings = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.severity == Some(DiagnosticSeverity::WARNING))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![(9, "命令 helo の定義が見つかりません。".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_globals() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = Url::from_file_path(dummy_root().join("dup_main.hsp")).unwrap();
+        let sub_uri = Url::from_file_path(dummy_root().join("dup_sub.as")).unwrap();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"dup_sub.as\"\n#deffunc init\n\treturn\n#deffunc local run\n\treturn\n#deffunc setup\n\treturn\n\tinit\n\trun\n".into(),
+        );
+        ls.open_doc(
+            sub_uri.clone(),
+            NO_VERSION,
+            "#deffunc init\n\treturn\n#deffunc local run\n\treturn\n#ifdef __hsp64__\n#deffunc setup\n\treturn\n#endif\n".into(),
+        );
+
+        let diagnostics = ls.diagnose();
+        let errors = |uri: &Url| {
+            diagnostics
+                .iter()
+                .filter(|(u, _, _)| u == uri)
+                .flat_map(|(_, _, diagnostics)| diagnostics)
+                .filter(|d| d.message.contains("global"))
+                .map(|d| {
+                    let related = d.related_information.as_ref().unwrap();
+                    (
+                        d.range.start.line,
+                        d.message.clone(),
+                        related[0].location.uri.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // globalな init は両方のファイルで報告される。localな run と、条件つきコンパイルのブロックの中で定義された setup は報告されない。
+        assert_eq!(
+            errors(&main_uri),
+            vec![(
+                2,
+                "init はほかのファイルでもglobalに定義されています。".to_string(),
+                sub_uri.clone()
+            )]
+        );
+        assert_eq!(
+            errors(&sub_uri),
+            vec![(
+                0,
+                "init はほかのファイルでもglobalに定義されています。".to_string(),
+                main_uri.clone()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = Url::from_file_path(dummy_root().join("cycle_main.hsp")).unwrap();
+        let a_uri = Url::from_file_path(dummy_root().join("cycle_a.as")).unwrap();
+        let b_uri = Url::from_file_path(dummy_root().join("cycle_b.as")).unwrap();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"cycle_a.as\"\n\tcycle_a\n".into(),
+        );
+        ls.open_doc(
+            a_uri.clone(),
+            NO_VERSION,
+            "#include \"cycle_b.as\"\n#deffunc cycle_a\n\treturn\n".into(),
+        );
+        ls.open_doc(
+            b_uri.clone(),
+            NO_VERSION,
+            "#include \"cycle_a.as\"\n#deffunc cycle_b\n\treturn\n".into(),
+        );
+
+        let diagnostics = ls.diagnose();
+        let warnings = |uri: &Url| {
+            diagnostics
+                .iter()
+                .filter(|(u, _, _)| u == uri)
+                .flat_map(|(_, _, diagnostics)| diagnostics)
+                .filter(|d| d.message.starts_with("includeが循環しています"))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        // 循環に含まれる `#include` 文ごとに報告する。
+        assert!(warnings(&main_uri).is_empty());
+        let a_warnings = warnings(&a_uri);
+        assert_eq!(
+            a_warnings
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["includeが循環しています: cycle_a.as → cycle_b.as → cycle_a.as"]
+        );
+        assert_eq!(
+            a_warnings[0]
+                .related_information
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|info| (info.location.uri.clone(), info.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (a_uri.clone(), "cycle_b.as をincludeしています。"),
+                (b_uri.clone(), "cycle_a.as をincludeしています。"),
+            ]
+        );
+        assert_eq!(warnings(&b_uri).len(), 1);
+
+        // 循環していても、シンボルは1回ずつ解析される。
+        let mut names = ls
+            .workspace_symbol("cycle_".into())
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["cycle_a", "cycle_b"]);
+    }
+
+    #[test]
+    fn test_missing_include_guard() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = Url::from_file_path(dummy_root().join("guard_main.hsp")).unwrap();
+        let sub_uri = Url::from_file_path(dummy_root().join("guard_sub.as")).unwrap();
+        let lib_uri = Url::from_file_path(dummy_root().join("guard_lib.as")).unwrap();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"guard_sub.as\"\n#include \"guard_lib.as\"\n".into(),
+        );
+        ls.open_doc(
+            sub_uri.clone(),
+            NO_VERSION,
+            "#include \"guard_lib.as\"\n".into(),
+        );
+        ls.open_doc(
+            lib_uri.clone(),
+            NO_VERSION,
+            "#deffunc guard_lib\n\treturn\n".into(),
+        );
+
+        let diagnostics = ls.diagnose();
+        let ranges = |uri: &Url| {
+            diagnostics
+                .iter()
+                .filter(|(u, _, _)| u == uri)
+                .flat_map(|(_, _, diagnostics)| diagnostics)
+                .filter(|d| d.message.contains("インクルードガードがない"))
+                .map(|d| (d.range.start.line, d.range.start.character))
+                .collect::<Vec<_>>()
+        };
+
+        // includeしている `#include` 文に報告する。
+        assert_eq!(ranges(&main_uri), vec![(2, 0)]);
+        assert_eq!(ranges(&sub_uri), vec![(0, 0)]);
+        assert_eq!(ranges(&lib_uri), vec![]);
+    }
+
+    #[test]
+    fn test_func_without_uselib_in_included_file() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = Url::from_file_path(dummy_root().join("uselib_main.hsp")).unwrap();
+        let header_uri = Url::from_file_path(dummy_root().join("uselib_header.as")).unwrap();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#func early \"early\"\n#uselib \"user32.dll\"\n#include \"uselib_header.as\"\n"
+                .into(),
+        );
+        ls.open_doc(
+            header_uri.clone(),
+            NO_VERSION,
+            "#func header_func \"header_func\"\n".into(),
+        );
+
+        let diagnostics = ls.diagnose();
+        let rows = |uri: &Url| {
+            diagnostics
+                .iter()
+                .filter(|(u, _, _)| u == uri)
+                .flat_map(|(_, _, diagnostics)| diagnostics)
+                .filter(|d| d.message.contains("#uselib がありません"))
+                .map(|d| d.range.start.line)
+                .collect::<Vec<_>>()
+        };
+
+        // includeされるファイルは、includeする側の `#uselib` を使うことがあるので報告しない。
+        assert_eq!(rows(&main_uri), vec![1]);
+        assert!(rows(&header_uri).is_empty());
+    }
+
+    #[test]
+    fn test_unused_symbols() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("unused_symbols.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module
+#deffunc local helper
+#deffunc api
+	helper
+#global
+#define global EXPORTED 1
+#const LIMIT 10
+	x = 1
+	y = 2
+	mes y
+	api
+#deffunc unused_cmd
+"#
+            .into(),
+        );
+
+        let hints = |ls: &mut LangService| {
+            ls.diagnose()
+                .into_iter()
+                .filter(|(u, _, _)| *u == uri)
+                .flat_map(|(_, _, diagnostics)| diagnostics)
+                .filter(|d| d.tags == Some(vec![DiagnosticTag::UNNECESSARY]))
+                .map(|d| (d.range.start.line, d.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            hints(&mut ls),
+            vec![
+                (7, "LIMIT は使用されていません。".to_string()),
+                (8, "x は使用されていません。".to_string()),
+                (12, "unused_cmd は使用されていません。".to_string()),
+            ]
+        );
+
+        ls.set_lint_unused(false);
+        assert_eq!(hints(&mut ls), vec![]);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.did_initialize();
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("shadowing.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#define global mes(%1) logmes %1
+	count = 1
+	total = 0
+#deffunc add int count
+	total += count
+	return
+#module
+#deffunc local sub int total
+	return
+#global
+	add count
+"#
+            .into(),
+        );
+
+        let warnings = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.contains("隠して") || d.message.contains("組み込み"))
+            .map(|d| {
+                let related = (d.related_information.iter().flatten())
+                    .map(|info| info.location.range.start.line)
+                    .collect::<Vec<_>>();
+                (d.range.start.line, d.message, related)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    1,
+                    "mes は標準命令などの組み込みの名前と同じです。この定義によって mes の意味が変わります。".to_string(),
+                    vec![]
+                ),
+                (
+                    4,
+                    "パラメータ count は同じ名前の変数を隠しています。この命令の中では変数を参照できません。".to_string(),
+                    vec![2]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_labels() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = Url::from_file_path(dummy_root().join("unused_labels.hsp")).unwrap();
+        let sub_uri = Url::from_file_path(dummy_root().join("unused_labels_sub.as")).unwrap();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#include "unused_labels_sub.as"
+*l_main
+	onclick *on_click
+	button gosub "ok", *on_ok
+	goto *l_loop
+*l_loop
+	stop
+*on_click
+	return
+*on_ok
+	return
+*from_sub
+	return
+*l_unused
+	stop
+"#
+            .into(),
+        );
+        ls.open_doc(sub_uri.clone(), NO_VERSION, "\tgosub *from_sub\n".into());
+
+        let hints = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == main_uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.starts_with("ラベル"))
+            .filter(|d| d.tags == Some(vec![DiagnosticTag::UNNECESSARY]))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hints,
+            vec![
+                (2, "ラベル *l_main は使用されていません。".to_string()),
+                (14, "ラベル *l_unused は使用されていません。".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unresolved_label() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("unresolved_label.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+	onkey gosub *on_key
+	goto *l_main
+	gosub *l_mian
+*l_main
+	button goto "OK", *on_ok
+	stop
+*on_key
+	return
+"#
+            .into(),
+        );
+
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.starts_with("ラベル"))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (3, "ラベル *l_mian の定義が見つかりません。".to_string()),
+                (5, "ラベル *on_ok の定義が見つかりません。".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_misuse() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("field_misuse.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module m_counter count
+#modinit
+	count = 0
+	return
+#modcfunc counter_get
+	return count
+#deffunc counter_dump
+	mes count
+	return
+#global
+	newmod c, m_counter
+	mes counter_get(c)
+"#
+            .into(),
+        );
+
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.starts_with("モジュール変数"))
+            .map(|d| d.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![8]);
+    }
+
+    #[test]
+    fn test_arity_mismatch() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("arity_mismatch.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module
+#deffunc swap var a, var b, int c, local t
+	return
+#defcfunc add int a, int b
+	return a + b
+#global
+	swap x, y
+	swap x, y, 1,
+	swap x
+	swap x, y, 1, 2, 3
+	mes add(1, 2)
+	mes add()
+	mes add(1, 2, 3)
+"#
+            .into(),
+        );
+
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.contains("の引数は"))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    9,
+                    "swap の引数は2〜3個ですが、1個指定されています。".to_string()
+                ),
+                (
+                    10,
+                    "swap の引数は2〜3個ですが、5個指定されています。".to_string()
+                ),
+                (
+                    13,
+                    "add の引数は0〜2個ですが、3個指定されています。".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arity_with_default_value() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("arity_default_value.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+	dim buf
+#deffunc f int a, var b = buf, var c
+	return
+#deffunc g var a, var b = buf
+	return
+	f 1, , x
+	f 1
+	g x
+"#
+            .into(),
+        );
+
+        // 既定値のあるパラメータは省略できる。
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.contains("の引数は"))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![(7, "f の引数は3個ですが、1個指定されています。".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_param_type_mismatch() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("param_type_mismatch.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module
+#deffunc fill array a, int n
+	return
+#defcfunc sum var a, int n
+	return a + n
+#global
+	fill x, 1
+	fill 1 + 2, 3
+	fill x, *l_main
+	mes sum(x, 1)
+	mes sum("x", 1)
+*l_main
+	stop
+"#
+            .into(),
+        );
+
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.starts_with("変数") || d.message.contains("ラベル"))
+            .map(|d| (d.range.start.line, d.range.start.character, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (8, 6, "変数か配列の要素が必要です。".to_string()),
+                (9, 9, "int 型のパラメータにラベルは渡せません。".to_string()),
+                (11, 9, "変数か配列の要素が必要です。".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_function_misuse() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("command_function_misuse.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#module
+#deffunc log str s
+	mes s
+	return
+#defcfunc twice int n
+	return n * 2
+#global
+	log "ok"
+	mes twice(2)
+	twice 2
+	x = log("ng") + 1
+"#
+            .into(),
+        );
+
+        let errors = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.contains("なので"))
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    10,
+                    "twice は関数なので命令としては使えません。戻り値を変数に代入するなどして、式の中で呼び出してください。".to_string()
+                ),
+                (11, "log は命令なので式の中では使えません。".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_type_change() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("array_type_change.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+	sdim buf, 64, 16
+	sdim buf, 128
+	dim buf, 8
+	dimtype buf, vartype("int"), 4
+	dimtype values, 3, 10
+	ddim values, 20
+	mes buf, values
+"#
+            .into(),
+        );
+
+        let warnings = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.starts_with("配列"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|d| (d.range.start.line, d.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(
+                3,
+                "配列 buf を str の配列から int の配列に確保し直しています。"
+            )]
+        );
+        assert_eq!(
+            warnings[0]
+                .related_information
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|info| (info.location.range.start.line, info.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(2, "ここで str の配列として確保されています。")]
+        );
+    }
+
+    #[test]
+    fn test_pull_diagnostics() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(dummy_root().join("pull_diagnostics.hsp")).unwrap();
+        ls.open_doc(uri.clone(), 1, "; ham-entry\n\thelo\n".into());
+
+        let result_id = match ls.document_diagnostic(uri.clone(), None) {
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) => {
+                let report = report.full_document_diagnostic_report;
+                assert_eq!(report.items.len(), 1);
+                report.result_id.unwrap()
+            }
+            _ => panic!("full report expected"),
+        };
+
+        // 変化がなければ、変化なしとして報告する。
+        assert!(matches!(
+            ls.document_diagnostic(uri.clone(), Some(result_id.clone())),
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))
+        ));
+
+        let previous = || {
+            vec![PreviousResultId {
+                uri: uri.clone(),
+                value: result_id.clone(),
+            }]
+        };
+        match ls.workspace_diagnostic(previous()) {
+            WorkspaceDiagnosticReportResult::Report(report) => {
+                assert!(matches!(
+                    report.items.as_slice(),
+                    [WorkspaceDocumentDiagnosticReport::Unchanged(_)]
+                ));
+            }
+            _ => panic!("report expected"),
+        }
+
+        // diagnosticsがなくなったら、空の結果を報告する。
+        ls.change_doc(uri.clone(), 2, "; ham-entry\n".into());
+        match ls.workspace_diagnostic(previous()) {
+            WorkspaceDiagnosticReportResult::Report(report) => match report.items.as_slice() {
+                [WorkspaceDocumentDiagnosticReport::Full(full)] => {
+                    assert_eq!(full.uri, uri);
+                    assert_eq!(full.full_document_diagnostic_report.items, vec![]);
+                }
+                _ => panic!("full report expected"),
+            },
+            _ => panic!("report expected"),
+        }
+    }
+}
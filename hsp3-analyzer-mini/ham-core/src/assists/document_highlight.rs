@@ -26,6 +26,10 @@ pub(crate) fn document_highlight(
 
     highlights.retain(|(_, loc)| loc.doc == doc);
 
+    // 複合代入の左辺のように、書き込みと読み込みの両方である箇所は書き込みとして扱う。
+    highlights.sort_by_key(|&(kind, loc)| (loc.start(), kind != DocumentHighlightKind::WRITE));
+    highlights.dedup_by_key(|(_, loc)| loc.start());
+
     Some(
         highlights
             .into_iter()
@@ -36,3 +40,36 @@ pub(crate) fn document_highlight(
             .collect(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_read_write() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("document_highlight.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "a = 1\na += 2\nmes a\ndim a, 3\n".into(),
+        );
+
+        let highlights = ls
+            .document_highlight(uri, Position::new(2, 4))
+            .into_iter()
+            .map(|h| (h.range.start.line, h.kind.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            highlights,
+            vec![
+                (0, DocumentHighlightKind::WRITE),
+                (1, DocumentHighlightKind::WRITE),
+                (2, DocumentHighlightKind::READ),
+                (3, DocumentHighlightKind::WRITE),
+            ]
+        );
+    }
+}
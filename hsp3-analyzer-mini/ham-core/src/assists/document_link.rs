@@ -0,0 +1,146 @@
+//! ドキュメントリンク (`#include` のファイル名をクリックしてそのファイルを開けるようにする)
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{DocumentLink, Url};
+
+/// `#include` 文の (`#` の位置, ファイル名の文字列の位置, ファイル名の文字列) を集めるビジター。
+#[derive(Default)]
+struct V {
+    includes: Vec<(Pos, Loc, RcStr)>,
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if let PStmt::Include(stmt) = stmt {
+            if let Some(file_path) = &stmt.file_path_opt {
+                if file_path.body.kind == TokenKind::Str {
+                    self.includes.push((
+                        stmt.hash.body_pos(),
+                        file_path.body.loc,
+                        file_path.body.text.clone(),
+                    ));
+                }
+            }
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// ドキュメントにある `#include` 文の (ファイル名の文字列の位置, ファイル名の文字列, includeされるドキュメント) を集める。
+///
+/// includeされるドキュメントは解析で解決されたもの。(解決されなかったらNone)
+pub(super) fn collect_include_file_paths(
+    doc: DocId,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<(Loc, RcStr, Option<DocId>)>> {
+    let mut resolved = vec![];
+    wa.require_project_for_doc(doc)
+        .collect_doc_includes(doc, &mut resolved);
+    let targets = resolved
+        .into_iter()
+        .map(|(loc, target_doc)| (loc.start(), target_doc))
+        .collect::<HashMap<_, _>>();
+
+    let DocSyntax { root, .. } = wa.get_syntax(doc)?;
+    let mut v = V::default();
+    v.on_root(root);
+
+    Some(
+        v.includes
+            .into_iter()
+            .map(|(hash_pos, file_path_loc, text)| {
+                (file_path_loc, text, targets.get(&hash_pos).cloned())
+            })
+            .collect(),
+    )
+}
+
+pub(crate) fn document_link(
+    uri: Url,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<DocumentLink>> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+
+    Some(
+        collect_include_file_paths(doc, wa)?
+            .into_iter()
+            .filter_map(|(file_path_loc, _, target_doc_opt)| {
+                let target = docs.get_uri(target_doc_opt?)?.clone().into_url();
+                Some(DocumentLink {
+                    range: loc_to_range(file_path_loc),
+                    target: Some(target),
+                    tooltip: None,
+                    data: None,
+                })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+
+    #[test]
+    fn test_document_link() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("main.hsp");
+        let sub_uri = dummy_url("sub/sub.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"sub/sub.hsp\"\n#include \"missing.as\"\n".into(),
+        );
+        ls.open_doc(sub_uri.clone(), NO_VERSION, "\tmes 1\n".into());
+
+        let links = ls
+            .document_link(main_uri)
+            .into_iter()
+            .map(|link| {
+                (
+                    link.range.start.line,
+                    link.range.start.character,
+                    link.target,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(links, vec![(1, 9, Some(sub_uri))]);
+    }
+
+    #[test]
+    fn test_document_link_without_entrypoint() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("link_main.hsp");
+        let sub_uri = dummy_url("link_sub/link_sub.as");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "#include \"link_sub.as\"\n".into(),
+        );
+        ls.open_doc(sub_uri.clone(), NO_VERSION, "".into());
+
+        // 解析で解決されたのと同じドキュメントにリンクする。
+        let targets = ls
+            .document_link(main_uri.clone())
+            .into_iter()
+            .map(|link| link.target)
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec![Some(sub_uri.clone())]);
+
+        let definitions = ls
+            .definitions(main_uri, Position::new(0, 12))
+            .into_iter()
+            .map(|location| location.uri)
+            .collect::<Vec<_>>();
+        assert_eq!(definitions, vec![sub_uri]);
+    }
+}
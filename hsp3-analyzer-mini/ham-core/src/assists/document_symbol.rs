@@ -1,6 +1,6 @@
 use super::*;
 use crate::{analysis::*, parse::p_param_ty::PParamCategory};
-use lsp_types::DocumentSymbolResponse;
+use lsp_types::{DocumentSymbol, DocumentSymbolResponse};
 
 // completion, workspace/symbol も参照
 fn to_lsp_symbol_kind(kind: HspSymbolKind) -> Option<lsp_types::SymbolKind> {
@@ -33,32 +33,373 @@ fn to_lsp_symbol_kind(kind: HspSymbolKind) -> Option<lsp_types::SymbolKind> {
     Some(it)
 }
 
+fn is_deffunc_kind(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc => true,
+        _ => false,
+    }
+}
+
+fn new_document_symbol(
+    name: String,
+    kind: lsp_types::SymbolKind,
+    range: crate::source::Range,
+    selection_range: crate::source::Range,
+) -> DocumentSymbol {
+    #[allow(deprecated)]
+    DocumentSymbol {
+        name,
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: to_lsp_range(range),
+        selection_range: to_lsp_range(selection_range),
+        children: None,
+    }
+}
+
+fn attach(
+    symbol: DocumentSymbol,
+    stack: &mut [(crate::source::Range, DocumentSymbol)],
+    roots: &mut Vec<DocumentSymbol>,
+) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => roots.push(symbol),
+    }
+}
+
+/// 範囲の包含関係にしたがってシンボルを入れ子にする。
+fn nest(mut nodes: Vec<(crate::source::Range, DocumentSymbol)>) -> Vec<DocumentSymbol> {
+    nodes.sort_by(|(l, _), (r, _)| l.start().cmp(&r.start()).then(r.end().cmp(&l.end())));
+
+    let mut roots = vec![];
+    let mut stack: Vec<(crate::source::Range, DocumentSymbol)> = vec![];
+    for (range, symbol) in nodes {
+        while let Some((top, _)) = stack.last() {
+            if top.start() <= range.start() && range.end() <= top.end() {
+                break;
+            }
+
+            let (_, done) = stack.pop().unwrap();
+            attach(done, &mut stack, &mut roots);
+        }
+        stack.push((range, symbol));
+    }
+    while let Some((_, done)) = stack.pop() {
+        attach(done, &mut stack, &mut roots);
+    }
+    roots
+}
+
+/// イベントハンドラをまとめるグループの名前
+const EVENT_HANDLERS_GROUP: &str = "イベントハンドラ";
+
+/// イベントハンドラのラベルを1つのグループにまとめる。
+fn event_handlers_group(handlers: Vec<DocumentSymbol>) -> Option<DocumentSymbol> {
+    let first = handlers.first()?;
+    let start = first.range.start;
+    let end = handlers
+        .iter()
+        .map(|h| h.range.end)
+        .max_by_key(|p| (p.line, p.character))?;
+
+    #[allow(deprecated)]
+    Some(DocumentSymbol {
+        name: EVENT_HANDLERS_GROUP.to_string(),
+        detail: None,
+        kind: lsp_types::SymbolKind::NAMESPACE,
+        tags: None,
+        deprecated: None,
+        range: lsp_types::Range::new(start, end),
+        selection_range: first.selection_range,
+        children: Some(handlers),
+    })
+}
+
+/// モジュールや `#deffunc` の中にシンボルを入れ子にしたツリーを構築する。
+///
+/// イベントハンドラのラベルは入れ子にせず、末尾のグループにまとめる。
+fn hierarchical_symbols(
+    doc: DocId,
+    symbols: Vec<(SymbolRc, Loc)>,
+    handlers: &HashSet<SymbolRc>,
+    wa: &mut WorkspaceAnalysis,
+) -> Vec<DocumentSymbol> {
+    let contains =
+        |outer: Loc, inner: Loc| outer.start() <= inner.start() && inner.end() <= outer.end();
+
+    let mut blocks = vec![];
+    wa.collect_doc_blocks(doc, &mut blocks);
+    let tokens = wa.get_syntax(doc).map(|syntax| syntax.tokens);
+
+    let mut heads = HashSet::new();
+    let mut nodes = vec![];
+
+    for block in blocks {
+        // 範囲の先頭の行で定義されているシンボル (モジュール名や命令の名前) を範囲の代表とする。
+        let head_opt = symbols.iter().enumerate().find(|&(i, (symbol, loc))| {
+            let kind_ok = match block.kind {
+                DocBlockKind::Module(_) => symbol.kind == HspSymbolKind::Module,
+                DocBlockKind::DefFunc => is_deffunc_kind(symbol.kind),
+            };
+            kind_ok
+                && !heads.contains(&i)
+                && loc.start_row() == block.loc.start_row()
+                && contains(block.loc, *loc)
+        });
+
+        let (name, kind, selection_range) = match head_opt {
+            Some((i, (symbol, loc))) => {
+                heads.insert(i);
+                let kind = match to_lsp_symbol_kind(symbol.kind) {
+                    Some(it) => it,
+                    None => continue,
+                };
+                (symbol.name().to_string(), kind, loc.range)
+            }
+            None => {
+                // 名前のないモジュールや #modinit など (名前のないモジュールは `module#N` と表示する。)
+                let keyword_opt = tokens.as_ref().and_then(|tokens| {
+                    let i = tokens
+                        .iter()
+                        .position(|t| t.body.loc.start() == block.loc.start())?;
+                    Some(tokens.get(i + 1)?.body_text().to_string())
+                });
+                let name = match &block.kind {
+                    DocBlockKind::Module(label) => label.to_string(),
+                    _ => format!("#{}", keyword_opt.as_deref().unwrap_or("")),
+                };
+                let kind = match block.kind {
+                    DocBlockKind::Module(_) => lsp_types::SymbolKind::MODULE,
+                    DocBlockKind::DefFunc => lsp_types::SymbolKind::FUNCTION,
+                };
+                (name, kind, crate::source::Range::empty(block.loc.start()))
+            }
+        };
+
+        nodes.push((
+            block.loc.range,
+            new_document_symbol(name, kind, block.loc.range, selection_range),
+        ));
+    }
+
+    let mut handler_nodes = vec![];
+    for (i, (symbol, loc)) in symbols.into_iter().enumerate() {
+        if heads.contains(&i) {
+            continue;
+        }
+
+        if handlers.contains(&symbol) {
+            handler_nodes.push(new_document_symbol(
+                symbol.name().to_string(),
+                lsp_types::SymbolKind::EVENT,
+                loc.range,
+                loc.range,
+            ));
+            continue;
+        }
+
+        let kind = match to_lsp_symbol_kind(symbol.kind) {
+            Some(it) => it,
+            None => continue,
+        };
+        nodes.push((
+            loc.range,
+            new_document_symbol(symbol.name().to_string(), kind, loc.range, loc.range),
+        ));
+    }
+
+    let mut roots = nest(nodes);
+    roots.extend(event_handlers_group(handler_nodes));
+    roots
+}
+
 pub(crate) fn symbol(
     uri: Url,
+    hierarchical: bool,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
 ) -> Option<DocumentSymbolResponse> {
     let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
 
     let mut symbols = vec![];
-    wa.require_project_for_doc(doc)
-        .collect_doc_symbols(doc, &mut symbols);
+    let project = wa.require_project_for_doc(doc);
+    project.collect_doc_symbols(doc, &mut symbols);
+
+    let handlers = symbols
+        .iter()
+        .filter(|(symbol, _)| project.is_event_handler(symbol))
+        .map(|(symbol, _)| symbol.clone())
+        .collect::<HashSet<_>>();
 
     // 空のシンボルを除去する (名前が空のシンボルがどこかで登録されている(?))
     symbols.retain(|(s, _)| !s.name().is_empty());
 
     symbols.sort_by_key(|(_, loc)| loc.start());
+    symbols.dedup_by(|(l, l_loc), (r, r_loc)| l_loc == r_loc && l.name() == r.name());
+
+    if hierarchical {
+        return Some(DocumentSymbolResponse::Nested(hierarchical_symbols(
+            doc, symbols, &handlers, wa,
+        )));
+    }
 
     let symbol_information_list = symbols
         .into_iter()
         .filter_map(|(symbol, loc)| {
             let name = symbol.name();
-            let kind = to_lsp_symbol_kind(symbol.kind)?;
             let location = loc_to_location(loc, docs)?;
 
+            if handlers.contains(&symbol) {
+                let mut info = new_lsp_symbol_information(
+                    name.to_string(),
+                    lsp_types::SymbolKind::EVENT,
+                    location,
+                );
+                info.container_name = Some(EVENT_HANDLERS_GROUP.to_string());
+                return Some(info);
+            }
+
+            let kind = to_lsp_symbol_kind(symbol.kind)?;
             Some(new_lsp_symbol_information(name.to_string(), kind, location))
         })
         .collect();
 
     Some(DocumentSymbolResponse::Flat(symbol_information_list))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    fn dump(symbols: &[DocumentSymbol], depth: usize, out: &mut Vec<String>) {
+        for symbol in symbols {
+            out.push(format!("{}{}", "  ".repeat(depth), symbol.name));
+            dump(symbol.children.as_deref().unwrap_or(&[]), depth + 1, out);
+        }
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        let mut ls = LangService::new_standalone();
+        ls.set_hierarchical_document_symbol(true);
+
+        let uri = dummy_url("document_symbol.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"
+#module m_outline x
+#modinit
+    x = 1
+    return
+#deffunc f int a
+*l_inner
+    return
+#global
+
+*l_main
+    f 1
+"#
+            .into(),
+        );
+
+        let symbols = match ls.document_symbol(uri) {
+            Some(DocumentSymbolResponse::Nested(it)) => it,
+            _ => panic!(),
+        };
+        let mut out = vec![];
+        dump(&symbols, 0, &mut out);
+        assert_eq!(
+            out,
+            vec![
+                "m_outline",
+                "  x",
+                "  #modinit",
+                "  f",
+                "    a",
+                "    l_inner",
+                "l_main",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unnamed_modules() {
+        let mut ls = LangService::new_standalone();
+        ls.set_hierarchical_document_symbol(true);
+
+        let uri = dummy_url("document_symbol_unnamed.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc f\n\treturn\n#global\n#module m_named\n#global\n#module\n#deffunc g\n\treturn\n#global\n".into(),
+        );
+
+        let symbols = match ls.document_symbol(uri) {
+            Some(DocumentSymbolResponse::Nested(it)) => it,
+            _ => panic!(),
+        };
+        let mut out = vec![];
+        dump(&symbols, 0, &mut out);
+        assert_eq!(out, vec!["module#0", "  f", "m_named", "module#2", "  g"]);
+    }
+
+    #[test]
+    fn test_event_handlers() {
+        let mut ls = LangService::new_standalone();
+        ls.set_hierarchical_document_symbol(true);
+
+        let uri = dummy_url("document_symbol_handlers.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"
+    onclick gosub *on_click
+    onexit *on_exit
+*l_main
+    stop
+*on_click
+    return
+*on_exit
+    end
+"#
+            .into(),
+        );
+
+        let symbols = match ls.document_symbol(uri.clone()) {
+            Some(DocumentSymbolResponse::Nested(it)) => it,
+            _ => panic!(),
+        };
+        let mut out = vec![];
+        dump(&symbols, 0, &mut out);
+        assert_eq!(
+            out,
+            vec!["l_main", "イベントハンドラ", "  on_click", "  on_exit"]
+        );
+
+        ls.set_hierarchical_document_symbol(false);
+        let symbols = match ls.document_symbol(uri) {
+            Some(DocumentSymbolResponse::Flat(it)) => it,
+            _ => panic!(),
+        };
+        assert_eq!(
+            symbols
+                .iter()
+                .map(|s| (s.name.as_str(), s.container_name.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("l_main", None),
+                ("on_click", Some("イベントハンドラ")),
+                ("on_exit", Some("イベントハンドラ")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! シンボルの一覧の出力 (`ham.dumpSymbols` コマンド)
+
+use super::*;
+use std::fmt::Write as _;
+
+/// ワークスペースで定義されているシンボルを、定義箇所の順に1行ずつテキストにする。
+///
+/// 各行は `URI:行:列<TAB>種類<TAB>名前<TAB>使用回数` の形式。(行・列は1から数える。)
+pub(crate) fn dump_symbols(
+    target_doc: impl Fn(DocId) -> bool,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> String {
+    let mut stats = vec![];
+    wa.collect_symbol_stats(&mut stats);
+
+    let mut items = stats
+        .into_iter()
+        .filter(|stat| target_doc(stat.def_loc.doc))
+        .filter_map(|stat| {
+            let uri = docs.get_uri(stat.def_loc.doc)?.clone().into_url();
+            Some((uri, stat))
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|(l_uri, l), (r_uri, r)| {
+        l_uri
+            .as_str()
+            .cmp(r_uri.as_str())
+            .then_with(|| l.def_loc.start().cmp(&r.def_loc.start()))
+    });
+
+    let mut output = String::new();
+    for (uri, stat) in items {
+        let pos = stat.def_loc.start();
+        let _ = writeln!(
+            output,
+            "{}:{}:{}\t{}\t{}\t{}",
+            uri,
+            pos.row + 1,
+            pos.column16 + 1,
+            stat.symbol.kind.as_str(),
+            stat.symbol.name(),
+            stat.use_count
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{commands::*, docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_root;
+    use lsp_types::Url;
+    use serde_json::Value;
+
+    #[test]
+    fn test_dump_symbols() {
+        let mut ls = LangService::new_standalone();
+
+        let dummy_root = dummy_root();
+        let uri = Url::from_file_path(&dummy_root.join("dump_symbols.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#const N 1\n#deffunc f\n\tmes N\n\tf\n".into(),
+        );
+
+        let dump = match ls.execute_command(COMMAND_DUMP_SYMBOLS, vec![]) {
+            Ok(CommandOutcome::Value(Value::String(it))) => it,
+            _ => panic!("string expected"),
+        };
+        assert_eq!(
+            dump,
+            format!("{0}:1:8\t定数\tN\t1\n{0}:2:10\t命令\tf\t1\n", uri)
+        );
+    }
+}
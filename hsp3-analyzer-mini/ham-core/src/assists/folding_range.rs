@@ -0,0 +1,202 @@
+//! 折りたたみ範囲
+//!
+//! モジュール、`#deffunc` の本体、`repeat`～`loop` などのループ、`if` のブロック、連続するコメントを折りたためるようにする。
+
+use super::*;
+use crate::{
+    analysis::{DocBlock, DocBlockKind},
+    parse::*,
+};
+use lsp_types::{FoldingRange, FoldingRangeKind, Url};
+
+/// ループの開始と終了を表す命令の組
+static LOOP_COMMANDS: &[(&str, &str)] = &[
+    ("repeat", "loop"),
+    ("foreach", "loop"),
+    ("while", "wend"),
+    ("do", "until"),
+    ("for", "next"),
+    ("switch", "swend"),
+];
+
+/// 範囲の最終行を折りたたまないように、終了行の1つ前までを範囲とする。
+fn new_range(start_row: usize, end_row: usize, kind_opt: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line: start_row as u32,
+        start_character: None,
+        end_line: end_row.saturating_sub(1).max(start_row) as u32,
+        end_character: None,
+        kind: kind_opt,
+        collapsed_text: None,
+    }
+}
+
+/// ループと `if` のブロックを集めるビジター。
+#[derive(Default)]
+struct V {
+    /// 開いているループの (終了命令の名前, 開始行)
+    loop_stack: Vec<(&'static str, usize)>,
+    ranges: Vec<FoldingRange>,
+}
+
+impl V {
+    fn on_block_braces(&mut self, block: &PBlock) {
+        if let (Some(left), Some(right)) = (&block.left_opt, &block.right_opt) {
+            self.ranges.push(new_range(
+                left.body.loc.start_row(),
+                right.body.loc.start_row(),
+                None,
+            ));
+        }
+    }
+
+    fn on_command(&mut self, command: &PToken) {
+        let name = command.body_text();
+        let row = command.body.loc.start_row();
+
+        if let Some(&(_, end)) = LOOP_COMMANDS
+            .iter()
+            .find(|(start, _)| start.eq_ignore_ascii_case(name))
+        {
+            self.loop_stack.push((end, row));
+            return;
+        }
+
+        // 対応する開始命令がみつかるまでスタックを戻す。
+        if let Some(i) = self
+            .loop_stack
+            .iter()
+            .rposition(|(end, _)| end.eq_ignore_ascii_case(name))
+        {
+            let (_, start_row) = self.loop_stack[i];
+            self.loop_stack.truncate(i);
+            self.ranges.push(new_range(start_row, row, None));
+        }
+    }
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        match stmt {
+            PStmt::Command(stmt) => self.on_command(&stmt.command),
+            PStmt::If(stmt) => {
+                self.on_block_braces(&stmt.body);
+                self.on_block_braces(&stmt.alt);
+            }
+            _ => {}
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+/// 行頭から始まるコメントが2行以上続く部分を集める。
+fn collect_comment_ranges(text: &str, tokens: &[PToken], ranges: &mut Vec<FoldingRange>) {
+    let lines = text.lines().collect::<Vec<_>>();
+
+    let own_line = |loc: Loc| {
+        let start = loc.start();
+        lines
+            .get(start.row as usize)
+            .and_then(|line| line.get(..start.column8 as usize))
+            .map_or(false, |prefix| prefix.trim().is_empty())
+    };
+
+    let mut current: Option<(usize, usize)> = None;
+    let comments = tokens
+        .iter()
+        .flat_map(|token| token.iter())
+        .filter(|t| t.kind == TokenKind::Comment && own_line(t.loc));
+
+    for comment in comments {
+        let (start_row, end_row) = (comment.loc.start_row(), comment.loc.end_row());
+
+        match &mut current {
+            Some((_, last)) if *last + 1 == start_row => *last = end_row,
+            _ => {
+                if let Some((first, last)) = current.take() {
+                    if first < last {
+                        ranges.push(new_range(first, last + 1, Some(FoldingRangeKind::Comment)));
+                    }
+                }
+                current = Some((start_row, end_row));
+            }
+        }
+    }
+
+    if let Some((first, last)) = current {
+        if first < last {
+            ranges.push(new_range(first, last + 1, Some(FoldingRangeKind::Comment)));
+        }
+    }
+}
+
+pub(crate) fn folding_range(
+    uri: Url,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<FoldingRange>> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+
+    let mut blocks = vec![];
+    wa.collect_doc_blocks(doc, &mut blocks);
+
+    let mut ranges = blocks
+        .into_iter()
+        .map(|DocBlock { kind, loc }| {
+            let kind_opt = match kind {
+                DocBlockKind::Module(_) | DocBlockKind::DefFunc => Some(FoldingRangeKind::Region),
+            };
+            new_range(loc.start_row(), loc.end_row(), kind_opt)
+        })
+        .collect::<Vec<_>>();
+
+    let DocSyntax { text, tokens, root } = wa.get_syntax(doc)?;
+
+    let mut v = V::default();
+    v.on_root(root);
+    ranges.extend(v.ranges);
+
+    collect_comment_ranges(&text, &tokens, &mut ranges);
+
+    ranges.retain(|r| r.start_line < r.end_line);
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges.dedup_by_key(|r| (r.start_line, r.end_line));
+    Some(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_folding_range() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("folding_range.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; 説明1
+; 説明2
+#module
+#deffunc f
+	repeat 3
+		if cnt {
+			mes cnt
+		}
+	loop
+	return
+#global
+"#
+            .into(),
+        );
+
+        let ranges = ls
+            .folding_range(uri)
+            .into_iter()
+            .map(|r| (r.start_line, r.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(0, 1), (2, 9), (3, 9), (4, 7), (5, 6)]);
+    }
+}
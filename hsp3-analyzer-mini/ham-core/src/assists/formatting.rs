@@ -322,6 +322,57 @@ impl PVisitor for V {
     }
 }
 
+/// 行末の空白を削除する変更を列挙する。
+///
+/// 空行にある空白は改行の字句に含まれているので、それも削除する。
+fn remove_trailing_whitespace(tokens: &[PToken], edits: &mut Vec<(Range, String)>) {
+    // 長さが0の字句 (文の終わりなど) は飛ばす。
+    let all = tokens
+        .iter()
+        .flat_map(|token| token.iter())
+        .filter(|t| !t.text.is_empty())
+        .collect::<Vec<_>>();
+
+    for (i, t) in all.iter().enumerate() {
+        match t.kind {
+            TokenKind::Blank => {
+                let at_eol = all
+                    .get(i + 1)
+                    .map_or(true, |next| next.kind == TokenKind::Newlines);
+                if at_eol {
+                    edits.push((t.loc.range, "".into()));
+                }
+            }
+            TokenKind::Comment if !t.text.contains('\n') => {
+                let len = t.text.trim_end().len();
+                if len < t.text.len() {
+                    let start = t.loc.start() + Pos::from(&t.text[..len]);
+                    edits.push((Range::from(start..t.loc.end()), "".into()));
+                }
+            }
+            TokenKind::Newlines => {
+                // 最後の改行の後ろは次の行の字下げなので、それより前にある空行だけをみる。
+                let last = t.text.rfind('\n').unwrap_or(0);
+                let mut l = match t.text.find('\n') {
+                    Some(l) => l + 1,
+                    None => continue,
+                };
+                while l <= last {
+                    let r = l + t.text[l..].find('\n').unwrap();
+                    let line = t.text[l..r].trim_end_matches('\r');
+                    if !line.is_empty() {
+                        let start = t.loc.start() + Pos::from(&t.text[..l]);
+                        let end = start + Pos::from(line);
+                        edits.push((Range::from(start..end), "".into()));
+                    }
+                    l = r + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 pub(crate) fn formatting(
     uri: Url,
     docs: &Docs,
@@ -339,6 +390,7 @@ pub(crate) fn formatting(
     ctx.on_root(root);
 
     let mut edits = ctx.edits;
+    remove_trailing_whitespace(&ctx.tokens, &mut edits);
     edits.sort_by_key(|(range, text)| (range.start(), text.len()));
 
     // 重なった変更を削除する。
@@ -1,4 +1,5 @@
 use super::*;
+use crate::analysis::{describe_var_types, SignatureData};
 use lsp_types::{
     Documentation, Hover, HoverContents, MarkedString, MarkupContent, MarkupKind, Position, Url,
 };
@@ -14,16 +15,146 @@ pub(crate) fn hover(
 
     let (contents, loc) = (|| -> Option<_> {
         let (symbol, symbol_loc) = project.locate_symbol(doc, pos)?;
+
+        // 別名のマクロは展開先の命令の情報を表示する。
+        let (symbol, alias_opt) = match project.resolve_alias(&symbol) {
+            Some(target) => (target, Some(symbol)),
+            None => (symbol, None),
+        };
         let (name, kind, details) = project.get_symbol_details(&symbol)?;
 
         let mut contents = vec![];
         contents.push(plain_text_to_marked_string(format!("{} ({})", name, kind)));
+        if let Some(alias) = alias_opt {
+            contents.push(plain_text_to_marked_string(format!(
+                "{} は {} の別名です。",
+                alias.name(),
+                name
+            )));
+        }
+
+        if let Some(signature_data) = symbol.signature_opt() {
+            let ctype = match symbol.kind {
+                HspSymbolKind::DefCFunc
+                | HspSymbolKind::ModCFunc
+                | HspSymbolKind::Macro { ctype: true } => true,
+                _ => false,
+            };
+            contents.push(MarkedString::LanguageString(LanguageString {
+                language: "hsp3".to_string(),
+                value: signature_to_string(&signature_data, ctype),
+            }));
+        }
+
+        if let Some(method) = &*symbol.com_method_opt.borrow() {
+            let index = method.index_opt.as_deref().unwrap_or("?");
+            contents.push(plain_text_to_marked_string(match &method.interface_opt {
+                Some(interface) => format!(
+                    "インターフェイス {} のメソッド (インデックス {})",
+                    interface.name(),
+                    index
+                ),
+                None => format!("COMのメソッド (インデックス {})", index),
+            }));
+        }
+
+        if let Some(cmd) = &*symbol.plugin_cmd_opt.borrow() {
+            let id = cmd.command_id_opt.as_deref().unwrap_or("?");
+            contents.push(plain_text_to_marked_string(match &cmd.plugin_opt {
+                Some(plugin) => format!("プラグイン {} のコマンド (ID {})", plugin, id),
+                None => format!("プラグインのコマンド (ID {})", id),
+            }));
+        }
+
+        if let Some(lib) = &*symbol.lib_opt.borrow() {
+            contents.push(plain_text_to_marked_string(format!("DLL: {}", lib)));
+        }
+
+        if symbol.kind == HspSymbolKind::ComInterface {
+            let mut methods = vec![];
+            project.collect_com_methods(&symbol, &mut methods);
+            if !methods.is_empty() {
+                let names = methods
+                    .iter()
+                    .map(|method| method.name().to_string())
+                    .collect::<Vec<_>>();
+                contents.push(plain_text_to_marked_string(format!(
+                    "メソッド: {}",
+                    names.join(", ")
+                )));
+            }
+        }
 
-        if let Some(desc) = details.desc {
+        if symbol.kind == HspSymbolKind::StaticVar {
+            let mut decls = project
+                .array_decls(&symbol)
+                .iter()
+                .map(|decl| decl.describe(&symbol.name))
+                .collect::<Vec<_>>();
+            decls.dedup();
+            if !decls.is_empty() {
+                contents.push(MarkedString::LanguageString(LanguageString {
+                    language: "hsp3".to_string(),
+                    value: decls.join("\n"),
+                }));
+            }
+
+            if let Some(ty) = describe_var_types(project.var_type_sites(&symbol)) {
+                contents.push(plain_text_to_marked_string(format!("型: {}", ty)));
+            }
+
+            let mut modules = vec![];
+            project.collect_newmod_modules(&symbol, &mut modules);
+            if !modules.is_empty() {
+                let names = modules
+                    .iter()
+                    .map(|module| module.name().to_string())
+                    .collect::<Vec<_>>();
+                contents.push(plain_text_to_marked_string(format!(
+                    "{} のインスタンスの配列",
+                    names.join(", ")
+                )));
+            }
+        }
+
+        if let Some(value) = project.const_value(&symbol) {
+            contents.push(plain_text_to_marked_string(format!("= {}", value)));
+        }
+
+        if let Some(def) = symbol.macro_opt.borrow().clone() {
+            let body = def.body_to_string();
+            if !body.is_empty() {
+                contents.push(MarkedString::LanguageString(LanguageString {
+                    language: "hsp3".to_string(),
+                    value: format!("; 置換テキスト\n{}", body),
+                }));
+            }
+
+            if let Some(expanded) = project.expand_macro_call_at(doc, pos, &def) {
+                contents.push(MarkedString::LanguageString(LanguageString {
+                    language: "hsp3".to_string(),
+                    value: format!("; 展開結果\n{}", expanded),
+                }));
+            }
+        }
+
+        if let Some(desc) = &details.desc {
             contents.push(plain_text_to_marked_string(desc.to_string()));
         }
 
-        contents.extend(details.docs.into_iter().map(plain_text_to_marked_string));
+        contents.extend(
+            details_tag_sections(&details)
+                .into_iter()
+                .chain(details.docs)
+                .map(plain_text_to_marked_string),
+        );
+
+        if !details.examples.is_empty() {
+            contents.push(MarkedString::LanguageString(LanguageString {
+                language: "hsp3".to_string(),
+                value: format!("; 使用例\n{}", details.examples.join("\n")),
+            }));
+        }
 
         Some((contents, symbol_loc))
     })()
@@ -73,6 +204,40 @@ pub(crate) fn hover(
     })
 }
 
+/// シグネチャを `f int a, str b` や `f(int a)` のような形式で書き出す。
+pub(super) fn signature_to_string(signature_data: &SignatureData, ctype: bool) -> String {
+    let mut s = NamePath::new(&signature_data.name).base.to_string();
+    let mut sep = if ctype { "(" } else { " " };
+
+    for param in &signature_data.params {
+        s += sep;
+        match (&param.ty_opt, &param.name_opt) {
+            (Some(ty), Some(name)) => {
+                s += ty.to_str();
+                s += " ";
+                s += name;
+            }
+            (Some(ty), None) => s += ty.to_str(),
+            (None, Some(name)) => s += name,
+            _ => s += "???",
+        }
+        if let Some(default) = &param.default_opt {
+            s += " = ";
+            s += default;
+        }
+        sep = ", ";
+    }
+
+    if ctype {
+        s += if signature_data.params.is_empty() {
+            "()"
+        } else {
+            ")"
+        };
+    }
+    s
+}
+
 fn documentation_to_marked_string(d: Documentation) -> MarkedString {
     match d {
         Documentation::String(value)
@@ -86,3 +251,388 @@ fn documentation_to_marked_string(d: Documentation) -> MarkedString {
         }) => markdown_marked_string(value),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+
+    fn to_strings(hover: Hover) -> Vec<String> {
+        match hover.contents {
+            HoverContents::Array(contents) => contents
+                .into_iter()
+                .map(|c| match c {
+                    MarkedString::String(s) => s,
+                    MarkedString::LanguageString(s) => s.value,
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_hover_signature() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; 挨拶する
+#deffunc greet str name, int count
+	return
+
+#defcfunc twice int x
+	return x * 2
+
+	greet "a", twice(1)
+"#
+            .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(7, 2)).unwrap());
+        assert_eq!(
+            contents,
+            vec!["greet (命令)", "greet str name, int count", "挨拶する"]
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(7, 13)).unwrap());
+        assert_eq!(contents[1], "twice(int x)");
+    }
+
+    #[test]
+    fn test_hover_alias() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_alias.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "; 挨拶する\n#deffunc greet str name\n\treturn\n#define hello greet\n\thello \"a\"\n"
+                .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(4, 2)).unwrap());
+        assert_eq!(
+            contents,
+            vec![
+                "greet (命令)",
+                "hello は greet の別名です。",
+                "greet str name",
+                "挨拶する",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover_com_method() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_com_method.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#usecom IShellLink "{000214EE-0000-0000-C000-000000000046}" "{00021401-0000-0000-C000-000000000046}"
+#comfunc IShellLink_SetPath 20 str
+#comfunc IShellLink_GetPath 3 var, int, nullptr, int
+	newcom link, IShellLink
+	IShellLink_SetPath link, "a.exe"
+"#
+            .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(4, 3)).unwrap());
+        assert_eq!(
+            contents,
+            vec![
+                "IShellLink_SetPath (COMメソッド)",
+                "IShellLink_SetPath comobj, str",
+                "インターフェイス IShellLink のメソッド (インデックス 20)",
+            ]
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(3, 15)).unwrap());
+        assert_eq!(
+            contents[1],
+            "メソッド: IShellLink_GetPath, IShellLink_SetPath"
+        );
+    }
+
+    #[test]
+    fn test_hover_module_instance() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_module_instance.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module m_point x\n#modinit\n\treturn\n#global\n\tnewmod pts, m_point\n\tnewmod pts, m_point\n\tdelmod pts(0)\n"
+                .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(6, 9)).unwrap());
+        assert_eq!(contents.last().unwrap(), "m_point のインスタンスの配列");
+    }
+
+    #[test]
+    fn test_hover_plugin_cmd() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_plugin_cmd.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#regcmd "_hsp3cmdinit@4", "hspext.dll"
+#cmd ext_move $00
+	ext_move
+"#
+            .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(2, 3)).unwrap());
+        assert_eq!(
+            contents.last().unwrap(),
+            "プラグイン hspext.dll のコマンド (ID $00)"
+        );
+    }
+
+    #[test]
+    fn test_hover_lib_func() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_lib_func.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#uselib "user32.dll"
+#func MessageBoxA "MessageBoxA" int, sptr, sptr, int
+#uselib "kernel32.dll"
+#cfunc GetTickCount "GetTickCount"
+	MessageBoxA 0, "a", "b", 0
+	mes GetTickCount()
+"#
+            .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(4, 3)).unwrap());
+        assert_eq!(contents.last().unwrap(), "DLL: user32.dll");
+
+        let contents = to_strings(ls.hover(uri, Position::new(5, 7)).unwrap());
+        assert_eq!(contents.last().unwrap(), "DLL: kernel32.dll");
+    }
+
+    #[test]
+    fn test_hover_doc_tags() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_doc_tags.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"; 挨拶する
+; @param name 名前
+; @return なし
+; @deprecated
+; @example
+;	greet "a"
+#deffunc greet str name
+	return
+
+	greet "a"
+"#
+            .into(),
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(9, 2)).unwrap());
+        assert_eq!(
+            contents,
+            vec![
+                "greet (命令)",
+                "greet str name",
+                "挨拶する",
+                "非推奨",
+                "パラメータ:\n- name: 名前",
+                "戻り値: なし",
+                "; 使用例\n\tgreet \"a\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover_const_value() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_const_value.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#const FLAG_A $10
+#const FLAG_B FLAG_A << 1
+#const double RATIO 1 + 2
+#enum KIND_X = 1
+#enum KIND_Y
+#enum KIND_Z
+	mes FLAG_B, RATIO, KIND_Z
+"#
+            .into(),
+        );
+
+        let mut value_at = |character: u32| {
+            let contents = to_strings(ls.hover(uri.clone(), Position::new(6, character)).unwrap());
+            contents[1].clone()
+        };
+        assert_eq!(value_at(6), "= 0x20");
+        assert_eq!(value_at(14), "= 3.0");
+        assert_eq!(value_at(21), "= 3");
+    }
+
+    #[test]
+    fn test_hover_const_value_from_include() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("hover_const_main.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#include "hover_const_lib.as"
+#const MAIN_VALUE LIB_BASE + LIB_LOCAL
+#const MAIN_NEXT MAIN_VALUE + 1
+	mes MAIN_NEXT
+"#
+            .into(),
+        );
+        ls.open_doc(
+            dummy_url("hover_const_lib.as"),
+            NO_VERSION,
+            "#const global LIB_BASE 100\n#const LIB_LOCAL 5\n".into(),
+        );
+
+        // includeされたファイルの定数を参照していても、値を計算する。
+        let contents = to_strings(ls.hover(main_uri, Position::new(4, 6)).unwrap());
+        assert_eq!(contents[1], "= 106");
+    }
+
+    #[test]
+    fn test_hover_enum_value_from_include() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("hover_enum_main.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"; ham-entry
+#include "hover_enum_lib.as"
+#enum MAIN_A = LIB_BASE
+#enum MAIN_B
+	mes MAIN_B
+"#
+            .into(),
+        );
+        ls.open_doc(
+            dummy_url("hover_enum_lib.as"),
+            NO_VERSION,
+            "#const global LIB_BASE 100\n".into(),
+        );
+
+        // 直前の `#enum` の値がincludeされたファイルの定数で決まるときも、次の値を計算する。
+        let contents = to_strings(ls.hover(main_uri, Position::new(4, 6)).unwrap());
+        assert_eq!(contents[1], "= 101");
+    }
+
+    #[test]
+    fn test_hover_enum_value_unknown() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_enum_unknown.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#enum UNKNOWN_A = UNDEFINED_VALUE\n#enum UNKNOWN_B\n\tmes UNKNOWN_B\n".into(),
+        );
+
+        // 直前の値が分からなければ、次の値も分からない。
+        let contents = to_strings(ls.hover(uri, Position::new(2, 6)).unwrap());
+        assert!(contents.iter().all(|content| !content.starts_with("= ")));
+    }
+
+    #[test]
+    fn test_hover_var_type() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_var_type.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#module
+#deffunc get_name var result
+	result = "name"
+	return
+#global
+	count = 1
+	count = count + 1
+	sdim text, 64
+	get_name name
+	value = 1
+	value = "x"
+	mes count, text, name, value
+"#
+            .into(),
+        );
+
+        let mut type_at = |character: u32| {
+            to_strings(ls.hover(uri.clone(), Position::new(11, character)).unwrap())
+                .into_iter()
+                .find(|s| s.starts_with("型: "))
+        };
+        assert_eq!(type_at(5).as_deref(), Some("型: int"));
+        assert_eq!(type_at(12).as_deref(), Some("型: str"));
+        // `var` パラメータに代入された型が伝わる。
+        assert_eq!(type_at(18).as_deref(), Some("型: str"));
+        assert_eq!(type_at(24).as_deref(), Some("型: 不定 (int, str)"));
+    }
+
+    #[test]
+    fn test_hover_array_decl() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_array_decl.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tsdim buf, 64, 16\n\tdimtype xs, 3, N * 2\n\tmes buf, xs\n".into(),
+        );
+
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(2, 5)).unwrap());
+        assert_eq!(contents[1], "str buf(64, 16)");
+
+        let contents = to_strings(ls.hover(uri, Position::new(2, 10)).unwrap());
+        assert_eq!(contents[1], "double xs(N * 2)");
+    }
+
+    #[test]
+    fn test_hover_macro() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("hover_macro.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#define ctype add(%1, %2 = 1) (%1 + %2 + offset)
+#define ctype twice(%1) add(%1,%1)
+	offset = 1
+	mes twice(a * 2)
+	mes twice
+"#
+            .into(),
+        );
+
+        // 呼び出しの上では、1段階だけ展開した結果を表示する。
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(3, 6)).unwrap());
+        assert_eq!(
+            &contents[contents.len() - 2..],
+            &["; 置換テキスト\nadd(%1,%1)", "; 展開結果\nadd(a * 2,a * 2)"]
+        );
+
+        // 定義の上や、引数のない使用箇所では置換テキストだけを表示する。
+        let contents = to_strings(ls.hover(uri.clone(), Position::new(0, 15)).unwrap());
+        assert_eq!(
+            contents.last().unwrap(),
+            "; 置換テキスト\n(%1 + %2 + offset)"
+        );
+
+        let contents = to_strings(ls.hover(uri, Position::new(4, 6)).unwrap());
+        assert_eq!(contents.last().unwrap(), "; 置換テキスト\nadd(%1,%1)");
+    }
+}
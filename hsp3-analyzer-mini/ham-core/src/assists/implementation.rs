@@ -0,0 +1,135 @@
+//! 実装へ移動
+//!
+//! `newmod` から `#modinit` へ、`delmod` から `#modterm` へ、`#modfunc` の呼び出しからその本体へ移動する。
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{Location, Position, Url};
+
+/// カーソル位置にある命令の名前と、引数の識別子の位置をみつけるビジター。
+struct V {
+    pos: Pos16,
+    out: Option<(RcStr, Vec<Option<Loc>>)>,
+}
+
+impl PVisitor for V {
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        if self.out.is_some() {
+            return;
+        }
+
+        if let PStmt::Command(PCommandStmt { command, args, .. }) = stmt {
+            if command.body.loc.range.contains_inclusive(self.pos) {
+                let arg_locs = args
+                    .iter()
+                    .map(|arg| match &arg.expr_opt {
+                        Some(PExpr::Compound(compound)) => Some(compound.name().body.loc),
+                        _ => None,
+                    })
+                    .collect();
+                self.out = Some((command.body.text.clone(), arg_locs));
+                return;
+            }
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+pub(crate) fn implementations(
+    uri: Url,
+    position: Position,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<Location>> {
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    let command_opt = {
+        let DocSyntax { root, .. } = wa.get_syntax(doc)?;
+        let mut v = V { pos, out: None };
+        v.on_root(root);
+        v.out
+    };
+
+    let project = wa.require_project_for_doc(doc);
+    let symbol_at = |loc_opt: Option<&Option<Loc>>| -> Option<SymbolRc> {
+        let loc = (*loc_opt?)?;
+        let (symbol, _) = project.locate_symbol(loc.doc, Pos16::from(loc.start()))?;
+        Some(symbol)
+    };
+
+    let mut locs = vec![];
+    match command_opt {
+        Some((command, args)) if command.eq_ignore_ascii_case("newmod") => {
+            let module = symbol_at(args.get(1)).filter(|s| s.kind == HspSymbolKind::Module)?;
+            project.collect_module_deffuncs(&module, PDefFuncKind::ModInit, &mut locs);
+        }
+        Some((command, args)) if command.eq_ignore_ascii_case("delmod") => {
+            let var = symbol_at(args.first())?;
+            let mut modules = vec![];
+            project.collect_newmod_modules(&var, &mut modules);
+            for module in &modules {
+                project.collect_module_deffuncs(module, PDefFuncKind::ModTerm, &mut locs);
+            }
+        }
+        _ => {
+            let (symbol, _) = project.locate_symbol(doc, pos)?;
+            match symbol.kind {
+                HspSymbolKind::ModFunc | HspSymbolKind::ModCFunc => {
+                    project.collect_symbol_defs(&symbol, &mut locs)
+                }
+                HspSymbolKind::Module => {
+                    project.collect_module_deffuncs(&symbol, PDefFuncKind::ModInit, &mut locs);
+                    project.collect_module_deffuncs(&symbol, PDefFuncKind::ModTerm, &mut locs);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    Some(
+        locs.into_iter()
+            .filter_map(|loc| loc_to_location(loc, docs))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_implementation() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("implementation.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#module mymodule x
+#modinit
+	return
+#modterm
+	return
+#modfunc f
+	return
+#global
+	newmod v, mymodule
+	f v
+	delmod v
+"#
+            .into(),
+        );
+
+        let mut rows = |position: Position| {
+            ls.implementations(uri.clone(), position)
+                .into_iter()
+                .map(|location| location.range.start.line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rows(Position::new(8, 2)), vec![1]);
+        assert_eq!(rows(Position::new(9, 1)), vec![5]);
+        assert_eq!(rows(Position::new(10, 2)), vec![3]);
+    }
+}
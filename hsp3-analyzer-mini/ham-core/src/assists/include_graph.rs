@@ -0,0 +1,269 @@
+//! includeのグラフ (`ham/includeGraph`, `ham/includedBy` リクエスト)
+
+use super::*;
+use lsp_types::TextDocumentIdentifier;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IncludeGraphParams {
+    /// 木の根にするドキュメント (省略したらエントリーポイントを根にする。)
+    pub(crate) text_document: Option<TextDocumentIdentifier>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IncludedByParams {
+    pub(crate) text_document: TextDocumentIdentifier,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IncludeGraphNode {
+    pub(crate) uri: Url,
+    /// commonディレクトリにあるファイルなら、その名前
+    pub(crate) common_name: Option<String>,
+    /// 親がこのドキュメントをincludeしている `#include` 文の位置 (根にはない。)
+    include_location: Option<Location>,
+    /// 祖先と同じドキュメント (循環しているinclude) か。子要素は持たない。
+    pub(crate) cyclic: bool,
+    /// すでに別の場所に現れたドキュメントか。子要素を省略する。
+    pub(crate) omitted: bool,
+    pub(crate) children: Vec<IncludeGraphNode>,
+}
+
+struct V<'a> {
+    docs: &'a Docs,
+    wa: &'a WorkspaceAnalysis,
+    graph: &'a IncludeGraph,
+    /// すでに子要素を列挙したドキュメント
+    done: HashSet<DocId>,
+    /// 列挙中のドキュメントの祖先
+    stack: Vec<DocId>,
+}
+
+impl V<'_> {
+    fn on_doc(&mut self, doc: DocId, include_loc_opt: Option<Loc>) -> Option<IncludeGraphNode> {
+        let mut node = IncludeGraphNode {
+            uri: self.docs.get_uri(doc)?.clone().into_url(),
+            common_name: self.wa.common_doc_name(doc).map(|name| name.to_string()),
+            include_location: include_loc_opt.and_then(|loc| loc_to_location(loc, self.docs)),
+            cyclic: false,
+            omitted: false,
+            children: vec![],
+        };
+
+        if self.stack.contains(&doc) {
+            node.cyclic = true;
+            return Some(node);
+        }
+        if !self.done.insert(doc) {
+            node.omitted = true;
+            return Some(node);
+        }
+
+        self.stack.push(doc);
+        for &(loc, child) in self.graph.includes(doc) {
+            node.children.extend(self.on_doc(child, Some(loc)));
+        }
+        self.stack.pop();
+        Some(node)
+    }
+}
+
+/// includeの関係を木構造にする。
+///
+/// `root_opt` を指定したら、そのドキュメントを根とする木を返す。
+/// そうでなければ、エントリーポイントごとの木を返す。
+/// 2回目以降に現れたドキュメントは子要素を省略する。
+pub(crate) fn include_graph(
+    root_opt: Option<&Url>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Vec<IncludeGraphNode> {
+    let graph = wa.include_graph();
+
+    let roots = match root_opt {
+        Some(uri) => match docs.find_by_uri(&CanonicalUri::from_url(uri)) {
+            Some(doc) => vec![doc],
+            None => return vec![],
+        },
+        None => graph.entrypoints.clone(),
+    };
+
+    let mut v = V {
+        docs,
+        wa,
+        graph: &graph,
+        done: HashSet::new(),
+        stack: vec![],
+    };
+    roots
+        .into_iter()
+        .filter_map(|doc| v.on_doc(doc, None))
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IncluderItem {
+    /// `#include` 文の位置
+    location: Location,
+    /// 指定したドキュメントまでのincludeの段数 (直接includeしているなら1)
+    depth: usize,
+}
+
+/// 指定したドキュメントを直接または推移的にincludeしている `#include` 文を列挙する。
+///
+/// 直接includeしているものから順に並ぶ。
+pub(crate) fn included_by(uri: &Url, docs: &Docs, wa: &mut WorkspaceAnalysis) -> Vec<IncluderItem> {
+    let doc = match docs.find_by_uri(&CanonicalUri::from_url(uri)) {
+        Some(it) => it,
+        None => return vec![],
+    };
+
+    wa.include_graph()
+        .includers(doc)
+        .into_iter()
+        .filter_map(|(loc, depth)| {
+            Some(IncluderItem {
+                location: loc_to_location(loc, docs)?,
+                depth,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+
+    fn summary(node: &IncludeGraphNode, depth: usize, out: &mut Vec<String>) {
+        let name = node.uri.path_segments().unwrap().next_back().unwrap();
+        let line = node
+            .include_location
+            .as_ref()
+            .map_or(String::new(), |l| format!(":{}", l.range.start.line));
+        let mark = if node.cyclic {
+            " (cyclic)"
+        } else if node.omitted {
+            " (omitted)"
+        } else {
+            ""
+        };
+        out.push(format!("{}{}{}{}", "  ".repeat(depth), name, line, mark));
+        for child in &node.children {
+            summary(child, depth + 1, out);
+        }
+    }
+
+    #[test]
+    fn test_include_graph() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        ls.open_doc(
+            dummy_url("graph_main.hsp"),
+            NO_VERSION,
+            "; ham-entry\n#include \"graph_a.as\"\n#include \"graph_b.as\"\n".into(),
+        );
+        ls.open_doc(
+            dummy_url("graph_a.as"),
+            NO_VERSION,
+            "#include \"graph_b.as\"\n#include \"graph_a.as\"\n".into(),
+        );
+        ls.open_doc(dummy_url("graph_b.as"), NO_VERSION, "".into());
+
+        let mut lines = vec![];
+        for node in ls.include_graph(None) {
+            summary(&node, 0, &mut lines);
+        }
+        assert_eq!(
+            lines,
+            vec![
+                "graph_main.hsp",
+                "  graph_a.as:1",
+                "    graph_b.as:0",
+                "    graph_a.as:1 (cyclic)",
+                "  graph_b.as:2 (omitted)",
+            ]
+        );
+
+        // 指定したドキュメントを根にする。
+        let mut lines = vec![];
+        for node in ls.include_graph(Some(dummy_url("graph_a.as"))) {
+            summary(&node, 0, &mut lines);
+        }
+        assert_eq!(
+            lines,
+            vec!["graph_a.as", "  graph_b.as:0", "  graph_a.as:1 (cyclic)"]
+        );
+    }
+
+    #[test]
+    fn test_included_by() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("included_by_main.hsp");
+        let lib_uri = dummy_url("included_by_lib.as");
+        let util_uri = dummy_url("included_by_util.as");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"included_by_lib.as\"\n#include \"included_by_util.as\"\n"
+                .into(),
+        );
+        ls.open_doc(
+            lib_uri.clone(),
+            NO_VERSION,
+            "#include \"included_by_util.as\"\n".into(),
+        );
+        ls.open_doc(util_uri.clone(), NO_VERSION, "".into());
+
+        let name = |uri: &Url| {
+            uri.path_segments()
+                .unwrap()
+                .next_back()
+                .unwrap()
+                .to_string()
+        };
+        let items = ls
+            .included_by(util_uri.clone())
+            .into_iter()
+            .map(|item| {
+                (
+                    name(&item.location.uri),
+                    item.location.range.start.line,
+                    item.depth,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                ("included_by_main.hsp".to_string(), 2, 1),
+                ("included_by_lib.as".to_string(), 0, 1),
+                ("included_by_main.hsp".to_string(), 1, 2),
+            ]
+        );
+
+        // `#include` の行で参照を探すと、同じファイルを取り込んでいる `#include` 文が列挙される。
+        let mut refs = ls
+            .references(lib_uri, Position::new(0, 12), false)
+            .into_iter()
+            .map(|l| (name(&l.uri), l.range.start.line))
+            .collect::<Vec<_>>();
+        refs.sort();
+        assert_eq!(
+            refs,
+            vec![
+                ("included_by_lib.as".to_string(), 0),
+                ("included_by_main.hsp".to_string(), 1),
+                ("included_by_main.hsp".to_string(), 2),
+            ]
+        );
+    }
+}
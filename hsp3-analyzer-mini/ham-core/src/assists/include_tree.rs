@@ -0,0 +1,97 @@
+//! includeの木構造の表示 (`ham.showIncludeTree` コマンド)
+
+use super::*;
+use crate::assists::{
+    include_graph::{include_graph, IncludeGraphNode},
+    rewrites::add_include::relative_path,
+};
+use std::{fmt::Write as _, path::Path};
+
+/// ドキュメントの表示名。ワークスペースのルートからの相対パスにする。
+fn node_name(node: &IncludeGraphNode, root_dir_opt: Option<&Path>) -> String {
+    if let Some(name) = &node.common_name {
+        return name.clone();
+    }
+
+    node.uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| relative_path(root_dir_opt?, &path))
+        .unwrap_or_else(|| node.uri.to_string())
+}
+
+fn write_node(
+    node: &IncludeGraphNode,
+    depth: usize,
+    root_dir_opt: Option<&Path>,
+    output: &mut String,
+) {
+    let mark = if node.cyclic {
+        " (循環)"
+    } else if node.omitted {
+        " (省略)"
+    } else {
+        ""
+    };
+    let _ = writeln!(
+        output,
+        "{}{}{}",
+        "  ".repeat(depth),
+        node_name(node, root_dir_opt),
+        mark
+    );
+
+    for child in &node.children {
+        write_node(child, depth + 1, root_dir_opt, output);
+    }
+}
+
+/// エントリーポイントからincludeされるドキュメントを、字下げした木構造のテキストにする。
+///
+/// 2回目以降に現れたドキュメントは子要素を省略する。
+pub(crate) fn include_tree(
+    root_dir_opt: Option<&Path>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> String {
+    let mut output = String::new();
+    for node in include_graph(None, docs, wa) {
+        write_node(&node, 0, root_dir_opt, &mut output);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{commands::*, docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::Url;
+    use serde_json::Value;
+
+    #[test]
+    fn test_include_tree() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        ls.open_doc(
+            dummy_url("main.hsp"),
+            NO_VERSION,
+            "; ham-entry\n#include \"a.as\"\n#include \"lib/b.as\"\n".into(),
+        );
+        ls.open_doc(
+            dummy_url("a.as"),
+            NO_VERSION,
+            "#include \"lib/b.as\"\n#include \"main.hsp\"\n".into(),
+        );
+        ls.open_doc(dummy_url("lib/b.as"), NO_VERSION, "".into());
+
+        let tree = match ls.execute_command(COMMAND_SHOW_INCLUDE_TREE, vec![]) {
+            Ok(CommandOutcome::Value(Value::String(it))) => it,
+            _ => panic!("string expected"),
+        };
+        assert_eq!(
+            tree,
+            "main.hsp\n  a.as\n    lib/b.as\n    main.hsp (循環)\n  lib/b.as (省略)\n"
+        );
+    }
+}
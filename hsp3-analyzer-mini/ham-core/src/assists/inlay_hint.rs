@@ -0,0 +1,362 @@
+//! インレイヒント
+//!
+//! ユーザー定義命令や ctype マクロの呼び出しの引数にパラメータ名を表示する。
+//! 設定で有効にしたときは、変数の最初の代入に推測した型も表示する。
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range, Url};
+
+/// パラメータ名を表示する種類のシンボルか？
+fn kind_is_target(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc
+        | HspSymbolKind::LibFunc
+        | HspSymbolKind::Macro { ctype: true } => true,
+        _ => false,
+    }
+}
+
+/// 命令・関数の呼び出しと変数の代入を探してヒントを集めるビジター。
+struct V {
+    use_site_map: HashMap<Pos, SymbolRc>,
+    /// 型のヒントを表示しないときは None
+    def_site_map_opt: Option<HashMap<Pos, SymbolRc>>,
+    /// 最初の代入をすでに通過した変数
+    assigned: HashSet<SymbolRc>,
+    /// ヒントを集める行の範囲 (終端を含む)
+    rows: (u32, u32),
+    hints: Vec<InlayHint>,
+}
+
+impl V {
+    fn in_rows(&self, pos: Pos) -> bool {
+        (self.rows.0..=self.rows.1).contains(&pos.row)
+    }
+
+    /// 変数への代入をみつけたとき、それが最初の代入なら変数名の後ろに型を表示する。
+    fn on_var_def(&mut self, name: &PToken, ty_opt: Option<(VarType, bool)>) {
+        let symbol = match &self.def_site_map_opt {
+            Some(map) => match map.get(&name.body_pos()) {
+                Some(it) if it.kind == HspSymbolKind::StaticVar => it.clone(),
+                _ => return,
+            },
+            None => return,
+        };
+        if !self.assigned.insert(symbol) {
+            return;
+        }
+
+        let (ty, is_array) = match ty_opt {
+            Some(it) => it,
+            None => return,
+        };
+        let end = name.body.loc.end();
+        if !self.in_rows(end) {
+            return;
+        }
+
+        let label = if is_array {
+            format!(": {}[]", ty.to_str())
+        } else {
+            format!(": {}", ty.to_str())
+        };
+        self.hints.push(InlayHint {
+            position: to_position(end),
+            label: InlayHintLabel::String(label),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            padding_right: None,
+            data: None,
+        });
+    }
+
+    fn on_call(&mut self, callee: &PToken, args: &[PArg]) {
+        let symbol = match self.use_site_map.get(&callee.body_pos()) {
+            Some(it) if kind_is_target(it.kind) => it,
+            _ => return,
+        };
+        let signature_data = match symbol.signature_opt() {
+            Some(it) => it,
+            None => return,
+        };
+
+        for (arg, param) in args.iter().zip(&signature_data.params) {
+            let (expr, name) = match (&arg.expr_opt, &param.name_opt) {
+                (Some(expr), Some(name)) => (expr, name),
+                (None, Some(name)) => {
+                    self.on_omitted_arg(arg, name, param.default_opt.as_ref());
+                    continue;
+                }
+                _ => continue,
+            };
+
+            // 名前のないマクロのパラメータ (`%1` など) はヒントにならない。
+            if name.starts_with('%') {
+                continue;
+            }
+
+            // 引数が同名の変数なら、ヒントは冗長なので出さない。
+            if let PExpr::Compound(PCompound::Name(token)) = expr {
+                if token.body_text() == name.as_str() {
+                    continue;
+                }
+            }
+
+            let start = expr.compute_range().start();
+            if !self.in_rows(start) {
+                continue;
+            }
+
+            self.hints.push(InlayHint {
+                position: to_position(start),
+                label: InlayHintLabel::String(format!("{}:", name)),
+                kind: Some(InlayHintKind::PARAMETER),
+                text_edits: None,
+                tooltip: None,
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+    }
+
+    /// 省略された引数 (`f a, , c` の2番目など) の位置に、パラメータの既定値を表示する。
+    fn on_omitted_arg(&mut self, arg: &PArg, name: &RcStr, default_opt: Option<&RcStr>) {
+        let (comma, default) = match (&arg.comma_opt, default_opt) {
+            (Some(comma), Some(default)) => (comma, default),
+            _ => return,
+        };
+
+        let start = comma.body.loc.start();
+        if !self.in_rows(start) {
+            return;
+        }
+
+        self.hints.push(InlayHint {
+            position: to_position(start),
+            label: InlayHintLabel::String(format!("{} = {}", name, default)),
+            kind: Some(InlayHintKind::PARAMETER),
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            padding_right: None,
+            data: None,
+        });
+    }
+}
+
+impl PVisitor for V {
+    fn on_compound(&mut self, compound: &PCompound) {
+        if let PCompound::Paren(np) = compound {
+            self.on_call(&np.name, &np.args);
+        }
+
+        self.on_compound_default(compound);
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        match stmt {
+            PStmt::Assign(PAssignStmt { left, op_opt, args }) => {
+                // 単純な代入 `x = ...` の型だけを推測する。(`x = 1, 2` は配列への代入になる。)
+                let ty_opt = match (left, op_opt) {
+                    (PCompound::Name(_), Some(op)) if op.kind() == TokenKind::Equal => args
+                        .first()
+                        .and_then(|arg| arg.expr_opt.as_ref())
+                        .and_then(infer_expr_type)
+                        .map(|ty| (ty, args.len() >= 2)),
+                    _ => None,
+                };
+                self.on_var_def(left.name(), ty_opt);
+            }
+            PStmt::Command(stmt) => {
+                self.on_call(&stmt.command, &stmt.args);
+
+                if let Some(ty) = VarType::from_dim_command(stmt.command.body_text()) {
+                    if let Some(PArg {
+                        expr_opt: Some(PExpr::Compound(compound)),
+                        ..
+                    }) = stmt.args.first()
+                    {
+                        self.on_var_def(compound.name(), Some((ty, true)));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.on_stmt_default(stmt);
+    }
+}
+
+pub(crate) fn inlay_hint(
+    uri: Url,
+    range: Range,
+    types: bool,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<InlayHint>> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+
+    let project = wa.require_project_for_doc(doc);
+    let use_site_map = project.use_site_map(doc);
+    let def_site_map_opt = if types {
+        Some(project.def_site_map(doc))
+    } else {
+        None
+    };
+    let DocSyntax { root, .. } = wa.get_syntax(doc)?;
+
+    let mut v = V {
+        use_site_map,
+        def_site_map_opt,
+        assigned: HashSet::new(),
+        rows: (range.start.line, range.end.line),
+        hints: vec![],
+    };
+    v.on_root(root);
+
+    let mut hints = v.hints;
+    hints.sort_by_key(|hint| hint.position);
+    Some(hints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+    use lsp_types::Position;
+
+    fn hint_labels(ls: &mut LangService, uri: Url) -> Vec<(u32, u32, String)> {
+        let range = Range::new(Position::new(0, 0), Position::new(u32::MAX, 0));
+        ls.inlay_hint(uri, range)
+            .into_iter()
+            .map(|hint| {
+                let label = match hint.label {
+                    InlayHintLabel::String(label) => label,
+                    InlayHintLabel::LabelParts(_) => unreachable!(),
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inlay_hint() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("inlay_hint.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#module
+#deffunc myfunc int x, int y
+	return
+#defcfunc add int a, int b
+	return a + b
+#global
+	x = 1
+	myfunc 10, 20
+	myfunc x, add(1, 2)
+	mes 1
+"#
+            .into(),
+        );
+
+        assert_eq!(
+            hint_labels(&mut ls, uri),
+            vec![
+                (7, 8, "x:".to_string()),
+                (7, 12, "y:".to_string()),
+                (8, 11, "y:".to_string()),
+                (8, 15, "a:".to_string()),
+                (8, 18, "b:".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inlay_hint_default_value() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("inlay_hint_default_value.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#deffunc myfunc int x, int y = 10, int z
+	return
+	myfunc 1, , 3
+	myfunc , 2, 3
+"#
+            .into(),
+        );
+
+        // 省略された引数には既定値を表示する。(既定値がなければ何も表示しない。)
+        assert_eq!(
+            hint_labels(&mut ls, uri),
+            vec![
+                (2, 8, "x:".to_string()),
+                (2, 11, "y = 10".to_string()),
+                (2, 13, "z:".to_string()),
+                (3, 10, "y:".to_string()),
+                (3, 13, "z:".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inlay_hint_macro() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("inlay_hint_macro.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"#define ctype clamp(%1, %2, %3) limit(%1, %2, %3) ; (value, , high)
+	mes clamp(1, 0, 2)
+"#
+            .into(),
+        );
+
+        // 名前のないパラメータにはヒントを表示しない。
+        assert_eq!(
+            hint_labels(&mut ls, uri),
+            vec![(1, 11, "value:".to_string()), (1, 17, "high:".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_inlay_hint_types() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("inlay_hint_types.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            r#"	a = 1
+	a = "x"
+	b = 1.5 * a
+	sdim s, 64
+	t = 1, 2
+	u += 1
+"#
+            .into(),
+        );
+
+        // 既定では型を表示しない。
+        assert_eq!(hint_labels(&mut ls, uri.clone()), vec![]);
+
+        ls.set_inlay_hint_types(true);
+        assert_eq!(
+            hint_labels(&mut ls, uri),
+            vec![
+                (0, 2, ": int".to_string()),
+                (2, 2, ": double".to_string()),
+                (3, 7, ": str[]".to_string()),
+                (4, 2, ": int[]".to_string()),
+            ]
+        );
+    }
+}
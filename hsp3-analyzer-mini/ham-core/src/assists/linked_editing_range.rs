@@ -0,0 +1,87 @@
+//! 連動編集 (ラベルや `#deffunc` の名前を、同じファイル内の使用箇所と一緒に書き換える)
+
+use super::*;
+use lsp_types::{LinkedEditingRanges, Position, Url};
+
+/// 連動編集の対象になる種類のシンボルか？
+fn kind_is_target(kind: HspSymbolKind) -> bool {
+    match kind {
+        HspSymbolKind::Label
+        | HspSymbolKind::DefFunc
+        | HspSymbolKind::DefCFunc
+        | HspSymbolKind::ModFunc
+        | HspSymbolKind::ModCFunc => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn linked_editing_range(
+    uri: Url,
+    position: Position,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<LinkedEditingRanges> {
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    let project = wa.require_project_for_doc(doc);
+    let (symbol, _) = project.locate_symbol(doc, pos)?;
+    if !kind_is_target(symbol.kind) {
+        return None;
+    }
+
+    let mut locs = vec![];
+    project.collect_symbol_defs(&symbol, &mut locs);
+    project.collect_symbol_uses(&symbol, &mut locs);
+
+    // 他のファイルにも出現するときは、名前の変更を使ってもらう。
+    if locs.is_empty() || locs.iter().any(|loc| loc.doc != doc) {
+        return None;
+    }
+
+    locs.sort();
+    locs.dedup();
+
+    let ranges = locs
+        .into_iter()
+        .map(|loc| loc_to_range(rename::name_part(loc, wa)))
+        .collect();
+    Some(LinkedEditingRanges {
+        ranges,
+        word_pattern: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    fn linked_rows(ls: &mut LangService, uri: &Url, position: Position) -> Option<Vec<u32>> {
+        let ranges = ls.linked_editing_range(uri.clone(), position)?;
+        Some(ranges.ranges.iter().map(|r| r.start.line).collect())
+    }
+
+    #[test]
+    fn test_linked_editing_range() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("linked_editing_range.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tgosub *l\n\tgoto *l\n*l\n\treturn\n#deffunc f\n\treturn\n\tf\n\tx = 1\n".into(),
+        );
+
+        assert_eq!(
+            linked_rows(&mut ls, &uri, Position::new(2, 1)),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(
+            linked_rows(&mut ls, &uri, Position::new(6, 1)),
+            Some(vec![4, 6])
+        );
+
+        // 変数は対象外
+        assert_eq!(linked_rows(&mut ls, &uri, Position::new(7, 1)), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! モジュールの可視性のレポート (`ham/moduleVisibility` リクエスト)
+
+use super::*;
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ModuleVisibilityItem {
+    name: String,
+    module: Option<String>,
+    /// `"usedOutside"` または `"unusedOutside"`
+    hint: &'static str,
+    message: String,
+    location: Location,
+    external_uses: Vec<Location>,
+}
+
+pub(crate) fn module_visibility(
+    target_doc: impl Fn(DocId) -> bool,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Vec<ModuleVisibilityItem> {
+    let mut issues = vec![];
+    wa.collect_module_visibility_issues(&mut issues);
+    issues.sort_by_key(|issue| issue.def_loc);
+
+    issues
+        .into_iter()
+        .filter(|issue| target_doc(issue.def_loc.doc))
+        .filter_map(|issue| {
+            Some(ModuleVisibilityItem {
+                name: issue.symbol.name().to_string(),
+                module: issue.module_name_opt.as_ref().map(|name| name.to_string()),
+                hint: match issue.hint {
+                    ModuleVisibilityHint::UsedOutside => "usedOutside",
+                    ModuleVisibilityHint::UnusedOutside => "unusedOutside",
+                },
+                message: issue.message(),
+                location: loc_to_location(issue.def_loc, docs)?,
+                external_uses: issue
+                    .external_uses
+                    .iter()
+                    .filter_map(|&loc| loc_to_location(loc, docs))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+
+        ls.open_doc(
+            dummy_url("mod_visibility.hsp"),
+            NO_VERSION,
+            r#"
+#module m_visibility
+#deffunc local f_local
+    return
+#deffunc f_helper
+    return
+#deffunc f_api
+    f_helper
+    return
+#global
+
+    f_local@m_visibility
+    f_api
+"#
+            .into(),
+        );
+
+        let items = ls
+            .module_visibility()
+            .into_iter()
+            .map(|item| (item.name, item.hint))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                ("f_local".to_string(), "usedOutside"),
+                ("f_helper".to_string(), "unusedOutside"),
+            ]
+        );
+    }
+}
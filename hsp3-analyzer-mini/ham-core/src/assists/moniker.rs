@@ -0,0 +1,136 @@
+use super::*;
+use lsp_types::{Moniker, MonikerKind, Position, UniquenessLevel, Url};
+
+/// モニカーのスキーム
+const SCHEME: &str = "hsp3";
+
+/// ドキュメントの識別子として使うパスを計算する。
+///
+/// ワークスペース内のファイルはルートからの相対パスにする。
+/// それ以外のファイル (common など) は環境によってパスが異なるので、ファイル名だけを使う。
+fn doc_path(doc: DocId, root_uri_opt: Option<&CanonicalUri>, docs: &Docs) -> Option<String> {
+    let path = docs.get_uri(doc)?.to_file_path()?;
+
+    let relative_opt = root_uri_opt
+        .and_then(|root_uri| root_uri.to_file_path())
+        .and_then(|root_dir| Some(path.strip_prefix(&root_dir).ok()?.to_path_buf()));
+
+    let components = match &relative_opt {
+        Some(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        None => vec![path.file_name()?.to_string_lossy().into_owned()],
+    };
+    Some(components.join("/"))
+}
+
+pub(crate) fn moniker(
+    uri: Url,
+    position: Position,
+    root_uri_opt: Option<&CanonicalUri>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<Moniker>> {
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+    let project = wa.require_project_for_doc(doc);
+
+    let (symbol, _) = project.locate_symbol(doc, pos)?;
+
+    let mut def_locs = vec![];
+    project.collect_symbol_defs(&symbol, &mut def_locs);
+    let def_loc_opt = def_locs.into_iter().min();
+
+    let name = symbol.name();
+
+    // hsphelp に書かれているシンボル (組み込みの命令・関数など)
+    if symbol.kind == HspSymbolKind::Unknown {
+        return Some(vec![Moniker {
+            scheme: SCHEME.to_string(),
+            identifier: name.to_string(),
+            unique: UniquenessLevel::Scheme,
+            kind: Some(MonikerKind::Import),
+        }]);
+    }
+
+    let def_doc = def_loc_opt.map_or(doc, |loc| loc.doc);
+    let path = doc_path(def_doc, root_uri_opt, docs)?;
+
+    let moniker = match &symbol.ns_opt {
+        // 名前空間に属さないシンボル (パラメータなど) は定義箇所で識別する。
+        None => {
+            let row = def_loc_opt.map_or(0, |loc| loc.start_row());
+            Moniker {
+                scheme: SCHEME.to_string(),
+                identifier: format!("{}:{}:{}", path, row + 1, name),
+                unique: UniquenessLevel::Document,
+                kind: Some(MonikerKind::Local),
+            }
+        }
+        Some(ns) => Moniker {
+            scheme: SCHEME.to_string(),
+            identifier: format!("{}:{}@{}", path, name, ns),
+            unique: UniquenessLevel::Project,
+            kind: Some(if def_doc == doc {
+                MonikerKind::Export
+            } else {
+                MonikerKind::Import
+            }),
+        },
+    };
+    Some(vec![moniker])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+
+        ls.open_doc(
+            dummy_url("mod_moniker.hsp"),
+            NO_VERSION,
+            r#"
+#module m_moniker
+#deffunc f int a
+    return a
+#global
+            "#
+            .into(),
+        );
+
+        let main_uri = dummy_url("main.hsp");
+        ls.open_doc(main_uri.clone(), NO_VERSION, "f 1\n".into());
+
+        let identify = |ls: &mut LangService, uri: &Url, line: u32, character: u32| {
+            let monikers = ls
+                .moniker(uri.clone(), Position { line, character })
+                .expect("moniker");
+            let m = &monikers[0];
+            (m.identifier.clone(), m.unique, m.kind)
+        };
+
+        assert_eq!(
+            identify(&mut ls, &main_uri, 0, 0),
+            (
+                "mod_moniker.hsp:f@".to_string(),
+                UniquenessLevel::Project,
+                Some(MonikerKind::Import)
+            )
+        );
+
+        let mod_uri = dummy_url("mod_moniker.hsp");
+        assert_eq!(
+            identify(&mut ls, &mod_uri, 3, 11),
+            (
+                "mod_moniker.hsp:3:a".to_string(),
+                UniquenessLevel::Document,
+                Some(MonikerKind::Local)
+            )
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! 入力中の整形 (ドキュメンテーションコメントの継続など)
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{Position, TextEdit, Url};
+
+/// 行頭にあるコメントの開始部分 (インデント、`;` や `///`、後続の空白) を切り出す。
+fn comment_leader(line: &str) -> Option<&str> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+
+    let marker = if rest.starts_with(';') {
+        rest.chars().take_while(|&c| c == ';').count()
+    } else if rest.starts_with("///") {
+        rest.chars().take_while(|&c| c == '/').count()
+    } else {
+        return None;
+    };
+
+    let spaces = rest[marker..]
+        .chars()
+        .take_while(|&c| c == ' ' || c == '\t')
+        .count();
+    Some(&line[..indent + marker + spaces])
+}
+
+/// 行の先頭から指定された列 (UTF-16 単位) までの部分を切り出す。
+fn line_prefix(line: &str, column16: u32) -> &str {
+    let mut column = 0;
+    for (i, c) in line.char_indices() {
+        if column >= column16 as usize {
+            return &line[..i];
+        }
+        column += c.len_utf16();
+    }
+    line
+}
+
+/// 改行したとき、前の行がコメントならコメントの開始部分を次の行にも入れる。
+fn continue_comment(lines: &[&str], position: Position) -> Option<Vec<TextEdit>> {
+    let row = position.line as usize;
+    if row == 0 {
+        return None;
+    }
+
+    let leader = comment_leader(lines.get(row - 1)?)?;
+
+    // カーソルの前に空白以外のものがあるときは何もしない。
+    let prefix = line_prefix(lines.get(row).copied().unwrap_or(""), position.character);
+    if !prefix.trim().is_empty() {
+        return None;
+    }
+
+    Some(vec![TextEdit {
+        range: lsp_types::Range::new(Position::new(position.line, 0), position),
+        new_text: leader.to_string(),
+    }])
+}
+
+/// 命令・関数の定義の行番号とパラメータ名を集めるビジター。
+#[derive(Default)]
+struct V {
+    deffuncs: Vec<(usize, Vec<RcStr>)>,
+}
+
+impl PVisitor for V {
+    fn on_deffunc_stmt(&mut self, stmt: &PDefFuncStmt) {
+        let names = stmt
+            .params
+            .iter()
+            .filter_map(|param| Some(param.name_opt.as_ref()?.body.text.clone()))
+            .collect();
+        self.deffuncs.push((stmt.hash.body.loc.start_row(), names));
+
+        self.on_stmts(&stmt.stmts);
+    }
+}
+
+/// `#deffunc` などの直前の行で `///` を入力したとき、ドキュメンテーションコメントのひな形を展開する。
+fn expand_doc_template(lines: &[&str], position: Position, root: &PRoot) -> Option<Vec<TextEdit>> {
+    let row = position.line as usize;
+    let line = lines.get(row)?;
+    if line.trim() != "///" || line_prefix(line, position.character) != line.trim_end() {
+        return None;
+    }
+
+    let params = {
+        let mut v = V::default();
+        v.on_root(root);
+        v.deffuncs
+            .into_iter()
+            .find(|&(r, _)| r == row + 1)
+            .map(|(_, params)| params)?
+    };
+
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let param_lines = params
+        .iter()
+        .map(|name| format!("{}/// {}: \n", indent, name))
+        .collect::<String>();
+
+    let mut edits = vec![TextEdit {
+        range: lsp_types::Range::new(position, position),
+        new_text: " ".to_string(),
+    }];
+    if !param_lines.is_empty() {
+        let next_line = Position::new(position.line + 1, 0);
+        edits.push(TextEdit {
+            range: lsp_types::Range::new(next_line, next_line),
+            new_text: param_lines,
+        });
+    }
+    Some(edits)
+}
+
+pub(crate) fn on_type_formatting(
+    uri: Url,
+    position: Position,
+    ch: &str,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<TextEdit>> {
+    let (doc, _) = from_document_position(&uri, position, docs)?;
+    let DocSyntax { text, root, .. } = wa.get_syntax(doc)?;
+    let lines = text.lines().collect::<Vec<_>>();
+
+    match ch {
+        "\n" => continue_comment(&lines, position),
+        "/" => expand_doc_template(&lines, position, root),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_continue_comment() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("on_type_formatting.hsp");
+        ls.open_doc(uri.clone(), NO_VERSION, "\t; 概要\n\t\nmes 1\n".into());
+
+        let edits = ls
+            .on_type_formatting(uri.clone(), Position::new(1, 1), "\n")
+            .expect("edits");
+        assert_eq!(
+            edits
+                .iter()
+                .map(|e| e.new_text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["\t; "]
+        );
+
+        assert!(ls
+            .on_type_formatting(uri, Position::new(3, 0), "\n")
+            .is_none());
+    }
+
+    #[test]
+    fn test_expand_doc_template() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("on_type_formatting.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n///\n#deffunc f int a, str b\n\treturn\n#global\n".into(),
+        );
+
+        let edits = ls
+            .on_type_formatting(uri, Position::new(1, 3), "/")
+            .expect("edits");
+        assert_eq!(
+            edits
+                .iter()
+                .map(|e| e.new_text.as_str())
+                .collect::<Vec<_>>(),
+            vec![" ", "/// a: \n/// b: \n"]
+        );
+    }
+}
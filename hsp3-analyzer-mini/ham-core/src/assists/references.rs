@@ -1,5 +1,22 @@
 use super::*;
 
+/// カーソルが `#include` 文の上にあるなら、includeされるドキュメントを直接または推移的にincludeしている
+/// `#include` 文の位置を集める。
+fn include_references(doc: DocId, pos: Pos16, wa: &mut WorkspaceAnalysis) -> Option<Vec<Loc>> {
+    let graph = wa.include_graph();
+    let &(_, target) = graph
+        .includes(doc)
+        .iter()
+        .find(|(loc, _)| loc.is_touched(doc, pos))?;
+    Some(
+        graph
+            .includers(target)
+            .into_iter()
+            .map(|(loc, _)| loc)
+            .collect(),
+    )
+}
+
 pub(crate) fn references(
     uri: Url,
     position: Position,
@@ -8,14 +25,34 @@ pub(crate) fn references(
     wa: &mut WorkspaceAnalysis,
 ) -> Option<Vec<Location>> {
     let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    if let Some(locs) = include_references(doc, pos, wa) {
+        return Some(
+            locs.into_iter()
+                .filter_map(|loc| loc_to_location(loc, docs))
+                .collect(),
+        );
+    }
+
     let project = wa.require_project_for_doc(doc);
     let (symbol, _) = project.locate_symbol(doc, pos)?;
 
+    // 別名のマクロは展開先の命令とみなして、別名を通した使用箇所もまとめて列挙する。
+    let symbol = project.resolve_alias(&symbol).unwrap_or(symbol);
+    let mut symbols = vec![symbol.clone()];
+    project.collect_aliases(&symbol, &mut symbols);
+
     let mut locs = vec![];
-    if include_definition {
-        project.collect_symbol_defs(&symbol, &mut locs);
+    for symbol in &symbols {
+        if include_definition {
+            project.collect_symbol_defs(symbol, &mut locs);
+        }
+        project.collect_symbol_uses(symbol, &mut locs);
     }
-    project.collect_symbol_uses(&symbol, &mut locs);
+
+    // 1つの出現が定義と使用の両方にカウントされることもあるので、重複を削除する。
+    locs.sort();
+    locs.dedup();
 
     Some(
         locs.into_iter()
@@ -23,3 +60,233 @@ pub(crate) fn references(
             .collect(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+
+        let mod_uri = dummy_url("mod_references.as");
+        ls.open_doc(
+            mod_uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc f\n\treturn\n#global\n".into(),
+        );
+        let main_uri = dummy_url("main.hsp");
+        ls.open_doc(main_uri.clone(), NO_VERSION, "\tf\n\tf\n".into());
+
+        let rows = |locations: Vec<Location>| {
+            let mut rows = locations
+                .into_iter()
+                .map(|l| (l.uri == main_uri, l.range.start.line))
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+
+        let uses = ls.references(main_uri.clone(), Position::new(0, 1), false);
+        assert_eq!(rows(uses), vec![(true, 0), (true, 1)]);
+
+        let all = ls.references(main_uri.clone(), Position::new(0, 1), true);
+        assert_eq!(rows(all), vec![(false, 1), (true, 0), (true, 1)]);
+    }
+
+    #[test]
+    fn test_unnamed_module() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("unnamed_module_references.hsp");
+        let sub_uri = dummy_url("unnamed_module_sub.as");
+        ls.open_doc(
+            sub_uri.clone(),
+            NO_VERSION,
+            "#module\n\tcounter = 5\n#deffunc local inc\n\tcounter++\n\treturn\n#global\n".into(),
+        );
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#include \"unnamed_module_sub.as\"\n#module\n\tcounter = 0\n#deffunc local inc\n\tcounter++\n\treturn\n#deffunc step\n\tinc\n\tmes counter\n\treturn\n#global\n\tstep\n\tmes counter@\n".into(),
+        );
+
+        let rows = |ls: &mut LangService, line: u32, character: u32| {
+            let mut rows = ls
+                .references(uri.clone(), Position::new(line, character), true)
+                .into_iter()
+                .map(|l| (l.uri.path().ends_with(".as"), l.range.start.line))
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+        // 名前のないモジュールの変数や命令は、ほかのファイルの名前のないモジュールのものと区別される。
+        // (モジュールの表示用の名前 `module#N` は解決に影響しない。)
+        assert_eq!(
+            rows(&mut ls, 2, 2),
+            vec![(false, 2), (false, 4), (false, 8)]
+        );
+        assert_eq!(rows(&mut ls, 7, 2), vec![(false, 3), (false, 7)]);
+        assert_eq!(rows(&mut ls, 12, 6), vec![(false, 12)]);
+    }
+
+    #[test]
+    fn test_unnamed_module_ns() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let uri = dummy_url("unnamed_module_ns.hsp");
+        ls.open_doc(
+            dummy_url("unnamed_module_ns_sub.as"),
+            NO_VERSION,
+            "#module\n\tcounter = 5\n#deffunc local inc\n\tcounter++\n\treturn\n#global\n".into(),
+        );
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#module\n\tcounter = 0\n#global\n#include \"unnamed_module_ns_sub.as\"\n\tinc@M1\n\tmes counter@M1, counter@M0\n".into(),
+        );
+
+        let rows = |ls: &mut LangService, line: u32, character: u32| {
+            let mut rows = ls
+                .references(uri.clone(), Position::new(line, character), true)
+                .into_iter()
+                .map(|l| (l.uri.path().ends_with(".as"), l.range.start.line))
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+        // 名前のないモジュールのシンボルは、コンパイラがつける名前空間の名前で修飾して参照できる。
+        assert_eq!(rows(&mut ls, 5, 2), vec![(false, 5), (true, 2)]);
+        assert_eq!(rows(&mut ls, 6, 6), vec![(false, 6), (true, 1), (true, 3)]);
+        assert_eq!(rows(&mut ls, 6, 20), vec![(false, 2), (false, 6)]);
+    }
+
+    #[test]
+    fn test_undef() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("undef_references.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#define VALUE 1\n\tmes VALUE\n#undef VALUE\n#define VALUE 2\n\tmes VALUE\n#undef VALUE\n"
+                .into(),
+        );
+
+        let mut rows = |line: u32, character: u32| {
+            let mut rows = ls
+                .references(uri.clone(), Position::new(line, character), true)
+                .into_iter()
+                .map(|l| l.range.start.line)
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+
+        // 使用箇所では、その位置で有効な定義を指す。
+        assert_eq!(rows(1, 6), vec![0, 1, 2]);
+        assert_eq!(rows(4, 6), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_label() {
+        let mut ls = LangService::new_standalone();
+
+        let sub_uri = dummy_url("label_references_sub.hsp");
+        ls.open_doc(
+            sub_uri.clone(),
+            NO_VERSION,
+            "*on_click\n\tgoto *l_main\n".into(),
+        );
+        let main_uri = dummy_url("label_references.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "\tonclick gosub *on_click\n\tbutton gosub \"OK\", *on_click\n*l_main\n\tgoto *l_main\n#include \"label_references_sub.hsp\"\n"
+                .into(),
+        );
+
+        let mut rows = |uri: &Url, line: u32, character: u32| {
+            let mut rows = ls
+                .references(uri.clone(), Position::new(line, character), true)
+                .into_iter()
+                .map(|l| (l.uri == main_uri, l.range.start.line))
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+
+        // includeしたファイルのラベルも解決される。
+        assert_eq!(
+            rows(&main_uri, 0, 17),
+            vec![(false, 0), (true, 0), (true, 1)]
+        );
+        assert_eq!(
+            rows(&main_uri, 3, 8),
+            vec![(false, 1), (true, 2), (true, 3)]
+        );
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("alias_references.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc greet\n\treturn\n#global\n#define hello greet\n\thello\n\tgreet\n"
+                .into(),
+        );
+
+        let mut rows = |line: u32, character: u32, include_definition: bool| {
+            let mut rows = ls
+                .references(
+                    uri.clone(),
+                    Position::new(line, character),
+                    include_definition,
+                )
+                .into_iter()
+                .map(|l| l.range.start.line)
+                .collect::<Vec<_>>();
+            rows.sort();
+            rows
+        };
+
+        // 別名を通した呼び出しも、展開先の命令の参照に含まれる。
+        assert_eq!(rows(6, 2, false), vec![4, 5, 6]);
+        assert_eq!(rows(5, 2, false), vec![4, 5, 6]);
+        // 別名の定義 (4行目の `hello`) も定義箇所に含まれる。
+        assert_eq!(rows(5, 2, true), vec![1, 4, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_macro_body() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("macro_references.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#define ctype add_offset(%1) (%1 + offset)\n#define ctype twice(%1) add_offset(add_offset(%1))\n\toffset = 1\n\tmes twice(2)\n"
+                .into(),
+        );
+
+        // マクロの本体に書かれた名前も、マクロを使っている場所で解決される。
+        let mut rows = ls
+            .references(uri.clone(), Position::new(2, 1), true)
+            .into_iter()
+            .map(|l| (l.range.start.line, l.range.start.character))
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![(0, 35), (2, 1)]);
+
+        // 入れ子のマクロの名前も使用箇所になる。
+        let mut rows = ls
+            .references(uri, Position::new(0, 16), false)
+            .into_iter()
+            .map(|l| (l.range.start.line, l.range.start.character))
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![(1, 24), (1, 35)]);
+    }
+}
@@ -4,29 +4,121 @@ use lsp_types::{
     PrepareRenameResponse, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
 };
 
+/// 名前を変更できないキーワード
+static RESERVED_WORDS: &[&str] = &[
+    "if",
+    "else",
+    "ctype",
+    "global",
+    "local",
+    "int",
+    "double",
+    "str",
+    "label",
+    "var",
+    "array",
+    "modvar",
+    "onexit",
+    "thismod",
+    "_break",
+    "_continue",
+];
+
+/// 名前を変更してもよいドキュメントか？
+///
+/// HSP3 のインストールディレクトリにあるファイルや、commonやincludeディレクトリから開いたファイルは変更しない。
+fn is_renameable_doc(
+    doc: DocId,
+    user_doc: &impl Fn(DocId) -> bool,
+    wa: &WorkspaceAnalysis,
+) -> bool {
+    user_doc(doc) && wa.common_doc_name(doc).is_none()
+}
+
 pub(crate) fn prepare_rename(
     uri: Url,
     position: Position,
+    user_doc: impl Fn(DocId) -> bool,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
 ) -> Option<PrepareRenameResponse> {
     let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    // 文字列やコメントの中は名前変更の対象外
+    if wa.in_str_or_comment(doc, pos).unwrap_or(true) {
+        return None;
+    }
+
     let project = wa.require_project_for_doc(doc);
+    let (symbol, loc) = project.locate_symbol(doc, pos)?;
+
+    // 組み込みの命令・関数やキーワードは名前を変更できない。
+    match symbol.kind {
+        HspSymbolKind::Unresolved | HspSymbolKind::Unknown | HspSymbolKind::PluginCmd => {
+            return None
+        }
+        _ => {}
+    }
+    if RESERVED_WORDS.contains(&symbol.name().as_str()) {
+        return None;
+    }
 
-    // FIXME: カーソル直下に識別子があって、それの定義がワークスペース内のファイル (commonやhsphelpでない) にあったときだけSomeを返す。
+    // 定義がワークスペース内のファイル (commonやhsphelpでない) にあるときだけ名前を変更できる。
+    let mut def_locs = vec![];
+    project.collect_symbol_defs(&symbol, &mut def_locs);
+    if def_locs.is_empty() {
+        return None;
+    }
+    if !def_locs
+        .iter()
+        .all(|def_loc| is_renameable_doc(def_loc.doc, &user_doc, wa))
+    {
+        return None;
+    }
 
-    let (_, loc) = project.locate_symbol(doc, pos)?;
-    let range = loc_to_range(loc);
+    let range = loc_to_range(name_part(loc, wa));
     Some(PrepareRenameResponse::Range(range))
 }
 
+/// 識別子として有効な名前か？ (`@` による名前空間の指定は含まない。)
+fn is_valid_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first = match chars.next() {
+        Some(it) => it,
+        None => return false,
+    };
+
+    let is_ident_char = |c: char| {
+        c == '_' || c == '`' || c.is_alphanumeric() || (!c.is_ascii() && !c.is_whitespace())
+    };
+    !first.is_ascii_digit() && is_ident_char(first) && chars.all(is_ident_char)
+}
+
+/// `f@m` のような名前空間つきの識別子は、`@` より前の部分だけを置き換える。
+pub(crate) fn name_part(loc: Loc, wa: &mut WorkspaceAnalysis) -> Loc {
+    let text = match wa.get_ident_at(loc.doc, Pos16::from(loc.start())) {
+        Some((text, ident_loc)) if ident_loc == loc => text,
+        _ => return loc,
+    };
+
+    match text.find('@') {
+        Some(i) if i > 0 => Loc::new3(loc.doc, loc.start(), loc.start() + Pos::from(&text[..i])),
+        _ => loc,
+    }
+}
+
 pub(crate) fn rename(
     uri: Url,
     position: Position,
     new_name: String,
+    user_doc: impl Fn(DocId) -> bool,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
 ) -> Option<WorkspaceEdit> {
+    if !is_valid_ident(&new_name) {
+        return None;
+    }
+
     // カーソルの下にある識別子と同一のシンボルの出現箇所 (定義箇所および使用箇所) を列挙する。
     let locs = {
         let (doc, pos) = from_document_position(&uri, position, docs)?;
@@ -46,13 +138,21 @@ pub(crate) fn rename(
         locs.sort();
         locs.dedup();
 
-        locs
+        locs.into_iter()
+            .map(|loc| name_part(loc, wa))
+            .collect::<Vec<_>>()
     };
 
     // 名前変更の編集手順を構築する。(シンボルが書かれている位置をすべて新しい名前で置き換える。)
+    // 編集はドキュメントごとにまとめる。(locsはドキュメントの順に並んでいる。)
     let changes = {
-        let mut edits = vec![];
+        let mut edits: Vec<(DocId, TextDocumentEdit)> = vec![];
         for loc in locs {
+            // common ディレクトリなどのファイルは変更しない。
+            if !is_renameable_doc(loc.doc, &user_doc, wa) {
+                return None;
+            }
+
             let location = match loc_to_location(loc, docs) {
                 Some(location) => location,
                 None => continue,
@@ -60,29 +160,32 @@ pub(crate) fn rename(
 
             let (uri, range) = (location.uri, location.range);
 
-            // common ディレクトリのファイルは変更しない。
-            if uri.as_str().contains("common") {
-                return None;
-            }
-
-            let version = docs.get_version(loc.doc).unwrap_or(NO_VERSION);
-
-            let text_document = OptionalVersionedTextDocumentIdentifier {
-                uri,
-                version: Some(version),
-            };
             let text_edit = TextEdit {
                 range,
                 new_text: new_name.to_string(),
             };
 
-            edits.push(TextDocumentEdit {
-                text_document,
-                edits: vec![OneOf::Left(text_edit)],
-            });
+            match edits.last_mut() {
+                Some((doc, edit)) if *doc == loc.doc => edit.edits.push(OneOf::Left(text_edit)),
+                _ => {
+                    let version = docs.get_version(loc.doc).unwrap_or(NO_VERSION);
+
+                    let text_document = OptionalVersionedTextDocumentIdentifier {
+                        uri,
+                        version: Some(version),
+                    };
+                    edits.push((
+                        loc.doc,
+                        TextDocumentEdit {
+                            text_document,
+                            edits: vec![OneOf::Left(text_edit)],
+                        },
+                    ));
+                }
+            }
         }
 
-        DocumentChanges::Edits(edits)
+        DocumentChanges::Edits(edits.into_iter().map(|(_, edit)| edit).collect())
     };
 
     Some(WorkspaceEdit {
@@ -90,3 +193,156 @@ pub(crate) fn rename(
         ..WorkspaceEdit::default()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_url, TempDir};
+    use std::fs;
+
+    #[test]
+    fn test_prepare_rename() {
+        let mut ls = LangService::new_standalone();
+
+        let uri = dummy_url("prepare_rename.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#deffunc f int a\n\tmes \"f\" ; f\n\treturn\n\tf 1\n".into(),
+        );
+
+        let prepare = |ls: &mut LangService, line: u32, character: u32| match ls
+            .prepare_rename(uri.clone(), Position::new(line, character))
+        {
+            Some(PrepareRenameResponse::Range(range)) => {
+                Some((range.start.line, range.start.character, range.end.character))
+            }
+            _ => None,
+        };
+
+        // ユーザー定義の命令とパラメータ
+        assert_eq!(prepare(&mut ls, 3, 1), Some((3, 1, 2)));
+        assert_eq!(prepare(&mut ls, 0, 15), Some((0, 15, 16)));
+        // 文字列とコメントの中
+        assert_eq!(prepare(&mut ls, 1, 6), None);
+        assert_eq!(prepare(&mut ls, 1, 11), None);
+        // 定義のない命令
+        assert_eq!(prepare(&mut ls, 1, 1), None);
+        // キーワード
+        assert_eq!(prepare(&mut ls, 0, 11), None);
+    }
+
+    #[test]
+    fn test_rename_across_docs() {
+        let mut ls = LangService::new_standalone();
+
+        let mod_uri = dummy_url("mod_rename.as");
+        ls.open_doc(
+            mod_uri.clone(),
+            NO_VERSION,
+            "#module m_rename\n#deffunc local f\n\treturn\n#deffunc g\n\tf\n\treturn\n#global\n"
+                .into(),
+        );
+        let main_uri = dummy_url("main.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "#include \"mod_rename.as\"\n\tf@m_rename\n".into(),
+        );
+
+        assert!(ls
+            .rename(main_uri.clone(), Position::new(1, 1), "1bad".into())
+            .is_none());
+
+        let edit = ls
+            .rename(main_uri.clone(), Position::new(1, 1), "h".into())
+            .expect("rename");
+        let edits = match edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            _ => unreachable!(),
+        };
+
+        let mut summary = edits
+            .iter()
+            .map(|edit| {
+                let ranges = edit
+                    .edits
+                    .iter()
+                    .map(|e| match e {
+                        OneOf::Left(e) => (
+                            e.range.start.line,
+                            e.range.start.character,
+                            e.range.end.character,
+                        ),
+                        OneOf::Right(e) => unreachable!("{:?}", e),
+                    })
+                    .collect::<Vec<_>>();
+                (edit.text_document.uri == main_uri, ranges)
+            })
+            .collect::<Vec<_>>();
+        summary.sort();
+        assert_eq!(
+            summary,
+            vec![
+                (false, vec![(1, 15, 16), (4, 1, 2)]),
+                // `@m_rename` は残す。
+                (true, vec![(1, 1, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_in_file_named_common() {
+        let mut ls = LangService::new_standalone();
+
+        // ファイル名に "common" を含んでいても、ワークスペースのファイルなら名前を変更できる。
+        let uri = dummy_url("common_utils.as");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#deffunc common_hello\n\treturn\n\tcommon_hello\n".into(),
+        );
+
+        assert!(ls
+            .prepare_rename(uri.clone(), Position::new(2, 1))
+            .is_some());
+        assert!(ls
+            .rename(uri.clone(), Position::new(2, 1), "hello".into())
+            .is_some());
+    }
+
+    #[test]
+    fn test_rename_symbol_in_common() {
+        let temp = TempDir::new("rename-symbol-in-common");
+        let root = temp.path().join("ws");
+        let common_dir = temp.path().join("hsp3").join("common");
+        for d in [&root, &common_dir] {
+            fs::create_dir_all(d).unwrap();
+        }
+        fs::write(
+            common_dir.join("rename_lib.as"),
+            "#deffunc rename_lib_hello\n\treturn\n",
+        )
+        .unwrap();
+        let main_uri = Url::from_file_path(root.join("main.hsp")).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.set_hsp3_root(temp.path().join("hsp3"));
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.did_initialize();
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "#include \"rename_lib.as\"\n\trename_lib_hello\n".into(),
+        );
+
+        // commonにあるファイルで定義された命令は名前を変更できない。
+        assert!(ls
+            .prepare_rename(main_uri.clone(), Position::new(1, 1))
+            .is_none());
+        assert!(ls
+            .rename(main_uri.clone(), Position::new(1, 1), "hello".into())
+            .is_none());
+    }
+}
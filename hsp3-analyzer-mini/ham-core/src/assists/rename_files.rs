@@ -0,0 +1,182 @@
+//! ファイルの名前の変更に合わせた `#include` の書き換え (`workspace/willRenameFiles`)
+
+use super::*;
+use crate::{
+    assists::{document_link::collect_include_file_paths, rewrites::add_include::relative_path},
+    utils::canonical_path::canonicalize_path,
+};
+use lsp_types::{
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit,
+    WorkspaceEdit,
+};
+use std::path::{Path, PathBuf};
+
+/// 名前の変更によってパスがどう変わるか。(ディレクトリの名前が変わるときは、その中にあるファイルも移動する。)
+fn renamed_path(renames: &[(PathBuf, PathBuf)], path: &Path) -> Option<PathBuf> {
+    renames.iter().find_map(|(old_path, new_path)| {
+        let rest = path.strip_prefix(old_path).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            new_path.clone()
+        } else {
+            new_path.join(rest)
+        })
+    })
+}
+
+/// ファイルやディレクトリの名前が変更されるとき、それを参照している `#include` を書き換える編集を作る。
+///
+/// includeされるファイルのパスは、includeするファイルがあるディレクトリからの相対パスにする。
+/// commonディレクトリのファイルは変更しない。
+pub(crate) fn will_rename_files(
+    renames: Vec<(Url, Url)>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<WorkspaceEdit> {
+    let renames = renames
+        .into_iter()
+        .filter_map(|(old_uri, new_uri)| {
+            let old_path = CanonicalUri::from_url(&old_uri).to_file_path()?;
+            // 変更後のパスはまだ存在しないので、存在する部分だけ正規化される。
+            let new_path = canonicalize_path(&new_uri.to_file_path().ok()?);
+            Some((old_path, new_path))
+        })
+        .collect::<Vec<_>>();
+    if renames.is_empty() {
+        return None;
+    }
+
+    let mut doc_uris = docs
+        .iter()
+        .filter(|&(doc, _)| wa.common_doc_name(doc).is_none())
+        .map(|(doc, uri)| (doc, uri.clone()))
+        .collect::<Vec<_>>();
+    doc_uris.sort_by_key(|&(doc, _)| doc);
+
+    let mut edits = vec![];
+    for (doc, uri) in doc_uris {
+        let doc_path = match uri.to_file_path() {
+            Some(it) => it,
+            None => continue,
+        };
+        let doc_dir = match doc_path.parent() {
+            Some(it) => it.to_path_buf(),
+            None => continue,
+        };
+        // includeするファイル自身も移動するなら、移動先のディレクトリからの相対パスにする。
+        let new_doc_dir = renamed_path(&renames, &doc_path)
+            .and_then(|path| Some(path.parent()?.to_path_buf()))
+            .unwrap_or_else(|| doc_dir.clone());
+
+        let includes = match collect_include_file_paths(doc, wa) {
+            Some(it) => it,
+            None => continue,
+        };
+
+        let text_edits = includes
+            .into_iter()
+            .filter_map(|(file_path_loc, text, target_doc_opt)| {
+                let name = text.as_str().trim_matches('"');
+
+                // 解決できないincludeは、includeするファイルからの相対パスとみなす。
+                let target = match target_doc_opt.and_then(|d| docs.get_uri(d)?.to_file_path()) {
+                    Some(path) => path,
+                    None => canonicalize_path(&doc_dir.join(name.replace("\\\\", "/"))),
+                };
+                let new_target = renamed_path(&renames, &target)?;
+
+                let mut new_name = relative_path(&new_doc_dir, &new_target)?;
+                // 区切り文字の書き方を元に合わせる。
+                if name.contains('\\') {
+                    new_name = new_name.replace('/', "\\\\");
+                }
+                if new_name == name {
+                    return None;
+                }
+
+                Some(OneOf::Left(TextEdit {
+                    range: loc_to_range(file_path_loc),
+                    new_text: format!("\"{}\"", new_name),
+                }))
+            })
+            .collect::<Vec<_>>();
+        if text_edits.is_empty() {
+            continue;
+        }
+
+        edits.push(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri.into_url(),
+                version: docs.get_version(doc),
+            },
+            edits: text_edits,
+        });
+    }
+
+    if edits.is_empty() {
+        return None;
+    }
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        ..WorkspaceEdit::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::{DocumentChanges, OneOf, Url};
+
+    #[test]
+    fn test_will_rename_files() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        ls.open_doc(
+            dummy_url("main.hsp"),
+            NO_VERSION,
+            "; ham-entry\n#include \"lib/a.as\"\n#include \"b.as\"\n".into(),
+        );
+        ls.open_doc(dummy_url("lib/a.as"), NO_VERSION, "".into());
+        ls.open_doc(
+            dummy_url("sub/c.hsp"),
+            NO_VERSION,
+            "#include \"..\\\\lib\\\\a.as\"\n".into(),
+        );
+        ls.open_doc(dummy_url("b.as"), NO_VERSION, "".into());
+
+        let edit = ls
+            .will_rename_files(vec![(dummy_url("lib/a.as"), dummy_url("lib2/a2.as"))])
+            .unwrap();
+        let edits = match edit.document_changes {
+            Some(DocumentChanges::Edits(it)) => it,
+            _ => panic!("edits expected"),
+        };
+        let actual = edits
+            .into_iter()
+            .flat_map(|edit| {
+                let uri = edit.text_document.uri;
+                edit.edits.into_iter().map(move |e| match e {
+                    OneOf::Left(e) => (uri.clone(), e.range.start.line, e.new_text),
+                    OneOf::Right(e) => (
+                        uri.clone(),
+                        e.text_edit.range.start.line,
+                        e.text_edit.new_text,
+                    ),
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actual,
+            vec![
+                (dummy_url("main.hsp"), 1, "\"lib2/a2.as\"".to_string()),
+                (
+                    dummy_url("sub/c.hsp"),
+                    0,
+                    "\"..\\\\lib2\\\\a2.as\"".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! 文字列リテラルの中だけを正規表現で置換する機能
+//!
+//! 識別子やコメントには触れないので、アセットのパスを書き換えるときなどに使える。
+
+use super::*;
+use crate::token::TokenData;
+use lsp_types::{
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit,
+    WorkspaceEdit,
+};
+use regex::Regex;
+
+/// 文字列リテラルから引用符を除いた部分の範囲と内容を得る。
+fn str_content(token: &TokenData) -> Option<(crate::source::Range, &str)> {
+    if token.kind != TokenKind::Str {
+        return None;
+    }
+
+    let text = token.text.as_str();
+    let (prefix, suffix) = if text.starts_with("{\"") {
+        ("{\"", "\"}")
+    } else {
+        ("\"", "\"")
+    };
+
+    // 閉じられていない文字列リテラルは終端の引用符がない。
+    let content = text.strip_prefix(prefix)?;
+    let content = content.strip_suffix(suffix).unwrap_or(content);
+
+    let start = token.loc.start() + Pos::from(prefix);
+    let end = start + Pos::from(content);
+    Some((crate::source::Range::from(start..end), content))
+}
+
+/// 文字列リテラルの中身を置換したときの変更点を列挙する。
+pub(crate) fn replace_in_str_tokens<'a>(
+    tokens: impl Iterator<Item = &'a TokenData>,
+    regex: &Regex,
+    replacement: &str,
+    edits: &mut Vec<(crate::source::Range, String)>,
+) {
+    for token in tokens {
+        let (range, content) = match str_content(token) {
+            Some(it) => it,
+            None => continue,
+        };
+
+        if !regex.is_match(content) {
+            continue;
+        }
+
+        let new_text = regex.replace_all(content, replacement);
+        if new_text != content {
+            edits.push((range, new_text.into_owned()));
+        }
+    }
+}
+
+/// ワークスペース内のドキュメントの文字列リテラルを置換する編集を生成する。
+pub(crate) fn replace_in_strings(
+    regex: &Regex,
+    replacement: &str,
+    target_docs: &[DocId],
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<WorkspaceEdit> {
+    let mut changes = vec![];
+
+    for &doc in target_docs {
+        let tokens = match wa.get_syntax(doc) {
+            Some(syntax) => syntax.tokens,
+            None => continue,
+        };
+
+        let mut edits = vec![];
+        replace_in_str_tokens(
+            tokens.iter().map(|t| t.body.as_ref()),
+            regex,
+            replacement,
+            &mut edits,
+        );
+        if edits.is_empty() {
+            continue;
+        }
+
+        let uri = match docs.get_uri(doc) {
+            Some(it) => it.clone().into_url(),
+            None => continue,
+        };
+
+        changes.push(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri,
+                version: docs.get_version(doc),
+            },
+            edits: edits
+                .into_iter()
+                .map(|(range, new_text)| {
+                    OneOf::Left(TextEdit {
+                        range: to_lsp_range(range),
+                        new_text,
+                    })
+                })
+                .collect(),
+        });
+    }
+
+    if changes.is_empty() {
+        return None;
+    }
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(changes)),
+        ..WorkspaceEdit::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::replace_in_strings_fn;
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_outside_strings_are_kept() {
+        let text = r#"; "a.bmp" はコメント
+	a_bmp = 1 : a.bmp = 2
+	picload "a.bmp" : mes "x.bmp"
+	mes "a.bmp
+"#;
+        let output = replace_in_strings_fn(text.into(), r"a\.bmp", "b.png").unwrap();
+        assert_eq!(
+            output,
+            r#"; "a.bmp" はコメント
+	a_bmp = 1 : a.bmp = 2
+	picload "b.png" : mes "x.bmp"
+	mes "b.png
+"#
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        // エスケープシーケンスは書かれたままのテキストに対してマッチさせる。
+        let text = r#"	mes "say \"a.bmp\"" : mes "img\\a.bmp"
+"#;
+        let output = replace_in_strings_fn(text.into(), r"a\.bmp", "b.png").unwrap();
+        assert_eq!(
+            output,
+            r#"	mes "say \"b.png\"" : mes "img\\b.png"
+"#
+        );
+
+        let output = replace_in_strings_fn(text.into(), r"\\\\", "/").unwrap();
+        assert_eq!(
+            output,
+            r#"	mes "say \"a.bmp\"" : mes "img/a.bmp"
+"#
+        );
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let text = "\tmes {\"a.bmp\n\"a.bmp\"\n}\" : mes {\"a.bmp\"}\n";
+        let output = replace_in_strings_fn(text.into(), r"a\.bmp", "b.png").unwrap();
+        assert_eq!(output, "\tmes {\"b.png\n\"b.png\"\n}\" : mes {\"b.png\"}\n");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(replace_in_strings_fn("mes \"a\"".into(), "(", "").is_err());
+    }
+
+    #[test]
+    fn test_replace_in_strings() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("replace_in_strings.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tpicload \"a.bmp\" ; \"a.bmp\"\n\tmes {\"a.bmp\"}\n".into(),
+        );
+
+        let edit = ls.replace_in_strings(r"\.bmp$", ".png").unwrap().unwrap();
+        let edits = match edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            _ => unreachable!(),
+        };
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].text_document.uri, uri);
+
+        // 引用符の内側だけを置き換える。
+        let summary = edits[0]
+            .edits
+            .iter()
+            .map(|e| match e {
+                OneOf::Left(e) => (
+                    e.range.start.line,
+                    e.range.start.character,
+                    e.range.end.character,
+                    e.new_text.as_str(),
+                ),
+                OneOf::Right(e) => unreachable!("{:?}", e),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![(0, 10, 15, "a.png"), (1, 7, 12, "a.png")]);
+
+        assert!(ls.replace_in_strings("no_match", "").unwrap().is_none());
+    }
+}
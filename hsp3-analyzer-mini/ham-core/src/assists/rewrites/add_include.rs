@@ -0,0 +1,152 @@
+//! 未解決のシンボルを定義しているファイルを `#include` するアクション
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{CodeAction, CodeActionKind, Position, Range, TextEdit, Url};
+use std::path::{Component, Path};
+
+/// ディレクトリ `base` からファイル `target` への相対パスを `/` 区切りで計算する。
+pub(crate) fn relative_path(base: &Path, target: &Path) -> Option<String> {
+    let base = base.components().collect::<Vec<_>>();
+    let target = target.components().collect::<Vec<_>>();
+
+    let common = base.iter().zip(&target).take_while(|(l, r)| l == r).count();
+    // ドライブが異なるときなど、共通の親がなければ相対パスにできない。
+    if common == 0 {
+        return None;
+    }
+
+    let mut parts = vec![];
+    for _ in &base[common..] {
+        parts.push("..".to_string());
+    }
+    for c in &target[common..] {
+        match c {
+            Component::Normal(name) => parts.push(name.to_str()?.to_string()),
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// `#include` 文を挿入する行。既存の `#include` 文があればその後ろ、なければ先頭とする。
+pub(crate) fn insert_row(root: &PRoot) -> u32 {
+    root.stmts
+        .iter()
+        .rev()
+        .find_map(|stmt| match stmt {
+            PStmt::Include(stmt) => Some(stmt.hash.body.loc.start_row() as u32 + 1),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+pub(crate) fn add_include(
+    uri: &Url,
+    range: Range,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<CodeAction>> {
+    let (doc, pos) = from_document_position(uri, range.start, docs)?;
+    let version = docs.get_version(doc);
+
+    let (symbol, _) = wa.require_project_for_doc(doc).locate_symbol(doc, pos)?;
+    if symbol.kind != HspSymbolKind::Unresolved {
+        return None;
+    }
+
+    let mut target_docs = vec![];
+    wa.collect_docs_defining(symbol.name.as_str(), &mut target_docs);
+    target_docs.retain(|&d| d != doc);
+    if target_docs.is_empty() {
+        return None;
+    }
+
+    let path = docs.get_uri(doc)?.to_file_path()?;
+    let base_dir = path.parent()?;
+
+    let include_paths = target_docs
+        .into_iter()
+        .filter_map(|target_doc| match wa.common_doc_name(target_doc) {
+            Some(name) => Some(name.to_string()),
+            None => {
+                let target = docs.get_uri(target_doc)?.to_file_path()?;
+                relative_path(base_dir, &target)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let DocSyntax { text, root, .. } = wa.get_syntax(doc)?;
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let position = Position::new(insert_row(root), 0);
+
+    Some(
+        include_paths
+            .into_iter()
+            .map(|include_path| CodeAction {
+                title: format!("#include \"{}\" を追加する", include_path),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(new_doc_workspace_edit(
+                    uri.clone(),
+                    version,
+                    vec![TextEdit {
+                        range: Range::new(position, position),
+                        new_text: format!("#include \"{}\"{}", include_path, eol),
+                    }],
+                )),
+                ..Default::default()
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::CodeActionContext;
+
+    #[test]
+    fn test_relative_path() {
+        let base = dummy_root().join("src");
+        assert_eq!(
+            relative_path(&base, &dummy_root().join("src/mod.as")).as_deref(),
+            Some("mod.as")
+        );
+        assert_eq!(
+            relative_path(&base, &dummy_root().join("lib/util/mod.as")).as_deref(),
+            Some("../lib/util/mod.as")
+        );
+    }
+
+    #[test]
+    fn test_add_include() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        let main_uri = dummy_url("src/main.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"other.as\"\n\tgreet\n".into(),
+        );
+        ls.open_doc(
+            dummy_url("lib/greet.as"),
+            NO_VERSION,
+            "#module\n#deffunc greet\n\treturn\n#global\n".into(),
+        );
+
+        let position = Position::new(2, 2);
+        let actions = ls.code_action(
+            main_uri,
+            Range::new(position, position),
+            CodeActionContext::default(),
+        );
+        let action = actions
+            .into_iter()
+            .find(|a| a.title.starts_with("#include"))
+            .expect("add include action");
+        assert_eq!(action.title, "#include \"../lib/greet.as\" を追加する");
+    }
+}
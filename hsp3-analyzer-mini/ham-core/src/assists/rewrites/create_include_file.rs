@@ -0,0 +1,113 @@
+//! 解決できない `#include` のファイルを作成するアクション
+
+use super::*;
+use lsp_types::{
+    CodeAction, CodeActionKind, CreateFile, CreateFileOptions, DocumentChangeOperation,
+    DocumentChanges, Range, ResourceOp, Url, WorkspaceEdit,
+};
+
+/// `#include` 文の字面からファイル名を取り出す。(includeの名前は標準化されているので、書かれたとおりの名前を使う。)
+fn include_file_name(stmt_text: &str) -> Option<&str> {
+    let l = stmt_text.find('"')?;
+    let rest = &stmt_text[l + 1..];
+    let r = rest.find('"').unwrap_or(rest.len());
+    let name = rest[..r].trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(name)
+}
+
+pub(crate) fn create_include_file(
+    uri: &Url,
+    range: Range,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<CodeAction>> {
+    let (doc, pos) = from_document_position(uri, range.start, docs)?;
+
+    let mut unresolved_includes = vec![];
+    wa.collect_unresolved_includes(&mut unresolved_includes);
+    let (_, loc) = unresolved_includes
+        .into_iter()
+        .find(|(_, loc)| loc.is_touched(doc, pos))?;
+
+    let DocSyntax { text, .. } = wa.get_syntax(doc)?;
+    let stmt_text = &text[loc.start().index as usize..loc.end().index as usize];
+    let name = include_file_name(stmt_text)?;
+
+    // includeするファイルと同じディレクトリからの相対パスとみなす。
+    let base_dir = uri.to_file_path().ok()?.parent()?.to_path_buf();
+    let path = name
+        .split(|c| c == '/' || c == '\\')
+        .fold(base_dir, |path, part| path.join(part));
+    let new_uri = Url::from_file_path(&path).ok()?;
+
+    Some(vec![CodeAction {
+        title: format!("ファイル {} を作成する", name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: new_uri,
+                    options: Some(CreateFileOptions {
+                        overwrite: Some(false),
+                        ignore_if_exists: Some(true),
+                    }),
+                    annotation_id: None,
+                })),
+            ])),
+            ..WorkspaceEdit::default()
+        }),
+        ..Default::default()
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::{CodeActionContext, Position};
+
+    #[test]
+    fn test_create_include_file() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = dummy_url("create_include_file.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            "; ham-entry\n#include \"Lib/Missing.as\"\n".into(),
+        );
+
+        // 探したディレクトリを列挙する。
+        let messages = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(uri, _, _)| *uri == main_uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("includeを解決できません: \"lib/missing.as\""));
+        assert!(messages[0].contains(&format!("\n- {}", dummy_root().display())));
+
+        let pos = Position::new(1, 12);
+        let actions = ls.code_action(main_uri, Range::new(pos, pos), CodeActionContext::default());
+        let action = actions
+            .into_iter()
+            .find(|a| a.title == "ファイル Lib/Missing.as を作成する")
+            .expect("create file");
+        let uri = match action.edit.unwrap().document_changes {
+            Some(DocumentChanges::Operations(ops)) => match &ops[..] {
+                [DocumentChangeOperation::Op(ResourceOp::Create(op))] => op.uri.clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(uri, dummy_url("Lib/Missing.as"));
+    }
+}
@@ -44,6 +44,8 @@ impl PVisitor for V {
             self.on_token(token);
         }
         self.on_token_opt(param.name_opt.as_ref());
+        self.on_token_opt(param.equal_opt.as_ref());
+        self.on_expr_opt(param.default_opt.as_ref());
         self.depth -= 1;
         self.on_token_opt(param.comma_opt.as_ref());
         self.depth -= 1;
@@ -0,0 +1,190 @@
+//! 未定義の命令の定義を生成するアクション
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{CodeAction, CodeActionKind, Position, Range, TextEdit, Url};
+
+/// カーソル位置にある命令文と、それを囲むモジュールの `#global` をみつけるビジター。
+struct V<'a> {
+    pos: Pos16,
+    /// 訪問中のモジュールの `#global` (なければ None)
+    global_opt: Option<&'a PGlobalStmt>,
+    in_module: bool,
+    out: Option<(&'a PCommandStmt, bool, Option<&'a PGlobalStmt>)>,
+}
+
+impl<'a> V<'a> {
+    fn on_stmts(&mut self, stmts: &'a [PStmt]) {
+        for stmt in stmts {
+            if self.out.is_some() {
+                return;
+            }
+            self.on_stmt(stmt);
+        }
+    }
+
+    fn on_stmt(&mut self, stmt: &'a PStmt) {
+        match stmt {
+            PStmt::Command(stmt) if stmt.command.body.loc.range.contains_inclusive(self.pos) => {
+                self.out = Some((stmt, self.in_module, self.global_opt));
+            }
+            PStmt::If(stmt) => {
+                for block in [&stmt.body, &stmt.alt] {
+                    self.on_stmts(&block.outer_stmts);
+                    self.on_stmts(&block.inner_stmts);
+                }
+            }
+            PStmt::DefFunc(stmt) => self.on_stmts(&stmt.stmts),
+            PStmt::Module(stmt) => {
+                self.in_module = true;
+                self.global_opt = stmt.global_opt.as_ref();
+                self.on_stmts(&stmt.stmts);
+                self.in_module = false;
+                self.global_opt = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 引数の式からパラメータの型を推測する。
+fn infer_param_ty(arg: &PArg) -> PParamTy {
+    match &arg.expr_opt {
+        Some(PExpr::Literal(token)) => match token.kind() {
+            TokenKind::Str => PParamTy::Str,
+            TokenKind::Number if token.body_text().contains('.') => PParamTy::Double,
+            _ => PParamTy::Int,
+        },
+        Some(PExpr::Label(_)) => PParamTy::Label,
+        _ => PParamTy::Int,
+    }
+}
+
+pub(crate) fn generate_deffunc(
+    uri: &Url,
+    range: Range,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<CodeAction>> {
+    let (doc, pos) = from_document_position(uri, range.start, docs)?;
+    let version = docs.get_version(doc);
+
+    let (symbol, _) = wa.require_project_for_doc(doc).locate_symbol(doc, pos)?;
+    if symbol.kind != HspSymbolKind::Unresolved {
+        return None;
+    }
+
+    let DocSyntax { text, root, .. } = wa.get_syntax(doc)?;
+
+    let (stmt, in_module, global_opt) = {
+        let mut v = V {
+            pos,
+            global_opt: None,
+            in_module: false,
+            out: None,
+        };
+        v.on_stmts(&root.stmts);
+        v.out?
+    };
+
+    let name = stmt.command.body_text();
+    let params = stmt
+        .args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| format!("{} p{}", infer_param_ty(arg).to_str(), i + 1))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let deffunc = if params.is_empty() {
+        format!("#deffunc {name}{eol}\treturn{eol}", name = name, eol = eol)
+    } else {
+        format!(
+            "#deffunc {name} {params}{eol}\treturn{eol}",
+            name = name,
+            params = params,
+            eol = eol
+        )
+    };
+
+    // モジュールの中で使われているときはモジュールの末尾に、そうでなければファイルの先頭にモジュールを作って挿入する。
+    let (position, new_text) = match (in_module, global_opt) {
+        (true, Some(global)) => (
+            Position::new(global.hash.body.loc.start_row() as u32, 0),
+            format!("{}{}", deffunc, eol),
+        ),
+        (true, None) => {
+            let end = to_position(Pos::from(text.as_str()));
+            let sep = if text.is_empty() || text.ends_with('\n') {
+                eol
+            } else {
+                ""
+            };
+            (end, format!("{}{}{}", sep, eol, deffunc))
+        }
+        (false, _) => (
+            Position::new(0, 0),
+            format!("#module{eol}{}#global{eol}{eol}", deffunc, eol = eol),
+        ),
+    };
+
+    Some(vec![CodeAction {
+        title: format!("命令 {} の定義を生成する", name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(new_doc_workspace_edit(
+            uri.clone(),
+            version,
+            vec![TextEdit {
+                range: Range::new(position, position),
+                new_text,
+            }],
+        )),
+        ..Default::default()
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+    use lsp_types::{CodeActionContext, DocumentChanges, OneOf};
+
+    fn generated_text(ls: &mut LangService, uri: &Url, position: Position) -> Option<String> {
+        let range = Range::new(position, position);
+        let actions = ls.code_action(uri.clone(), range, CodeActionContext::default());
+        let action = actions
+            .into_iter()
+            .find(|a| a.title.ends_with("の定義を生成する"))?;
+
+        match action.edit?.document_changes? {
+            DocumentChanges::Edits(edits) => match &edits[0].edits[0] {
+                OneOf::Left(edit) => Some(edit.new_text.clone()),
+                OneOf::Right(_) => None,
+            },
+            DocumentChanges::Operations(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_generate_deffunc() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("generate_deffunc.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "\tgreet \"hello\", 1.5, *l\n#module\n#deffunc f\n\tg\n\treturn\n#global\n".into(),
+        );
+
+        assert_eq!(
+            generated_text(&mut ls, &uri, Position::new(0, 2)).as_deref(),
+            Some("#module\n#deffunc greet str p1, double p2, label p3\n\treturn\n#global\n\n")
+        );
+        assert_eq!(
+            generated_text(&mut ls, &uri, Position::new(3, 1)).as_deref(),
+            Some("#deffunc g\n\treturn\n\n")
+        );
+        assert_eq!(generated_text(&mut ls, &uri, Position::new(2, 10)), None);
+    }
+}
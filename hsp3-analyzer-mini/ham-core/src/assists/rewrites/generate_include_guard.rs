@@ -2,10 +2,17 @@
 
 use super::*;
 use lsp_types::{
-    CodeAction, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, Range,
-    TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+    CodeAction, CodeActionKind, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    Position, Range, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
 };
 
+/// ファイル名からインクルードガードのシンボルを生成する。
+fn guard_name(doc: DocId, docs: &Docs) -> Option<String> {
+    let path = docs.get_uri(doc)?.clone().into_url().to_file_path().ok()?;
+    let name = path.file_name()?.to_str()?;
+    Some(name.replace(".", "_") + "_included")
+}
+
 pub(crate) fn generate_include_guard(
     uri: &Url,
     range: Range,
@@ -26,12 +33,7 @@ pub(crate) fn generate_include_guard(
         return None;
     }
 
-    // ファイル名からシンボルを生成する。
-    let name = {
-        let path = docs.get_uri(doc)?.clone().into_url().to_file_path().ok()?;
-        let name = path.file_name()?.to_str()?;
-        name.replace(".", "_") + "_included"
-    };
+    let name = guard_name(doc, docs)?;
     let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
     let new_text = format!(
         "#ifndef {name}{eol}#define {name}{eol}{eol}#endif{eol}",
@@ -55,3 +57,141 @@ pub(crate) fn generate_include_guard(
         ..Default::default()
     }])
 }
+
+/// 複数回includeされているのにインクルードガードがないファイルを、インクルードガードで囲むアクション
+pub(crate) fn wrap_in_include_guard(
+    uri: &Url,
+    range: Range,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<CodeAction>> {
+    let (doc, _) = from_document_position(uri, range.start, docs)?;
+    let version = docs.get_version(doc);
+
+    let mut missing_docs = vec![];
+    wa.collect_docs_missing_include_guard(&mut missing_docs);
+    if !missing_docs.contains(&doc) {
+        return None;
+    }
+
+    let DocSyntax { text, .. } = wa.get_syntax(doc)?;
+
+    let name = guard_name(doc, docs)?;
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let header = format!(
+        "#ifndef {name}{eol}#define {name}{eol}{eol}",
+        name = name,
+        eol = eol,
+    );
+    // 最後の行が改行で終わっていなければ改行を補う。
+    let footer = if text.is_empty() || text.ends_with('\n') {
+        format!("{eol}#endif{eol}", eol = eol)
+    } else {
+        format!("{eol}{eol}#endif{eol}", eol = eol)
+    };
+
+    let start = Position::new(0, 0);
+    let end = to_position(Pos::from(text.as_str()));
+
+    Some(vec![CodeAction {
+        title: "ファイル全体をインクルードガードで囲む".into(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version,
+                },
+                edits: vec![
+                    OneOf::Left(TextEdit {
+                        range: Range::new(start, start),
+                        new_text: header,
+                    }),
+                    OneOf::Left(TextEdit {
+                        range: Range::new(end, end),
+                        new_text: footer,
+                    }),
+                ],
+            }])),
+            ..WorkspaceEdit::default()
+        }),
+        ..Default::default()
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::{CodeActionContext, DiagnosticSeverity};
+
+    #[test]
+    fn test_wrap_in_include_guard() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let main_uri = dummy_url("main.hsp");
+        let mod_uri = dummy_url("mod_guard.as");
+        ls.open_doc(
+            main_uri,
+            NO_VERSION,
+            "; ham-entry\n#include \"mod_guard.as\"\n#include \"mod_guard.as\"\n".into(),
+        );
+        ls.open_doc(
+            mod_uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc f\n\treturn\n#global\n".into(),
+        );
+
+        let diagnostics = ls
+            .diagnose()
+            .into_iter()
+            .find(|(uri, _, _)| *uri == mod_uri)
+            .map(|(_, _, diagnostics)| diagnostics)
+            .unwrap_or_default();
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.severity == Some(DiagnosticSeverity::WARNING)));
+
+        let actions = ls.code_action(
+            mod_uri.clone(),
+            Range::default(),
+            CodeActionContext::default(),
+        );
+        let action = actions
+            .iter()
+            .find(|a| a.kind == Some(CodeActionKind::QUICKFIX))
+            .expect("quickfix");
+        let edits = match &action.edit.as_ref().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => &edits[0].edits,
+            _ => unreachable!(),
+        };
+        let new_texts = edits
+            .iter()
+            .map(|edit| match edit {
+                OneOf::Left(edit) => edit.new_text.as_str(),
+                OneOf::Right(edit) => edit.text_edit.new_text.as_str(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            new_texts,
+            vec![
+                "#ifndef mod_guard_as_included\n#define mod_guard_as_included\n\n",
+                "\n#endif\n"
+            ]
+        );
+
+        // ガードをつけると警告は消える。
+        ls.change_doc(
+            mod_uri.clone(),
+            2,
+            "#ifndef mod_guard_as_included\n#define mod_guard_as_included\n#module\n#deffunc f\n\treturn\n#global\n#endif\n".into(),
+        );
+        assert!(ls
+            .code_action(mod_uri, Range::default(), CodeActionContext::default())
+            .iter()
+            .all(|a| a.kind != Some(CodeActionKind::QUICKFIX)));
+    }
+}
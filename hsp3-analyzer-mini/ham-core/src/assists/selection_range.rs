@@ -0,0 +1,166 @@
+//! 選択範囲の拡大
+//!
+//! トークン → 式 → 引数 → 文 → `#deffunc` → モジュールの順に範囲を広げる。
+
+use super::*;
+use crate::parse::*;
+use lsp_types::{Position, SelectionRange, Url};
+
+/// 指定された位置を含む構文要素の範囲を、外側から順に集めるビジター。
+struct V {
+    pos: Pos16,
+    ranges: Vec<Range>,
+}
+
+impl V {
+    /// 範囲が位置を含み、直前の (外側の) 範囲に収まっていれば追加する。
+    fn push(&mut self, range: Range) -> bool {
+        if !range.contains_inclusive(self.pos) {
+            return false;
+        }
+
+        if let Some(last) = self.ranges.last() {
+            if !(last.start() <= range.start() && range.end() <= last.end()) {
+                return false;
+            }
+            if *last == range {
+                return true;
+            }
+        }
+
+        self.ranges.push(range);
+        true
+    }
+}
+
+impl PVisitor for V {
+    fn on_token(&mut self, token: &PToken) {
+        self.push(token.body.loc.range);
+    }
+
+    fn on_compound(&mut self, compound: &PCompound) {
+        if self.push(compound.compute_range()) {
+            self.on_compound_default(compound);
+        }
+    }
+
+    fn on_args(&mut self, args: &[PArg]) {
+        // 引数リスト全体
+        let mut ranges = args
+            .iter()
+            .filter_map(|arg| arg.expr_opt.as_ref())
+            .map(|expr| expr.compute_range());
+        if let Some(first) = ranges.next() {
+            let last = ranges.next_back().unwrap_or(first);
+            if !self.push(first.join(last)) {
+                return;
+            }
+        }
+
+        self.on_args_default(args);
+    }
+
+    fn on_expr(&mut self, expr: &PExpr) {
+        if self.push(expr.compute_range()) {
+            self.on_expr_default(expr);
+        }
+    }
+
+    fn on_stmt(&mut self, stmt: &PStmt) {
+        let mut range = stmt.compute_body_range();
+
+        // モジュールの範囲には `#global` も含める。
+        if let PStmt::Module(PModuleStmt {
+            global_opt: Some(global),
+            ..
+        }) = stmt
+        {
+            range = range.join(global.keyword.body.loc.range);
+        }
+
+        if self.push(range) {
+            self.on_stmt_default(stmt);
+        }
+    }
+}
+
+pub(crate) fn selection_range(
+    uri: Url,
+    positions: Vec<Position>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<SelectionRange>> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+    let DocSyntax { root, .. } = wa.get_syntax(doc)?;
+
+    let result = positions
+        .into_iter()
+        .map(|position| {
+            let mut v = V {
+                pos: Pos16::new(position.line, position.character),
+                ranges: vec![],
+            };
+            v.on_root(root);
+
+            // 内側の範囲が外側の範囲を親として持つように組み立てる。
+            v.ranges
+                .into_iter()
+                .fold(None, |parent: Option<SelectionRange>, range| {
+                    Some(SelectionRange {
+                        range: to_lsp_range(range),
+                        parent: parent.map(Box::new),
+                    })
+                })
+                .unwrap_or(SelectionRange {
+                    range: lsp_types::Range::new(position, position),
+                    parent: None,
+                })
+        })
+        .collect();
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_selection_range() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("selection_range.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module\n#deffunc f\n\tmes 1 + x, 3\n\treturn\n#global\n".into(),
+        );
+
+        let result = ls.selection_range(uri, vec![Position::new(2, 10)]);
+        let mut ranges = vec![];
+        let mut current = result.first();
+        while let Some(sr) = current {
+            let r = sr.range;
+            ranges.push((r.start.line, r.start.character, r.end.line, r.end.character));
+            current = sr.parent.as_deref();
+        }
+
+        assert_eq!(
+            ranges,
+            vec![
+                // x
+                (2, 9, 2, 10),
+                // 1 + x
+                (2, 5, 2, 10),
+                // 1 + x, 3
+                (2, 5, 2, 13),
+                // mes ...
+                (2, 1, 2, 13),
+                // #deffunc
+                (1, 0, 3, 7),
+                // #module
+                (0, 0, 4, 7),
+            ]
+        );
+    }
+}
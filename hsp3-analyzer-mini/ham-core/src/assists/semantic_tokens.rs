@@ -1,5 +1,9 @@
 use super::*;
-use crate::{analysis::*, parse::p_param_ty::PParamCategory};
+use crate::{analysis::*, parse::p_param_ty::PParamCategory, parse::PToken};
+use lsp_types::{
+    SemanticToken, SemanticTokens, SemanticTokensDelta, SemanticTokensEdit,
+    SemanticTokensFullDeltaResult,
+};
 
 // SemanticTokensLegend を参照
 fn to_semantic_token_kind(symbol: &SymbolRc) -> Option<(u32, u32)> {
@@ -21,6 +25,7 @@ fn to_semantic_token_kind(symbol: &SymbolRc) -> Option<(u32, u32)> {
         HspSymbolKind::Macro { .. } => (3, 0), // macro
         HspSymbolKind::Module => (4, 0),       // namespace
         HspSymbolKind::PluginCmd => (5, 0),    // keyword
+        HspSymbolKind::Label => (6, 0),        // label
 
         // Not supported:
         // HspSymbolKind::ComInterface => ?,
@@ -29,48 +34,126 @@ fn to_semantic_token_kind(symbol: &SymbolRc) -> Option<(u32, u32)> {
     Some((ty, modifiers))
 }
 
-pub(crate) fn full(
-    uri: Url,
+/// キーワードにあたる字句の位置を列挙する。(`if`, `else` やプリプロセッサ命令の `#deffunc` など)
+fn collect_keywords(tokens: &[PToken], out: &mut Vec<Loc>) {
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind() {
+            TokenKind::If | TokenKind::Else => out.push(token.body.loc),
+            TokenKind::Hash => {
+                let next = match tokens.get(i + 1) {
+                    Some(it) if it.kind() == TokenKind::Ident => it,
+                    _ => continue,
+                };
+
+                // `#` と命令の名前の間に空白があるときは、名前の部分だけをキーワードとする。
+                if token.body.loc.end() == next.body.loc.start() {
+                    out.push(token.body.loc.unite(&next.body.loc));
+                } else {
+                    out.push(next.body.loc);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 前回の応答で返したトークン列
+///
+/// `semanticTokens/full/delta` リクエストに差分だけを返すために使う。
+#[derive(Default)]
+pub(crate) struct SemanticTokensCache {
+    last_id: usize,
+    map: HashMap<DocId, (String, Vec<SemanticToken>)>,
+}
+
+impl SemanticTokensCache {
+    fn insert(&mut self, doc: DocId, data: Vec<SemanticToken>) -> String {
+        self.last_id += 1;
+        let result_id = self.last_id.to_string();
+        self.map.insert(doc, (result_id.clone(), data));
+        result_id
+    }
+
+    /// ドキュメントが閉じられたときや削除されたときに、前回の応答を捨てる。
+    pub(crate) fn remove(&mut self, doc: DocId) {
+        self.map.remove(&doc);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.map.len()
+    }
+}
+
+/// ドキュメント内のトークンを列挙する。(位置は差分ではなく絶対位置)
+///
+/// 範囲が指定されたときは、その範囲と重なる行にあるトークンだけを列挙する。
+fn collect_tokens(
+    doc: DocId,
+    range_opt: Option<lsp_types::Range>,
     docs: &Docs,
     wa: &mut WorkspaceAnalysis,
-) -> Option<Vec<lsp_types::SemanticToken>> {
-    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+) -> Option<Vec<SemanticToken>> {
+    let row_is_in_range = |row: usize| match range_opt {
+        Some(range) => range.start.line as usize <= row && row <= range.end.line as usize,
+        None => true,
+    };
+
     let project = wa.require_project_for_doc(doc);
 
     let mut symbols = vec![];
     project.collect_symbol_occurrences(&mut symbols);
 
-    let mut tokens: Vec<lsp_types::SemanticToken> = vec![];
+    let mut occurrences = symbols
+        .into_iter()
+        .filter(|(_, loc)| loc.doc == doc && row_is_in_range(loc.start_row()))
+        .filter_map(|(symbol, loc)| Some((loc, to_semantic_token_kind(&symbol)?)))
+        .collect::<Vec<_>>();
 
-    for (symbol, loc) in symbols {
-        if loc.doc != doc {
-            continue;
-        }
+    let mut keywords = vec![];
+    collect_keywords(&wa.get_syntax(doc)?.tokens, &mut keywords);
+    occurrences.extend(
+        keywords
+            .into_iter()
+            .filter(|loc| row_is_in_range(loc.start_row()))
+            .map(|loc| (loc, (5, 0))),
+    );
 
-        let (token_type, token_modifiers_bitset) = match to_semantic_token_kind(&symbol) {
-            Some(it) => it,
-            None => continue,
-        };
+    let mut tokens: Vec<SemanticToken> = vec![];
 
+    for (loc, (token_type, token_modifiers_bitset)) in occurrences {
         let location = loc_to_location(loc, docs)?;
 
         let Position {
             line: y1,
             character: x1,
         } = location.range.start;
+        let Position {
+            line: y2,
+            character: x2,
+        } = location.range.end;
+
+        // 複数行にまたがる字句はない。
+        if y1 != y2 || x1 >= x2 {
+            continue;
+        }
 
-        tokens.push(lsp_types::SemanticToken {
+        tokens.push(SemanticToken {
             delta_line: y1,
             delta_start: x1,
-            length: symbol.name().encode_utf16().count() as u32,
+            length: x2 - x1,
             token_type,
             token_modifiers_bitset,
         });
     }
 
-    // Compute delta.
-    tokens.sort_by_key(|t| (t.delta_line, t.delta_start + t.length));
+    tokens.sort_by_key(|t| (t.delta_line, t.delta_start));
+    tokens.dedup_by_key(|t| (t.delta_line, t.delta_start));
+    Some(tokens)
+}
 
+/// 絶対位置を直前のトークンからの差分に変換する。
+fn encode(mut tokens: Vec<SemanticToken>) -> Vec<SemanticToken> {
     for i in (1..tokens.len()).rev() {
         let y1 = tokens[i - 1].delta_line;
         let x1 = tokens[i - 1].delta_start;
@@ -81,6 +164,197 @@ pub(crate) fn full(
             tokens[i].delta_line -= y1;
         }
     }
+    tokens
+}
 
-    Some(tokens)
+/// 前回のトークン列から新しいトークン列への変更を、先頭と末尾の共通部分を除いた1個の編集として表す。
+fn compute_edit(old: &[SemanticToken], new: &[SemanticToken]) -> Option<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(l, r)| l == r).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return None;
+    }
+
+    // 位置と個数は整数の個数で数える。(トークン1個につき5個)
+    Some(SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    })
+}
+
+pub(crate) fn full(
+    uri: Url,
+    docs: &Docs,
+    cache: &mut SemanticTokensCache,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<SemanticTokens> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+    let data = encode(collect_tokens(doc, None, docs, wa)?);
+    let result_id = cache.insert(doc, data.clone());
+
+    Some(SemanticTokens {
+        result_id: Some(result_id),
+        data,
+    })
+}
+
+pub(crate) fn full_delta(
+    uri: Url,
+    previous_result_id: &str,
+    docs: &Docs,
+    cache: &mut SemanticTokensCache,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<SemanticTokensFullDeltaResult> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+    let data = encode(collect_tokens(doc, None, docs, wa)?);
+
+    let edits = match cache.map.get(&doc) {
+        Some((id, old)) if id == previous_result_id => {
+            compute_edit(old, &data).into_iter().collect::<Vec<_>>()
+        }
+        _ => {
+            // 前回の結果がわからないときはすべて返す。
+            let result_id = cache.insert(doc, data.clone());
+            return Some(SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                result_id: Some(result_id),
+                data,
+            }));
+        }
+    };
+
+    let result_id = cache.insert(doc, data);
+    Some(SemanticTokensFullDeltaResult::TokensDelta(
+        SemanticTokensDelta {
+            result_id: Some(result_id),
+            edits,
+        },
+    ))
+}
+
+pub(crate) fn range(
+    uri: Url,
+    range: lsp_types::Range,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<SemanticTokens> {
+    let doc = docs.find_by_uri(&CanonicalUri::from_url(&uri))?;
+    let data = encode(collect_tokens(doc, Some(range), docs, wa)?);
+
+    Some(SemanticTokens {
+        result_id: None,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_semantic_tokens() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("semantic_tokens.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#define M 1\n*l_main\n\tif M {\n\t\tgoto *l_main\n\t}\n".into(),
+        );
+
+        // 絶対位置に戻して比較する。
+        let mut y = 0;
+        let mut x = 0;
+        let tokens = ls
+            .semantic_tokens(uri)
+            .data
+            .into_iter()
+            .map(|t| {
+                if t.delta_line != 0 {
+                    x = 0;
+                }
+                y += t.delta_line;
+                x += t.delta_start;
+                (y, x, t.length, t.token_type)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (0, 0, 7, 5), // #define
+                (0, 8, 1, 3), // M
+                (1, 1, 6, 6), // l_main
+                (2, 1, 2, 5), // if
+                (2, 4, 1, 3), // M
+                (3, 8, 6, 6), // l_main
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_delta_and_range() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("semantic_tokens_delta.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#const A 1\n#const B 2\nmes A\nmes B\n".into(),
+        );
+
+        let full = ls.semantic_tokens(uri.clone());
+        let result_id = full.result_id.clone().unwrap();
+        assert_eq!(full.data.len(), 6);
+
+        // 変更がなければ編集は空になる。
+        match ls.semantic_tokens_delta(uri.clone(), result_id.clone()) {
+            Some(SemanticTokensFullDeltaResult::TokensDelta(delta)) => {
+                assert!(delta.edits.is_empty())
+            }
+            _ => panic!("expected delta"),
+        }
+
+        // 古い結果IDを指定したときはすべてのトークンを返す。
+        match ls.semantic_tokens_delta(uri.clone(), result_id) {
+            Some(SemanticTokensFullDeltaResult::Tokens(tokens)) => {
+                assert_eq!(tokens.data, full.data)
+            }
+            _ => panic!("expected full tokens"),
+        }
+
+        let range = lsp_types::Range::new(Position::new(2, 0), Position::new(2, 5));
+        let tokens = ls.semantic_tokens_range(uri, range).unwrap();
+        assert_eq!(
+            tokens
+                .data
+                .iter()
+                .map(|t| (t.delta_line, t.delta_start, t.length))
+                .collect::<Vec<_>>(),
+            vec![(2, 4, 1)]
+        );
+    }
+
+    #[test]
+    fn test_compute_edit() {
+        let token = |n: u32| SemanticToken {
+            delta_line: n,
+            ..SemanticToken::default()
+        };
+        let old = vec![token(1), token(2), token(3)];
+        let new = vec![token(1), token(4), token(5), token(3)];
+
+        let edit = compute_edit(&old, &new).unwrap();
+        assert_eq!((edit.start, edit.delete_count), (5, 5));
+        assert_eq!(edit.data, Some(vec![token(4), token(5)]));
+        assert!(compute_edit(&old, &old).is_none());
+    }
 }
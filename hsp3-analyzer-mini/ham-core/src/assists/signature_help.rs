@@ -24,6 +24,7 @@ impl SignatureHelpHost {
 }
 
 pub(crate) struct SignatureHelpContext {
+    pub(crate) symbol: SymbolRc,
     pub(crate) signature_data: Rc<SignatureData>,
     pub(crate) arg_index: usize,
     pub(crate) ctype: bool,
@@ -71,6 +72,7 @@ impl V {
             .count();
 
         self.out = Some(SignatureHelpContext {
+            symbol,
             signature_data,
             ctype,
             arg_index,
@@ -125,6 +127,7 @@ pub(crate) fn signature_help(
     }
 
     let SignatureHelpContext {
+        symbol,
         signature_data,
         ctype,
         arg_index,
@@ -133,6 +136,7 @@ pub(crate) fn signature_help(
         .get_signature_help_context(doc, pos)?;
 
     let command = NamePath::new(&signature_data.name).base;
+    let details = symbol.compute_details();
 
     let mut params = vec![
         ParameterInformation {
@@ -146,11 +150,17 @@ pub(crate) fn signature_help(
         let mut s = command.to_string();
         let mut sep = if ctype { "(" } else { " " };
 
-        for (i, (ty_opt, name_opt, info_opt)) in signature_data.params.iter().enumerate() {
+        for (i, param) in signature_data.params.iter().enumerate() {
+            let SignatureParam {
+                ty_opt,
+                name_opt,
+                info_opt,
+                default_opt,
+            } = param;
             s += sep;
 
             let start = s.len() as u32;
-            match (ty_opt, &name_opt) {
+            match (ty_opt, name_opt) {
                 (Some(ty), Some(name)) => {
                     s += ty.to_str();
                     s += " ";
@@ -160,10 +170,23 @@ pub(crate) fn signature_help(
                 (None, Some(name)) => s += name,
                 _ => s += "???",
             }
+            if let Some(default) = default_opt {
+                s += " = ";
+                s += default;
+            }
 
             let end = s.len() as u32;
             params[i].label = ParameterLabel::LabelOffsets([start, end]);
-            params[i].documentation = info_opt.clone().map(|s| Documentation::String(s));
+            // パラメータの後ろのコメントがなければ、`@param` などで書かれた説明を使う。
+            let info_opt = info_opt.clone().or_else(|| {
+                let name = name_opt.as_ref()?;
+                details
+                    .params
+                    .iter()
+                    .find(|(n, _)| n == name.as_str())
+                    .map(|(_, desc)| desc.clone())
+            });
+            params[i].documentation = info_opt.map(Documentation::String);
 
             sep = ", ";
         }
@@ -178,7 +201,9 @@ pub(crate) fn signature_help(
         signatures: vec![SignatureInformation {
             label: signature_label,
             parameters: Some(params),
-            documentation: None,
+            documentation: details
+                .desc
+                .map(|desc| Documentation::String(desc.to_string())),
             active_parameter: None,
         }],
         active_parameter: Some(arg_index as u32),
@@ -190,11 +215,7 @@ pub(crate) fn signature_help(
 mod tests {
     use super::*;
     use crate::lang_service::{docs::NO_VERSION, LangService};
-
-    fn dummy_url(s: &str) -> Url {
-        let dummy_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".no_exist");
-        Url::from_file_path(&dummy_root.join(s)).unwrap()
-    }
+    use crate::utils::test_utils::dummy_url;
 
     #[test]
     fn test() {
@@ -325,4 +346,158 @@ f 1, ""
         );
         assert!(opt.is_none());
     }
+
+    #[test]
+    fn default_value_test() {
+        let mut ls = LangService::new_standalone();
+
+        let main_uri = dummy_url("default_signature_help.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"#deffunc f int a, int b = 10, str c = "x" + "y"
+	return
+	f 1, 2
+"#
+            .into(),
+        );
+
+        let sig = ls
+            .signature_help(main_uri, Position::new(2, 6))
+            .expect("signature_help");
+        let label = &sig.signatures[0].label;
+        assert_eq!(label, r#"f int a, int b = 10, str c = "x" + "y""#);
+
+        // パラメータの範囲は既定値を含む。
+        let ranges = sig.signatures[0]
+            .parameters
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|p| match p.label {
+                ParameterLabel::LabelOffsets([start, end]) => &label[start as usize..end as usize],
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec!["int a", "int b = 10", r#"str c = "x" + "y""#]);
+        assert_eq!(sig.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn macro_test() {
+        let mut ls = LangService::new_standalone();
+
+        let main_uri = dummy_url("macro_signature_help.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"#define ctype clamp(%1, %2, %3) limit(%1, %2, %3) ; clamp(value, low, high)
+#define ctype sq(%1) ((%1) * (%1))
+	mes clamp(1, 0, 2)
+	mes sq(3)
+"#
+            .into(),
+        );
+
+        let label_and_active = |ls: &mut LangService, line: u32, character: u32| {
+            let sig = ls
+                .signature_help(main_uri.clone(), Position { line, character })
+                .expect("signature_help");
+            (
+                sig.signatures[0].label.clone(),
+                sig.active_parameter.expect("active_parameter"),
+            )
+        };
+
+        // パラメータ名は行末のコメントから取る。
+        assert_eq!(
+            label_and_active(&mut ls, 2, 14),
+            ("clamp(value, low, high)".into(), 1)
+        );
+
+        // コメントがなければ `%1` などになる。
+        assert_eq!(label_and_active(&mut ls, 3, 8), ("sq(%1)".into(), 0));
+    }
+
+    #[test]
+    fn plugin_cmd_test() {
+        let mut ls = LangService::new_standalone();
+
+        let main_uri = dummy_url("plugin_cmd_signature_help.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"#regcmd "_hsp3cmdinit@4", "hspext.dll"
+#cmd ext_move $00 ; ext_move src, dest
+; @param name 名前
+#cmd ext_find $01
+#cmd ext_stop $02
+	ext_move 1, 2
+	ext_find 3
+	ext_stop 4
+"#
+            .into(),
+        );
+
+        let label_and_active = |ls: &mut LangService, line: u32, character: u32| {
+            let sig = ls.signature_help(main_uri.clone(), Position { line, character })?;
+            Some((
+                sig.signatures[0].label.clone(),
+                sig.active_parameter.expect("active_parameter"),
+            ))
+        };
+
+        // パラメータ名は行末のコメントから取る。
+        assert_eq!(
+            label_and_active(&mut ls, 5, 13),
+            Some(("ext_move src, dest".into(), 1))
+        );
+
+        // 行末のコメントがなければ `@param` を使う。
+        assert_eq!(
+            label_and_active(&mut ls, 6, 11),
+            Some(("ext_find name".into(), 0))
+        );
+
+        // 注釈がなければシグネチャヘルプは出ない。
+        assert_eq!(label_and_active(&mut ls, 7, 10), None);
+    }
+
+    #[test]
+    fn doc_tags_test() {
+        let mut ls = LangService::new_standalone();
+
+        let main_uri = dummy_url("doc_tags_signature_help.hsp");
+        ls.open_doc(
+            main_uri.clone(),
+            NO_VERSION,
+            r#"; 範囲を指定する
+; @param low 下限
+; @param high 上限
+#deffunc set_range int low, int high
+	return
+	set_range 1, 2
+"#
+            .into(),
+        );
+
+        let sig = ls
+            .signature_help(main_uri, Position::new(5, 12))
+            .expect("signature_help");
+        let sig = &sig.signatures[0];
+        let to_str = |d: &Option<Documentation>| match d {
+            Some(Documentation::String(s)) => s.clone(),
+            _ => String::new(),
+        };
+        assert_eq!(to_str(&sig.documentation), "範囲を指定する");
+        assert_eq!(
+            sig.parameters
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|p| to_str(&p.documentation))
+                .collect::<Vec<_>>(),
+            vec!["下限", "上限"]
+        );
+    }
 }
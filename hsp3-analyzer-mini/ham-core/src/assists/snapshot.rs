@@ -0,0 +1,119 @@
+//! 解析結果のスナップショット (回帰テスト用)
+
+use super::{hover::signature_to_string, symbol_stats::kind_name, *};
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+use std::fmt::Write as _;
+
+fn severity_name(severity_opt: Option<DiagnosticSeverity>) -> &'static str {
+    match severity_opt {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "unknown",
+    }
+}
+
+fn scope_to_string(symbol: &SymbolRc, wa: &mut WorkspaceAnalysis) -> String {
+    let local = match &symbol.scope_opt {
+        Some(Scope::Global) => return "global".to_string(),
+        Some(Scope::Local(it)) => it.clone(),
+        None => return "-".to_string(),
+    };
+
+    let module_opt = local
+        .module_opt
+        .and_then(|m| wa.module_label(m))
+        .map(|label| format!("module {}", label));
+    match (module_opt, local.deffunc_opt) {
+        (Some(module), Some(_)) => format!("{}, deffunc", module),
+        (Some(module), None) => module,
+        (None, Some(_)) => "deffunc".to_string(),
+        (None, None) => "toplevel".to_string(),
+    }
+}
+
+fn is_ctype(kind: HspSymbolKind) -> bool {
+    matches!(
+        kind,
+        HspSymbolKind::DefCFunc | HspSymbolKind::ModCFunc | HspSymbolKind::Macro { ctype: true }
+    )
+}
+
+/// ディレクトリにあるドキュメントの解析結果をテキストにする。
+///
+/// ドキュメントごとに、ディレクトリからの相対パスの順に、シンボルと診断を位置の順に書き出す。
+/// (行・列は1から数える。) 絶対パスやIDを含まないので、環境によらず同じ出力になる。
+pub(crate) fn analysis_snapshot(
+    root_dir: &Path,
+    diagnostics: Vec<(Url, Option<i32>, Vec<Diagnostic>)>,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> String {
+    let relative_path = |uri: &CanonicalUri| -> Option<String> {
+        let path = uri.to_file_path()?;
+        let relative = path.strip_prefix(root_dir).ok()?;
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    };
+
+    let mut target_docs = docs
+        .iter()
+        .filter_map(|(doc, uri)| Some((relative_path(uri)?, doc)))
+        .collect::<Vec<_>>();
+    target_docs.sort();
+
+    let mut stats = vec![];
+    wa.collect_symbol_stats(&mut stats);
+    stats.sort_by_key(|stat| (stat.def_loc.start(), stat.symbol.name()));
+
+    let mut diagnostic_map: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    for (uri, _, ds) in diagnostics {
+        diagnostic_map.entry(uri).or_default().extend(ds);
+    }
+
+    let mut output = String::new();
+    for (path, doc) in target_docs {
+        let _ = writeln!(output, "# {}", path);
+
+        for stat in stats.iter().filter(|stat| stat.def_loc.doc == doc) {
+            let symbol = &stat.symbol;
+            let pos = stat.def_loc.start();
+            let _ = writeln!(
+                output,
+                "symbol {}:{} {} {} [{}] uses={}",
+                pos.row + 1,
+                pos.column16 + 1,
+                kind_name(symbol.kind),
+                symbol.name(),
+                scope_to_string(symbol, wa),
+                stat.use_count
+            );
+            if let Some(signature_data) = symbol.signature_opt() {
+                let signature = signature_to_string(&signature_data, is_ctype(symbol.kind));
+                let _ = writeln!(output, "  signature: {}", signature);
+            }
+        }
+
+        let uri = match docs.get_uri(doc) {
+            Some(it) => it.clone().into_url(),
+            None => continue,
+        };
+        let mut ds = diagnostic_map.remove(&uri).unwrap_or_default();
+        ds.sort_by(|l, r| {
+            (l.range.start.line, l.range.start.character)
+                .cmp(&(r.range.start.line, r.range.start.character))
+                .then_with(|| l.message.cmp(&r.message))
+        });
+        for d in ds {
+            let _ = writeln!(
+                output,
+                "diagnostic {}:{} {}: {}",
+                d.range.start.line + 1,
+                d.range.start.character + 1,
+                severity_name(d.severity),
+                d.message
+            );
+        }
+    }
+    output
+}
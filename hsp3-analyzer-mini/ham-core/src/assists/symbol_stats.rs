@@ -0,0 +1,107 @@
+//! シンボルの使用頻度の統計 (`ham/symbolStats` リクエスト)
+
+use super::*;
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SymbolStatItem {
+    name: String,
+    kind: &'static str,
+    location: Location,
+    use_count: usize,
+    /// 定義しているファイルが最後に編集された時刻 (UNIX時間のミリ秒)
+    last_edited: Option<u64>,
+}
+
+pub(super) fn kind_name(kind: HspSymbolKind) -> &'static str {
+    match kind {
+        HspSymbolKind::Unresolved | HspSymbolKind::Unknown => "unknown",
+        HspSymbolKind::Label => "label",
+        HspSymbolKind::StaticVar => "staticVar",
+        HspSymbolKind::Const => "const",
+        HspSymbolKind::Enum => "enum",
+        HspSymbolKind::Macro { .. } => "macro",
+        HspSymbolKind::DefFunc => "deffunc",
+        HspSymbolKind::DefCFunc => "defcfunc",
+        HspSymbolKind::ModFunc => "modfunc",
+        HspSymbolKind::ModCFunc => "modcfunc",
+        HspSymbolKind::Param(_) => "param",
+        HspSymbolKind::Module => "module",
+        HspSymbolKind::Field => "field",
+        HspSymbolKind::LibFunc => "libfunc",
+        HspSymbolKind::PluginCmd => "pluginCmd",
+        HspSymbolKind::ComInterface => "comInterface",
+        HspSymbolKind::ComFunc => "comfunc",
+    }
+}
+
+pub(crate) fn symbol_stats(
+    target_doc: impl Fn(DocId) -> bool,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Vec<SymbolStatItem> {
+    let mut stats = vec![];
+    wa.collect_symbol_stats(&mut stats);
+
+    // よく使われているものから順に並べる。
+    stats.sort_by(|l, r| {
+        r.use_count
+            .cmp(&l.use_count)
+            .then_with(|| l.symbol.name().cmp(&r.symbol.name()))
+            .then_with(|| l.def_loc.cmp(&r.def_loc))
+    });
+
+    stats
+        .into_iter()
+        .filter(|stat| target_doc(stat.def_loc.doc))
+        .filter_map(|stat| {
+            Some(SymbolStatItem {
+                name: stat.symbol.name().to_string(),
+                kind: kind_name(stat.symbol.kind),
+                location: loc_to_location(stat.def_loc, docs)?,
+                use_count: stat.use_count,
+                last_edited: stat.last_edited_opt.and_then(|time| {
+                    Some(time.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+                }),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+
+        let uri = dummy_url("symbol_stats.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#deffunc f\n\treturn\n#deffunc g\n\treturn\n\tg\n\tg\n\tf\n".into(),
+        );
+
+        let stats = ls.symbol_stats();
+        let summary = stats
+            .iter()
+            .map(|item| {
+                (
+                    item.name.as_str(),
+                    item.use_count,
+                    item.last_edited.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![("g", 2, false), ("f", 1, false)]);
+
+        ls.change_doc(uri, 2, "#deffunc f\n\treturn\n\tf\n".into());
+        let stats = ls.symbol_stats();
+        assert_eq!(stats.len(), 1);
+        assert!(stats[0].last_edited.is_some());
+    }
+}
@@ -0,0 +1,63 @@
+//! 型定義へ移動 (`newmod` で作られたモジュール変数から `#module` へ)
+
+use super::*;
+use lsp_types::{Location, Position, Url};
+
+pub(crate) fn type_definitions(
+    uri: Url,
+    position: Position,
+    docs: &Docs,
+    wa: &mut WorkspaceAnalysis,
+) -> Option<Vec<Location>> {
+    let (doc, pos) = from_document_position(&uri, position, docs)?;
+
+    let project = wa.require_project_for_doc(doc);
+    let (symbol, _) = project.locate_symbol(doc, pos)?;
+
+    let mut modules = vec![];
+    match symbol.kind {
+        HspSymbolKind::Module => modules.push(symbol),
+        HspSymbolKind::StaticVar => project.collect_newmod_modules(&symbol, &mut modules),
+        _ => return None,
+    }
+
+    let mut locs = vec![];
+    for module in &modules {
+        project.collect_symbol_defs(module, &mut locs);
+    }
+
+    Some(
+        locs.into_iter()
+            .filter_map(|loc| loc_to_location(loc, docs))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang_service::LangService;
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_type_definition() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("type_definition.hsp");
+        ls.open_doc(
+            uri.clone(),
+            NO_VERSION,
+            "#module mymodule x\n#modinit\n\treturn\n#global\n\tnewmod v, mymodule\n\tmes v\n"
+                .into(),
+        );
+
+        let rows = ls
+            .type_definitions(uri.clone(), Position::new(5, 5))
+            .into_iter()
+            .map(|location| location.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![0]);
+
+        // モジュール変数でないもの
+        assert_eq!(ls.type_definitions(uri, Position::new(5, 2)), vec![]);
+    }
+}
@@ -0,0 +1,103 @@
+//! ワークスペースの統計 (`ham/workspaceStats` リクエスト)
+
+use super::{symbol_stats::kind_name, *};
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WorkspaceStats {
+    /// 解析しているスクリプトファイルの数 (commonのファイルを含む。)
+    doc_count: usize,
+    /// スクリプトファイルの行数の合計
+    line_count: usize,
+    /// エントリーポイントごとのプロジェクトの数
+    project_count: usize,
+    /// シンボルの種類ごとの、定義されている数
+    symbol_counts: BTreeMap<&'static str, usize>,
+    /// エントリーポイントから最も深くincludeされているファイルまでの段数
+    include_depth: usize,
+    analysis: AnalysisTimingItem,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnalysisTimingItem {
+    /// 解析した回数
+    count: usize,
+    /// 最後の解析にかかった時間 (ミリ秒)
+    last_ms: f64,
+    /// 解析にかかった時間の合計 (ミリ秒)
+    total_ms: f64,
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub(crate) fn workspace_stats(wa: &mut WorkspaceAnalysis) -> WorkspaceStats {
+    let timing = wa.analysis_timing();
+
+    let mut stats = vec![];
+    wa.collect_symbol_stats(&mut stats);
+    let mut symbol_counts = BTreeMap::new();
+    for stat in &stats {
+        *symbol_counts
+            .entry(kind_name(stat.symbol.kind))
+            .or_default() += 1;
+    }
+
+    let (doc_count, line_count) = wa.count_script_lines();
+
+    WorkspaceStats {
+        doc_count,
+        line_count,
+        project_count: wa.project_count(),
+        symbol_counts,
+        include_depth: wa.include_graph().max_depth(),
+        analysis: AnalysisTimingItem {
+            count: timing.count,
+            last_ms: to_ms(timing.last),
+            total_ms: to_ms(timing.total),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::{dummy_root, dummy_url};
+    use lsp_types::Url;
+
+    #[test]
+    fn test() {
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(dummy_root()).unwrap()));
+
+        ls.open_doc(
+            dummy_url("workspace_stats.hsp"),
+            NO_VERSION,
+            "; ham-entry\n#include \"workspace_stats_a.as\"\n\tf\n".into(),
+        );
+        ls.open_doc(
+            dummy_url("workspace_stats_a.as"),
+            NO_VERSION,
+            "#include \"workspace_stats_b.as\"\n#deffunc f\n\treturn\n".into(),
+        );
+        ls.open_doc(
+            dummy_url("workspace_stats_b.as"),
+            NO_VERSION,
+            "#const K 1\n#deffunc g\n\treturn\n#deffunc h\n\treturn\n".into(),
+        );
+
+        let stats = ls.workspace_stats();
+        assert_eq!((stats.doc_count, stats.line_count), (3, 11));
+        assert_eq!(stats.project_count, 1);
+        assert_eq!(
+            stats.symbol_counts.into_iter().collect::<Vec<_>>(),
+            vec![("const", 1), ("deffunc", 3)]
+        );
+        assert_eq!(stats.include_depth, 2);
+        assert!(stats.analysis.count >= 1);
+    }
+}
@@ -35,18 +35,83 @@ pub(crate) fn symbol(
     wa: &mut WorkspaceAnalysis,
 ) -> Vec<SymbolInformation> {
     let mut symbols = vec![];
-    wa.require_some_project()
-        .collect_all_symbols(query, &mut symbols);
+    wa.collect_all_symbols(query, &mut symbols);
 
-    symbols
+    // 命令・関数の中でだけ有効なシンボルは除く。
+    // モジュールの中で定義されたシンボルは、モジュール名をコンテナ名として表示する。
+    let mut items = symbols
         .into_iter()
-        .filter(|(symbol, _)| symbol.scope_opt.as_ref().map_or(false, |s| s.is_public()))
+        .filter(|(symbol, _)| match &symbol.scope_opt {
+            Some(Scope::Global) => true,
+            Some(Scope::Local(scope)) => scope.deffunc_opt.is_none(),
+            None => false,
+        })
         .filter_map(|(symbol, loc)| {
             let name = symbol.name();
             let kind = to_lsp_symbol_kind(symbol.kind)?;
             let location = loc_to_location(loc, docs)?;
 
-            Some(new_lsp_symbol_information(name.to_string(), kind, location))
+            let mut info = new_lsp_symbol_information(name.to_string(), kind, location);
+            info.container_name = symbol
+                .ns_opt
+                .as_ref()
+                .filter(|ns| !ns.is_empty())
+                .map(|ns| ns.to_string());
+            Some(info)
         })
-        .collect()
+        .collect::<Vec<_>>();
+
+    items.sort_by(|l, r| {
+        (&l.name, l.location.uri.as_str(), l.location.range.start).cmp(&(
+            &r.name,
+            r.location.uri.as_str(),
+            r.location.range.start,
+        ))
+    });
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang_service::{docs::NO_VERSION, LangService};
+    use crate::utils::test_utils::dummy_url;
+
+    #[test]
+    fn test_workspace_symbol() {
+        let mut ls = LangService::new_standalone();
+        let uri = dummy_url("workspace_symbol.hsp");
+        ls.open_doc(
+            uri,
+            NO_VERSION,
+            r#"#module m_Player
+#deffunc player_Init int hp
+	local tmp
+	count = hp
+	return
+#global
+#const PLAYER_MAX 4
+"#
+            .into(),
+        );
+
+        let names = |query: &str, ls: &mut LangService| {
+            ls.workspace_symbol(query.into())
+                .into_iter()
+                .map(|s| (s.name, s.container_name))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names("player", &mut ls),
+            vec![
+                ("PLAYER_MAX".to_string(), None),
+                ("player_Init".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            names("COUNT", &mut ls),
+            vec![("count".to_string(), Some("m_Player".to_string()))]
+        );
+        assert!(names("tmp", &mut ls).is_empty());
+    }
 }
@@ -1,24 +1,115 @@
+mod builtin_db;
+pub(crate) mod commands;
 pub(crate) mod docs;
+mod file_prefetch;
+mod include_resolver;
+pub(crate) mod manifest;
 mod search_common;
 pub(crate) mod search_hsphelp;
 
-use self::docs::{DocChange, Docs};
+use self::{
+    commands::*,
+    docs::{read_source_file, DocChange, Docs, ProjectDocs},
+};
 use super::*;
 use crate::{
     analysis::*,
-    assists::{self, diagnose::DiagnosticsCache},
+    assists::{self, diagnose::DiagnosticsCache, semantic_tokens::SemanticTokensCache},
     help_source::HsSymbol,
     lang::Lang,
     lang_service::{
-        docs::DocChangeOrigin, search_common::search_common, search_hsphelp::search_hsphelp,
+        builtin_db::load_builtin_db,
+        docs::{DocChangeOrigin, FileEncoding, LargeDoc},
+        file_prefetch::FilePrefetch,
+        include_resolver::find_include_file,
+        manifest::{Manifest, MANIFEST_FILE_NAME},
+        search_common::search_common,
+        search_hsphelp::search_hsphelp,
     },
-    utils::read_file::read_file,
+    source::DocId,
+    utils::canonical_path::canonicalize_path,
 };
 use lsp_types::*;
+use serde::Deserialize;
+
+/// スクリプトファイルの文字コード
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SourceEncoding {
+    /// 内容から推測する。(`#cmpopt utf8` があるか UTF-8 の日本語を含むなら UTF-8、そうでなければ shift_jis)
+    Auto,
+    #[serde(alias = "shift_jis")]
+    Sjis,
+    #[serde(alias = "utf-8")]
+    Utf8,
+}
+
+impl SourceEncoding {
+    /// 設定やモードラインに書かれた名前から文字コードを得る。(大文字・小文字は区別しない。)
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        let encoding = match name.to_ascii_lowercase().as_str() {
+            "auto" => SourceEncoding::Auto,
+            "sjis" | "shift_jis" | "cp932" => SourceEncoding::Sjis,
+            "utf8" | "utf-8" => SourceEncoding::Utf8,
+            _ => return None,
+        };
+        Some(encoding)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SourceEncoding::Auto => "auto",
+            SourceEncoding::Sjis => "shift_jis",
+            SourceEncoding::Utf8 => "UTF-8",
+        }
+    }
+}
+
+impl Default for SourceEncoding {
+    fn default() -> Self {
+        SourceEncoding::Auto
+    }
+}
+
+/// 既定のドキュメントのサイズの上限 (1 MiB)
+pub(crate) const DEFAULT_LARGE_FILE_SIZE: usize = 1024 * 1024;
+
+/// ワークスペースの走査から既定で除外するパス
+const DEFAULT_EXCLUDE: &[&str] = &["**/obj/**", "**/backup/**", "**/.git/**"];
+
+fn default_exclude() -> Vec<glob::Pattern> {
+    DEFAULT_EXCLUDE
+        .iter()
+        .map(|p| glob::Pattern::new(p).unwrap())
+        .collect()
+}
 
 pub(crate) struct LangServiceOptions {
     pub(crate) lint_enabled: bool,
+    /// 使われていないシンボルを報告するか
+    pub(crate) lint_unused: bool,
     pub(crate) watcher_enabled: bool,
+    /// 必要になっていないワークスペースのファイルも、別のスレッドで先読みするか
+    pub(crate) prefetch_enabled: bool,
+    pub(crate) encoding: SourceEncoding,
+    /// スクリプトを実行するランタイムの名前 (`#runtime` がないプロジェクトで使う。)
+    pub(crate) target: Option<String>,
+    /// これより大きい (バイト数) ドキュメントは、シンボルの収集だけを行う。
+    pub(crate) large_file_size: usize,
+    /// 大きいドキュメントを解析しないか
+    pub(crate) skip_large_files: bool,
+    /// 設定で指定されたエントリーポイント
+    pub(crate) entrypoints: Vec<PathBuf>,
+    /// `#include` するファイルを探すディレクトリ (commonより優先する。)
+    pub(crate) include_dirs: Vec<PathBuf>,
+    /// ワークスペースの走査とファイルの監視から除外するパスのパターン (ルートからの相対パスにマッチさせる。)
+    pub(crate) exclude: Vec<glob::Pattern>,
+    /// クライアントが入れ子になったドキュメントシンボルに対応しているか
+    pub(crate) hierarchical_document_symbol: bool,
+    /// インレイヒントで変数の型を表示するか
+    pub(crate) inlay_hint_types: bool,
+    /// クライアントが補完のスニペットに対応しているか
+    pub(crate) completion_snippet: bool,
 }
 
 impl LangServiceOptions {
@@ -26,7 +117,19 @@ impl LangServiceOptions {
     pub(crate) fn minimal() -> Self {
         Self {
             lint_enabled: false,
+            lint_unused: true,
             watcher_enabled: false,
+            prefetch_enabled: false,
+            encoding: SourceEncoding::default(),
+            target: None,
+            large_file_size: DEFAULT_LARGE_FILE_SIZE,
+            skip_large_files: false,
+            entrypoints: vec![],
+            include_dirs: vec![],
+            exclude: vec![],
+            hierarchical_document_symbol: false,
+            inlay_hint_types: false,
+            completion_snippet: false,
         }
     }
 }
@@ -35,7 +138,19 @@ impl Default for LangServiceOptions {
     fn default() -> Self {
         Self {
             lint_enabled: true,
+            lint_unused: true,
             watcher_enabled: true,
+            prefetch_enabled: true,
+            encoding: SourceEncoding::default(),
+            target: None,
+            large_file_size: DEFAULT_LARGE_FILE_SIZE,
+            skip_large_files: false,
+            entrypoints: vec![],
+            include_dirs: vec![],
+            exclude: default_exclude(),
+            hierarchical_document_symbol: false,
+            inlay_hint_types: false,
+            completion_snippet: false,
         }
     }
 }
@@ -48,12 +163,30 @@ pub(super) struct LangService {
     options: LangServiceOptions,
     docs: Docs,
     diagnostics_cache: DiagnosticsCache,
+    semantic_tokens_cache: SemanticTokensCache,
+    /// ワークスペースの読み込みに関わる設定が変更されたか
+    workspace_config_changed: bool,
+    /// 収集したが、まだ読み込んでいないスクリプトファイル
+    pending_files: HashSet<PathBuf>,
+    /// 先読みが終わったファイルの内容 (ドキュメントとして読み込むときに使う。)
+    prefetched_files: HashMap<PathBuf, (String, FileEncoding)>,
+    file_prefetch_opt: Option<FilePrefetch>,
+    /// ワークスペースのルートにある設定ファイル (ginger.toml) の内容
+    manifest: Manifest,
+    /// 設定ファイルの誤り (クライアントに通知するまで溜めておく。)
+    manifest_errors: Vec<String>,
+    /// (includeするドキュメント, includeのファイル名) -> ファイルシステム上で解決されたドキュメント
+    include_targets: Rc<HashMap<(DocId, String), DocId>>,
+    /// includeをファイルシステム上で探した結果 (ファイルが作成・削除されたら捨てる。)
+    include_cache: HashMap<(DocId, String), Option<DocId>>,
+    /// ドキュメントが変更されてから、includeを解決しなおしていないか
+    includes_changed: bool,
 }
 
 impl LangService {
     pub(super) fn new(hsp3_root: PathBuf, options: LangServiceOptions) -> Self {
         Self {
-            hsp3_root,
+            hsp3_root: canonicalize_path(&hsp3_root),
             options,
             ..Default::default()
         }
@@ -73,6 +206,11 @@ impl LangService {
         ls
     }
 
+    #[cfg(test)]
+    pub(crate) fn options(&self) -> &LangServiceOptions {
+        &self.options
+    }
+
     pub(super) fn watcher_enabled(&self) -> bool {
         self.options.watcher_enabled
     }
@@ -83,6 +221,167 @@ impl LangService {
         }
     }
 
+    pub(super) fn set_hsp3_root(&mut self, hsp3_root: PathBuf) {
+        // ドキュメントのパスと比較するため、シンボリックリンクなどを解決しておく。
+        let hsp3_root = canonicalize_path(&hsp3_root);
+        if self.hsp3_root != hsp3_root {
+            self.hsp3_root = hsp3_root;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    pub(super) fn set_lint_enabled(&mut self, lint_enabled: bool) {
+        self.options.lint_enabled = lint_enabled;
+    }
+
+    pub(super) fn set_lint_unused(&mut self, enabled: bool) {
+        self.options.lint_unused = enabled;
+    }
+
+    pub(super) fn set_encoding(&mut self, encoding: SourceEncoding) {
+        if self.options.encoding != encoding {
+            self.options.encoding = encoding;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    pub(super) fn set_target(&mut self, target: String) {
+        self.options.target = Some(target);
+    }
+
+    pub(super) fn set_large_file_size(&mut self, size: usize) {
+        if self.options.large_file_size != size {
+            self.options.large_file_size = size;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    pub(super) fn set_skip_large_files(&mut self, skip: bool) {
+        if self.options.skip_large_files != skip {
+            self.options.skip_large_files = skip;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    pub(super) fn set_hierarchical_document_symbol(&mut self, supported: bool) {
+        self.options.hierarchical_document_symbol = supported;
+    }
+
+    pub(super) fn set_completion_snippet(&mut self, supported: bool) {
+        self.options.completion_snippet = supported;
+    }
+
+    pub(super) fn set_inlay_hint_types(&mut self, enabled: bool) {
+        self.options.inlay_hint_types = enabled;
+    }
+
+    pub(super) fn set_exclude(&mut self, exclude: Vec<glob::Pattern>) {
+        if self.options.exclude != exclude {
+            self.options.exclude = exclude;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    /// ワークスペースの走査やファイルの監視から除外されるパスか？
+    fn is_excluded(&self, path: &Path) -> bool {
+        let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
+        let path = match &root_dir_opt {
+            Some(dir) => path.strip_prefix(dir).unwrap_or(path),
+            None => path,
+        };
+        self.options
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(path))
+    }
+
+    /// 設定ファイル (ginger.toml) の指定を優先して、スクリプトファイルの文字コードを決める。
+    fn encoding(&self) -> SourceEncoding {
+        self.manifest.encoding.unwrap_or(self.options.encoding)
+    }
+
+    fn lint_enabled(&self) -> bool {
+        self.manifest
+            .lint
+            .enabled
+            .unwrap_or(self.options.lint_enabled)
+    }
+
+    fn lint_unused(&self) -> bool {
+        self.manifest
+            .lint
+            .unused
+            .unwrap_or(self.options.lint_unused)
+    }
+
+    /// ワークスペースのルートにある設定ファイル (ginger.toml) を読み込む。
+    ///
+    /// 読み込めなければ設定ファイルがないものとして扱い、その理由を記録する。
+    /// 内容が変わったら、ワークスペースの読み込みに関わる設定が変わったとみなす。
+    fn load_manifest(&mut self) {
+        let manifest = match self.root_dir() {
+            Some(dir) => Manifest::load(&dir).unwrap_or_else(|err| {
+                warn!("{}", err);
+                self.manifest_errors.push(err);
+                Manifest::default()
+            }),
+            None => Manifest::default(),
+        };
+        if self.manifest != manifest {
+            self.manifest = manifest;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    /// `#include` するファイルを探すディレクトリ
+    ///
+    /// 設定ファイルのincludeディレクトリを、エディタの設定のものより優先する。
+    fn include_dirs(&self) -> Vec<PathBuf> {
+        (self.manifest.include_paths.iter())
+            .chain(&self.options.include_dirs)
+            .cloned()
+            .collect()
+    }
+
+    /// ワークスペースのルートにある設定ファイルを指すURIか？
+    fn is_manifest(&self, uri: &CanonicalUri) -> bool {
+        match (self.root_dir(), uri.to_file_path()) {
+            (Some(dir), Some(path)) => path == dir.join(MANIFEST_FILE_NAME),
+            _ => false,
+        }
+    }
+
+    /// 設定ファイルが変更、作成、削除されたら読み込み直す。
+    fn on_manifest_changed(&mut self) {
+        info!("{} が変更されました。", MANIFEST_FILE_NAME);
+        self.load_manifest();
+        self.did_change_configuration();
+    }
+
+    /// 設定ファイルの誤りを取り出す。
+    pub(super) fn take_manifest_errors(&mut self) -> Vec<String> {
+        take(&mut self.manifest_errors)
+    }
+
+    pub(super) fn set_entrypoints(&mut self, entrypoints: Vec<PathBuf>) {
+        if self.options.entrypoints != entrypoints {
+            self.options.entrypoints = entrypoints;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    pub(super) fn set_include_dirs(&mut self, include_dirs: Vec<PathBuf>) {
+        if self.options.include_dirs != include_dirs {
+            self.options.include_dirs = include_dirs;
+            self.workspace_config_changed = true;
+        }
+    }
+
+    /// ワークスペースのルートディレクトリ
+    pub(super) fn root_dir(&self) -> Option<PathBuf> {
+        self.root_uri_opt.as_ref().and_then(|x| x.to_file_path())
+    }
+
     pub(super) fn initialize(&mut self, root_uri_opt: Option<Url>) {
         if let Some(uri) = root_uri_opt {
             self.root_uri_opt = Some(CanonicalUri::from_url(&uri));
@@ -90,13 +389,43 @@ impl LangService {
     }
 
     pub(super) fn did_initialize(&mut self) {
+        self.load_manifest();
+        self.load_workspace_host();
+        self.scan_script_files();
+        self.workspace_config_changed = false;
+    }
+
+    /// 設定の変更を反映する。(`workspace/didChangeConfiguration`)
+    ///
+    /// HSP3 のルート、文字コード、除外パターン、エントリーポイント、includeディレクトリのいずれかが変わったときは、
+    /// HSP3 の共通ファイルやエントリーポイントを探しなおして、ワークスペースを読み込み直す。
+    /// lint の設定は次に diagnostics を計算するときに反映される。
+    pub(super) fn did_change_configuration(&mut self) {
+        if !self.workspace_config_changed {
+            return;
+        }
+        self.workspace_config_changed = false;
+
+        info!("設定が変更されたので、ワークスペースを読み込み直します。");
+        self.load_workspace_host();
+        self.reindex_workspace();
+    }
+
+    /// HSP3 の共通ファイルやヘルプ、エントリーポイントを探して、解析器に渡す。
+    fn load_workspace_host(&mut self) {
         let mut builtin_env = SymbolEnv::default();
         let mut common_docs = HashMap::new();
         let mut entrypoints = vec![];
 
-        search_common(&self.hsp3_root, &mut self.docs, &mut common_docs);
+        let include_dirs = self.include_dirs();
+        search_common(
+            &self.hsp3_root,
+            &include_dirs,
+            &mut self.docs,
+            &mut common_docs,
+        );
 
-        let hsphelp_info = search_hsphelp(
+        let mut hsphelp_info = search_hsphelp(
             &self.hsp3_root,
             &common_docs,
             &mut self.docs,
@@ -104,6 +433,8 @@ impl LangService {
         )
         .unwrap_or_default();
 
+        load_builtin_db(&mut builtin_env, &mut hsphelp_info.builtin_items);
+
         info!("ルートディレクトリからgingerプロジェクトファイルを収集します。");
         {
             let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
@@ -140,6 +471,24 @@ impl LangService {
                 entrypoints.extend(docs);
             }
 
+            // 設定や設定ファイルで指定されたエントリーポイント
+            let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
+            for path in self
+                .options
+                .entrypoints
+                .iter()
+                .chain(&self.manifest.entry_points)
+            {
+                let path = match &root_dir_opt {
+                    Some(dir) if path.is_relative() => dir.join(path),
+                    _ => path.clone(),
+                };
+                match self.docs.ensure_file_opened(&path) {
+                    Some(doc) => entrypoints.push(doc),
+                    None => warn!("エントリーポイントをopenできません。{:?}", path),
+                }
+            }
+
             trace!(
                 "entrypoints={:?}",
                 entrypoints
@@ -149,73 +498,373 @@ impl LangService {
             );
         }
 
+        // ランタイムの命令を定義しているファイルは、すべてのプロジェクトで暗黙にincludeされているとみなす。
+        let implicit_docs = (self.manifest.runtime)
+            .and_then(|runtime| runtime.header_name())
+            .and_then(|name| common_docs.get(name).cloned())
+            .into_iter()
+            .collect();
+
         self.wa.initialize(WorkspaceHost {
             builtin_env: Rc::new(builtin_env),
             common_docs: Rc::new(common_docs),
             hsphelp_info: Rc::new(hsphelp_info),
             entrypoints,
+            implicit_docs,
         });
 
+        // includeディレクトリが変わると、includeの解決結果も変わる。
+        self.include_cache.clear();
+        self.includes_changed = true;
+    }
+
+    /// ワークスペースのスクリプトファイルを収集する。
+    ///
+    /// ファイルはすぐには読み込まず、必要になったときに読み込む。(`load_needed_files` を参照。)
+    /// 残りのファイルは別のスレッドで先読みして、順次読み込む。
+    fn scan_script_files(&mut self) {
         info!("ルートディレクトリからスクリプトファイルを収集します。");
+        let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
+        let script_files = root_dir_opt
+            .into_iter()
+            .filter_map(|dir| glob::glob(&format!("{}/**/*.hsp", dir.to_str()?)).ok())
+            .flatten()
+            .filter_map(|path_opt| path_opt.ok())
+            .collect::<Vec<_>>();
+        for path in script_files {
+            if self.is_excluded(&path) {
+                continue;
+            }
+
+            // すでに読み込まれているファイル
+            let known = CanonicalUri::from_file_path(&path)
+                .map_or(false, |uri| self.docs.find_by_uri(&uri).is_some());
+            if known {
+                continue;
+            }
+
+            self.pending_files.insert(path);
+        }
+
+        self.prefetched_files.clear();
+        self.file_prefetch_opt = if self.options.prefetch_enabled && !self.pending_files.is_empty()
         {
-            let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
-            let script_files = root_dir_opt
-                .into_iter()
-                .filter_map(|dir| glob::glob(&format!("{}/**/*.hsp", dir.to_str()?)).ok())
-                .flatten()
-                .filter_map(|path_opt| path_opt.ok());
-            for path in script_files {
-                self.docs.change_file(&path);
+            let mut paths = self.pending_files.iter().cloned().collect::<Vec<_>>();
+            paths.sort();
+            let skip_size_opt = if self.options.skip_large_files {
+                Some(self.options.large_file_size)
+            } else {
+                None
+            };
+            Some(FilePrefetch::start(paths, self.encoding(), skip_size_opt))
+        } else {
+            None
+        };
+    }
+
+    /// 収集したファイルを読み込む。
+    fn load_pending_file(&mut self, path: &Path) {
+        if self.pending_files.remove(path) {
+            self.docs.change_file(path);
+        }
+    }
+
+    /// 先読みが終わったファイルを読み込む。
+    fn receive_prefetched_files(&mut self) {
+        let files = match &self.file_prefetch_opt {
+            Some(prefetch) => prefetch.try_iter().collect::<Vec<_>>(),
+            None => return,
+        };
+
+        for (path, result) in files {
+            if !self.pending_files.contains(&path) {
+                continue;
+            }
+            if let Some(contents) = result {
+                self.prefetched_files.insert(path.clone(), contents);
+            }
+            self.load_pending_file(&path);
+        }
+    }
+
+    /// 読み込まれたドキュメントからincludeされているファイルを、推移的に読み込む。
+    fn load_needed_files(&mut self) {
+        while !self.pending_files.is_empty() {
+            let mut names = vec![];
+            self.wa.collect_include_names(&mut names);
+
+            // includeの解決はファイル名だけで行うので、名前が一致するファイルを読み込む。
+            let basenames = names
+                .iter()
+                .map(|name| name.as_str().rsplit('/').next().unwrap_or_default())
+                .collect::<HashSet<_>>();
+            let paths = self
+                .pending_files
+                .iter()
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map_or(false, |name| {
+                            basenames.contains(name.to_ascii_lowercase().as_str())
+                        })
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            if paths.is_empty() {
+                break;
+            }
+
+            for path in paths {
+                self.load_pending_file(&path);
+            }
+            self.apply_doc_changes();
+        }
+
+        self.resolve_includes();
+    }
+
+    /// 読み込まれたドキュメントの `#include` を、ファイルシステム上のファイルに解決する。
+    ///
+    /// includeするファイルがあるディレクトリ、includeディレクトリ、commonディレクトリの順に探す。
+    /// みつかったファイルがまだ読み込まれていなければ読み込み、そのincludeも解決する。
+    fn resolve_includes(&mut self) {
+        let include_dirs = self.include_dirs();
+        let common_dir = self.hsp3_root.join("common");
+
+        while take(&mut self.includes_changed) {
+            let mut includes = vec![];
+            self.wa.collect_doc_include_names(&mut includes);
+
+            let mut targets = HashMap::new();
+            let mut loaded = false;
+            for (doc, name) in includes {
+                let key = (doc, name.to_string());
+                if let Some(&target_opt) = self.include_cache.get(&key) {
+                    targets.extend(target_opt.map(|target| (key, target)));
+                    continue;
+                }
+
+                let base_dir_opt = (self.docs.get_uri(doc))
+                    .and_then(|uri| uri.to_file_path())
+                    .and_then(|path| Some(path.parent()?.to_path_buf()));
+                let path = match find_include_file(
+                    name.as_str(),
+                    base_dir_opt.as_deref(),
+                    &include_dirs,
+                    &common_dir,
+                ) {
+                    Some(it) => it,
+                    None => {
+                        self.include_cache.insert(key, None);
+                        continue;
+                    }
+                };
+
+                let known =
+                    CanonicalUri::from_file_path(&path).and_then(|uri| self.docs.find_by_uri(&uri));
+                let target = match known {
+                    Some(it) => it,
+                    None => {
+                        self.pending_files.remove(&path);
+                        match self.docs.ensure_file_opened(&path) {
+                            Some(it) => {
+                                loaded = true;
+                                it
+                            }
+                            None => continue,
+                        }
+                    }
+                };
+                self.include_cache.insert(key.clone(), Some(target));
+                targets.insert(key, target);
+            }
+
+            if *self.include_targets != targets {
+                self.include_targets = Rc::new(targets);
+                self.update_project_docs();
+            }
+            if loaded {
+                self.apply_doc_changes();
+            }
+        }
+    }
+
+    /// ドキュメントのファイル名やincludeの解決結果を解析器に渡す。
+    fn update_project_docs(&mut self) {
+        let mut project_docs = match &self.root_uri_opt {
+            Some(root_uri) => self.docs.get_docs_in(root_uri),
+            None => ProjectDocs::default(),
+        };
+        project_docs.include_targets = self.include_targets.clone();
+        self.wa.set_project_docs(Rc::new(project_docs));
+    }
+
+    /// まだ読み込んでいないファイルをすべて読み込む。(ワークスペース全体を対象とする処理の前に呼ぶ。)
+    fn load_all_files(&mut self) {
+        self.receive_prefetched_files();
+
+        let mut paths = self.pending_files.iter().cloned().collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            self.load_pending_file(&path);
+        }
+        self.file_prefetch_opt = None;
+        self.poll();
+    }
+
+    /// ファイルとして管理しているドキュメントをすべて読み込み直し、スクリプトファイルを収集しなおす。
+    ///
+    /// エディタで開かれているドキュメントはそのまま。なくなったファイルや除外されたファイルは閉じる。
+    fn reindex_workspace(&mut self) {
+        self.pending_files.clear();
+
+        let uris = self
+            .docs
+            .iter()
+            .map(|(_, uri)| uri.clone())
+            .collect::<Vec<_>>();
+        for uri in uris {
+            match uri.to_file_path() {
+                Some(path) if path.exists() && !self.is_excluded(&path) => {
+                    self.docs.change_file(&path);
+                }
+                Some(_) => self.docs.close_file_by_uri(uri),
+                None => {}
             }
         }
+
+        self.scan_script_files();
     }
 
     /// ドキュメントの変更を集積して、解析器の状態を更新する。
     fn poll(&mut self) {
+        self.receive_prefetched_files();
         self.apply_doc_changes();
+        self.load_needed_files();
     }
 
     fn apply_doc_changes(&mut self) {
+        self.apply_doc_changes_with(&mut |_, _| {});
+    }
+
+    /// ドキュメントの変更を反映する。変更を1つ処理するたびに `on_progress(処理した数, 全体の数)` を呼ぶ。
+    fn apply_doc_changes_with(&mut self, on_progress: &mut dyn FnMut(usize, usize)) {
         let mut doc_changes = vec![];
         self.docs.drain_doc_changes(&mut doc_changes);
+        let total = doc_changes.len();
+        if total != 0 {
+            self.includes_changed = true;
+        }
 
         let opened_or_closed = doc_changes.iter().any(|change| match change {
             DocChange::Opened { .. } | DocChange::Closed { .. } => true,
             _ => false,
         });
 
-        for change in doc_changes.drain(..) {
+        for (i, change) in doc_changes.drain(..).enumerate() {
+            on_progress(i, total);
+
             match change {
                 DocChange::Opened { doc, lang, origin }
                 | DocChange::Changed { doc, lang, origin } => {
-                    let text = match origin {
+                    let text: RcStr = match origin {
                         DocChangeOrigin::Editor(text) => text,
                         DocChangeOrigin::Path(path) => {
-                            let mut text = String::new();
-                            if !read_file(&path, &mut text) {
-                                warn!("ファイルを開けません。{:?}", path);
+                            // 解析しない大きさのファイルは読み込まない。
+                            let file_size = fs::metadata(&path).map_or(0, |m| m.len() as usize);
+                            if self.options.skip_large_files
+                                && file_size > self.options.large_file_size
+                            {
+                                self.prefetched_files.remove(&path);
+                                self.on_large_doc(doc, file_size);
+                                self.wa.update_doc(doc, lang, RcStr::default());
                                 continue;
                             }
+
+                            let (text, file_encoding) =
+                                match self.prefetched_files.remove(&path).or_else(|| {
+                                    let mut text = String::new();
+                                    read_source_file(&path, self.encoding(), &mut text)
+                                        .map(|e| (text, e))
+                                }) {
+                                    Some(it) => it,
+                                    None => {
+                                        warn!("ファイルを開けません。{:?}", path);
+                                        continue;
+                                    }
+                                };
+                            if file_encoding.malformed {
+                                warn!(
+                                    "ファイルを {} として読み込めない部分がありました。{:?}",
+                                    file_encoding.encoding.as_str(),
+                                    path
+                                );
+                            }
+                            self.docs.set_file_encoding(doc, file_encoding);
                             text.into()
                         }
                     };
 
+                    if text.len() > self.options.large_file_size {
+                        let size = text.len();
+                        self.on_large_doc(doc, size);
+                        if self.options.skip_large_files {
+                            self.wa.update_doc(doc, lang, RcStr::default());
+                            continue;
+                        }
+                    } else {
+                        self.docs.set_large_doc(doc, None);
+                        self.wa.set_symbols_only(doc, false);
+                    }
+
                     self.wa.update_doc(doc, lang, text);
                 }
                 DocChange::Closed { doc } => {
+                    self.semantic_tokens_cache.remove(doc);
                     self.wa.close_doc(doc);
                 }
             }
         }
+        on_progress(total, total);
 
         if opened_or_closed {
-            if let Some(root_uri) = &self.root_uri_opt {
-                let project_docs = self.docs.get_docs_in(root_uri);
-                self.wa.set_project_docs(Rc::new(project_docs));
-            }
+            self.include_cache.clear();
+            self.update_project_docs();
         }
     }
 
+    /// サイズの上限を超えたドキュメントを記録する。
+    fn on_large_doc(&mut self, doc: DocId, size: usize) {
+        let skipped = self.options.skip_large_files;
+        info!(
+            "ドキュメントが大きいため、{}。(doc={}, size={})",
+            if skipped {
+                "解析しません"
+            } else {
+                "シンボルの収集だけを行います"
+            },
+            doc,
+            size
+        );
+        self.docs
+            .set_large_doc(doc, Some(LargeDoc { size, skipped }));
+        self.wa.set_symbols_only(doc, !skipped);
+    }
+
+    /// 必要なファイルを読み込んで解析し、定義されているシンボルの数を返す。
+    ///
+    /// (残りのファイルは先読みが終わり次第読み込まれる。)
+    /// ファイルを読み込むたびに `on_progress(読み込んだ数, 全体の数)` を呼ぶ。
+    pub(super) fn index_workspace(&mut self, on_progress: &mut dyn FnMut(usize, usize)) -> usize {
+        self.receive_prefetched_files();
+        self.apply_doc_changes_with(on_progress);
+        self.load_needed_files();
+
+        let mut stats = vec![];
+        self.wa.collect_symbol_stats(&mut stats);
+        stats.len()
+    }
+
     pub(super) fn shutdown(&mut self) {}
 
     pub(super) fn open_doc(&mut self, uri: Url, version: i32, text: String) {
@@ -225,29 +874,180 @@ impl LangService {
     }
 
     pub(super) fn change_doc(&mut self, uri: Url, version: i32, text: String) {
+        self.edit_doc(
+            uri,
+            version,
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text,
+            }],
+        );
+    }
+
+    /// エディタでの変更 (`textDocument/didChange`) を反映する。
+    ///
+    /// - バージョン番号が最後に受け取ったもの以下なら、古い変更とみなして無視する。
+    /// - バージョン番号が飛んでいたら、途中の変更が抜けている。
+    ///   差分は適用できないので、全体を置き換える変更がなければ内容を読みなおす。
+    ///   その後も、全体を置き換える変更を受け取るまで差分は無視する。
+    ///   (読みなおした内容はエディタの内容と一致しないため。)
+    pub(super) fn edit_doc(
+        &mut self,
+        uri: Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) {
         let uri = CanonicalUri::from_url(&uri);
 
-        self.docs.change_doc_in_editor(uri, version, text.into());
+        let doc_opt = self.docs.find_by_uri(&uri);
+        let last_opt = doc_opt.and_then(|doc| self.docs.get_editor_text(doc));
+
+        // 差分を適用できる状態か？
+        let mut in_sync = doc_opt.map_or(true, |doc| !self.docs.is_editor_out_of_sync(doc));
+        let mut text_opt = None;
+        if let Some((last_version, last_text)) = last_opt {
+            if version <= last_version {
+                warn!(
+                    "古いバージョンの変更を無視します ({:?} version={} last={})",
+                    uri, version, last_version
+                );
+                return;
+            }
+            if version > last_version + 1 {
+                warn!(
+                    "変更が抜けています ({:?} version={} last={})",
+                    uri, version, last_version
+                );
+                in_sync = false;
+            }
+            text_opt = Some(last_text.to_string());
+        }
+
+        for change in changes {
+            match change.range {
+                None => {
+                    text_opt = Some(change.text);
+                    in_sync = true;
+                }
+                Some(range) => {
+                    if !in_sync {
+                        continue;
+                    }
+                    in_sync = match &mut text_opt {
+                        Some(text) => docs::apply_text_edit(text, range, &change.text),
+                        None => false,
+                    };
+                    if !in_sync {
+                        warn!("変更を適用できません ({:?} range={:?})", uri, range);
+                    }
+                }
+            }
+        }
+
+        let was_out_of_sync = doc_opt.map_or(false, |doc| self.docs.is_editor_out_of_sync(doc));
+        if !in_sync && !was_out_of_sync {
+            // ファイルの内容で置き換える。(ファイルがなければ、最後に受け取った内容のままにする。)
+            let mut text = String::new();
+            let read = uri
+                .to_file_path()
+                .and_then(|path| read_source_file(&path, self.encoding(), &mut text));
+            if read.is_some() {
+                error!(
+                    "エディタの内容が不明なため、ファイルから読みなおします ({:?})",
+                    uri
+                );
+                text_opt = Some(text);
+            } else {
+                error!(
+                    "エディタの内容が不明なため、古い内容のまま解析します ({:?})",
+                    uri
+                );
+            }
+        }
+
+        let text = match text_opt {
+            Some(it) => it,
+            None => return,
+        };
+        self.docs
+            .change_doc_in_editor(uri.clone(), version, text.into());
+
+        if let Some(doc) = self.docs.find_by_uri(&uri) {
+            self.docs.set_editor_out_of_sync(doc, !in_sync);
+        }
     }
 
     pub(super) fn close_doc(&mut self, uri: Url) {
         let uri = CanonicalUri::from_url(&uri);
 
+        // ファイルの内容に戻すとき、先読みした古い内容を使わないようにする。
+        if let Some(path) = uri.to_file_path() {
+            self.prefetched_files.remove(&path);
+        }
+
+        // 閉じたドキュメントのセマンティックトークンはもう要求されない。
+        if let Some(doc) = self.docs.find_by_uri(&uri) {
+            self.semantic_tokens_cache.remove(doc);
+        }
+
         self.docs.close_doc_in_editor(uri);
     }
 
+    /// ファイルの監視で通知されたURIを無視するべきか？
+    ///
+    /// ファイルを指さないURI (`untitled:` など) はエディタで開かれているドキュメントとしてだけ扱う。
+    fn is_unwatched(&self, uri: &CanonicalUri) -> bool {
+        match uri.to_file_path() {
+            Some(path) => self.is_excluded(&path),
+            None => true,
+        }
+    }
+
+    /// まだ読み込んでいないファイルが変更されたら、先読みした内容を捨てる。
+    fn forget_pending_file(&mut self, uri: &CanonicalUri) {
+        if let Some(path) = uri.to_file_path() {
+            self.pending_files.remove(&path);
+            self.prefetched_files.remove(&path);
+        }
+    }
+
     pub(super) fn on_file_created(&mut self, uri: Url) {
         let uri = CanonicalUri::from_url(&uri);
+        if self.is_manifest(&uri) {
+            self.on_manifest_changed();
+            return;
+        }
+        if self.is_unwatched(&uri) {
+            return;
+        }
+        self.forget_pending_file(&uri);
         self.docs.change_file_by_uri(uri);
     }
 
     pub(super) fn on_file_changed(&mut self, uri: Url) {
         let uri = CanonicalUri::from_url(&uri);
+        if self.is_manifest(&uri) {
+            self.on_manifest_changed();
+            return;
+        }
+        if self.is_unwatched(&uri) {
+            return;
+        }
+        self.forget_pending_file(&uri);
         self.docs.change_file_by_uri(uri);
     }
 
     pub(super) fn on_file_deleted(&mut self, uri: Url) {
         let uri = CanonicalUri::from_url(&uri);
+        if self.is_manifest(&uri) {
+            self.on_manifest_changed();
+            return;
+        }
+        if !uri.is_file() {
+            return;
+        }
+        self.forget_pending_file(&uri);
         self.docs.close_file_by_uri(uri);
     }
 
@@ -264,6 +1064,28 @@ impl LangService {
             assists::rewrites::flip_comma::flip_comma(&uri, range, &self.docs, &mut self.wa)
                 .unwrap_or_default(),
         );
+        actions.extend(
+            assists::rewrites::add_include::add_include(&uri, range, &self.docs, &mut self.wa)
+                .unwrap_or_default(),
+        );
+        actions.extend(
+            assists::rewrites::create_include_file::create_include_file(
+                &uri,
+                range,
+                &self.docs,
+                &mut self.wa,
+            )
+            .unwrap_or_default(),
+        );
+        actions.extend(
+            assists::rewrites::generate_deffunc::generate_deffunc(
+                &uri,
+                range,
+                &self.docs,
+                &mut self.wa,
+            )
+            .unwrap_or_default(),
+        );
         actions.extend(
             assists::rewrites::generate_include_guard::generate_include_guard(
                 &uri,
@@ -273,14 +1095,43 @@ impl LangService {
             )
             .unwrap_or_default(),
         );
+        actions.extend(
+            assists::rewrites::generate_include_guard::wrap_in_include_guard(
+                &uri,
+                range,
+                &self.docs,
+                &mut self.wa,
+            )
+            .unwrap_or_default(),
+        );
         actions
     }
 
     pub(super) fn completion(&mut self, uri: Url, position: Position) -> CompletionList {
         self.poll();
 
-        assists::completion::completion(uri, position, &self.docs, &mut self.wa)
-            .unwrap_or_else(assists::completion::incomplete_completion_list)
+        assists::completion::completion(
+            uri,
+            position,
+            self.options.completion_snippet,
+            &self.docs,
+            &mut self.wa,
+        )
+        .unwrap_or_else(assists::completion::incomplete_completion_list)
+    }
+
+    pub(super) fn code_lens(&mut self, uri: Url) -> Vec<CodeLens> {
+        self.poll();
+
+        assists::code_lens::code_lens(uri, &self.docs, &mut self.wa).unwrap_or_default()
+    }
+
+    pub(super) fn code_lens_resolve(&mut self, code_lens: CodeLens) -> CodeLens {
+        self.poll();
+
+        // 解決できなかったときは、そのまま返す。
+        assists::code_lens::code_lens_resolve(code_lens.clone(), &self.docs, &mut self.wa)
+            .unwrap_or(code_lens)
     }
 
     pub(super) fn completion_resolve(
@@ -296,12 +1147,44 @@ impl LangService {
         assists::formatting::formatting(uri, &self.docs, &mut self.wa)
     }
 
+    pub(super) fn on_type_formatting(
+        &mut self,
+        uri: Url,
+        position: Position,
+        ch: &str,
+    ) -> Option<Vec<TextEdit>> {
+        self.poll();
+
+        assists::on_type_formatting::on_type_formatting(uri, position, ch, &self.docs, &mut self.wa)
+    }
+
     pub(super) fn definitions(&mut self, uri: Url, position: Position) -> Vec<Location> {
         self.poll();
 
         assists::definitions::definitions(uri, position, &self.docs, &mut self.wa).unwrap_or(vec![])
     }
 
+    pub(super) fn declarations(&mut self, uri: Url, position: Position) -> Vec<Location> {
+        self.poll();
+
+        assists::definitions::declarations(uri, position, &self.docs, &mut self.wa)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn implementations(&mut self, uri: Url, position: Position) -> Vec<Location> {
+        self.poll();
+
+        assists::implementation::implementations(uri, position, &self.docs, &mut self.wa)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn type_definitions(&mut self, uri: Url, position: Position) -> Vec<Location> {
+        self.poll();
+
+        assists::type_definition::type_definitions(uri, position, &self.docs, &mut self.wa)
+            .unwrap_or_default()
+    }
+
     pub(super) fn document_highlight(
         &mut self,
         uri: Url,
@@ -313,10 +1196,50 @@ impl LangService {
             .unwrap_or(vec![])
     }
 
+    pub(super) fn document_link(&mut self, uri: Url) -> Vec<DocumentLink> {
+        self.poll();
+
+        assists::document_link::document_link(uri, &self.docs, &mut self.wa).unwrap_or_default()
+    }
+
+    pub(super) fn folding_range(&mut self, uri: Url) -> Vec<FoldingRange> {
+        self.poll();
+
+        assists::folding_range::folding_range(uri, &self.docs, &mut self.wa).unwrap_or_default()
+    }
+
+    pub(super) fn inlay_hint(&mut self, uri: Url, range: lsp_types::Range) -> Vec<InlayHint> {
+        self.poll();
+
+        assists::inlay_hint::inlay_hint(
+            uri,
+            range,
+            self.options.inlay_hint_types,
+            &self.docs,
+            &mut self.wa,
+        )
+        .unwrap_or_default()
+    }
+
+    pub(super) fn linked_editing_range(
+        &mut self,
+        uri: Url,
+        position: Position,
+    ) -> Option<LinkedEditingRanges> {
+        self.poll();
+
+        assists::linked_editing_range::linked_editing_range(uri, position, &self.docs, &mut self.wa)
+    }
+
     pub(super) fn document_symbol(&mut self, uri: Url) -> Option<DocumentSymbolResponse> {
         self.poll();
 
-        assists::document_symbol::symbol(uri, &self.docs, &mut self.wa)
+        assists::document_symbol::symbol(
+            uri,
+            self.options.hierarchical_document_symbol,
+            &self.docs,
+            &mut self.wa,
+        )
     }
 
     pub(super) fn hover(&mut self, uri: Url, position: Position) -> Option<Hover> {
@@ -325,18 +1248,188 @@ impl LangService {
         assists::hover::hover(uri, position, &self.docs, &mut self.wa)
     }
 
+    pub(super) fn moniker(&mut self, uri: Url, position: Position) -> Option<Vec<Moniker>> {
+        self.poll();
+
+        assists::moniker::moniker(
+            uri,
+            position,
+            self.root_uri_opt.as_ref(),
+            &self.docs,
+            &mut self.wa,
+        )
+    }
+
     pub(super) fn references(
         &mut self,
         uri: Url,
         position: Position,
         include_definition: bool,
     ) -> Vec<Location> {
-        self.poll();
+        self.load_all_files();
 
         assists::references::references(uri, position, include_definition, &self.docs, &mut self.wa)
             .unwrap_or(vec![])
     }
 
+    /// ユーザーのファイルか？ (HSP3 のインストールディレクトリにあるファイルではないか？)
+    fn is_user_doc(&self, doc: DocId) -> bool {
+        self.docs
+            .get_uri(doc)
+            .and_then(|uri| uri.to_file_path())
+            .map_or(true, |path| !path.starts_with(&self.hsp3_root))
+    }
+
+    /// ユーザーのファイルをすべて集める。(`is_user_doc` を参照。)
+    fn user_docs(&self) -> HashSet<DocId> {
+        self.docs
+            .iter()
+            .map(|(doc, _)| doc)
+            .filter(|&doc| self.is_user_doc(doc))
+            .collect()
+    }
+
+    /// ワークスペース内の文字列リテラルの中身だけを正規表現で置換する。
+    ///
+    /// HSP3 のインストールディレクトリにあるファイルは対象外。
+    pub(super) fn replace_in_strings(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        self.load_all_files();
+
+        let regex =
+            regex::Regex::new(pattern).map_err(|err| format!("正規表現が不正です。({})", err))?;
+
+        let mut target_docs = self.user_docs().into_iter().collect::<Vec<_>>();
+        target_docs.sort();
+
+        Ok(assists::replace_in_strings::replace_in_strings(
+            &regex,
+            replacement,
+            &target_docs,
+            &self.docs,
+            &mut self.wa,
+        ))
+    }
+
+    pub(super) fn include_tree(&mut self) -> String {
+        self.load_all_files();
+
+        let root_dir_opt = self.root_uri_opt.as_ref().and_then(|x| x.to_file_path());
+        assists::include_tree::include_tree(root_dir_opt.as_deref(), &self.docs, &mut self.wa)
+    }
+
+    pub(super) fn include_graph(
+        &mut self,
+        root_opt: Option<Url>,
+    ) -> Vec<assists::include_graph::IncludeGraphNode> {
+        self.load_all_files();
+
+        assists::include_graph::include_graph(root_opt.as_ref(), &self.docs, &mut self.wa)
+    }
+
+    pub(super) fn included_by(&mut self, uri: Url) -> Vec<assists::include_graph::IncluderItem> {
+        self.load_all_files();
+
+        assists::include_graph::included_by(&uri, &self.docs, &mut self.wa)
+    }
+
+    pub(super) fn dump_symbols(&mut self) -> String {
+        self.load_all_files();
+
+        let user_docs = self.user_docs();
+        let target_doc = |doc| user_docs.contains(&doc);
+        assists::dump_symbols::dump_symbols(target_doc, &self.docs, &mut self.wa)
+    }
+
+    /// `workspace/executeCommand` で要求されたコマンドを実行する。
+    pub(super) fn execute_command(
+        &mut self,
+        command: &str,
+        arguments: Vec<serde_json::Value>,
+    ) -> Result<CommandOutcome, String> {
+        use serde_json::Value;
+
+        match command {
+            COMMAND_REPLACE_IN_STRINGS => {
+                let (pattern, replacement) = match arguments.as_slice() {
+                    [Value::String(pattern), Value::String(replacement)] => (pattern, replacement),
+                    _ => {
+                        return Err(format!(
+                            "{} の引数には (パターン, 置換後の文字列) を指定してください。",
+                            COMMAND_REPLACE_IN_STRINGS
+                        ))
+                    }
+                };
+
+                Ok(match self.replace_in_strings(pattern, replacement)? {
+                    Some(edit) => CommandOutcome::ApplyEdit {
+                        label: "文字列リテラルの置換".to_string(),
+                        edit,
+                    },
+                    None => CommandOutcome::Value(Value::Null),
+                })
+            }
+            COMMAND_REINDEX_WORKSPACE => {
+                self.reindex_workspace();
+                Ok(CommandOutcome::Value(Value::Null))
+            }
+            COMMAND_SHOW_INCLUDE_TREE => {
+                Ok(CommandOutcome::Value(Value::String(self.include_tree())))
+            }
+            COMMAND_DUMP_SYMBOLS => Ok(CommandOutcome::Value(Value::String(self.dump_symbols()))),
+            _ => Err(format!("不明なコマンドです。({})", command)),
+        }
+    }
+
+    pub(super) fn module_visibility(
+        &mut self,
+    ) -> Vec<assists::module_visibility::ModuleVisibilityItem> {
+        self.load_all_files();
+
+        let user_docs = self.user_docs();
+        let target_doc = |doc| user_docs.contains(&doc);
+        assists::module_visibility::module_visibility(target_doc, &self.docs, &mut self.wa)
+    }
+
+    pub(super) fn symbol_stats(&mut self) -> Vec<assists::symbol_stats::SymbolStatItem> {
+        self.load_all_files();
+
+        let user_docs = self.user_docs();
+        let target_doc = |doc| user_docs.contains(&doc);
+        assists::symbol_stats::symbol_stats(target_doc, &self.docs, &mut self.wa)
+    }
+
+    /// ワークスペースのドキュメントの解析結果をテキストにする。(`analysis_snapshot` 関数を参照。)
+    pub(super) fn analysis_snapshot(&mut self) -> String {
+        self.load_all_files();
+
+        let root_dir = match self
+            .root_uri_opt
+            .as_ref()
+            .and_then(|uri| uri.to_file_path())
+        {
+            Some(it) => it,
+            None => return String::new(),
+        };
+        let diagnostics = self.collect_diagnostics();
+        assists::snapshot::analysis_snapshot(&root_dir, diagnostics, &self.docs, &mut self.wa)
+    }
+
+    pub(super) fn workspace_stats(&mut self) -> assists::workspace_stats::WorkspaceStats {
+        self.load_all_files();
+        assists::workspace_stats::workspace_stats(&mut self.wa)
+    }
+
+    pub(super) fn build_options(&mut self) -> Vec<assists::build_options::ProjectBuildOptions> {
+        self.load_all_files();
+
+        let target = self.options.target.as_deref();
+        assists::build_options::build_options(target, &self.docs, &mut self.wa)
+    }
+
     pub(super) fn prepare_rename(
         &mut self,
         uri: Url,
@@ -344,7 +1437,9 @@ impl LangService {
     ) -> Option<PrepareRenameResponse> {
         self.poll();
 
-        assists::rename::prepare_rename(uri, position, &self.docs, &mut self.wa)
+        let user_docs = self.user_docs();
+        let user_doc = |doc| user_docs.contains(&doc);
+        assists::rename::prepare_rename(uri, position, user_doc, &self.docs, &mut self.wa)
     }
 
     pub(super) fn rename(
@@ -353,20 +1448,67 @@ impl LangService {
         position: Position,
         new_name: String,
     ) -> Option<WorkspaceEdit> {
-        self.poll();
+        self.load_all_files();
 
-        assists::rename::rename(uri, position, new_name, &self.docs, &mut self.wa)
+        let user_docs = self.user_docs();
+        let user_doc = |doc| user_docs.contains(&doc);
+        assists::rename::rename(uri, position, new_name, user_doc, &self.docs, &mut self.wa)
+    }
+
+    /// ファイルの名前の変更に合わせて `#include` を書き換える編集を作る。(引数は変更前と変更後のURIの組)
+    pub(super) fn will_rename_files(&mut self, renames: Vec<(Url, Url)>) -> Option<WorkspaceEdit> {
+        self.load_all_files();
+
+        assists::rename_files::will_rename_files(renames, &self.docs, &mut self.wa)
     }
 
     pub(super) fn semantic_tokens(&mut self, uri: Url) -> lsp_types::SemanticTokens {
         self.poll();
 
-        let tokens =
-            assists::semantic_tokens::full(uri, &self.docs, &mut self.wa).unwrap_or(vec![]);
-        SemanticTokens {
-            data: tokens,
-            result_id: None,
-        }
+        assists::semantic_tokens::full(
+            uri,
+            &self.docs,
+            &mut self.semantic_tokens_cache,
+            &mut self.wa,
+        )
+        .unwrap_or_default()
+    }
+
+    pub(super) fn semantic_tokens_delta(
+        &mut self,
+        uri: Url,
+        previous_result_id: String,
+    ) -> Option<SemanticTokensFullDeltaResult> {
+        self.poll();
+
+        assists::semantic_tokens::full_delta(
+            uri,
+            &previous_result_id,
+            &self.docs,
+            &mut self.semantic_tokens_cache,
+            &mut self.wa,
+        )
+    }
+
+    pub(super) fn selection_range(
+        &mut self,
+        uri: Url,
+        positions: Vec<Position>,
+    ) -> Vec<SelectionRange> {
+        self.poll();
+
+        assists::selection_range::selection_range(uri, positions, &self.docs, &mut self.wa)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn semantic_tokens_range(
+        &mut self,
+        uri: Url,
+        range: lsp_types::Range,
+    ) -> Option<SemanticTokens> {
+        self.poll();
+
+        assists::semantic_tokens::range(uri, range, &self.docs, &mut self.wa)
     }
 
     pub(super) fn signature_help(&mut self, uri: Url, position: Position) -> Option<SignatureHelp> {
@@ -376,26 +1518,35 @@ impl LangService {
     }
 
     pub(super) fn workspace_symbol(&mut self, query: String) -> Vec<SymbolInformation> {
-        self.poll();
+        self.load_all_files();
 
         assists::workspace_symbol::symbol(&query, &self.docs, &mut self.wa)
     }
 
-    pub(super) fn diagnose(&mut self) -> Vec<(Url, Option<i32>, Vec<lsp_types::Diagnostic>)> {
-        if !self.options.lint_enabled {
+    /// diagnosticsを計算する。lintが無効なら空になる。
+    fn collect_diagnostics(&mut self) -> Vec<(Url, Option<i32>, Vec<lsp_types::Diagnostic>)> {
+        if !self.lint_enabled() {
             return vec![];
         }
 
         self.poll();
 
-        let mut diagnostics =
-            assists::diagnose::diagnose(&self.docs, &mut self.diagnostics_cache, &mut self.wa);
+        let mut search_dirs = self.include_dirs();
+        search_dirs.push(self.hsp3_root.join("common"));
+        let mut diagnostics = assists::diagnose::collect_diagnostics(
+            &self.docs,
+            self.lint_unused(),
+            &search_dirs,
+            self.encoding(),
+            &mut self.wa,
+        );
 
         // hsp3のファイルにdiagnosticsを出さない。
         diagnostics.retain(|(uri, _, _)| {
-            let ok = uri
-                .to_file_path()
-                .map_or(true, |path| !path.starts_with(&self.hsp3_root));
+            let ok = self
+                .docs
+                .find_by_uri(&CanonicalUri::from_url(uri))
+                .map_or(true, |doc| self.is_user_doc(doc));
 
             if !ok {
                 trace!(
@@ -408,4 +1559,690 @@ impl LangService {
         });
         diagnostics
     }
+
+    /// 前回から変化したdiagnosticsを計算する。(プッシュ型)
+    pub(super) fn diagnose(&mut self) -> Vec<(Url, Option<i32>, Vec<lsp_types::Diagnostic>)> {
+        let mut diagnostics = self.collect_diagnostics();
+        assists::diagnose::filter_diagnostics(
+            &mut diagnostics,
+            &self.docs,
+            &mut self.diagnostics_cache,
+        );
+        diagnostics
+    }
+
+    /// ドキュメントのdiagnosticsを計算する。(プル型)
+    pub(super) fn document_diagnostic(
+        &mut self,
+        uri: Url,
+        previous_result_id: Option<String>,
+    ) -> lsp_types::DocumentDiagnosticReportResult {
+        let diagnostics = self
+            .collect_diagnostics()
+            .into_iter()
+            .find(|(u, _, _)| *u == uri)
+            .map(|(_, _, diagnostics)| diagnostics)
+            .unwrap_or_default();
+        assists::diagnose::document_report(diagnostics, previous_result_id)
+    }
+
+    /// ワークスペース全体のdiagnosticsを計算する。(プル型)
+    pub(super) fn workspace_diagnostic(
+        &mut self,
+        previous_result_ids: Vec<lsp_types::PreviousResultId>,
+    ) -> lsp_types::WorkspaceDiagnosticReportResult {
+        let diagnostics = self.collect_diagnostics();
+        assists::diagnose::workspace_report(diagnostics, previous_result_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{dummy_root, TempDir};
+
+    #[test]
+    fn test_is_excluded() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_exclude(default_exclude());
+
+        assert!(!ls.is_excluded(&root.join("main.hsp")));
+        assert!(!ls.is_excluded(&root.join("src/objects.hsp")));
+        assert!(ls.is_excluded(&root.join("obj/main.hsp")));
+        assert!(ls.is_excluded(&root.join("src/backup/main.hsp")));
+
+        ls.set_exclude(vec![glob::Pattern::new("vendor/**").unwrap()]);
+        assert!(ls.is_excluded(&root.join("vendor/lib/mod.as")));
+        assert!(!ls.is_excluded(&root.join("obj/main.hsp")));
+    }
+
+    #[test]
+    fn test_did_change_configuration() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let uri = Url::from_file_path(root.join("config.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            docs::NO_VERSION,
+            "; ham-entry\n\thelo\n".into(),
+        );
+        let count = |diagnostics: Vec<(Url, Option<i32>, Vec<lsp_types::Diagnostic>)>| {
+            diagnostics
+                .into_iter()
+                .filter(|(u, _, _)| *u == uri)
+                .map(|(_, _, d)| d.len())
+                .sum::<usize>()
+        };
+        assert_eq!(count(ls.diagnose()), 1);
+
+        // 同じ値を設定しても、ワークスペースは読み込み直さない。
+        let encoding = ls.options.encoding;
+        ls.set_encoding(encoding);
+        assert!(!ls.workspace_config_changed);
+
+        ls.set_encoding(SourceEncoding::Utf8);
+        assert!(ls.workspace_config_changed);
+
+        // lintを無効にしたら、報告済みのdiagnosticsを消す。
+        ls.set_lint_enabled(false);
+        ls.did_change_configuration();
+        assert!(!ls.workspace_config_changed);
+        let diagnostics = ls.diagnose();
+        assert!(diagnostics.iter().any(|(u, _, _)| *u == uri));
+        assert_eq!(count(diagnostics), 0);
+    }
+
+    #[test]
+    fn test_edit_reanalyzes_only_affected_projects() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_lint_enabled(true);
+
+        let a_uri = Url::from_file_path(root.join("incr_a.hsp")).unwrap();
+        let b_uri = Url::from_file_path(root.join("incr_b.hsp")).unwrap();
+        let lib_uri = Url::from_file_path(root.join("incr_lib.as")).unwrap();
+        ls.open_doc(
+            a_uri.clone(),
+            1,
+            "; ham-entry\n#include \"incr_lib.as\"\n\tincr_lib_cmd\n".into(),
+        );
+        ls.open_doc(b_uri.clone(), 1, "; ham-entry\n\tincr_b_cmd\n".into());
+        ls.open_doc(lib_uri.clone(), 1, "".into());
+
+        // (差分ではなく、すべてのdiagnosticsを数える。)
+        let count = |ls: &mut LangService, uri: &Url| {
+            ls.collect_diagnostics()
+                .into_iter()
+                .filter(|(u, _, _)| u == uri)
+                .flat_map(|(_, _, d)| d)
+                .filter(|d| d.message.contains("incr_"))
+                .count()
+        };
+        assert_eq!((count(&mut ls, &a_uri), count(&mut ls, &b_uri)), (1, 1));
+
+        // 一方のプロジェクトだけを編集しても、他方の解析結果は変わらない。
+        ls.change_doc(b_uri.clone(), 2, "; ham-entry\n".into());
+        assert_eq!((count(&mut ls, &a_uri), count(&mut ls, &b_uri)), (1, 0));
+
+        // includeされているドキュメントを編集したら、includeしているプロジェクトを解析しなおす。
+        ls.change_doc(lib_uri, 2, "#deffunc incr_lib_cmd\n\treturn\n".into());
+        assert_eq!((count(&mut ls, &a_uri), count(&mut ls, &b_uri)), (0, 0));
+    }
+
+    #[test]
+    fn test_sjis_incompatible_chars() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_lint_enabled(true);
+        ls.set_encoding(SourceEncoding::Sjis);
+
+        let sjis_uri = Url::from_file_path(root.join("sjis_chars.hsp")).unwrap();
+        ls.open_doc(
+            sjis_uri.clone(),
+            docs::NO_VERSION,
+            "; ham-entry\n\tmes \"a😀b 日本語\" ; ©\n".into(),
+        );
+        // モードラインで UTF-8 と指定されているものは対象外
+        let utf8_uri = Url::from_file_path(root.join("utf8_chars.hsp")).unwrap();
+        ls.open_doc(
+            utf8_uri.clone(),
+            docs::NO_VERSION,
+            "; ham-entry\n; ham: encoding=utf-8\n\tmes \"😀\"\n".into(),
+        );
+        // `#cmpopt utf8` があるものも UTF-8 としてコンパイルされるので対象外
+        let cmpopt_uri = Url::from_file_path(root.join("cmpopt_utf8_chars.hsp")).unwrap();
+        ls.open_doc(
+            cmpopt_uri,
+            docs::NO_VERSION,
+            "; ham-entry\n#cmpopt utf8 1\n\tmes \"😀\"\n".into(),
+        );
+
+        let diagnostics = ls
+            .diagnose()
+            .into_iter()
+            .flat_map(|(uri, _, d)| d.into_iter().map(move |d| (uri.clone(), d)))
+            .filter(|(_, d)| d.message.contains("shift_jis"))
+            .map(|(uri, d)| {
+                let r = d.range;
+                (
+                    uri == sjis_uri,
+                    (r.start.line, r.start.character, r.end.character),
+                    d.message,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    true,
+                    (1, 7, 9),
+                    "文字 😀 (U+1F600) は shift_jis で表せないため、コンパイルすると ? に置き換わります。"
+                        .to_string()
+                ),
+                (
+                    true,
+                    (1, 18, 19),
+                    "文字 © (U+00A9) は shift_jis で表せないため、コンパイルすると ? に置き換わります。"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include_dirs() {
+        let temp = TempDir::new("include-dirs");
+        let lib_dir = temp.path().to_path_buf();
+        fs::write(lib_dir.join("mylib.as"), "#deffunc mylib_hello\n\treturn\n").unwrap();
+
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_lint_enabled(true);
+        ls.set_include_dirs(vec![lib_dir]);
+        ls.did_initialize();
+
+        let uri = Url::from_file_path(root.join("include_dirs.hsp")).unwrap();
+        ls.open_doc(
+            uri.clone(),
+            docs::NO_VERSION,
+            "; ham-entry\n#include \"mylib.as\"\n\tmylib_hello\n".into(),
+        );
+
+        let diagnostics = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, d)| d)
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(diagnostics, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_workspace_copy_of_common_file() {
+        let temp = TempDir::new("workspace-copy-of-common");
+        let dir = temp.path();
+        let root = dir.join("ws");
+        let common_dir = dir.join("hsp3").join("common");
+        for d in [&root, &common_dir] {
+            fs::create_dir_all(d).unwrap();
+        }
+        let lib = "#deffunc copied_lib_hello\n\treturn\n";
+        fs::write(common_dir.join("copied_lib.as"), lib).unwrap();
+        fs::write(root.join("copied_lib.as"), lib).unwrap();
+        let main_uri = Url::from_file_path(root.join("main.hsp")).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.set_hsp3_root(dir.join("hsp3"));
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.did_initialize();
+        ls.open_doc(
+            main_uri.clone(),
+            docs::NO_VERSION,
+            "#include \"copied_lib.as\"\n\tcopied_lib_hello\n".into(),
+        );
+
+        // includeはワークスペースにあるコピーに解決されるので、commonのファイルの定義は重複しない。
+        let count = ls
+            .completion(main_uri.clone(), Position::new(1, 4))
+            .items
+            .into_iter()
+            .filter(|item| item.label == "copied_lib_hello")
+            .count();
+        assert_eq!(count, 1);
+
+        assert_eq!(ls.workspace_symbol("copied_lib_".into()).len(), 1);
+
+        // 定義と参照はワークスペースにあるコピーにまとまる。
+        let lib_uri = Url::from_file_path(root.join("copied_lib.as")).unwrap();
+        let defs = ls
+            .definitions(main_uri.clone(), Position::new(1, 4))
+            .into_iter()
+            .map(|loc| loc.uri)
+            .collect::<Vec<_>>();
+        assert_eq!(defs, vec![lib_uri.clone()]);
+
+        let mut refs = ls
+            .references(main_uri.clone(), Position::new(1, 4), true)
+            .into_iter()
+            .map(|loc| (loc.uri == lib_uri, loc.range.start.line))
+            .collect::<Vec<_>>();
+        refs.sort();
+        assert_eq!(refs, vec![(false, 1), (true, 0)]);
+    }
+
+    #[test]
+    fn test_change_include_dirs() {
+        let temp = TempDir::new("change-include-dirs");
+        let root = temp.path().join("ws");
+        let lib_dir = temp.path().join("lib");
+        for dir in [&root, &lib_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let main_path = root.join("main.hsp");
+        fs::write(
+            &main_path,
+            "; ham-entry\n#include \"shared.as\"\n#include \"\"\n",
+        )
+        .unwrap();
+        fs::write(lib_dir.join("shared.as"), "#deffunc shared_lib\n").unwrap();
+        let main_uri = Url::from_file_path(&main_path).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.did_initialize();
+
+        let names = |ls: &mut LangService| {
+            ls.workspace_symbol("shared_".into())
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>()
+        };
+        let labels = |ls: &mut LangService| {
+            ls.completion(main_uri.clone(), Position::new(2, 10))
+                .items
+                .into_iter()
+                .map(|item| item.label)
+                .filter(|label| label.starts_with("shared"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&mut ls), Vec::<String>::new());
+        assert_eq!(labels(&mut ls), Vec::<String>::new());
+
+        // 設定が変わったら、追加されたincludeディレクトリからincludeを解決しなおして、補完の候補にする。
+        ls.set_include_dirs(vec![lib_dir]);
+        ls.did_change_configuration();
+        assert_eq!(names(&mut ls), vec!["shared_lib"]);
+        assert_eq!(labels(&mut ls), vec!["shared.as"]);
+    }
+
+    #[test]
+    fn test_untitled_doc() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+
+        // 保存されていないドキュメントも解析する。
+        let uri = Url::parse("untitled:Untitled-1").unwrap();
+        ls.open_doc(
+            uri.clone(),
+            1,
+            "#deffunc untitled_hello\n\tuntitled_hello\n".into(),
+        );
+
+        let completion = ls.completion(uri.clone(), Position::new(1, 4));
+        assert!(completion
+            .items
+            .iter()
+            .any(|item| item.label == "untitled_hello"));
+
+        let defs = ls.definitions(uri.clone(), Position::new(1, 4));
+        assert_eq!(
+            defs.into_iter()
+                .map(|loc| (loc.uri, loc.range.start))
+                .collect::<Vec<_>>(),
+            vec![(uri.clone(), Position::new(0, 9))]
+        );
+
+        // ファイルの監視の通知では閉じない。
+        ls.on_file_deleted(uri.clone());
+        ls.on_file_changed(uri.clone());
+        assert!(ls.hover(uri.clone(), Position::new(1, 4)).is_some());
+
+        ls.close_doc(uri.clone());
+        assert_eq!(ls.definitions(uri, Position::new(1, 4)), vec![]);
+    }
+
+    #[test]
+    fn test_edit_doc_versions() {
+        let temp = TempDir::new("edit-doc-versions");
+        let root = temp.path();
+        let path = root.join("main.hsp");
+        fs::write(&path, "mes \"saved\"\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.open_doc(uri.clone(), 1, "mes \"a\"\n".into());
+
+        let text = |ls: &LangService| {
+            let doc = (ls.docs)
+                .find_by_uri(&CanonicalUri::from_url(&uri))
+                .unwrap();
+            let (version, text) = ls.docs.get_editor_text(doc).unwrap();
+            (version, text.to_string())
+        };
+        let edit = |start: u32, end: u32, new_text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(0, start), Position::new(0, end))),
+            range_length: None,
+            text: new_text.to_string(),
+        };
+
+        // 差分を適用する。
+        ls.edit_doc(uri.clone(), 2, vec![edit(5, 6, "b"), edit(6, 6, "c")]);
+        assert_eq!(text(&ls), (2, "mes \"bc\"\n".to_string()));
+
+        // 変更がなくても壊さない。
+        ls.edit_doc(uri.clone(), 3, vec![]);
+        assert_eq!(text(&ls), (3, "mes \"bc\"\n".to_string()));
+
+        // 古いバージョンの変更は無視する。
+        ls.edit_doc(uri.clone(), 3, vec![edit(5, 7, "x")]);
+        ls.change_doc(uri.clone(), 2, "stale\n".into());
+        assert_eq!(text(&ls), (3, "mes \"bc\"\n".to_string()));
+
+        // 変更が抜けていても、全体を置き換える変更なら適用できる。
+        ls.change_doc(uri.clone(), 5, "mes \"d\"\n".into());
+        assert_eq!(text(&ls), (5, "mes \"d\"\n".to_string()));
+
+        // 変更が抜けていて差分しかなければ、ファイルから読みなおす。
+        ls.edit_doc(uri.clone(), 7, vec![edit(5, 6, "e")]);
+        assert_eq!(text(&ls), (7, "mes \"saved\"\n".to_string()));
+
+        // 読みなおした内容はエディタの内容と一致しないので、以降の差分は適用しない。
+        ls.edit_doc(uri.clone(), 8, vec![edit(0, 0, "f")]);
+        assert_eq!(text(&ls), (8, "mes \"saved\"\n".to_string()));
+
+        // 全体を置き換える変更を受け取ったら、差分の適用を再開する。
+        ls.change_doc(uri.clone(), 9, "mes \"g\"\n".into());
+        ls.edit_doc(uri.clone(), 10, vec![edit(5, 6, "h")]);
+        assert_eq!(text(&ls), (10, "mes \"h\"\n".to_string()));
+
+        // 範囲外の差分も同様。
+        ls.edit_doc(uri.clone(), 11, vec![edit(99, 99, "f")]);
+        assert_eq!(text(&ls), (11, "mes \"saved\"\n".to_string()));
+        ls.edit_doc(uri.clone(), 12, vec![edit(0, 0, "i")]);
+        assert_eq!(text(&ls), (12, "mes \"saved\"\n".to_string()));
+    }
+
+    #[test]
+    fn test_semantic_tokens_cache_is_evicted_on_close() {
+        let root = dummy_root();
+        let a_uri = Url::from_file_path(root.join("semantic_tokens_cache_a.hsp")).unwrap();
+        let b_uri = Url::from_file_path(root.join("semantic_tokens_cache_b.hsp")).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.open_doc(a_uri.clone(), 1, "mes 1\n".into());
+        ls.open_doc(b_uri.clone(), 1, "mes 2\n".into());
+        ls.semantic_tokens(a_uri.clone());
+        ls.semantic_tokens(b_uri.clone());
+        assert_eq!(ls.semantic_tokens_cache.len(), 2);
+
+        ls.close_doc(a_uri);
+        assert_eq!(ls.semantic_tokens_cache.len(), 1);
+
+        ls.close_doc(b_uri);
+        ls.poll();
+        assert_eq!(ls.semantic_tokens_cache.len(), 0);
+    }
+
+    #[test]
+    fn test_reopen_deleted_file() {
+        let temp = TempDir::new("reopen-deleted-file");
+        let root = temp.path();
+        let path = root.join("gen.hsp");
+        fs::write(root.join("main.hsp"), "; ham-entry\n#include \"gen.hsp\"\n").unwrap();
+        fs::write(&path, "#deffunc gen_saved\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.did_initialize();
+
+        let names = |ls: &mut LangService| {
+            let mut names = ls
+                .workspace_symbol("gen_".into())
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&mut ls), vec!["gen_saved"]);
+
+        // 開いている間に削除され、作りなおされたファイルは、閉じたときにファイルの内容に戻る。
+        ls.open_doc(uri.clone(), 1, "#deffunc gen_edited\n".into());
+        fs::remove_file(&path).unwrap();
+        ls.on_file_deleted(uri.clone());
+        assert_eq!(names(&mut ls), vec!["gen_edited"]);
+
+        fs::write(&path, "#deffunc gen_recreated\n").unwrap();
+        ls.on_file_created(uri.clone());
+        assert_eq!(names(&mut ls), vec!["gen_edited"]);
+
+        ls.close_doc(uri.clone());
+        assert_eq!(names(&mut ls), vec!["gen_recreated"]);
+
+        // 削除の通知がなくても、閉じたときにファイルがなければ閉じる。
+        ls.open_doc(uri.clone(), 1, "#deffunc gen_edited\n".into());
+        fs::remove_file(&path).unwrap();
+        ls.close_doc(uri);
+        assert_eq!(names(&mut ls), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_malformed_bytes() {
+        let temp = TempDir::new("diagnose-malformed-bytes");
+        let root = temp.path();
+        let path = root.join("malformed.hsp");
+        fs::write(&path, b"; ham: encoding=utf-8\nmes 1\nmes \"a\xff\"\n").unwrap();
+        let uri = Url::from_file_path(&path).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.set_lint_enabled(true);
+        ls.did_initialize();
+        ls.load_all_files();
+
+        // 最初の不正なバイトの位置に報告する。
+        let ranges = ls
+            .diagnose()
+            .into_iter()
+            .filter(|(u, _, _)| *u == uri)
+            .flat_map(|(_, _, diagnostics)| diagnostics)
+            .filter(|d| d.message.contains("バイト列"))
+            .map(|d| d.range)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![Range::new(Position::new(2, 6), Position::new(2, 7))]
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let temp = TempDir::new("manifest");
+        let root = temp.path();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("main.hsp"), "#include \"util.as\"\n").unwrap();
+        fs::write(root.join("lib/util.as"), "#deffunc manifest_util\n").unwrap();
+        let manifest_path = root.join("ginger.toml");
+        fs::write(
+            &manifest_path,
+            "entry-points = [\"main.hsp\"]\ninclude-paths = [\"lib\"]\n",
+        )
+        .unwrap();
+        let manifest_uri = Url::from_file_path(&manifest_path).unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.did_initialize();
+
+        let names = |ls: &mut LangService| {
+            ls.workspace_symbol("manifest_".into())
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&mut ls), vec!["manifest_util"]);
+        assert!(ls.take_manifest_errors().is_empty());
+
+        // 設定ファイルが変更されたら読み込み直す。
+        ls.set_lint_enabled(true);
+        fs::write(&manifest_path, "[lint]\nenabled = false\n").unwrap();
+        ls.on_file_changed(manifest_uri.clone());
+        assert!(!ls.lint_enabled());
+        assert!(ls.manifest.entry_points.is_empty());
+
+        // 不正な設定ファイルは無視して、誤りを報告する。
+        fs::write(&manifest_path, "entrypoints = [\"main.hsp\"]\n").unwrap();
+        ls.on_file_changed(manifest_uri);
+        assert_eq!(ls.manifest, Manifest::default());
+        assert_eq!(ls.take_manifest_errors().len(), 1);
+    }
+
+    #[test]
+    fn test_transitive_includes() {
+        let temp = TempDir::new("transitive-includes");
+        let root = temp.path();
+        fs::create_dir_all(root.join("lib/Sub")).unwrap();
+        fs::write(
+            root.join("main.hsp"),
+            "; ham-entry\n#include \"lib/a.as\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("lib/a.as"),
+            "#include \"sub/b.as\"\n#deffunc chain_a\n",
+        )
+        .unwrap();
+        fs::write(root.join("lib/Sub/b.as"), "#deffunc chain_b\n").unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.did_initialize();
+
+        // .as ファイルはスキャンされないが、includeをたどって読み込まれる。
+        let mut names = ls
+            .workspace_symbol("chain_".into())
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["chain_a", "chain_b"]);
+    }
+
+    #[test]
+    fn test_index_workspace() {
+        let temp = TempDir::new("lazy-loading");
+        let root = temp.path();
+        fs::write(root.join("main.hsp"), "#include \"sub.hsp\"\n\tlazy_sub\n").unwrap();
+        fs::write(root.join("sub.hsp"), "#deffunc lazy_sub\n\treturn\n").unwrap();
+        fs::write(root.join("other.hsp"), "#const LAZY_OTHER 1\n").unwrap();
+
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(root).unwrap()));
+        ls.set_entrypoints(vec![root.join("main.hsp")]);
+        ls.did_initialize();
+
+        // エントリーポイントと、そこからincludeされるファイルだけを読み込む。
+        let mut progress = vec![];
+        let symbol_count = ls.index_workspace(&mut |done, total| progress.push((done, total)));
+        assert_eq!(progress.last(), Some(&(1, 1)));
+        assert_eq!(symbol_count, 1);
+        assert_eq!(
+            ls.pending_files.iter().collect::<Vec<_>>(),
+            vec![&root.join("other.hsp")]
+        );
+
+        // ワークスペース全体を対象とする処理の前に、残りのファイルを読み込む。
+        ls.workspace_symbol("LAZY".into());
+        assert!(ls.pending_files.is_empty());
+    }
+
+    #[test]
+    fn test_large_doc() {
+        let root = dummy_root();
+        let mut ls = LangService::new_standalone();
+        ls.initialize(Some(Url::from_directory_path(&root).unwrap()));
+        ls.set_lint_enabled(true);
+        ls.set_large_file_size(64);
+
+        let main_uri = Url::from_file_path(root.join("large_main.hsp")).unwrap();
+        let large_uri = Url::from_file_path(root.join("large.as")).unwrap();
+        let large_text = format!(
+            "#deffunc large_cmd\n\thelo\n\treturn\n;{}\n",
+            "-".repeat(64)
+        );
+        ls.open_doc(
+            main_uri.clone(),
+            1,
+            "; ham-entry\n#include \"large.as\"\n\tlarge_cmd\n".into(),
+        );
+        ls.open_doc(large_uri.clone(), 1, large_text.clone());
+
+        let messages = |ls: &mut LangService| {
+            let mut messages = ls
+                .diagnose()
+                .into_iter()
+                .filter(|(u, _, _)| *u == main_uri || *u == large_uri)
+                .flat_map(|(u, _, d)| {
+                    let name = if u == main_uri { "main" } else { "large" };
+                    d.into_iter().map(move |d| {
+                        (name, (d.range.start.line, d.range.end.character), d.message)
+                    })
+                })
+                .collect::<Vec<_>>();
+            messages.sort();
+            messages
+        };
+
+        // シンボルだけを収集するので、リントは行わないが、定義は使える。
+        assert_eq!(
+            messages(&mut ls),
+            vec![(
+                "large",
+                (0, 18),
+                "このファイルは大きい (99 バイト) ため、シンボルの収集だけを行います。リントや変数の解析は行いません。".to_string()
+            )]
+        );
+
+        // 解析しないので、定義もみつからない。
+        ls.set_skip_large_files(true);
+        ls.change_doc(large_uri.clone(), 2, large_text);
+        assert_eq!(
+            messages(&mut ls),
+            vec![
+                (
+                    "large",
+                    (0, 0),
+                    "このファイルは大きい (99 バイト) ため、解析していません。".to_string()
+                ),
+                (
+                    "main",
+                    (2, 10),
+                    "命令 large_cmd の定義が見つかりません。".to_string()
+                ),
+            ]
+        );
+    }
 }
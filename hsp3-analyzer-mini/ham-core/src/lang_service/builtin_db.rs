@@ -0,0 +1,380 @@
+//! 標準命令・関数・システム変数の組み込みデータベース
+//!
+//! hsphelp が見つからない環境でも標準命令などを補完できるように、主要なものの情報を埋め込んでおく。
+//! hsphelp に同名の項目があるときはそちらを優先する。
+
+use super::*;
+
+#[derive(Clone, Copy)]
+enum BuiltinKind {
+    /// 命令
+    Command,
+    /// 関数
+    Function,
+    /// システム変数
+    SysVar,
+}
+
+use BuiltinKind::*;
+
+/// (名前, 種類, 説明, パラメータ)
+type BuiltinEntry = (
+    &'static str,
+    BuiltinKind,
+    &'static str,
+    &'static [&'static str],
+);
+
+#[rustfmt::skip]
+static BUILTINS: &[BuiltinEntry] = &[
+    // プログラム制御命令
+    ("await", Command, "一定時間待つ (ACTIVE)", &["p1"]),
+    ("break", Command, "ループから抜ける", &[]),
+    ("continue", Command, "ループをやり直す", &["p1"]),
+    ("end", Command, "プログラム終了", &["p1"]),
+    ("exec", Command, "Windowsのファイルを実行する", &["\"filename\"", "p1", "\"command\""]),
+    ("foreach", Command, "配列変数の要素数だけ繰り返す", &["p1"]),
+    ("gosub", Command, "指定ラベルにサブルーチンジャンプ", &["*label"]),
+    ("goto", Command, "指定ラベルにジャンプ", &["*label"]),
+    ("loop", Command, "ループの始まりに戻る", &[]),
+    ("onclick", Command, "クリック時にジャンプ", &["goto/gosub", "*label"]),
+    ("onerror", Command, "エラー発生時にジャンプ", &["goto/gosub", "*label"]),
+    ("onexit", Command, "終了時にジャンプ", &["goto/gosub", "*label"]),
+    ("onkey", Command, "キー入力時にジャンプ", &["goto/gosub", "*label"]),
+    ("repeat", Command, "ループの始まりの場所を示す", &["p1", "p2"]),
+    ("return", Command, "サブルーチンから復帰", &["p1"]),
+    ("stop", Command, "プログラム中断", &[]),
+    ("wait", Command, "一定時間待つ", &["p1"]),
+    ("assert", Command, "デバッグウィンドウ表示", &["p1"]),
+    ("exgoto", Command, "指定条件でジャンプ", &["p1", "p2", "p3", "*label"]),
+    ("logmes", Command, "デバッグメッセージ送信", &["\"message\""]),
+    ("on", Command, "数値による分岐", &["p1", "goto/gosub", "*label"]),
+    ("oncmd", Command, "Windowsメッセージ割り込み実行指定", &["goto/gosub", "*label", "p1"]),
+    ("run", Command, "指定したファイルに制御を移す", &["\"filename\"", "\"cmdline\""]),
+    ("setease", Command, "イージング関数の計算式を設定", &["p1", "p2", "p3"]),
+    // 基本入出力命令
+    ("dialog", Command, "ダイアログを開く", &["\"message\"", "p1", "\"option\""]),
+    ("getkey", Command, "キー入力チェック", &["p1", "p2"]),
+    ("mouse", Command, "マウスカーソル座標設定", &["p1", "p2", "p3"]),
+    ("stick", Command, "キー入力情報取得", &["p1", "p2", "p3"]),
+    // 画面制御命令
+    ("boxf", Command, "矩形を塗りつぶす", &["p1", "p2", "p3", "p4"]),
+    ("buffer", Command, "仮想画面を初期化", &["p1", "p2", "p3", "p4"]),
+    ("cls", Command, "画面クリア", &["p1"]),
+    ("color", Command, "カラー設定", &["p1", "p2", "p3"]),
+    ("font", Command, "フォント設定", &["\"fontname\"", "p1", "p2"]),
+    ("gcopy", Command, "画面コピー", &["p1", "p2", "p3", "p4", "p5"]),
+    ("gmode", Command, "画面コピーモード設定", &["p1", "p2", "p3", "p4"]),
+    ("gsel", Command, "描画先指定、ウィンドウ最前面、非表示設定", &["p1", "p2"]),
+    ("line", Command, "直線を描画", &["p1", "p2", "p3", "p4"]),
+    ("mes", Command, "メッセージ表示", &["\"strings\"", "sw"]),
+    ("pos", Command, "カレントポジション設定", &["p1", "p2"]),
+    ("print", Command, "メッセージ表示", &["\"strings\"", "sw"]),
+    ("pset", Command, "ドットを描画", &["p1", "p2"]),
+    ("redraw", Command, "再描画の設定", &["p1", "p2", "p3", "p4", "p5"]),
+    ("screen", Command, "ウィンドウを初期化", &["p1", "p2", "p3", "p4", "p5", "p6"]),
+    ("title", Command, "タイトルバー設定", &["\"strings\""]),
+    ("width", Command, "ウィンドウサイズ設定", &["p1", "p2", "p3", "p4"]),
+    ("bgscr", Command, "枠のないウィンドウを初期化", &["p1", "p2", "p3", "p4", "p5", "p6", "p7", "p8"]),
+    ("bmpsave", Command, "画面イメージセーブ", &["\"filename\""]),
+    ("celbitmap", Command, "ビットマップとしてバッファを扱う", &["p1", "p2", "p3"]),
+    ("celdiv", Command, "画像素材の分割サイズを設定", &["p1", "p2", "p3", "p4", "p5"]),
+    ("celload", Command, "画像ファイルをバッファにロード", &["\"filename\"", "p1", "p2"]),
+    ("celput", Command, "画像素材を描画", &["p1", "p2", "p3", "p4", "p5"]),
+    ("chgdisp", Command, "画面解像度を変更する", &["p1", "p2", "p3"]),
+    ("circle", Command, "円を描画", &["p1", "p2", "p3", "p4", "p5"]),
+    ("gfilter", Command, "テクスチャ補間の設定", &["p1"]),
+    ("gmulcolor", Command, "テクスチャ乗算値の設定", &["p1", "p2", "p3"]),
+    ("gradf", Command, "矩形をグラデーションで塗りつぶす", &["p1", "p2", "p3", "p4", "p5", "p6", "p7"]),
+    ("grect", Command, "回転する矩形で塗りつぶす", &["p1", "p2", "p3", "p4", "p5"]),
+    ("groll", Command, "ウィンドウの描画基点を設定", &["p1", "p2"]),
+    ("grotate", Command, "矩形画像を回転してコピー", &["p1", "p2", "p3", "p4", "p5", "p6"]),
+    ("gsquare", Command, "任意の四角形を描画", &["p1", "p2", "p3", "p4", "p5"]),
+    ("gzoom", Command, "変倍して画面コピー", &["p1", "p2", "p3", "p4", "p5", "p6", "p7", "p8"]),
+    ("hsvcolor", Command, "HSV形式でカラーを設定する", &["p1", "p2", "p3"]),
+    ("palcolor", Command, "描画パレット設定", &["p1"]),
+    ("palette", Command, "パレット設定", &["p1", "p2", "p3", "p4", "p5"]),
+    ("pget", Command, "1dotの点を取得", &["p1", "p2"]),
+    ("picload", Command, "画像ファイルをロード", &["\"filename\"", "p1"]),
+    ("syscolor", Command, "システムカラーを設定する", &["p1"]),
+    ("sysfont", Command, "システムフォント選択", &["p1"]),
+    ("viewcalc", Command, "座標変換の設定", &["p1", "p2", "p3", "p4", "p5"]),
+    // オブジェクト制御命令
+    ("button", Command, "ボタン表示", &["goto/gosub", "\"name\"", "*label"]),
+    ("clrobj", Command, "オブジェクトをクリア", &["p1", "p2"]),
+    ("combox", Command, "コンボボックス表示", &["p1", "p2", "p3"]),
+    ("input", Command, "入力ボックス表示", &["p1", "p2", "p3", "p4"]),
+    ("listbox", Command, "リストボックス表示", &["p1", "p2", "p3"]),
+    ("mesbox", Command, "メッセージボックス表示", &["p1", "p2", "p3", "p4", "p5"]),
+    ("objprm", Command, "オブジェクトの内容を変更", &["p1", "p2"]),
+    ("objsize", Command, "オブジェクトサイズ設定", &["p1", "p2", "p3"]),
+    ("axobj", Command, "ActiveXコントロールの配置", &["p1", "\"IID\"", "p2", "p3"]),
+    ("chkbox", Command, "チェックボックス表示", &["\"strings\"", "p1"]),
+    ("objcolor", Command, "オブジェクトのカラー設定", &["p1", "p2", "p3"]),
+    ("objenable", Command, "オブジェクトの有効・無効を設定", &["p1", "p2"]),
+    ("objimage", Command, "カスタムボタンの設定", &["p1", "p2", "p3", "p4", "p5", "p6", "p7"]),
+    ("objmode", Command, "オブジェクトモード設定", &["p1", "p2"]),
+    ("objsel", Command, "オブジェクトに入力フォーカスを設定", &["p1"]),
+    ("objskip", Command, "オブジェクトのフォーカス移動モードを設定", &["p1", "p2"]),
+    ("sendmsg", Command, "ウィンドウメッセージの送信", &["p1", "p2", "p3", "p4"]),
+    ("winobj", Command, "ウィンドウオブジェクトの配置", &["\"class\"", "\"title\"", "p1", "p2", "p3", "p4", "p5", "p6"]),
+    // マルチメディア制御命令
+    ("mci", Command, "MCIにコマンドを送る", &["\"strings\""]),
+    ("mmload", Command, "サウンドファイル読み込み", &["\"filename\"", "p1", "p2"]),
+    ("mmpan", Command, "パンニングの設定", &["p1", "p2"]),
+    ("mmplay", Command, "サウンド再生", &["p1"]),
+    ("mmstop", Command, "サウンド停止", &["p1"]),
+    ("mmvol", Command, "音量の設定", &["p1", "p2"]),
+    // メモリ管理命令
+    ("alloc", Command, "バッファを確保", &["p1", "p2"]),
+    ("ddim", Command, "実数型配列変数を作成", &["p1", "p2", "p3", "p4", "p5"]),
+    ("dim", Command, "配列変数を作成", &["p1", "p2", "p3", "p4", "p5"]),
+    ("dimtype", Command, "指定型の配列変数を作成", &["p1", "p2", "p3", "p4", "p5", "p6"]),
+    ("ldim", Command, "ラベル型配列変数を作成", &["p1", "p2", "p3", "p4", "p5"]),
+    ("memcpy", Command, "メモリブロックのコピー", &["p1", "p2", "p3", "p4", "p5"]),
+    ("memset", Command, "メモリブロックのクリア", &["p1", "p2", "p3", "p4"]),
+    ("poke", Command, "バッファに1byte書き込み", &["p1", "p2", "p3"]),
+    ("sdim", Command, "文字列型配列変数を作成", &["p1", "p2", "p3", "p4", "p5"]),
+    ("wpoke", Command, "バッファに2byte書き込み", &["p1", "p2", "p3"]),
+    ("lpoke", Command, "バッファに4byte書き込み", &["p1", "p2", "p3"]),
+    ("delmod", Command, "モジュール型変数の要素を削除", &["p1"]),
+    ("dup", Command, "クローン変数を作成", &["p1", "p2"]),
+    ("dupptr", Command, "ポインタからクローン変数を作成", &["p1", "p2", "p3", "p4"]),
+    ("memexpand", Command, "メモリブロックの再確保", &["p1", "p2"]),
+    ("mref", Command, "特殊なメモリを変数に割り当てる", &["p1", "p2"]),
+    ("newlab", Command, "ラベル型変数を初期化", &["p1", "p2"]),
+    ("newmod", Command, "モジュール型変数の作成", &["p1", "modname"]),
+    // ファイル操作命令
+    ("bload", Command, "バッファにファイルをロード", &["\"filename\"", "p1", "p2", "p3"]),
+    ("bsave", Command, "バッファをファイルにセーブ", &["\"filename\"", "p1", "p2", "p3"]),
+    ("chdir", Command, "ディレクトリ移動", &["\"dirname\""]),
+    ("delete", Command, "ファイル削除", &["\"filename\""]),
+    ("dirlist", Command, "ディレクトリ一覧を取得", &["p1", "\"filemask\"", "p2"]),
+    ("exist", Command, "ファイルのサイズ取得", &["\"filename\""]),
+    ("mkdir", Command, "ディレクトリ作成", &["\"dirname\""]),
+    ("bcopy", Command, "ファイルのコピー", &["\"src\"", "\"dest\""]),
+    ("memfile", Command, "メモリストリーム設定", &["p1", "p2", "p3"]),
+    // 文字列操作命令
+    ("getstr", Command, "バッファから文字列読み出し", &["p1", "p2", "p3", "p4", "p5"]),
+    ("noteadd", Command, "指定行の追加・変更", &["p1", "p2", "p3"]),
+    ("notedel", Command, "行の削除", &["p1"]),
+    ("noteget", Command, "指定行を読み込み", &["p1", "p2"]),
+    ("noteload", Command, "対象バッファ読み込み", &["\"filename\"", "p1"]),
+    ("notesave", Command, "対象バッファ保存", &["\"filename\""]),
+    ("notesel", Command, "対象バッファ指定", &["p1"]),
+    ("noteunsel", Command, "対象バッファの復帰", &[]),
+    ("split", Command, "文字列から分割された要素を代入", &["p1", "\"string\"", "p2"]),
+    ("strrep", Command, "文字列の置換", &["p1", "\"search\"", "\"replace\""]),
+    ("cnvstoa", Command, "通常文字列をANSI文字列に変換", &["p1", "\"string\""]),
+    ("cnvstow", Command, "通常文字列をunicodeに変換", &["p1", "\"string\""]),
+    ("sortget", Command, "ソート元のインデックスを取得", &["p1", "p2"]),
+    ("sortnote", Command, "メモリノート文字列をソート", &["p1", "p2"]),
+    ("sortstr", Command, "配列変数を文字列でソート", &["p1", "p2"]),
+    ("sortval", Command, "配列変数を数値でソート", &["p1", "p2"]),
+    // システム制御命令
+    ("getreq", Command, "システムリクエスト取得", &["p1", "p2"]),
+    ("randomize", Command, "乱数発生の初期化", &["p1"]),
+    ("setreq", Command, "システムリクエスト設定", &["p1", "p2"]),
+    // COMオブジェクト操作命令
+    ("comevarg", Command, "COMイベントのパラメーターを取得", &["p1", "p2", "p3", "p4"]),
+    ("comevent", Command, "COMイベントの取得開始", &["p1", "p2", "p3", "*label"]),
+    ("comres", Command, "メソッド返値代入変数を設定", &["p1"]),
+    ("delcom", Command, "COMオブジェクト型変数の破棄", &["p1"]),
+    ("mcall", Command, "メソッドの呼び出し", &["p1", "p2", "p3"]),
+    ("newcom", Command, "COMオブジェクト型変数の新規作成", &["p1", "p2", "p3", "p4"]),
+    ("querycom", Command, "COMオブジェクト型変数の作成", &["p1", "p2", "p3"]),
+    ("sarrayconv", Command, "Variant型との一括変換を行なう", &["p1", "p2", "p3", "p4"]),
+    // 基本入出力関数
+    ("abs", Function, "整数の絶対値を返す", &["p1"]),
+    ("absf", Function, "実数の絶対値を返す", &["p1"]),
+    ("atan", Function, "アークタンジェント値を返す", &["p1", "p2"]),
+    ("cos", Function, "コサイン値を返す", &["p1"]),
+    ("dirinfo", Function, "ディレクトリ情報の取得", &["p1"]),
+    ("double", Function, "実数値に変換", &["p1"]),
+    ("expf", Function, "指数を返す", &["p1"]),
+    ("gettime", Function, "時間・日付を取得する", &["p1"]),
+    ("ginfo", Function, "ウィンドウ情報の取得", &["p1"]),
+    ("int", Function, "整数値に変換", &["p1"]),
+    ("length", Function, "配列の1次元要素数を返す", &["p1"]),
+    ("limit", Function, "一定範囲内の整数を返す", &["p1", "p2", "p3"]),
+    ("limitf", Function, "一定範囲内の実数を返す", &["p1", "p2", "p3"]),
+    ("logf", Function, "対数を返す", &["p1"]),
+    ("objinfo", Function, "ウィンドウオブジェクト情報の取得", &["p1", "p2"]),
+    ("peek", Function, "バッファから1byte読み出し", &["p1", "p2"]),
+    ("powf", Function, "累乗を求める", &["p1", "p2"]),
+    ("rnd", Function, "乱数を発生", &["p1"]),
+    ("sin", Function, "サイン値を返す", &["p1"]),
+    ("sqrt", Function, "ルート値を返す", &["p1"]),
+    ("str", Function, "文字列に変換", &["p1"]),
+    ("sysinfo", Function, "システム情報の取得", &["p1"]),
+    ("tan", Function, "タンジェント値を返す", &["p1"]),
+    ("varptr", Function, "変数データのポインタを返す", &["p1"]),
+    ("vartype", Function, "変数の型を返す", &["p1"]),
+    ("wpeek", Function, "バッファから2byte読み出し", &["p1", "p2"]),
+    ("lpeek", Function, "バッファから4byte読み出し", &["p1", "p2"]),
+    ("callfunc", Function, "外部関数の呼び出し", &["p1", "p2", "p3"]),
+    ("getease", Function, "イージング値を整数で取得", &["p1", "p2"]),
+    ("geteasef", Function, "イージング値を実数で取得", &["p1", "p2"]),
+    ("length2", Function, "配列の2次元要素数を返す", &["p1"]),
+    ("length3", Function, "配列の3次元要素数を返す", &["p1"]),
+    ("length4", Function, "配列の4次元要素数を返す", &["p1"]),
+    ("libptr", Function, "外部呼び出しコマンドの情報アドレスを得る", &["p1"]),
+    ("varsize", Function, "変数データのバッファサイズを返す", &["p1"]),
+    ("varuse", Function, "変数の使用状況を返す", &["p1"]),
+    // 文字列操作関数
+    ("getpath", Function, "パスの一部を取得", &["p1", "p2"]),
+    ("instr", Function, "文字列の検索をする", &["p1", "p2", "\"search\""]),
+    ("strf", Function, "書式付き文字列を変換", &["\"format\"", "p1"]),
+    ("strlen", Function, "文字列の長さを調べる", &["p1"]),
+    ("strmid", Function, "文字列の一部を取り出す", &["p1", "p2", "p3"]),
+    ("strtrim", Function, "指定した文字だけを取り除く", &["p1", "p2", "p3"]),
+    ("noteinfo", Function, "メモリノートパッド情報取得", &["p1"]),
+    ("cnvatos", Function, "ANSI文字列を通常文字列に変換", &["p1"]),
+    ("cnvwtos", Function, "unicodeを通常文字列に変換", &["p1"]),
+    ("notefind", Function, "メモリノートパッド検索", &["\"search\"", "p1"]),
+    // システム変数
+    ("cnt", SysVar, "ループのカウンター", &[]),
+    ("dir_cmdline", SysVar, "コマンドライン文字列", &[]),
+    ("dir_cur", SysVar, "カレントディレクトリ", &[]),
+    ("dir_exe", SysVar, "実行ファイルがあるディレクトリ", &[]),
+    ("err", SysVar, "エラーコード", &[]),
+    ("hdc", SysVar, "現在のデバイスコンテキスト", &[]),
+    ("hinstance", SysVar, "現在のインスタンスハンドル", &[]),
+    ("hwnd", SysVar, "現在のウィンドウハンドル", &[]),
+    ("iparam", SysVar, "割り込み要因を示す値", &[]),
+    ("lparam", SysVar, "割り込み時に保存されたLPARAM", &[]),
+    ("looplev", SysVar, "repeatのネストレベル", &[]),
+    ("mousew", SysVar, "マウスカーソルのホイール値", &[]),
+    ("mousex", SysVar, "マウスカーソルのX座標", &[]),
+    ("mousey", SysVar, "マウスカーソルのY座標", &[]),
+    ("refdval", SysVar, "命令実行後の実数値の結果", &[]),
+    ("refstr", SysVar, "命令実行後の文字列の結果", &[]),
+    ("stat", SysVar, "命令実行後の結果", &[]),
+    ("strsize", SysVar, "読み出した文字列のサイズ", &[]),
+    ("sublev", SysVar, "サブルーチンのネストレベル", &[]),
+    ("thismod", SysVar, "現在のモジュール変数", &[]),
+    ("hspstat", SysVar, "HSPランタイムの情報", &[]),
+    ("hspver", SysVar, "HSPのバージョン番号", &[]),
+    ("notemax", SysVar, "メモリノートパッドの行数", &[]),
+    ("notesize", SysVar, "メモリノートパッドの文字数", &[]),
+    ("wparam", SysVar, "割り込み時に保存されたWPARAM", &[]),
+];
+
+/// パラメータの並びを表す文字列。命令なら `mes p1, p2`、関数なら `abs(p1)` の形にする。
+fn to_skeleton(kind: BuiltinKind, params: &[&str]) -> String {
+    let params = params.join(", ");
+    match kind {
+        Command if params.is_empty() => String::new(),
+        Command => format!(" {}", params),
+        Function => format!("({})", params),
+        SysVar => String::new(),
+    }
+}
+
+fn convert_entry(&(name, kind, desc, params): &BuiltinEntry) -> (SymbolRc, CompletionItem) {
+    let name_rc = RcStr::from(name);
+
+    let signature_opt = match kind {
+        Command | Function => Some(Rc::new(SignatureData {
+            name: name_rc.clone(),
+            params: params
+                .iter()
+                .map(|&p| SignatureParam {
+                    name_opt: Some(RcStr::from(p)),
+                    ..SignatureParam::default()
+                })
+                .collect(),
+        })),
+        SysVar => None,
+    };
+
+    let symbol = DefInfo::HspHelp {
+        name: name_rc.clone(),
+        details: SymbolDetails {
+            desc: Some(RcStr::from(desc)),
+            ..SymbolDetails::default()
+        },
+        signature_opt,
+    }
+    .into_symbol();
+
+    let completion_kind = match kind {
+        Command | Function => CompletionItemKind::FUNCTION,
+        SysVar => CompletionItemKind::VARIABLE,
+    };
+
+    let skeleton = to_skeleton(kind, params);
+    let completion_item = CompletionItem {
+        kind: Some(completion_kind),
+        label: name.to_string(),
+        label_details: if skeleton.is_empty() {
+            None
+        } else {
+            Some(CompletionItemLabelDetails {
+                detail: Some(skeleton),
+                description: None,
+            })
+        },
+        detail: Some(desc.to_string()),
+        // hsphelp の標準命令と同じ順位に並べる。
+        sort_text: Some(format!("x{}", name)),
+        ..Default::default()
+    };
+
+    (symbol, completion_item)
+}
+
+/// 組み込みのシンボルのうち、hsphelp で定義されていないものを環境と補完候補に追加する。
+pub(crate) fn load_builtin_db(
+    builtin_env: &mut SymbolEnv,
+    completion_items: &mut Vec<CompletionItem>,
+) {
+    for entry in BUILTINS {
+        if builtin_env.get(entry.0).is_some() {
+            continue;
+        }
+
+        let (symbol, completion_item) = convert_entry(entry);
+        builtin_env.insert(symbol.name.clone(), symbol);
+        completion_items.push(completion_item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_builtin_db() {
+        let mut builtin_env = SymbolEnv::default();
+
+        // hsphelp から読み込んだシンボルは上書きしない。
+        let mes = DefInfo::HspHelp {
+            name: "mes".into(),
+            details: SymbolDetails::default(),
+            signature_opt: None,
+        }
+        .into_symbol();
+        builtin_env.insert("mes".into(), mes.clone());
+
+        let mut items = vec![];
+        load_builtin_db(&mut builtin_env, &mut items);
+
+        assert_eq!(builtin_env.get("mes"), Some(mes));
+        assert!(items.iter().all(|item| item.label != "mes"));
+
+        let strmid = builtin_env.get("strmid").unwrap();
+        assert_eq!(strmid.signature_opt().unwrap().params.len(), 3);
+
+        let item = items.iter().find(|item| item.label == "bload").unwrap();
+        assert_eq!(
+            item.label_details.as_ref().unwrap().detail.as_deref(),
+            Some(" \"filename\", p1, p2, p3")
+        );
+
+        let item = items.iter().find(|item| item.label == "cnt").unwrap();
+        assert_eq!(item.kind, Some(CompletionItemKind::VARIABLE));
+
+        assert!(builtin_env.get("gradf").is_some());
+    }
+}
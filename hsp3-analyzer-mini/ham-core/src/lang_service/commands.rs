@@ -0,0 +1,32 @@
+//! `workspace/executeCommand` で実行できるコマンド
+
+use lsp_types::WorkspaceEdit;
+use serde_json::Value;
+
+/// 文字列リテラルの中身を置換する。引数: (パターン, 置換後の文字列)
+pub(crate) const COMMAND_REPLACE_IN_STRINGS: &str = "ham.replaceInStrings";
+
+/// ワークスペースのファイルを読み込み直す。
+pub(crate) const COMMAND_REINDEX_WORKSPACE: &str = "ham.reindexWorkspace";
+
+/// エントリーポイントからのincludeの木構造をテキストで返す。
+pub(crate) const COMMAND_SHOW_INCLUDE_TREE: &str = "ham.showIncludeTree";
+
+/// ワークスペースで定義されているシンボルの一覧をテキストで返す。
+pub(crate) const COMMAND_DUMP_SYMBOLS: &str = "ham.dumpSymbols";
+
+/// サーバーが提供するコマンドの一覧 (`initialize` で通知する。)
+pub(crate) const COMMANDS: &[&str] = &[
+    COMMAND_REPLACE_IN_STRINGS,
+    COMMAND_REINDEX_WORKSPACE,
+    COMMAND_SHOW_INCLUDE_TREE,
+    COMMAND_DUMP_SYMBOLS,
+];
+
+/// コマンドの実行結果
+pub(crate) enum CommandOutcome {
+    /// 結果をそのままクライアントに返す。
+    Value(Value),
+    /// クライアントに編集を適用させる。(`workspace/applyEdit`)
+    ApplyEdit { label: String, edit: WorkspaceEdit },
+}
@@ -1,5 +1,10 @@
 use super::*;
-use crate::source::DocId;
+use crate::{
+    source::DocId,
+    utils::read_file::{
+        decode_sjis_lossy, decode_utf8_lossy, find_modeline_encoding, guess_utf8, strip_utf8_bom,
+    },
+};
 
 /// テキストドキュメントのバージョン番号
 /// (エディタ上で編集されるたびに変わる番号。
@@ -29,6 +34,101 @@ pub(crate) enum DocChangeOrigin {
     Path(PathBuf),
 }
 
+/// ファイルを読み込んだときの文字コード
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileEncoding {
+    /// 実際に使った文字コード (Auto にはならない。)
+    pub(crate) encoding: SourceEncoding,
+    /// その文字コードとして不正なバイト列を含んでいたか (置換文字に変換される。)
+    pub(crate) malformed: bool,
+}
+
+/// サイズの上限を超えたドキュメントの情報
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LargeDoc {
+    /// バイト数
+    pub(crate) size: usize,
+    /// 解析をまったく行わなかったか (そうでなければシンボルの収集だけ行う。)
+    pub(crate) skipped: bool,
+}
+
+/// ソースファイルを読む。
+///
+/// 文字コードは、BOM、モードライン (`; ham: encoding=utf-8`)、設定 (`default`) の順に優先して決める。
+/// 設定が Auto なら内容から推測する。
+pub(crate) fn read_source_file(
+    path: &Path,
+    default: SourceEncoding,
+    out: &mut String,
+) -> Option<FileEncoding> {
+    let contents = fs::read(path).ok()?;
+
+    if let Some(rest) = strip_utf8_bom(&contents) {
+        let malformed = !decode_utf8_lossy(rest, out);
+        return Some(FileEncoding {
+            encoding: SourceEncoding::Utf8,
+            malformed,
+        });
+    }
+
+    let encoding = find_modeline_encoding(&contents)
+        .and_then(|name| SourceEncoding::from_name(&name))
+        .unwrap_or(default);
+    let encoding = match encoding {
+        SourceEncoding::Auto if guess_utf8(&contents) => SourceEncoding::Utf8,
+        SourceEncoding::Auto => SourceEncoding::Sjis,
+        _ => encoding,
+    };
+
+    let ok = match encoding {
+        SourceEncoding::Utf8 => decode_utf8_lossy(&contents, out),
+        _ => decode_sjis_lossy(&contents, out),
+    };
+    Some(FileEncoding {
+        encoding,
+        malformed: !ok,
+    })
+}
+
+/// LSPの位置 (UTF-16単位) をテキストのバイト位置に変換する。範囲外ならNone。
+fn position_to_index(text: &str, pos: lsp_types::Position) -> Option<usize> {
+    let mut start = 0;
+    for _ in 0..pos.line {
+        start += text[start..].find('\n')? + 1;
+    }
+
+    let line = &text[start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut column = 0;
+    for (i, c) in line.char_indices() {
+        if column >= pos.character {
+            return Some(start + i);
+        }
+        column += c.len_utf16() as u32;
+    }
+    if column >= pos.character {
+        Some(start + line.len())
+    } else {
+        None
+    }
+}
+
+/// テキストの範囲を置き換える。(`textDocument/didChange` の差分を適用する。)
+///
+/// 範囲がテキストに収まっていなければ、何もせずにfalseを返す。
+pub(crate) fn apply_text_edit(text: &mut String, range: lsp_types::Range, new_text: &str) -> bool {
+    let start = match position_to_index(text, range.start) {
+        Some(it) => it,
+        None => return false,
+    };
+    let end = match position_to_index(text, range.end) {
+        Some(it) if start <= it => it,
+        _ => return false,
+    };
+    text.replace_range(start..end, new_text);
+    true
+}
+
 /// テキストドキュメントを管理するもの。
 ///
 /// - テキストドキュメントにはIDを振って管理する。(`DocId`)
@@ -50,9 +150,22 @@ pub(crate) struct Docs {
     /// エディタで開かれているドキュメント
     editor_docs: HashSet<DocId>,
 
-    /// ファイルとして保存されているドキュメント
+    /// エディタから最後に与えられたテキスト (差分の適用に使う。)
+    editor_texts: HashMap<DocId, RcStr>,
+
+    /// 変更が抜けたため、エディタの内容と差分を適用するテキストが一致しなくなったドキュメント
+    /// (全体を置き換える変更を受け取るまで、差分は適用しない。)
+    editor_out_of_sync: HashSet<DocId>,
+
+    /// ファイルとして保存されているドキュメント (エディタで開かれているかどうかに関わらない。)
     file_docs: HashSet<DocId>,
 
+    /// ファイルから読み込んだドキュメントの文字コード
+    file_encodings: HashMap<DocId, FileEncoding>,
+
+    /// サイズの上限を超えたドキュメント
+    large_docs: HashMap<DocId, LargeDoc>,
+
     /// 最近の更新
     doc_changes: Vec<DocChange>,
 }
@@ -84,10 +197,67 @@ impl Docs {
         self.doc_to_uri.get(&doc)
     }
 
+    /// すべてのドキュメントのIDとURIを列挙する。
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (DocId, &CanonicalUri)> + '_ {
+        self.doc_to_uri.iter().map(|(&doc, uri)| (doc, uri))
+    }
+
     pub(crate) fn get_version(&self, doc: DocId) -> Option<TextDocumentVersion> {
         self.doc_versions.get(&doc).copied()
     }
 
+    /// エディタで開かれているドキュメントの、最後に与えられたバージョン番号とテキストを取得する。
+    pub(crate) fn get_editor_text(&self, doc: DocId) -> Option<(TextDocumentVersion, RcStr)> {
+        let text = self.editor_texts.get(&doc)?;
+        Some((self.get_version(doc)?, text.clone()))
+    }
+
+    /// エディタの内容と差分を適用するテキストが一致していないか？
+    pub(crate) fn is_editor_out_of_sync(&self, doc: DocId) -> bool {
+        self.editor_out_of_sync.contains(&doc)
+    }
+
+    pub(crate) fn set_editor_out_of_sync(&mut self, doc: DocId, out_of_sync: bool) {
+        if out_of_sync {
+            self.editor_out_of_sync.insert(doc);
+        } else {
+            self.editor_out_of_sync.remove(&doc);
+        }
+    }
+
+    pub(crate) fn set_file_encoding(&mut self, doc: DocId, encoding: FileEncoding) {
+        self.file_encodings.insert(doc, encoding);
+    }
+
+    /// ファイルから読み込んだドキュメントの文字コード (エディタで開かれているものはNone)
+    pub(crate) fn get_file_encoding(&self, doc: DocId) -> Option<FileEncoding> {
+        self.file_encodings.get(&doc).copied()
+    }
+
+    pub(crate) fn set_large_doc(&mut self, doc: DocId, large_opt: Option<LargeDoc>) {
+        match large_opt {
+            Some(large) => {
+                self.large_docs.insert(doc, large);
+            }
+            None => {
+                self.large_docs.remove(&doc);
+            }
+        }
+    }
+
+    /// サイズの上限を超えたドキュメントを列挙する。
+    pub(crate) fn iter_large_docs(&self) -> impl Iterator<Item = (DocId, LargeDoc)> + '_ {
+        self.large_docs.iter().map(|(&doc, &large)| (doc, large))
+    }
+
+    /// ファイルを読み込んだときに、不正なバイト列を含んでいたドキュメントを列挙する。
+    pub(crate) fn iter_malformed_files(&self) -> impl Iterator<Item = (DocId, FileEncoding)> + '_ {
+        self.file_encodings
+            .iter()
+            .filter(|(_, e)| e.malformed)
+            .map(|(&doc, &e)| (doc, e))
+    }
+
     /// 指定したURIが指すディレクトリの子孫であるドキュメントを探す。
     pub(crate) fn get_docs_in(&self, uri: &CanonicalUri) -> ProjectDocs {
         // ファイル名 -> 同じ名前を持つドキュメントのIDのリスト
@@ -128,6 +298,7 @@ impl Docs {
                 .map(|(doc, dir_id)| (doc, dirs[dir_id].clone()))
                 .collect(),
             doc_env,
+            include_targets: Rc::default(),
         }
     }
 
@@ -158,6 +329,8 @@ impl Docs {
         self.doc_to_uri.remove(&doc);
         self.uri_to_doc.remove(&uri);
         self.doc_versions.remove(&doc);
+        self.file_encodings.remove(&doc);
+        self.large_docs.remove(&doc);
         self.doc_changes.push(DocChange::Closed { doc });
     }
 
@@ -171,12 +344,16 @@ impl Docs {
         );
 
         let (created, doc) = self.touch_uri(uri);
+        self.editor_texts.insert(doc, text.clone());
+        self.editor_out_of_sync.remove(&doc);
         if created {
             self.do_open_doc(doc, version, Lang::Hsp3, DocChangeOrigin::Editor(text));
         } else {
             self.do_change_doc(doc, version, Lang::Hsp3, DocChangeOrigin::Editor(text));
         }
 
+        // エディタから与えられたテキストを使うので、ファイルの文字コードは関係なくなる。
+        self.file_encodings.remove(&doc);
         self.editor_docs.insert(doc);
     }
 
@@ -190,12 +367,15 @@ impl Docs {
         );
 
         let (created, doc) = self.touch_uri(uri);
+        self.editor_texts.insert(doc, text.clone());
         if created {
             self.do_open_doc(doc, version, Lang::Hsp3, DocChangeOrigin::Editor(text));
         } else {
             self.do_change_doc(doc, version, Lang::Hsp3, DocChangeOrigin::Editor(text));
         }
 
+        // エディタから与えられたテキストを使うので、ファイルの文字コードは関係なくなる。
+        self.file_encodings.remove(&doc);
         self.editor_docs.insert(doc);
     }
 
@@ -209,15 +389,37 @@ impl Docs {
         };
 
         self.editor_docs.remove(&doc);
+        self.editor_texts.remove(&doc);
+        self.editor_out_of_sync.remove(&doc);
 
         if !self.file_docs.contains(&doc) {
             self.do_close_doc(doc, &uri);
+            return;
+        }
+
+        // エディタの内容は保存されていないかもしれないので、ファイルの内容に戻す。
+        // (ファイルの監視がなければ、削除されたことに気づいていないかもしれない。)
+        match uri.to_file_path().filter(|path| path.is_file()) {
+            Some(path) => {
+                let lang = Lang::from_path(&path).unwrap_or(Lang::Hsp3);
+                let origin = DocChangeOrigin::Path(path);
+                self.do_change_doc(doc, NO_VERSION, lang, origin);
+            }
+            None => {
+                self.file_docs.remove(&doc);
+                self.do_close_doc(doc, &uri);
+            }
         }
     }
 
     fn do_change_file(&mut self, uri: CanonicalUri, path: &Path) -> Option<DocId> {
+        let lang = Lang::from_path(&path)?;
         let (created, doc) = self.touch_uri(uri);
 
+        // ファイルが存在することは、エディタで開かれているかどうかに関わらず記録する。
+        // (エディタで閉じられたとき、ファイルの内容に戻すため。)
+        let opened = self.file_docs.insert(doc);
+
         let open_in_editor = !created && self.editor_docs.contains(&doc);
         if open_in_editor {
             #[cfg(trace_docs)]
@@ -225,9 +427,7 @@ impl Docs {
             return Some(doc);
         }
 
-        let lang = Lang::from_path(&path)?;
         let origin = DocChangeOrigin::Path(path.to_path_buf());
-        let opened = self.file_docs.insert(doc);
         if opened {
             self.do_open_doc(doc, NO_VERSION, lang, origin);
         } else {
@@ -274,6 +474,9 @@ pub(crate) struct ProjectDocs {
 
     /// ファイル名 -> その名前のドキュメント
     pub(crate) doc_env: HashMap<String, Vec<DocId>>,
+
+    /// (includeするドキュメント, includeのファイル名) -> ファイルシステム上で解決されたドキュメント
+    pub(crate) include_targets: Rc<HashMap<(DocId, String), DocId>>,
 }
 
 impl ProjectDocs {
@@ -287,11 +490,18 @@ impl ProjectDocs {
 
     /// ファイル名からドキュメントを探す。
     ///
-    /// ディレクトリは無視して名前が一致するものを探す。
-    /// ただし `base_opt = Some(doc)` であり `doc` と同じディレクトリにその名前のファイルがあったら、それを使う。
+    /// `base_opt = Some(doc)` であり、`doc` のincludeがファイルシステム上で解決されていたら、それを使う。
+    /// そうでなければ、ディレクトリは無視して名前が一致するものを探す。
+    /// ただし `doc` と同じディレクトリにその名前のファイルがあったら、それを使う。
     pub(crate) fn find(&self, name: &str, base_opt: Option<DocId>) -> Option<DocId> {
         debug_assert!(!name.contains('\\'));
 
+        if let Some(base) = base_opt {
+            if let Some(&doc) = self.include_targets.get(&(base, name.to_string())) {
+                return Some(doc);
+            }
+        }
+
         let basename = match name.rfind('/') {
             Some(i) => &name[i + 1..],
             None => name,
@@ -312,3 +522,70 @@ impl ProjectDocs {
         docs.first().cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    fn read(name: &str, contents: &[u8], default: SourceEncoding) -> (String, FileEncoding) {
+        let dir = TempDir::new("read-source-file");
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+
+        let mut text = String::new();
+        let file_encoding = read_source_file(&path, default, &mut text).unwrap();
+        (text, file_encoding)
+    }
+
+    #[test]
+    fn test_apply_text_edit() {
+        let range = |l1, c1, l2, c2| lsp_types::Range {
+            start: lsp_types::Position::new(l1, c1),
+            end: lsp_types::Position::new(l2, c2),
+        };
+
+        let mut text = "mes \"あいう\"\nstop\n".to_string();
+        assert!(apply_text_edit(&mut text, range(0, 6, 0, 7), "x"));
+        assert_eq!(text, "mes \"あxう\"\nstop\n");
+        assert!(apply_text_edit(&mut text, range(0, 9, 1, 0), ":"));
+        assert_eq!(text, "mes \"あxう\":stop\n");
+        assert!(apply_text_edit(&mut text, range(1, 0, 1, 0), "end\n"));
+        assert_eq!(text, "mes \"あxう\":stop\nend\n");
+
+        // 範囲外
+        assert!(!apply_text_edit(&mut text, range(0, 99, 0, 99), "x"));
+        assert!(!apply_text_edit(&mut text, range(9, 0, 9, 0), "x"));
+        assert_eq!(text, "mes \"あxう\":stop\nend\n");
+    }
+
+    #[test]
+    fn test_read_source_file() {
+        let utf8 = |malformed| FileEncoding {
+            encoding: SourceEncoding::Utf8,
+            malformed,
+        };
+
+        // BOM は設定より優先する。
+        assert_eq!(
+            read("bom.hsp", b"\xEF\xBB\xBFmes 1", SourceEncoding::Sjis),
+            ("mes 1".to_string(), utf8(false))
+        );
+
+        // モードラインは設定より優先する。
+        assert_eq!(
+            read(
+                "modeline.hsp",
+                b"; ham: encoding=utf-8\nmes \"\xc3\xbd\"",
+                SourceEncoding::Sjis
+            ),
+            ("; ham: encoding=utf-8\nmes \"ý\"".to_string(), utf8(false))
+        );
+
+        // 不正なバイト列は置換文字にして、そのことを記録する。
+        assert_eq!(
+            read("malformed.hsp", b"mes \"\x93\xfa\"", SourceEncoding::Utf8),
+            ("mes \"\u{FFFD}\u{FFFD}\"".to_string(), utf8(true))
+        );
+    }
+}
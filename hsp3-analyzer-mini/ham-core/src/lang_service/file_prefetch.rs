@@ -0,0 +1,60 @@
+//! ワークスペースのファイルの先読み
+//!
+//! ファイルの読み込みと文字コードの変換を別のスレッドで行う。
+//! 解析は読み込まれたファイルを受け取ったときに (メインスレッドで) 行う。
+
+use super::{
+    docs::{read_source_file, FileEncoding},
+    SourceEncoding,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// 読み込んだファイル: (パス, 読み込みに成功したら (テキスト, 文字コード))
+pub(crate) type PrefetchedFile = (PathBuf, Option<(String, FileEncoding)>);
+
+pub(crate) struct FilePrefetch {
+    rx: Receiver<PrefetchedFile>,
+}
+
+impl FilePrefetch {
+    /// ファイルの先読みを開始する。
+    ///
+    /// `skip_size_opt` より大きいファイルは読み込まない。
+    /// `FilePrefetch` がドロップされたら、読み込みを途中でやめる。
+    pub(crate) fn start(
+        paths: Vec<PathBuf>,
+        encoding: SourceEncoding,
+        skip_size_opt: Option<usize>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for path in paths {
+                let too_large = skip_size_opt.map_or(false, |size| {
+                    fs::metadata(&path).map_or(false, |m| m.len() as usize > size)
+                });
+                let mut text = String::new();
+                let result = if too_large {
+                    None
+                } else {
+                    read_source_file(&path, encoding, &mut text).map(|e| (text, e))
+                };
+                if tx.send((path, result)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { rx }
+    }
+
+    /// 読み込みが終わったファイルを (待たずに) 取り出す。
+    pub(crate) fn try_iter(&self) -> impl Iterator<Item = PrefetchedFile> + '_ {
+        self.rx.try_iter()
+    }
+}
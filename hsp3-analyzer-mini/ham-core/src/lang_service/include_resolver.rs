@@ -0,0 +1,109 @@
+//! `#include` されるファイルをファイルシステムから探す
+
+use crate::utils::canonical_path::normalize_lexically;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// ディレクトリからの相対パスでファイルを探す。
+///
+/// includeのファイル名は小文字に標準化されているので、大文字・小文字を区別しないファイルシステムでなくてもみつかるように、
+/// 一致するファイルがなければ大文字・小文字を無視して探す。
+fn join_ignoring_case(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = normalize_lexically(&dir.join(name));
+    if exact.is_file() {
+        return Some(exact);
+    }
+
+    let mut path = dir.to_path_buf();
+    for part in name.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => {
+                path.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        let entry = fs::read_dir(&path)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |entry_name| entry_name.eq_ignore_ascii_case(part))
+            })?;
+        path.push(entry.file_name());
+    }
+
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// `#include` されるファイルを探す。
+///
+/// includeするファイルがあるディレクトリ、includeディレクトリ、commonディレクトリの順に探す。
+/// (HSP のコンパイラと同じく、includeするファイルに近いものを優先する。)
+pub(crate) fn find_include_file(
+    name: &str,
+    base_dir_opt: Option<&Path>,
+    include_dirs: &[PathBuf],
+    common_dir: &Path,
+) -> Option<PathBuf> {
+    base_dir_opt
+        .into_iter()
+        .chain(include_dirs.iter().map(|dir| dir.as_path()))
+        .chain(Some(common_dir))
+        .find_map(|dir| join_ignoring_case(dir, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    #[test]
+    fn test_find_include_file() {
+        let temp = TempDir::new("find-include-file");
+        let root = temp.path();
+        for dir in ["src/Lib", "include", "common"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("src/Lib/Util.as"), "").unwrap();
+        fs::write(root.join("include/util.as"), "").unwrap();
+        fs::write(root.join("include/only_include.as"), "").unwrap();
+        fs::write(root.join("common/hspdef.as"), "").unwrap();
+
+        let find = |name: &str, base_dir_opt: Option<&Path>| {
+            find_include_file(
+                name,
+                base_dir_opt,
+                &[root.join("include")],
+                &root.join("common"),
+            )
+        };
+        let src = root.join("src");
+
+        // includeするファイルのディレクトリを優先する。大文字・小文字は区別しない。
+        assert_eq!(
+            find("lib/util.as", Some(&src)),
+            Some(root.join("src/Lib/Util.as"))
+        );
+        assert_eq!(
+            find("util.as", Some(&src)),
+            Some(root.join("include/util.as"))
+        );
+        assert_eq!(
+            find("../include/only_include.as", Some(&src)),
+            Some(root.join("include/only_include.as"))
+        );
+        assert_eq!(find("hspdef.as", None), Some(root.join("common/hspdef.as")));
+        assert_eq!(find("missing.as", Some(&src)), None);
+    }
+}
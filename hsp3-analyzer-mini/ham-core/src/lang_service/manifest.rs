@@ -0,0 +1,134 @@
+//! プロジェクトの設定ファイル (ginger.toml)
+//!
+//! ワークスペースのルートに置く。LSPサーバーとコマンドラインツールで同じ設定を共有するためのもの。
+//!
+//! ```toml
+//! entry-points = ["main.hsp"]
+//! include-paths = ["lib"]
+//! runtime = "hsp3dish"
+//! encoding = "utf-8"
+//!
+//! [lint]
+//! enabled = true
+//! unused = false
+//! ```
+
+use super::SourceEncoding;
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// 設定ファイルの名前
+pub(crate) const MANIFEST_FILE_NAME: &str = "ginger.toml";
+
+/// スクリプトを実行するランタイム
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Runtime {
+    Hsp3,
+    Hsp3Dish,
+    Hgimg4,
+}
+
+impl Runtime {
+    /// ランタイムの命令を定義しているcommonのファイルの名前 (暗黙にincludeされているとみなす。)
+    pub(crate) fn header_name(self) -> Option<&'static str> {
+        match self {
+            Runtime::Hsp3 => None,
+            Runtime::Hsp3Dish => Some("hsp3dish.as"),
+            Runtime::Hgimg4 => Some("hgimg4.as"),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ManifestLint {
+    pub(crate) enabled: Option<bool>,
+    /// 使われていないシンボルを報告するか
+    pub(crate) unused: Option<bool>,
+}
+
+/// 設定ファイルの内容 (指定されていない項目はエディタの設定に従う。)
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Manifest {
+    /// エントリーポイントとして扱うスクリプトのパス (ワークスペースのルートからの相対パスも可)
+    pub(crate) entry_points: Vec<PathBuf>,
+    /// `#include` するファイルを探すディレクトリ (ワークスペースのルートからの相対パスも可)
+    pub(crate) include_paths: Vec<PathBuf>,
+    pub(crate) runtime: Option<Runtime>,
+    pub(crate) encoding: Option<SourceEncoding>,
+    pub(crate) lint: ManifestLint,
+}
+
+impl Manifest {
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| err.to_string())
+    }
+
+    /// ワークスペースのルートにある設定ファイルを読む。
+    ///
+    /// ファイルがなければ既定の設定を返す。パスはルートからの絶対パスにする。
+    pub(crate) fn load(root_dir: &Path) -> Result<Self, String> {
+        let path = root_dir.join(MANIFEST_FILE_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(it) => it,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Manifest::default()),
+            Err(err) => return Err(format!("{:?} を読み込めません。({})", path, err)),
+        };
+
+        let mut manifest =
+            Self::parse(&text).map_err(|err| format!("{:?} の内容が不正です。({})", path, err))?;
+        for path in manifest
+            .entry_points
+            .iter_mut()
+            .chain(manifest.include_paths.iter_mut())
+        {
+            if path.is_relative() {
+                *path = root_dir.join(&path);
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::parse(
+            r#"
+entry-points = ["main.hsp"]
+include-paths = ["lib"]
+runtime = "hsp3dish"
+encoding = "utf-8"
+
+[lint]
+unused = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                entry_points: vec![PathBuf::from("main.hsp")],
+                include_paths: vec![PathBuf::from("lib")],
+                runtime: Some(Runtime::Hsp3Dish),
+                encoding: Some(SourceEncoding::Utf8),
+                lint: ManifestLint {
+                    enabled: None,
+                    unused: Some(false),
+                },
+            }
+        );
+
+        assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+        assert!(Manifest::parse("entrypoints = []").is_err());
+        assert!(Manifest::parse("runtime = \"hsp2\"").is_err());
+    }
+}
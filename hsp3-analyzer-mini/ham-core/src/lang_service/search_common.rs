@@ -1,15 +1,13 @@
 use super::*;
-use crate::source::DocId;
+use crate::{source::DocId, utils::canonical_path::canonicalize_path};
 
-pub(crate) fn search_common(
-    hsp3_root: &Path,
-    docs: &mut Docs,
-    common_docs: &mut HashMap<String, DocId>,
-) {
-    // trace!("commonディレクトリにあるファイルを開きます。");
-    let common_dir = hsp3_root.join("common");
-
-    let patterns = match common_dir.to_str() {
+/// ディレクトリにあるスクリプトファイルを開いて、ディレクトリからの相対パスで登録する。
+///
+/// すでに登録されている名前は上書きしない。
+fn open_dir_files(dir: &Path, docs: &mut Docs, common_docs: &mut HashMap<String, DocId>) {
+    // シンボリックリンクなどを解決しておく。(相対パスをドキュメントのパスと揃えるため。)
+    let dir = &canonicalize_path(dir);
+    let patterns = match dir.to_str() {
         Some(dir) => vec![format!("{}/**/*.hsp", dir), format!("{}/**/*.as", dir)],
         None => vec![],
     };
@@ -28,17 +26,35 @@ pub(crate) fn search_common(
         .filter(|path| !is_excluded(&path))
     {
         (|| -> Option<()> {
-            // commonに対する相対パス
+            // dirに対する相対パス
             let relative = path
-                .strip_prefix(&common_dir)
+                .strip_prefix(dir)
                 .ok()?
                 .to_string_lossy()
                 .replace("\\", "/");
 
             let doc = docs.ensure_file_opened(&path)?;
-            // trace!("common/{} => doc={}", relative, doc);
-            common_docs.insert(relative, doc);
+            // trace!("{:?}/{} => doc={}", dir, relative, doc);
+            common_docs.entry(relative).or_insert(doc);
             None
         })();
     }
 }
+
+/// 追加のincludeディレクトリとcommonディレクトリにあるファイルを開く。
+///
+/// 同じ名前のファイルがあるときは、先に指定されたincludeディレクトリのものを優先し、commonのものは最後に使う。
+pub(crate) fn search_common(
+    hsp3_root: &Path,
+    include_dirs: &[PathBuf],
+    docs: &mut Docs,
+    common_docs: &mut HashMap<String, DocId>,
+) {
+    for dir in include_dirs {
+        // trace!("includeディレクトリにあるファイルを開きます。{:?}", dir);
+        open_dir_files(dir, docs, common_docs);
+    }
+
+    // trace!("commonディレクトリにあるファイルを開きます。");
+    open_dir_files(&hsp3_root.join("common"), docs, common_docs);
+}
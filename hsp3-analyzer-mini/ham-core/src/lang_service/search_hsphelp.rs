@@ -11,6 +11,9 @@ pub(crate) struct HspHelpInfo {
 
     /// ヘルプファイルに含まれる情報
     pub(crate) doc_symbols: HashMap<DocId, Vec<CompletionItem>>,
+
+    /// 組み込みのデータベースに含まれる標準命令などの情報 (ヘルプファイルにないもの)
+    pub(crate) builtin_items: Vec<CompletionItem>,
 }
 
 fn is_builtin(stem: &str) -> bool {
@@ -37,7 +40,11 @@ fn convert_symbol(hs_symbol: HsSymbol) -> (SymbolRc, CompletionItem) {
     let signature_opt = params_opt.map(|params| {
         let params = params
             .into_iter()
-            .map(|p| (None, Some(p.name.into()), p.details_opt))
+            .map(|p| SignatureParam {
+                name_opt: Some(p.name.into()),
+                info_opt: p.details_opt,
+                ..SignatureParam::default()
+            })
             .collect();
 
         Rc::new(SignatureData {
@@ -51,6 +58,7 @@ fn convert_symbol(hs_symbol: HsSymbol) -> (SymbolRc, CompletionItem) {
         details: SymbolDetails {
             desc: description.clone().map(RcStr::from),
             docs: documentation.clone(),
+            ..SymbolDetails::default()
         },
         signature_opt,
     }
@@ -38,35 +38,63 @@ use std::{
 mod analysis {
     use super::*;
 
+    mod build_options;
     mod comment;
+    mod const_eval;
+    mod data_file;
     mod doc_analysis;
+    mod event_handler;
+    mod include_graph;
     mod integrate;
+    mod macro_expand;
+    mod module_visibility;
     mod name_system;
+    mod newmod;
     mod preproc;
     mod project_analysis;
     mod sema;
     mod symbol;
+    mod syntax_error;
     mod syntax_linter;
     mod var;
+    mod var_type;
     mod workspace_analysis;
 
     pub(crate) use self::{
+        build_options::{
+            build_directive_at, collect_build_options, BuildDirective, BuildOption,
+            BuildOptionIssue, BuildOptionValue,
+        },
+        const_eval::{eval_const_expr, ConstValue},
         doc_analysis::DocAnalysis,
+        event_handler::collect_event_handler_sites,
+        include_graph::{find_include_cycles, IncludeGraph},
+        macro_expand::{
+            expand_macro, expand_macro_call_at, macro_args_to_tokens, tokens_to_string, MacroDef,
+        },
+        module_visibility::{ModuleVisibilityHint, ModuleVisibilityIssue},
         name_system::*,
-        preproc::{IncludeGuard, PreprocAnalysisResult, SignatureData},
-        project_analysis::{EntryPoints, ProjectAnalysis, ProjectAnalysisRef},
+        preproc::{IncludeGuard, PreprocAnalysisResult, SignatureData, SignatureParam},
+        project_analysis::{
+            deffunc_takes_modvar_at, EntryPoints, ProjectAnalysis, ProjectAnalysisRef,
+        },
         sema::{Diagnostic, Sema},
         symbol::{
-            module_name_as_ident, DefFuncData, DefFuncKey, DefFuncMap, ModuleData, ModuleKey,
-            ModuleMap, ModuleRc,
+            module_name_as_ident, unnamed_module_label, DefFuncData, DefFuncKey, DefFuncMap,
+            ModuleData, ModuleKey, ModuleMap, ModuleRc,
         },
-        symbol::{DefInfo, HspSymbolKind, SymbolDetails, SymbolRc},
+        symbol::{ComMethodData, DefInfo, HspSymbolKind, PluginCmdData, SymbolDetails, SymbolRc},
+        syntax_error::SyntaxError,
         syntax_linter::SyntaxLint,
-        workspace_analysis::{DocSyntax, WorkspaceAnalysis, WorkspaceHost},
+        var_type::{
+            collect_var_type_sites, describe_var_types, infer_expr_type, ArrayDecl, VarType,
+        },
+        workspace_analysis::{DocBlock, DocBlockKind, DocSyntax, WorkspaceAnalysis, WorkspaceHost},
     };
     pub(crate) use super::assists::{
         completion::{
-            collect_symbols_as_completion_items, in_preproc, in_str_or_comment, ACompletionItem,
+            collect_symbols_as_completion_items, in_preproc, in_str_or_comment,
+            include_path_prefix, ACompletionItem,
         },
         signature_help::{SignatureHelpContext, SignatureHelpHost},
     };
@@ -74,7 +102,7 @@ mod analysis {
     use crate::{
         lang::Lang,
         lang_service::{docs::ProjectDocs, search_hsphelp::HspHelpInfo},
-        parse::{PRoot, PToken},
+        parse::{PDefFuncKind, PRoot, PToken},
         source::*,
         token::{TokenData, TokenKind},
     };
@@ -152,11 +180,66 @@ mod token {
 }
 
 mod utils {
+    pub(crate) mod canonical_path;
     pub(crate) mod canonical_uri;
     pub(crate) mod rc_item;
     pub(crate) mod rc_slice;
     pub(crate) mod rc_str;
     pub(crate) mod read_file;
+    #[cfg(test)]
+    pub(crate) mod test_utils;
+}
+
+/// ディレクトリにあるスクリプトを解析して、シンボル・スコープ・シグネチャ・診断をテキストとして書き出す。
+///
+/// 出力は安定していて差分をとりやすいので、解析器の回帰テスト (スナップショットテスト) に使える。
+/// 環境によって結果が変わらないように、HSP3 のcommonディレクトリのファイルは読み込まない。
+pub fn analysis_snapshot(dir: &Path) -> String {
+    use crate::lang_service::{LangService, LangServiceOptions};
+
+    let dir = utils::canonical_path::canonicalize_path(dir);
+    let root_uri = match lsp_types::Url::from_directory_path(&dir) {
+        Ok(it) => it,
+        Err(()) => return String::new(),
+    };
+
+    let options = LangServiceOptions {
+        watcher_enabled: false,
+        prefetch_enabled: false,
+        ..LangServiceOptions::default()
+    };
+    // 存在しないディレクトリをHSP3のルートとする。
+    let mut ls = LangService::new(dir.join(".no_exist"), options);
+    ls.initialize(Some(root_uri));
+    ls.did_initialize();
+    ls.analysis_snapshot()
+}
+
+/// スクリプトの文字列リテラルの中身だけを正規表現で置換する。
+pub fn replace_in_strings_fn(
+    text: String,
+    pattern: &str,
+    replacement: &str,
+) -> Result<String, String> {
+    let regex = regex::Regex::new(pattern).map_err(|err| err.to_string())?;
+    let tokens = tokenize(1, RcStr::from(text.clone()));
+
+    let mut edits = vec![];
+    assists::replace_in_strings::replace_in_str_tokens(
+        tokens.iter(),
+        &regex,
+        replacement,
+        &mut edits,
+    );
+
+    let mut output = text;
+    for (range, new_text) in edits.into_iter().rev() {
+        output.replace_range(
+            range.start().index as usize..range.end().index as usize,
+            &new_text,
+        );
+    }
+    Ok(output)
 }
 
 pub fn rewrite_fn(text: String) -> String {
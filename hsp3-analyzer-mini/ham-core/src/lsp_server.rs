@@ -58,4 +58,5 @@ pub(super) struct LspMessageOpaque {
 
 pub(crate) mod error {
     pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+    pub(crate) const INVALID_PARAMS: i64 = -32602;
 }
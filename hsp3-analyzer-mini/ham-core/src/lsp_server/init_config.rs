@@ -1,20 +1,430 @@
-// `initialize` リクエスト
+// `initialize` リクエストと `workspace/didChangeConfiguration` 通知の設定
 
-use serde::Deserialize;
+use crate::lang_service::SourceEncoding;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
 
-#[derive(Default, Deserialize)]
-#[serde(rename_all = "camelCase")]
+/// `initializationOptions` の内容
+///
+/// `workspace/didChangeConfiguration` では一部の設定だけが送られてくることがあるので、
+/// 解析器の設定に対応する値は省略可能にして、指定されたものだけを反映する。
+#[derive(Default)]
 pub(super) struct InitConfig {
-    pub(super) document_symbol: DocumentSymbol,
+    /// HSP3 のインストールディレクトリ (コマンドライン引数より優先する)
+    pub(super) hsp3_root: Option<PathBuf>,
+    pub(super) encoding: Option<SourceEncoding>,
+    /// スクリプトを実行するランタイムの名前 (`#runtime` がないプロジェクトで使う。)
+    pub(super) target: Option<String>,
+    /// エントリーポイントとして扱うスクリプトのパス (ワークスペースのルートからの相対パスも可)
+    pub(super) entry_points: Vec<PathBuf>,
+    /// `#include` するファイルを探すディレクトリ (ワークスペースのルートからの相対パスも可)
+    pub(super) include_paths: Option<Vec<PathBuf>>,
+    /// ワークスペースの走査から除外するパスのパターン (未指定なら既定のパターンを使う。)
+    pub(super) exclude: Option<Vec<glob::Pattern>>,
+    pub(super) lint: Lint,
+    pub(super) large_file: LargeFile,
+    pub(super) format: Toggle,
+    pub(super) document_symbol: Toggle,
+    pub(super) semantic_tokens: Toggle,
+    pub(super) signature_help: Toggle,
+    pub(super) inlay_hints: InlayHints,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub(super) struct Lint {
+    /// 未指定なら環境変数 `HAM_LINT` に従う。
+    pub(super) enabled: Option<bool>,
+    /// どこからも使われていないシンボルをヒントとして報告するか
+    pub(super) unused: Option<Toggle>,
 }
 
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub(super) struct LargeFile {
+    /// これより大きい (バイト数) ファイルは、シンボルの収集だけを行う。
+    pub(super) size_limit: Option<usize>,
+    /// 大きいファイルを解析しないか
+    pub(super) skip: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub(super) struct InlayHints {
+    /// 変数の最初の代入に推測した型を表示するか (うるさいので既定では無効)
+    pub(super) types: Option<bool>,
+}
+
+/// 機能の有効・無効の設定
+///
+/// `{ "enabled": false }` のほかに、`false` のように真偽値だけでもよい。
+/// (VSCode 拡張機能の設定 `lint.unused` などの形)
 #[derive(Deserialize)]
-pub(super) struct DocumentSymbol {
+#[serde(from = "ToggleRepr")]
+pub(super) struct Toggle {
     pub(super) enabled: bool,
 }
 
-impl Default for DocumentSymbol {
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ToggleRepr {
+    Bool(bool),
+    Object {
+        #[serde(default = "default_enabled")]
+        enabled: bool,
+    },
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl From<ToggleRepr> for Toggle {
+    fn from(repr: ToggleRepr) -> Self {
+        match repr {
+            ToggleRepr::Bool(enabled) | ToggleRepr::Object { enabled } => Self { enabled },
+        }
+    }
+}
+
+impl Default for Toggle {
     fn default() -> Self {
         Self { enabled: true }
     }
 }
+
+/// `target` に指定できるランタイムの名前 (`#runtime` に指定するものと同じ)
+static KNOWN_TARGETS: &[&str] = &["hsp3", "hsp3utf", "hsp3_64", "hsp3dish", "hsp3gp", "hsp3cl"];
+
+/// `workspace/didChangeConfiguration` で送られてくる設定のセクション名 (VSCode 拡張機能の設定と同じ)
+const SETTINGS_SECTION: &str = "hsp3-analyzer-mini";
+
+fn parse<T: DeserializeOwned>(
+    section: &str,
+    key: &str,
+    value: Value,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    match serde_json::from_value(value) {
+        Ok(it) => Some(it),
+        Err(err) => {
+            errors.push(format!("{}.{} の値が不正です。({})", section, key, err));
+            None
+        }
+    }
+}
+
+impl InitConfig {
+    /// `initializationOptions` を解釈する。
+    ///
+    /// 不正な値は無視して既定値を使い、その理由を `errors` に追加する。
+    pub(super) fn from_value(value: Option<Value>, errors: &mut Vec<String>) -> Self {
+        let map = match value {
+            None | Some(Value::Null) => return InitConfig::default(),
+            Some(Value::Object(map)) => map,
+            Some(_) => {
+                errors.push("initializationOptions はオブジェクトである必要があります。".into());
+                return InitConfig::default();
+            }
+        };
+
+        Self::from_map("initializationOptions", map, true, errors)
+    }
+
+    /// `workspace/didChangeConfiguration` で送られてきた設定を解釈する。
+    ///
+    /// 設定は `initializationOptions` と同じ形式で、`hsp3-analyzer-mini` セクションの中にあってもよい。
+    /// クライアント側だけで使う設定もまざっているので、不明な設定は無視する。
+    pub(super) fn from_settings(value: Value, errors: &mut Vec<String>) -> Self {
+        let value = match value {
+            Value::Object(mut map) if map.contains_key(SETTINGS_SECTION) => {
+                map.remove(SETTINGS_SECTION).unwrap()
+            }
+            it => it,
+        };
+
+        let map = match value {
+            Value::Null => return InitConfig::default(),
+            Value::Object(map) => map,
+            _ => {
+                errors.push("設定はオブジェクトである必要があります。".into());
+                return InitConfig::default();
+            }
+        };
+
+        Self::from_map(SETTINGS_SECTION, map, false, errors)
+    }
+
+    fn from_map(
+        section: &str,
+        map: serde_json::Map<String, Value>,
+        strict: bool,
+        errors: &mut Vec<String>,
+    ) -> Self {
+        let mut config = InitConfig::default();
+
+        for (key, value) in map {
+            match key.as_str() {
+                // `hsp3-root` は VSCode 拡張機能の設定の名前
+                "hsp3Root" | "hsp3-root" => {
+                    if let Some(path) = parse::<PathBuf>(section, &key, value, errors) {
+                        if path.is_dir() {
+                            config.hsp3_root = Some(path);
+                        } else {
+                            errors.push(format!(
+                                "{}.{} のディレクトリがみつかりません。({:?})",
+                                section, key, path
+                            ));
+                        }
+                    }
+                }
+                "encoding" => config.encoding = parse(section, &key, value, errors),
+                "target" => {
+                    if let Some(target) =
+                        parse::<Option<String>>(section, &key, value, errors).flatten()
+                    {
+                        if KNOWN_TARGETS.contains(&target.as_str()) {
+                            config.target = Some(target);
+                        } else {
+                            errors.push(format!(
+                                "{}.{} のランタイムが不明です。({:?}、{} のいずれかを指定してください。)",
+                                section,
+                                key,
+                                target,
+                                KNOWN_TARGETS.join(", ")
+                            ));
+                        }
+                    }
+                }
+                "entryPoints" => {
+                    config.entry_points = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                "includePaths" => config.include_paths = parse(section, &key, value, errors),
+                "exclude" => {
+                    if let Some(patterns) = parse::<Vec<String>>(section, &key, value, errors) {
+                        let patterns = patterns
+                            .into_iter()
+                            .filter_map(|p| match glob::Pattern::new(&p) {
+                                Ok(it) => Some(it),
+                                Err(err) => {
+                                    errors.push(format!(
+                                        "{}.exclude のパターンが不正です。({:?}: {})",
+                                        section, p, err
+                                    ));
+                                    None
+                                }
+                            })
+                            .collect();
+                        config.exclude = Some(patterns);
+                    }
+                }
+                "lint" => {
+                    // `lint-enabled` と両方あるときに上書きしないように、指定されたものだけを反映する。
+                    let lint: Lint = parse(section, &key, value, errors).unwrap_or_default();
+                    if lint.enabled.is_some() {
+                        config.lint.enabled = lint.enabled;
+                    }
+                    if lint.unused.is_some() {
+                        config.lint.unused = lint.unused;
+                    }
+                }
+                // VSCode 拡張機能の設定の名前
+                "lint-enabled" => config.lint.enabled = parse(section, &key, value, errors),
+                "largeFile" => {
+                    config.large_file = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                "format" => config.format = parse(section, &key, value, errors).unwrap_or_default(),
+                "documentSymbol" => {
+                    config.document_symbol = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                "semanticTokens" => {
+                    config.semantic_tokens = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                "signatureHelp" => {
+                    config.signature_help = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                "inlayHints" => {
+                    config.inlay_hints = parse(section, &key, value, errors).unwrap_or_default()
+                }
+                _ if strict => errors.push(format!("{}.{} は不明な設定です。", section, key)),
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    /// パスの設定をワークスペースのルートからの絶対パスにして、存在を確認する。
+    ///
+    /// みつからないパスは取り除き、その理由を `errors` に追加する。
+    pub(super) fn resolve_paths(&mut self, root_dir_opt: Option<&Path>, errors: &mut Vec<String>) {
+        let resolve = |path: &Path| match root_dir_opt {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        };
+
+        self.entry_points = std::mem::take(&mut self.entry_points)
+            .into_iter()
+            .map(|path| resolve(&path))
+            .filter(|path| {
+                let ok = path.is_file();
+                if !ok {
+                    errors.push(format!(
+                        "エントリーポイントのファイルがみつかりません。({:?})",
+                        path
+                    ));
+                }
+                ok
+            })
+            .collect();
+
+        if let Some(include_paths) = &mut self.include_paths {
+            *include_paths = std::mem::take(include_paths)
+                .into_iter()
+                .map(|path| resolve(&path))
+                .filter(|path| {
+                    let ok = path.is_dir();
+                    if !ok {
+                        errors.push(format!("includeディレクトリがみつかりません。({:?})", path));
+                    }
+                    ok
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_value() {
+        let mut errors = vec![];
+        let config = InitConfig::from_value(
+            Some(json!({
+                "encoding": "utf-8",
+                "target": "hsp3dish",
+                "lint": { "enabled": false, "unused": { "enabled": false } },
+                "largeFile": { "sizeLimit": 1000 },
+                "format": { "enabled": false },
+                "inlayHints": { "types": true },
+                "exclude": ["out/**"],
+            })),
+            &mut errors,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+
+        assert_eq!(config.encoding, Some(SourceEncoding::Utf8));
+        assert_eq!(config.target.as_deref(), Some("hsp3dish"));
+        assert_eq!(config.lint.enabled, Some(false));
+        assert_eq!(config.lint.unused.map(|t| t.enabled), Some(false));
+        assert_eq!(config.large_file.size_limit, Some(1000));
+        assert_eq!(config.large_file.skip, None);
+        assert!(!config.format.enabled);
+        assert!(config.document_symbol.enabled);
+        assert_eq!(config.inlay_hints.types, Some(true));
+        assert_eq!(config.exclude.map(|p| p.len()), Some(1));
+
+        // 指定されなかった設定は None になる。
+        let config = InitConfig::from_value(None, &mut errors);
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.encoding, None);
+        assert_eq!(config.target, None);
+        assert_eq!(config.lint.enabled, None);
+    }
+
+    #[test]
+    fn test_from_value_errors() {
+        let mut errors = vec![];
+        let config = InitConfig::from_value(
+            Some(json!({
+                "encoding": "euc-jp",
+                "target": "hsp2",
+                "lint": { "enabled": "yes" },
+                "exclude": ["[", "ok/**"],
+                "unknownOption": 1,
+            })),
+            &mut errors,
+        );
+
+        // 不正な値は無視する。
+        assert_eq!(config.encoding, None);
+        assert_eq!(config.target, None);
+        assert_eq!(config.lint.enabled, None);
+        assert_eq!(config.exclude.map(|p| p.len()), Some(1));
+
+        errors.sort();
+        let keys = errors
+            .iter()
+            .map(|e| e.split(' ').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "initializationOptions.encoding",
+                "initializationOptions.exclude",
+                "initializationOptions.lint",
+                "initializationOptions.target",
+                "initializationOptions.unknownOption",
+            ]
+        );
+
+        let mut errors = vec![];
+        InitConfig::from_value(Some(json!([1])), &mut errors);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_from_settings() {
+        // VSCode の設定のセクションの中にあってもよい。不明な設定は無視する。
+        let mut errors = vec![];
+        let config = InitConfig::from_settings(
+            json!({
+                "hsp3-analyzer-mini": {
+                    "lint-enabled": false,
+                    "target": "hsp3cl",
+                    "inlayHints": { "types": true },
+                    "lint": { "unused": false },
+                    "lsp-bin": "/path/to/ham-lsp",
+                },
+            }),
+            &mut errors,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.lint.enabled, Some(false));
+        assert_eq!(config.target.as_deref(), Some("hsp3cl"));
+        assert_eq!(config.inlay_hints.types, Some(true));
+        assert_eq!(config.lint.unused.map(|t| t.enabled), Some(false));
+
+        let config = InitConfig::from_settings(json!({ "target": null }), &mut errors);
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.target, None);
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let temp = TempDir::new("init-config");
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(root.join("main.hsp"), "").unwrap();
+
+        let mut errors = vec![];
+        let mut config = InitConfig::from_value(
+            Some(json!({
+                "entryPoints": ["main.hsp", "missing.hsp"],
+                "includePaths": ["lib", "no_lib"],
+            })),
+            &mut errors,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+
+        config.resolve_paths(Some(root), &mut errors);
+        assert_eq!(config.entry_points, vec![root.join("main.hsp")]);
+        assert_eq!(config.include_paths, Some(vec![root.join("lib")]));
+        assert_eq!(errors.len(), 2);
+    }
+}
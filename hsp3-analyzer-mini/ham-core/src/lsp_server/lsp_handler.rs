@@ -1,5 +1,8 @@
 use super::*;
-use crate::lang_service::LangService;
+use crate::{
+    assists::include_graph::{IncludeGraphParams, IncludedByParams},
+    lang_service::{commands::*, LangService},
+};
 use lsp_types::request::Request;
 use lsp_types::*;
 use std::io;
@@ -7,34 +10,68 @@ use std::io;
 pub(super) struct LspHandler<W: io::Write> {
     sender: LspSender<W>,
     model: LangService,
+
+    /// サーバーからクライアントに送ったリクエストの最後のID
+    last_request_id: i64,
+
+    /// クライアントがdiagnosticsをプル型で要求するか。(そのときは publishDiagnostics を送らない。)
+    pull_diagnostics: bool,
+
+    /// クライアントがdiagnosticsの再要求 (`workspace/diagnostic/refresh`) に対応しているか。
+    diagnostic_refresh: bool,
+
+    /// クライアントが進捗の表示 (`window/workDoneProgress/create`) に対応しているか。
+    work_done_progress: bool,
 }
 
 impl<W: io::Write> LspHandler<W> {
     pub(crate) fn new(sender: LspSender<W>, model: LangService) -> Self {
-        Self { sender, model }
+        Self {
+            sender,
+            model,
+            last_request_id: 0,
+            pull_diagnostics: false,
+            diagnostic_refresh: false,
+            work_done_progress: false,
+        }
+    }
+
+    fn fresh_request_id(&mut self) -> i64 {
+        self.last_request_id += 1;
+        self.last_request_id
     }
 
     fn register_file_system_watcher(&mut self) {
         if !self.model.watcher_enabled() {
+            info!("ファイルの監視は無効です。");
             return;
         }
 
+        let id = self.fresh_request_id();
         self.sender.send_request(
-            // 他のリクエストを送らないので id=1 しか使わない。
-            1,
+            id,
             "client/registerCapability",
             RegistrationParams {
                 registrations: vec![Registration {
-                    id: "1".into(),
+                    id: id.to_string(),
                     method: "workspace/didChangeWatchedFiles".into(),
                     register_options: Some(
                         serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-                            watchers: vec![FileSystemWatcher {
-                                kind: Some(
-                                    WatchKind::Create | WatchKind::Change | WatchKind::Delete,
-                                ),
-                                glob_pattern: "**/*.hsp".into(),
-                            }],
+                            watchers: vec![
+                                FileSystemWatcher {
+                                    kind: Some(
+                                        WatchKind::Create | WatchKind::Change | WatchKind::Delete,
+                                    ),
+                                    glob_pattern: GlobPattern::String("**/*.{hsp,as}".into()),
+                                },
+                                // プロジェクトの設定ファイル
+                                FileSystemWatcher {
+                                    kind: Some(
+                                        WatchKind::Create | WatchKind::Change | WatchKind::Delete,
+                                    ),
+                                    glob_pattern: GlobPattern::String("**/ginger.toml".into()),
+                                },
+                            ],
                         })
                         .unwrap(),
                     ),
@@ -43,11 +80,119 @@ impl<W: io::Write> LspHandler<W> {
         );
     }
 
+    /// 設定の誤りをクライアントに通知する。
+    fn show_config_errors(&mut self, errors: Vec<String>) {
+        for message in errors {
+            warn!("{}", message);
+            self.sender.send_notification(
+                "window/showMessage",
+                ShowMessageParams {
+                    typ: MessageType::WARNING,
+                    message,
+                },
+            );
+        }
+    }
+
+    /// 設定を解析器に反映する。(`initialize` と `workspace/didChangeConfiguration` で共通の部分)
+    fn apply_config(&mut self, config: &mut init_config::InitConfig) {
+        if let Some(hsp3_root) = config.hsp3_root.take() {
+            self.model.set_hsp3_root(hsp3_root);
+        }
+
+        if let Some(lint_enabled) = config.lint.enabled {
+            self.model.set_lint_enabled(lint_enabled);
+        }
+        if let Some(unused) = &config.lint.unused {
+            self.model.set_lint_unused(unused.enabled);
+        }
+
+        if let Some(encoding) = config.encoding {
+            self.model.set_encoding(encoding);
+        }
+
+        if let Some(target) = config.target.take() {
+            self.model.set_target(target);
+        }
+
+        if let Some(types) = config.inlay_hints.types {
+            self.model.set_inlay_hint_types(types);
+        }
+
+        if let Some(size) = config.large_file.size_limit {
+            self.model.set_large_file_size(size);
+        }
+        if let Some(skip) = config.large_file.skip {
+            self.model.set_skip_large_files(skip);
+        }
+
+        if let Some(exclude) = config.exclude.take() {
+            self.model.set_exclude(exclude);
+        }
+
+        if !config.entry_points.is_empty() {
+            self.model
+                .set_entrypoints(std::mem::take(&mut config.entry_points));
+        }
+
+        if let Some(include_paths) = config.include_paths.take() {
+            self.model.set_include_dirs(include_paths);
+        }
+    }
+
     fn initialize<'a>(&'a mut self, params: InitializeParams) -> InitializeResult {
-        let init_config = params
-            .initialization_options
-            .and_then(|options| serde_json::from_value::<init_config::InitConfig>(options).ok())
-            .unwrap_or_default();
+        let mut init_config = {
+            let mut errors = vec![];
+            let mut init_config =
+                init_config::InitConfig::from_value(params.initialization_options, &mut errors);
+            let root_dir_opt = (params.root_uri.as_ref()).and_then(|uri| uri.to_file_path().ok());
+            init_config.resolve_paths(root_dir_opt.as_deref(), &mut errors);
+            self.show_config_errors(errors);
+            init_config
+        };
+
+        self.apply_config(&mut init_config);
+
+        let hierarchical_document_symbol = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|x| x.document_symbol.as_ref())
+            .and_then(|x| x.hierarchical_document_symbol_support)
+            .unwrap_or(false);
+        self.model
+            .set_hierarchical_document_symbol(hierarchical_document_symbol);
+
+        let completion_snippet = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|x| x.completion.as_ref())
+            .and_then(|x| x.completion_item.as_ref())
+            .and_then(|x| x.snippet_support)
+            .unwrap_or(false);
+        self.model.set_completion_snippet(completion_snippet);
+
+        self.pull_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .map_or(false, |x| x.diagnostic.is_some());
+
+        self.work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|x| x.work_done_progress)
+            .unwrap_or(false);
+
+        self.diagnostic_refresh = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|x| x.diagnostic.as_ref())
+            .and_then(|x| x.refresh_support)
+            .unwrap_or(false);
 
         let watchable = params
             .capabilities
@@ -58,70 +203,146 @@ impl<W: io::Write> LspHandler<W> {
 
         self.model.initialize(params.root_uri);
 
-        if watchable {
-            self.model.set_watchable(true);
-        }
+        // クライアントがファイルの監視に対応していなければ、監視しない。
+        // (その場合、エディタの外で行われた変更は `ham.reindexWorkspace` コマンドで反映する。)
+        self.model.set_watchable(watchable);
 
         InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Options(
                     TextDocumentSyncOptions {
                         open_close: Some(true),
-                        change: Some(TextDocumentSyncKind::FULL),
+                        // 変更は差分で受け取る。(`LangService::edit_doc` を参照。)
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
                         ..TextDocumentSyncOptions::default()
                     },
                 )),
                 code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(true),
                     trigger_characters: None,
                     ..CompletionOptions::default()
                 }),
+                declaration_provider: Some(DeclarationCapability::Simple(true)),
                 definition_provider: Some(OneOf::Left(true)),
-                document_formatting_provider: Some(OneOf::Left(true)),
+                diagnostic_provider: if self.pull_diagnostics {
+                    Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some(env!("CARGO_PKG_NAME").to_string()),
+                        // 他のファイルの変更によってdiagnosticsが変わりうる。
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        ..Default::default()
+                    }))
+                } else {
+                    None
+                },
+                document_formatting_provider: if init_config.format.enabled {
+                    Some(OneOf::Left(true))
+                } else {
+                    None
+                },
                 document_highlight_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "\n".to_string(),
+                    more_trigger_character: Some(vec!["/".to_string()]),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: COMMANDS.iter().map(|c| c.to_string()).collect(),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: None,
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
                 document_symbol_provider: if init_config.document_symbol.enabled {
                     Some(OneOf::Left(true))
                 } else {
                     None
                 },
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                moniker_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
+                type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
                 rename_provider: Some(OneOf::Right(RenameOptions {
                     prepare_provider: Some(true),
                     work_done_progress_options: WorkDoneProgressOptions::default(),
                 })),
-                semantic_tokens_provider: Some(
-                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                semantic_tokens_provider: if init_config.semantic_tokens.enabled {
+                    Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
                         SemanticTokensOptions {
                             legend: SemanticTokensLegend {
                                 token_types: vec![
-                                    SemanticTokenType::PARAMETER, // 0
-                                    SemanticTokenType::VARIABLE,  // 1
-                                    SemanticTokenType::FUNCTION,  // 2
-                                    SemanticTokenType::MACRO,     // 3
-                                    SemanticTokenType::NAMESPACE, // 4
-                                    SemanticTokenType::KEYWORD,   // 5
+                                    SemanticTokenType::PARAMETER,    // 0
+                                    SemanticTokenType::VARIABLE,     // 1
+                                    SemanticTokenType::FUNCTION,     // 2
+                                    SemanticTokenType::MACRO,        // 3
+                                    SemanticTokenType::NAMESPACE,    // 4
+                                    SemanticTokenType::KEYWORD,      // 5
+                                    SemanticTokenType::new("label"), // 6
                                 ],
                                 token_modifiers: vec![
                                     SemanticTokenModifier::READONLY, // 0b01
                                     SemanticTokenModifier::STATIC,   // 0b10
                                 ],
                             },
-                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            range: Some(true),
                             ..Default::default()
                         },
-                    ),
-                ),
-                signature_help_provider: Some(SignatureHelpOptions {
-                    trigger_characters: Some(vec![
-                        " ".to_string(),
-                        "(".to_string(),
-                        ",".to_string(),
-                    ]),
-                    ..Default::default()
-                }),
+                    ))
+                } else {
+                    None
+                },
+                signature_help_provider: if init_config.signature_help.enabled {
+                    Some(SignatureHelpOptions {
+                        trigger_characters: Some(vec![
+                            " ".to_string(),
+                            "(".to_string(),
+                            ",".to_string(),
+                        ]),
+                        ..Default::default()
+                    })
+                } else {
+                    None
+                },
                 workspace_symbol_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        // スクリプトファイルやそれを含むディレクトリの名前が変わるとき、`#include` を書き換える。
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![
+                                FileOperationFilter {
+                                    scheme: Some("file".into()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**/*.{hsp,as}".into(),
+                                        matches: Some(FileOperationPatternKind::File),
+                                        options: None,
+                                    },
+                                },
+                                FileOperationFilter {
+                                    scheme: Some("file".into()),
+                                    pattern: FileOperationPattern {
+                                        glob: "**/*".into(),
+                                        matches: Some(FileOperationPatternKind::Folder),
+                                        options: None,
+                                    },
+                                },
+                            ],
+                        }),
+                        ..WorkspaceFileOperationsServerCapabilities::default()
+                    }),
+                }),
                 ..ServerCapabilities::default()
             },
             // 参考: https://doc.rust-lang.org/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-crates
@@ -134,13 +355,107 @@ impl<W: io::Write> LspHandler<W> {
 
     fn did_initialize(&mut self) {
         self.model.did_initialize();
+        let errors = self.model.take_manifest_errors();
+        self.show_config_errors(errors);
+        self.index_workspace();
         self.register_file_system_watcher();
     }
 
+    /// 収集したファイルを読み込んで解析する。クライアントが対応していれば進捗を表示する。
+    fn index_workspace(&mut self) {
+        if !self.work_done_progress {
+            let symbol_count = self.model.index_workspace(&mut |_, _| {});
+            info!("{} 個のシンボルをインデックスしました。", symbol_count);
+            return;
+        }
+
+        let token = NumberOrString::String("ham/indexing".into());
+        let id = self.fresh_request_id();
+        self.sender.send_request(
+            id,
+            "window/workDoneProgress/create",
+            WorkDoneProgressCreateParams {
+                token: token.clone(),
+            },
+        );
+
+        let sender = &mut self.sender;
+        let mut send_progress = |progress: WorkDoneProgress| {
+            sender.send_notification(
+                "$/progress",
+                ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(progress),
+                },
+            );
+        };
+
+        send_progress(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: "Indexing HSP files".into(),
+            cancellable: Some(false),
+            message: None,
+            percentage: Some(0),
+        }));
+
+        // 通知が多くなりすぎないように、割合が変わったときだけ報告する。
+        let mut last_percentage = 0;
+        let symbol_count = self.model.index_workspace(&mut |done, total| {
+            let percentage = (done * 100).checked_div(total).unwrap_or(100) as u32;
+            if percentage == last_percentage {
+                return;
+            }
+            last_percentage = percentage;
+
+            send_progress(WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(format!("{}/{}", done, total)),
+                percentage: Some(percentage),
+            }));
+        });
+
+        send_progress(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(format!("{} symbols indexed", symbol_count)),
+        }));
+    }
+
     fn shutdown(&mut self) {
         self.model.shutdown();
     }
 
+    fn workspace_will_rename_files(&mut self, params: RenameFilesParams) -> Option<WorkspaceEdit> {
+        let renames = params
+            .files
+            .into_iter()
+            .filter_map(|file| {
+                Some((
+                    Url::parse(&file.old_uri).ok()?,
+                    Url::parse(&file.new_uri).ok()?,
+                ))
+            })
+            .collect();
+        self.model.will_rename_files(renames)
+    }
+
+    fn workspace_did_change_configuration(&mut self, params: DidChangeConfigurationParams) {
+        let mut config = {
+            let mut errors = vec![];
+            let mut config = init_config::InitConfig::from_settings(params.settings, &mut errors);
+            config.resolve_paths(self.model.root_dir().as_deref(), &mut errors);
+            self.show_config_errors(errors);
+            config
+        };
+
+        self.apply_config(&mut config);
+        self.model.did_change_configuration();
+
+        // プル型のときは、クライアントにdiagnosticsを要求しなおしてもらう。
+        if self.pull_diagnostics && self.diagnostic_refresh {
+            let id = self.fresh_request_id();
+            self.sender
+                .send_request(id, "workspace/diagnostic/refresh", Value::Null);
+        }
+    }
+
     fn did_exit(&mut self, _json: &str) {
         std::process::exit(0)
     }
@@ -151,15 +466,9 @@ impl<W: io::Write> LspHandler<W> {
     }
 
     fn text_document_did_change(&mut self, params: DidChangeTextDocumentParams) {
-        let text = (params.content_changes.into_iter())
-            .next()
-            .map(|c| c.text)
-            .unwrap_or("".to_string());
-
         let doc = params.text_document;
-        let version = doc.version;
-
-        self.model.change_doc(doc.uri, version, text);
+        self.model
+            .edit_doc(doc.uri, doc.version, params.content_changes);
     }
 
     fn text_document_did_close(&mut self, params: DidCloseTextDocumentParams) {
@@ -171,6 +480,14 @@ impl<W: io::Write> LspHandler<W> {
             .code_action(params.text_document.uri, params.range, params.context)
     }
 
+    fn text_document_code_lens(&mut self, params: CodeLensParams) -> Vec<CodeLens> {
+        self.model.code_lens(params.text_document.uri)
+    }
+
+    fn code_lens_resolve(&mut self, params: CodeLens) -> CodeLens {
+        self.model.code_lens_resolve(params)
+    }
+
     fn text_document_completion(&mut self, params: CompletionParams) -> CompletionList {
         self.model.completion(
             params.text_document_position.text_document.uri,
@@ -192,6 +509,15 @@ impl<W: io::Write> LspHandler<W> {
         self.model.formatting(params.text_document.uri)
     }
 
+    fn text_document_on_type_formatting(
+        &mut self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Option<Vec<TextEdit>> {
+        let p = params.text_document_position;
+        self.model
+            .on_type_formatting(p.text_document.uri, p.position, &params.ch)
+    }
+
     fn text_document_definition(
         &mut self,
         params: TextDocumentPositionParams,
@@ -207,6 +533,51 @@ impl<W: io::Write> LspHandler<W> {
         }
     }
 
+    fn text_document_declaration(
+        &mut self,
+        params: TextDocumentPositionParams,
+    ) -> request::GotoDeclarationResponse {
+        let declarations = self
+            .model
+            .declarations(params.text_document.uri, params.position);
+
+        if declarations.len() == 1 {
+            request::GotoDeclarationResponse::Scalar(declarations.into_iter().next().unwrap())
+        } else {
+            request::GotoDeclarationResponse::Array(declarations)
+        }
+    }
+
+    fn text_document_implementation(
+        &mut self,
+        params: TextDocumentPositionParams,
+    ) -> request::GotoImplementationResponse {
+        let locations = self
+            .model
+            .implementations(params.text_document.uri, params.position);
+
+        if locations.len() == 1 {
+            request::GotoImplementationResponse::Scalar(locations.into_iter().next().unwrap())
+        } else {
+            request::GotoImplementationResponse::Array(locations)
+        }
+    }
+
+    fn text_document_type_definition(
+        &mut self,
+        params: TextDocumentPositionParams,
+    ) -> request::GotoTypeDefinitionResponse {
+        let locations = self
+            .model
+            .type_definitions(params.text_document.uri, params.position);
+
+        if locations.len() == 1 {
+            request::GotoTypeDefinitionResponse::Scalar(locations.into_iter().next().unwrap())
+        } else {
+            request::GotoTypeDefinitionResponse::Array(locations)
+        }
+    }
+
     fn text_document_highlight(
         &mut self,
         params: TextDocumentPositionParams,
@@ -222,10 +593,43 @@ impl<W: io::Write> LspHandler<W> {
         self.model.document_symbol(params.text_document.uri)
     }
 
+    fn text_document_link(&mut self, params: DocumentLinkParams) -> Vec<DocumentLink> {
+        self.model.document_link(params.text_document.uri)
+    }
+
+    fn text_document_folding_range(&mut self, params: FoldingRangeParams) -> Vec<FoldingRange> {
+        self.model.folding_range(params.text_document.uri)
+    }
+
     fn text_document_hover(&mut self, params: TextDocumentPositionParams) -> Option<Hover> {
         self.model.hover(params.text_document.uri, params.position)
     }
 
+    fn text_document_inlay_hint(&mut self, params: InlayHintParams) -> Vec<InlayHint> {
+        self.model
+            .inlay_hint(params.text_document.uri, params.range)
+    }
+
+    fn text_document_linked_editing_range(
+        &mut self,
+        params: LinkedEditingRangeParams,
+    ) -> Option<LinkedEditingRanges> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        self.model.linked_editing_range(text_document.uri, position)
+    }
+
+    fn text_document_moniker(&mut self, params: MonikerParams) -> Option<Vec<Moniker>> {
+        let (uri, position) = {
+            let p = params.text_document_position_params;
+            (p.text_document.uri, p.position)
+        };
+
+        self.model.moniker(uri, position)
+    }
+
     fn text_document_prepare_rename(
         &mut self,
         params: TextDocumentPositionParams,
@@ -258,6 +662,32 @@ impl<W: io::Write> LspHandler<W> {
         SemanticTokensResult::Tokens(self.model.semantic_tokens(uri))
     }
 
+    fn text_document_semantic_tokens_full_delta(
+        &mut self,
+        params: SemanticTokensDeltaParams,
+    ) -> Option<SemanticTokensFullDeltaResult> {
+        self.model
+            .semantic_tokens_delta(params.text_document.uri, params.previous_result_id)
+    }
+
+    fn text_document_semantic_tokens_range(
+        &mut self,
+        params: SemanticTokensRangeParams,
+    ) -> Option<SemanticTokensRangeResult> {
+        let tokens = self
+            .model
+            .semantic_tokens_range(params.text_document.uri, params.range)?;
+        Some(SemanticTokensRangeResult::Tokens(tokens))
+    }
+
+    fn text_document_selection_range(
+        &mut self,
+        params: SelectionRangeParams,
+    ) -> Vec<SelectionRange> {
+        self.model
+            .selection_range(params.text_document.uri, params.positions)
+    }
+
     fn text_document_signature_help(
         &mut self,
         params: SignatureHelpParams,
@@ -279,13 +709,56 @@ impl<W: io::Write> LspHandler<W> {
                 _ => continue,
             }
         }
+
+        let errors = self.model.take_manifest_errors();
+        self.show_config_errors(errors);
+    }
+
+    fn workspace_execute_command(&mut self, params: ExecuteCommandParams) -> Result<Value, String> {
+        match self
+            .model
+            .execute_command(&params.command, params.arguments)?
+        {
+            CommandOutcome::Value(value) => Ok(value),
+            CommandOutcome::ApplyEdit { label, edit } => {
+                let id = self.fresh_request_id();
+                self.sender.send_request(
+                    id,
+                    "workspace/applyEdit",
+                    ApplyWorkspaceEditParams {
+                        label: Some(label),
+                        edit,
+                    },
+                );
+                Ok(Value::Null)
+            }
+        }
     }
 
     fn workspace_symbol(&mut self, params: WorkspaceSymbolParams) -> Vec<SymbolInformation> {
         self.model.workspace_symbol(params.query)
     }
 
+    fn text_document_diagnostic(
+        &mut self,
+        params: DocumentDiagnosticParams,
+    ) -> DocumentDiagnosticReportResult {
+        self.model
+            .document_diagnostic(params.text_document.uri, params.previous_result_id)
+    }
+
+    fn workspace_diagnostic(
+        &mut self,
+        params: WorkspaceDiagnosticParams,
+    ) -> WorkspaceDiagnosticReportResult {
+        self.model.workspace_diagnostic(params.previous_result_ids)
+    }
+
     fn diagnose(&mut self) {
+        if self.pull_diagnostics {
+            return;
+        }
+
         let diagnostics = self.model.diagnose();
 
         for (uri, version, diagnostics) in diagnostics {
@@ -306,7 +779,7 @@ impl<W: io::Write> LspHandler<W> {
         let method = match msg.method {
             Some(it) => it,
 
-            // registerCapabilityのレスポンス。
+            // registerCapabilityやapplyEditのレスポンス。
             None if json.contains("\"result\"") && !json.contains("\"error\"") => return,
 
             None => {
@@ -359,6 +832,16 @@ impl<W: io::Write> LspHandler<W> {
                 let response = self.text_document_code_action(msg.params);
                 self.sender.send_response(msg_id, response);
             }
+            request::CodeLensRequest::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<CodeLensParams>>(json).unwrap();
+                let response = self.text_document_code_lens(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::CodeLensResolve::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<CodeLens>>(json).unwrap();
+                let response = self.code_lens_resolve(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
             "textDocument/completion" => {
                 let msg = serde_json::from_str::<LspRequest<CompletionParams>>(json).unwrap();
                 let msg_id = msg.id;
@@ -383,6 +866,31 @@ impl<W: io::Write> LspHandler<W> {
                 let response = self.text_document_formatting(msg.params);
                 self.sender.send_response(msg_id, response);
             }
+            request::OnTypeFormatting::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<DocumentOnTypeFormattingParams>>(json)
+                    .unwrap();
+                let msg_id = msg.id;
+                let response = self.text_document_on_type_formatting(msg.params);
+                self.sender.send_response(msg_id, response);
+            }
+            request::GotoDeclaration::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<TextDocumentPositionParams>>(json).unwrap();
+                let response = self.text_document_declaration(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::GotoImplementation::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<TextDocumentPositionParams>>(json).unwrap();
+                let response = self.text_document_implementation(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::GotoTypeDefinition::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<TextDocumentPositionParams>>(json).unwrap();
+                let response = self.text_document_type_definition(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
             "textDocument/definition" => {
                 let msg =
                     serde_json::from_str::<LspRequest<TextDocumentPositionParams>>(json).unwrap();
@@ -397,11 +905,49 @@ impl<W: io::Write> LspHandler<W> {
                 let response = self.text_document_highlight(msg.params);
                 self.sender.send_response(msg_id, response);
             }
+            request::DocumentDiagnosticRequest::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<DocumentDiagnosticParams>>(json).unwrap();
+                let response = self.text_document_diagnostic(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::WorkspaceDiagnosticRequest::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<WorkspaceDiagnosticParams>>(json).unwrap();
+                let response = self.workspace_diagnostic(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
             request::DocumentSymbolRequest::METHOD => {
                 let msg = serde_json::from_str::<LspRequest<DocumentSymbolParams>>(json).unwrap();
                 let response = self.text_document_symbol(msg.params);
                 self.sender.send_response(msg.id, response);
             }
+            request::SelectionRangeRequest::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<SelectionRangeParams>>(json).unwrap();
+                let response = self.text_document_selection_range(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::DocumentLinkRequest::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<DocumentLinkParams>>(json).unwrap();
+                let response = self.text_document_link(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::FoldingRangeRequest::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<FoldingRangeParams>>(json).unwrap();
+                let response = self.text_document_folding_range(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::InlayHintRequest::METHOD => {
+                let msg = serde_json::from_str::<LspRequest<InlayHintParams>>(json).unwrap();
+                let response = self.text_document_inlay_hint(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::LinkedEditingRange::METHOD => {
+                let msg =
+                    serde_json::from_str::<LspRequest<LinkedEditingRangeParams>>(json).unwrap();
+                let response = self.text_document_linked_editing_range(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
             "textDocument/hover" => {
                 let msg: LspRequest<TextDocumentPositionParams> =
                     serde_json::from_str(json).unwrap();
@@ -409,6 +955,12 @@ impl<W: io::Write> LspHandler<W> {
                 let response = self.text_document_hover(msg.params);
                 self.sender.send_response(msg_id, response);
             }
+            request::MonikerRequest::METHOD => {
+                let msg: LspRequest<MonikerParams> = serde_json::from_str(json).unwrap();
+                let msg_id = msg.id;
+                let response = self.text_document_moniker(msg.params);
+                self.sender.send_response(msg_id, response);
+            }
             request::PrepareRenameRequest::METHOD => {
                 let msg: LspRequest<TextDocumentPositionParams> =
                     serde_json::from_str(json).unwrap();
@@ -435,19 +987,92 @@ impl<W: io::Write> LspHandler<W> {
                     self.text_document_semantic_tokens_full(msg.params);
                 self.sender.send_response(msg.id, response);
             }
+            request::SemanticTokensFullDeltaRequest::METHOD => {
+                let msg: LspRequest<SemanticTokensDeltaParams> =
+                    serde_json::from_str(json).expect("semantic tokens full delta msg");
+                let response = self.text_document_semantic_tokens_full_delta(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            request::SemanticTokensRangeRequest::METHOD => {
+                let msg: LspRequest<SemanticTokensRangeParams> =
+                    serde_json::from_str(json).expect("semantic tokens range msg");
+                let response = self.text_document_semantic_tokens_range(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
             request::SignatureHelpRequest::METHOD => {
                 let msg: LspRequest<SignatureHelpParams> = serde_json::from_str(json).unwrap();
                 let msg_id = msg.id;
                 let response = self.text_document_signature_help(msg.params);
                 self.sender.send_response(msg_id, response);
             }
+            request::WillRenameFiles::METHOD => {
+                let msg: LspRequest<RenameFilesParams> =
+                    serde_json::from_str(json).expect("workspace/willRenameFiles msg");
+                let response = self.workspace_will_rename_files(msg.params);
+                self.sender.send_response(msg.id, response);
+            }
+            "workspace/didChangeConfiguration" => {
+                let msg: LspNotification<DidChangeConfigurationParams> =
+                    serde_json::from_str(json).expect("workspace/didChangeConfiguration msg");
+                self.workspace_did_change_configuration(msg.params);
+                self.diagnose();
+            }
             "workspace/didChangeWatchedFiles" => {
                 let msg: LspNotification<DidChangeWatchedFilesParams> =
                     serde_json::from_str(json).expect("workspace/didChangeWatchedFiles msg");
                 self.workspace_did_change_watched_files(msg.params);
                 self.diagnose();
             }
-            request::WorkspaceSymbol::METHOD => {
+            request::ExecuteCommand::METHOD => {
+                let msg: LspRequest<ExecuteCommandParams> =
+                    serde_json::from_str(json).expect("workspace/executeCommand msg");
+                match self.workspace_execute_command(msg.params) {
+                    Ok(value) => self.sender.send_response(msg.id, value),
+                    Err(err) => self.sender.send_error_code(
+                        Some(Value::from(msg.id)),
+                        error::INVALID_PARAMS,
+                        &err,
+                    ),
+                }
+            }
+            "ham/moduleVisibility" => {
+                let msg: LspRequest<Option<Value>> =
+                    serde_json::from_str(json).expect("ham/moduleVisibility msg");
+                let response = self.model.module_visibility();
+                self.sender.send_response(msg.id, response);
+            }
+            "ham/symbolStats" => {
+                let msg: LspRequest<Option<Value>> =
+                    serde_json::from_str(json).expect("ham/symbolStats msg");
+                let response = self.model.symbol_stats();
+                self.sender.send_response(msg.id, response);
+            }
+            "ham/workspaceStats" => {
+                let msg: LspRequest<Option<Value>> =
+                    serde_json::from_str(json).expect("ham/workspaceStats msg");
+                let response = self.model.workspace_stats();
+                self.sender.send_response(msg.id, response);
+            }
+            "ham/includeGraph" => {
+                let msg: LspRequest<Option<IncludeGraphParams>> =
+                    serde_json::from_str(json).expect("ham/includeGraph msg");
+                let root_opt = msg.params.and_then(|p| p.text_document).map(|t| t.uri);
+                let response = self.model.include_graph(root_opt);
+                self.sender.send_response(msg.id, response);
+            }
+            "ham/includedBy" => {
+                let msg: LspRequest<IncludedByParams> =
+                    serde_json::from_str(json).expect("ham/includedBy msg");
+                let response = self.model.included_by(msg.params.text_document.uri);
+                self.sender.send_response(msg.id, response);
+            }
+            "ham/buildOptions" => {
+                let msg: LspRequest<Option<Value>> =
+                    serde_json::from_str(json).expect("ham/buildOptions msg");
+                let response = self.model.build_options();
+                self.sender.send_response(msg.id, response);
+            }
+            request::WorkspaceSymbolRequest::METHOD => {
                 let msg: LspRequest<WorkspaceSymbolParams> =
                     serde_json::from_str(json).expect("workspace/symbol msg");
                 let response = self.workspace_symbol(msg.params);
@@ -472,3 +1097,88 @@ impl<W: io::Write> LspHandler<W> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::dummy_root;
+    use serde_json::json;
+
+    #[test]
+    fn test_partial_config_change_keeps_other_settings() {
+        let mut handler = LspHandler::new(LspSender::new(vec![]), LangService::new_standalone());
+        let mut errors = vec![];
+
+        let mut config = init_config::InitConfig::from_value(
+            Some(json!({
+                "lint": { "unused": { "enabled": false } },
+                "inlayHints": { "types": true },
+                "largeFile": { "sizeLimit": 64, "skip": true },
+            })),
+            &mut errors,
+        );
+        handler.apply_config(&mut config);
+
+        // 一部の設定だけが変更される。
+        let mut config = init_config::InitConfig::from_settings(
+            json!({ "hsp3-analyzer-mini": { "largeFile": { "skip": false } } }),
+            &mut errors,
+        );
+        handler.apply_config(&mut config);
+        assert_eq!(errors, Vec::<String>::new());
+
+        let options = handler.model.options();
+        assert!(!options.lint_unused);
+        assert!(options.inlay_hint_types);
+        assert_eq!(options.large_file_size, 64);
+        assert!(!options.skip_large_files);
+    }
+
+    #[test]
+    fn test_incremental_sync() {
+        let mut handler = LspHandler::new(LspSender::new(vec![]), LangService::new_standalone());
+
+        let result = handler.initialize(InitializeParams::default());
+        assert_eq!(
+            result.capabilities.text_document_sync,
+            Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::INCREMENTAL),
+                    ..TextDocumentSyncOptions::default()
+                }
+            ))
+        );
+
+        let uri = Url::from_file_path(dummy_root().join("incremental_sync.hsp")).unwrap();
+        handler.text_document_did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                uri.clone(),
+                "hsp3".into(),
+                1,
+                "#deffunc foo\n".into(),
+            ),
+        });
+
+        // `foo` を `bar` に置き換える。
+        handler.text_document_did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri, 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, 9), Position::new(0, 12))),
+                range_length: None,
+                text: "bar".into(),
+            }],
+        });
+
+        let names = |handler: &mut LspHandler<Vec<u8>>, query: &str| {
+            handler
+                .model
+                .workspace_symbol(query.into())
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&mut handler, "bar"), vec!["bar"]);
+        assert!(names(&mut handler, "foo").is_empty());
+    }
+}
@@ -17,7 +17,7 @@ pub(crate) enum PIncludeKind {
     Addition,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub(crate) enum PDefFuncKind {
     DefFunc,
     DefCFunc,
@@ -34,6 +34,17 @@ impl PDefFuncKind {
             _ => false,
         }
     }
+
+    /// 本体でモジュール変数 (`thismod` やモジュールのフィールド) を使えるか？
+    pub(crate) fn takes_modvar(&self) -> bool {
+        match self {
+            PDefFuncKind::DefFunc | PDefFuncKind::DefCFunc => false,
+            PDefFuncKind::ModFunc
+            | PDefFuncKind::ModCFunc
+            | PDefFuncKind::ModInit
+            | PDefFuncKind::ModTerm => true,
+        }
+    }
 }
 
 #[must_use]
@@ -350,10 +361,8 @@ pub(crate) struct PConstStmt {
     #[allow(unused)]
     pub(crate) keyword: PToken,
     pub(crate) privacy_opt: Option<(PPrivacy, PToken)>,
-    #[allow(unused)]
     pub(crate) ty_opt: Option<(PConstTy, PToken)>,
     pub(crate) name_opt: Option<PToken>,
-    #[allow(unused)]
     pub(crate) init_opt: Option<PExpr>,
 }
 
@@ -400,7 +409,6 @@ pub(crate) struct PEnumStmt {
     pub(crate) name_opt: Option<PToken>,
     #[allow(unused)]
     pub(crate) equal_opt: Option<PToken>,
-    #[allow(unused)]
     pub(crate) init_opt: Option<PExpr>,
 }
 
@@ -408,6 +416,9 @@ pub(crate) struct PEnumStmt {
 pub(crate) struct PParam {
     pub(crate) param_ty_opt: Option<(PParamTy, PToken)>,
     pub(crate) name_opt: Option<PToken>,
+    pub(crate) equal_opt: Option<PToken>,
+    /// `int x = 10` の `10` のような既定値
+    pub(crate) default_opt: Option<PExpr>,
     pub(crate) comma_opt: Option<PToken>,
 }
 
@@ -426,6 +437,10 @@ impl Debug for PParam {
             None => write!(f, "?name? ")?,
         }
 
+        if let Some(default) = &self.default_opt {
+            write!(f, " = {:?}", default)?;
+        }
+
         write!(f, "{}", if self.comma_opt.is_some() { "," } else { "?,?" })
     }
 }
@@ -452,7 +467,6 @@ pub(crate) struct PUseLibStmt {
     pub(crate) hash: PToken,
     #[allow(unused)]
     pub(crate) keyword: PToken,
-    #[allow(unused)]
     pub(crate) file_path_opt: Option<PToken>,
 }
 
@@ -461,7 +475,6 @@ pub(crate) struct PUseLibStmt {
 #[must_use]
 pub(crate) struct PLibFuncStmt {
     pub(crate) hash: PToken,
-    #[allow(unused)]
     pub(crate) keyword: PToken,
     pub(crate) privacy_opt: Option<(PPrivacy, PToken)>,
     pub(crate) name_opt: Option<PToken>,
@@ -496,9 +509,8 @@ pub(crate) struct PComFuncStmt {
     pub(crate) keyword: PToken,
     pub(crate) privacy_opt: Option<(PPrivacy, PToken)>,
     pub(crate) name_opt: Option<PToken>,
-    #[allow(unused)]
+    /// vtable のインデックス
     pub(crate) index_opt: Option<PToken>,
-    #[allow(unused)]
     pub(crate) params: Vec<PParam>,
 }
 
@@ -509,7 +521,6 @@ pub(crate) struct PRegCmdStmt {
     pub(crate) hash: PToken,
     #[allow(unused)]
     pub(crate) keyword: PToken,
-    #[allow(unused)]
     pub(crate) args: Vec<PArg>,
 }
 
@@ -522,7 +533,6 @@ pub(crate) struct PCmdStmt {
     pub(crate) keyword: PToken,
     pub(crate) privacy_opt: Option<(PPrivacy, PToken)>,
     pub(crate) name_opt: Option<PToken>,
-    #[allow(unused)]
     pub(crate) command_id_opt: Option<PToken>,
 }
 
@@ -537,7 +547,6 @@ pub(crate) struct PModuleStmt {
     pub(crate) name_opt: Option<PToken>,
     pub(crate) fields: Vec<PParam>,
     pub(crate) stmts: Vec<PStmt>,
-    #[allow(unused)]
     pub(crate) global_opt: Option<PGlobalStmt>,
     pub(crate) behind: Loc,
 }
@@ -545,7 +554,6 @@ pub(crate) struct PModuleStmt {
 #[derive(Debug)]
 #[must_use]
 pub(crate) struct PGlobalStmt {
-    #[allow(unused)]
     pub(crate) hash: PToken,
     pub(crate) keyword: PToken,
 }
@@ -569,6 +577,21 @@ pub(crate) struct PUnknownPreProcStmt {
     pub(crate) tokens: Vec<PToken>,
 }
 
+impl PUnknownPreProcStmt {
+    pub(crate) fn keyword_text(&self) -> &str {
+        self.tokens.first().map_or("", |t| t.body_text())
+    }
+}
+
+/// 認識できない文 (新しいバージョンの構文など)
+///
+/// 文の終わりまでのトークンをそのまま持つ。
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct PUnknownStmt {
+    pub(crate) tokens: Vec<PToken>,
+}
+
 #[must_use]
 pub(crate) enum PStmt {
     Label(PLabel),
@@ -592,6 +615,7 @@ pub(crate) enum PStmt {
     Global(PGlobalStmt),
     Include(PIncludeStmt),
     UnknownPreProc(PUnknownPreProcStmt),
+    Unknown(PUnknownStmt),
 }
 
 impl Debug for PStmt {
@@ -616,6 +640,7 @@ impl Debug for PStmt {
             PStmt::Global(it) => Debug::fmt(it, f),
             PStmt::Include(it) => Debug::fmt(it, f),
             PStmt::UnknownPreProc(it) => Debug::fmt(it, f),
+            PStmt::Unknown(it) => Debug::fmt(it, f),
         }
     }
 }
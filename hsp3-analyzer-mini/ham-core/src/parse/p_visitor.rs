@@ -97,6 +97,8 @@ pub(crate) trait PVisitor {
             self.on_token(token);
         }
         self.on_token_opt(param.name_opt.as_ref());
+        self.on_token_opt(param.equal_opt.as_ref());
+        self.on_expr_opt(param.default_opt.as_ref());
         self.on_token_opt(param.comma_opt.as_ref());
     }
 
@@ -215,6 +217,7 @@ pub(crate) trait PVisitor {
                 self.on_token(&stmt.hash);
                 self.on_tokens(&stmt.tokens);
             }
+            PStmt::Unknown(stmt) => self.on_tokens(&stmt.tokens),
         }
     }
 
@@ -270,6 +273,13 @@ impl PStmt {
         visitor.on_stmt(self);
         visitor.finish()
     }
+
+    /// 後続する空白を含まない、文の範囲を計算する。
+    pub(crate) fn compute_body_range(&self) -> Range {
+        let mut visitor = VisitorForRange::default();
+        visitor.on_stmt(self);
+        visitor.finish()
+    }
 }
 
 #[derive(Default)]
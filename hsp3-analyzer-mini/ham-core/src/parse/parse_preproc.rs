@@ -3,9 +3,9 @@ use super::{
     parse_context::Px,
     parse_expr::{parse_args, parse_expr},
     parse_stmt::parse_stmt,
-    PCmdStmt, PConstStmt, PConstTy, PDefFuncKind, PDefFuncStmt, PDefineStmt, PEnumStmt,
-    PGlobalStmt, PIncludeKind, PIncludeStmt, PLibFuncStmt, PMacroParam, PModuleStmt, PParam,
-    PParamTy, PPrivacy, PRegCmdStmt, PStmt, PUnknownPreProcStmt, PUseLibStmt,
+    PCmdStmt, PComFuncStmt, PConstStmt, PConstTy, PDefFuncKind, PDefFuncStmt, PDefineStmt,
+    PEnumStmt, PGlobalStmt, PIncludeKind, PIncludeStmt, PLibFuncStmt, PMacroParam, PModuleStmt,
+    PParam, PParamTy, PPrivacy, PRegCmdStmt, PStmt, PUnknownPreProcStmt, PUseComStmt, PUseLibStmt,
 };
 use crate::token::TokenKind;
 
@@ -13,6 +13,14 @@ static DEFFUNC_LIKE_KEYWORDS: &[&str] = &[
     "deffunc", "defcfunc", "modfunc", "modcfunc", "modinit", "modterm",
 ];
 
+/// 構文木では表現しないが、HSP3 のプリプロセッサ命令として知られているもの
+///
+/// ここにない名前のプリプロセッサ命令は、認識できない構文として報告される。
+static KNOWN_UNMODELED_PREPROC_KEYWORDS: &[&str] = &[
+    "if", "ifdef", "ifndef", "else", "endif", "undef", "cmpopt", "packopt", "pack", "epack",
+    "runtime", "bootopt", "aht", "ahtmes", "ahtout",
+];
+
 impl TokenKind {
     fn is_end_of_preproc(self) -> bool {
         match self {
@@ -32,6 +40,13 @@ impl PToken {
     }
 }
 
+impl PUnknownPreProcStmt {
+    /// HSP3 のプリプロセッサ命令として知られているか？
+    pub(crate) fn is_known(&self) -> bool {
+        KNOWN_UNMODELED_PREPROC_KEYWORDS.contains(&self.keyword_text())
+    }
+}
+
 fn eat_ident(pattern: &str, px: &mut Px) -> Option<PToken> {
     if px.next() == TokenKind::Ident && px.next_token().body_text() == pattern {
         Some(px.bump())
@@ -220,18 +235,28 @@ fn parse_deffunc_params(px: &mut Px) -> Vec<PParam> {
                 params.push(PParam {
                     param_ty_opt: None,
                     name_opt: None,
+                    equal_opt: None,
+                    default_opt: None,
                     comma_opt: Some(comma),
                 });
             }
             TokenKind::Ident => {
                 let param_ty_opt = parse_param_ty(px);
                 let name_opt = px.eat(TokenKind::Ident);
+                let equal_opt = px.eat(TokenKind::Equal);
+                let default_opt = if equal_opt.is_some() {
+                    parse_expr(px)
+                } else {
+                    None
+                };
                 let comma_opt = px.eat(TokenKind::Comma);
                 let comma_seen = comma_opt.is_some();
 
                 params.push(PParam {
                     param_ty_opt,
                     name_opt,
+                    equal_opt,
+                    default_opt,
                     comma_opt,
                 });
 
@@ -333,6 +358,44 @@ fn parse_lib_func_stmt(hash: PToken, px: &mut Px) -> PLibFuncStmt {
     }
 }
 
+fn parse_usecom_stmt(hash: PToken, px: &mut Px) -> PUseComStmt {
+    assert_eq!(px.next_token().body_text(), "usecom");
+
+    let keyword = px.bump();
+    let privacy_opt = parse_privacy(px);
+    let name_opt = px.eat(TokenKind::Ident);
+    let args = parse_args(px);
+    parse_end_of_preproc(px);
+
+    PUseComStmt {
+        hash,
+        keyword,
+        privacy_opt,
+        name_opt,
+        args,
+    }
+}
+
+fn parse_comfunc_stmt(hash: PToken, px: &mut Px) -> PComFuncStmt {
+    assert_eq!(px.next_token().body_text(), "comfunc");
+
+    let keyword = px.bump();
+    let privacy_opt = parse_privacy(px);
+    let name_opt = px.eat(TokenKind::Ident);
+    let index_opt = px.eat(TokenKind::Number);
+    let params = parse_deffunc_params(px);
+    parse_end_of_preproc(px);
+
+    PComFuncStmt {
+        hash,
+        keyword,
+        privacy_opt,
+        name_opt,
+        index_opt,
+        params,
+    }
+}
+
 fn parse_regcmd_stmt(hash: PToken, px: &mut Px) -> PRegCmdStmt {
     assert_eq!(px.next_token().body_text(), "regcmd");
 
@@ -447,6 +510,8 @@ pub(crate) fn parse_preproc_stmt(px: &mut Px) -> Option<PStmt> {
         "modterm" => PStmt::DefFunc(parse_deffunc_like_stmt(hash, PDefFuncKind::ModTerm, px)),
         "uselib" => PStmt::UseLib(parse_uselib_stmt(hash, px)),
         "func" | "cfunc" => PStmt::LibFunc(parse_lib_func_stmt(hash, px)),
+        "usecom" => PStmt::UseCom(parse_usecom_stmt(hash, px)),
+        "comfunc" => PStmt::ComFunc(parse_comfunc_stmt(hash, px)),
         "regcmd" => PStmt::RegCmd(parse_regcmd_stmt(hash, px)),
         "cmd" => PStmt::Cmd(parse_cmd_stmt(hash, px)),
         "module" => PStmt::Module(parse_module_stmt(hash, px)),
@@ -4,6 +4,7 @@ use super::{
     parse_expr::{parse_args, parse_atomic_expr, parse_compound, parse_expr, parse_label},
     parse_preproc::parse_preproc_stmt,
     PAssignStmt, PBlock, PCommandStmt, PIfStmt, PInvokeStmt, PJumpModifier, PRoot, PStmt, PToken,
+    PUnknownStmt,
 };
 use crate::token::TokenKind;
 
@@ -238,11 +239,24 @@ pub(crate) fn parse_stmt(px: &mut Px) -> Option<PStmt> {
         TokenKind::If => parse_if_stmt(px).map(PStmt::If),
         TokenKind::Star => parse_label(px).map(PStmt::Label),
         TokenKind::Hash => parse_preproc_stmt(px),
-        _ => return None,
+        TokenKind::Else => return None,
+        kind if kind.is_end_of_stmt() => return None,
+        _ => Some(PStmt::Unknown(parse_unknown_stmt(px))),
     };
     stmt_opt
 }
 
+/// 文として解釈できないトークン列を、文の終わりまでまとめて読み飛ばす。
+///
+/// 1トークンずつ読み飛ばすと、残りのトークンが文の先頭とみなされて後続の解析が乱れるため。
+fn parse_unknown_stmt(px: &mut Px) -> PUnknownStmt {
+    let mut tokens = vec![px.bump()];
+    while !px.next().is_end_of_stmt() {
+        tokens.push(px.bump());
+    }
+    PUnknownStmt { tokens }
+}
+
 pub(crate) fn parse_root(tokens: Vec<PToken>) -> PRoot {
     let mut px = Px::new(tokens);
     let mut stmts = vec![];
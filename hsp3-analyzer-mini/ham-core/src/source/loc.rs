@@ -37,12 +37,10 @@ impl Loc {
         self.range.end()
     }
 
-    #[cfg(unused)]
     pub(crate) fn start_row(&self) -> usize {
         self.range.start().row as usize
     }
 
-    #[cfg(unused)]
     pub(crate) fn end_row(&self) -> usize {
         self.range.end().row as usize
     }
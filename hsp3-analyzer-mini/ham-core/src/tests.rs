@@ -417,3 +417,10 @@ fn formatting_blank_test() {
 
     expected.assert_eq(&actual);
 }
+
+#[test]
+fn analysis_snapshot_basic_test() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../tests/snapshot/basic");
+    let expected = expect_file!["../../tests/snapshot/basic.expected.txt"];
+    expected.assert_eq(&crate::analysis_snapshot(&dir));
+}
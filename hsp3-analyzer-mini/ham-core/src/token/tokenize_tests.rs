@@ -2,8 +2,9 @@
 #![cfg(test)]
 
 use crate::{
+    lang_service::{docs::read_source_file, SourceEncoding},
     source::DocId,
-    utils::{rc_str::RcStr, read_file::read_file},
+    utils::rc_str::RcStr,
 };
 use std::{fs, path::PathBuf, rc::Rc};
 
@@ -44,7 +45,7 @@ fn tokenize_standard_files() {
         {
             let text = Rc::get_mut(&mut text).unwrap();
             text.clear();
-            if !read_file(&path, text) {
+            if read_source_file(&path, SourceEncoding::Auto, text).is_none() {
                 eprintln!("couldn't read {:?}", path);
                 continue;
             }
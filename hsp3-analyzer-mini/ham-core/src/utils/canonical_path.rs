@@ -0,0 +1,194 @@
+//! ファイルパスの正規化
+//!
+//! 同じファイルを指す異なる表現を1つに揃える。
+//!
+//! - `a/../b` と `b` (`.` や `..` の除去)
+//! - シンボリックリンクとそのリンク先 (循環していても止まる。)
+//! - Windows の `\\?\C:\` と `C:\`、`\\?\UNC\server\share` と `\\server\share`
+//! - Windows の `subst` で割り当てたドライブと元のディレクトリ (`canonicalize` が解決する。)
+//! - Windows での大文字・小文字の違い (比較するときだけ無視する。)
+
+use std::{
+    borrow::Cow,
+    env::current_dir,
+    ffi::OsString,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// シンボリックリンクをたどる回数の上限 (リンクが循環しているときに止めるため。)
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// `.` や `..` を取り除く。(ファイルシステムにアクセスしない。)
+pub(crate) fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Windows の拡張パスの接頭辞を取り除き、通常の表現に戻す。
+///
+/// (`canonicalize` は Windows で `\\?\C:\...` の形のパスを返すが、エディタから送られるURIはこの形ではない。)
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let s = match path.to_str() {
+        Some(it) => it,
+        None => return path,
+    };
+
+    if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", rest));
+    }
+
+    if let Some(rest) = s.strip_prefix(r"\\?\") {
+        let b = rest.as_bytes();
+        if b.len() >= 2 && b[0].is_ascii_alphabetic() && b[1] == b':' {
+            return PathBuf::from(rest);
+        }
+    }
+
+    path
+}
+
+/// 存在する最も深い祖先を正規化して、存在しない残りの部分をつなげる。
+fn canonicalize_ancestors(path: &Path) -> Option<PathBuf> {
+    let mut rest: Vec<OsString> = vec![];
+    let mut base = path;
+    loop {
+        if let Ok(mut canonical_path) = base.canonicalize() {
+            for name in rest.into_iter().rev() {
+                canonical_path.push(name);
+            }
+            return Some(canonical_path);
+        }
+
+        rest.push(base.file_name()?.to_owned());
+        base = base.parent()?;
+    }
+}
+
+/// ファイルパスを正規化する。
+///
+/// 存在しないファイルのパスも、存在する部分だけ正規化して使う。
+pub(crate) fn canonicalize_path(path: &Path) -> PathBuf {
+    // 絶対パスでなければ、カレントディレクトリと繋ぐ。
+    let mut path = match current_dir() {
+        Ok(dir) if !path.is_absolute() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+
+    // canonicalize に成功するなら、これを正規形と信じて使う。
+    // 失敗したとき、リンク先が存在しないシンボリックリンクなら、リンク先のパスで試しなおす。
+    for _ in 0..MAX_SYMLINK_FOLLOWS {
+        if let Ok(canonical_path) = path.canonicalize() {
+            return strip_verbatim_prefix(canonical_path);
+        }
+
+        let target = match fs::read_link(&path) {
+            Ok(it) => it,
+            Err(_) => break,
+        };
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+
+    let path = normalize_lexically(&path);
+    match canonicalize_ancestors(&path) {
+        Some(canonical_path) => strip_verbatim_prefix(canonical_path),
+        None => path,
+    }
+}
+
+/// 大文字・小文字を区別しないファイルシステムでは、比較に使う表現を小文字に揃える。
+pub(crate) fn fold_case(s: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        Cow::Owned(s.to_lowercase())
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempDir;
+
+    #[test]
+    fn test_normalize_lexically() {
+        assert_eq!(
+            normalize_lexically(Path::new("/a/./b/../c/d.hsp")),
+            PathBuf::from("/a/c/d.hsp")
+        );
+    }
+
+    #[test]
+    fn test_strip_verbatim_prefix() {
+        let strip = |s: &str| strip_verbatim_prefix(PathBuf::from(s));
+        assert_eq!(strip(r"\\?\C:\hsp\a.hsp"), PathBuf::from(r"C:\hsp\a.hsp"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\a.hsp"),
+            PathBuf::from(r"\\server\share\a.hsp")
+        );
+        assert_eq!(
+            strip(r"\\?\Volume{x}\a.hsp"),
+            PathBuf::from(r"\\?\Volume{x}\a.hsp")
+        );
+        assert_eq!(strip("/hsp/a.hsp"), PathBuf::from("/hsp/a.hsp"));
+    }
+
+    #[test]
+    fn test_canonicalize_path() {
+        let temp = TempDir::new("canonicalize-path");
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.hsp"), "").unwrap();
+        let dir = dir.canonicalize().unwrap();
+
+        assert_eq!(
+            canonicalize_path(&dir.join("sub/../sub/./a.hsp")),
+            dir.join("sub/a.hsp")
+        );
+
+        // 存在しないファイルも、存在するディレクトリの部分は正規化する。
+        assert_eq!(
+            canonicalize_path(&dir.join("sub/../new/b.hsp")),
+            dir.join("new/b.hsp")
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            symlink(dir.join("sub"), dir.join("link")).unwrap();
+            assert_eq!(
+                canonicalize_path(&dir.join("link/a.hsp")),
+                dir.join("sub/a.hsp")
+            );
+            assert_eq!(
+                canonicalize_path(&dir.join("link/new.hsp")),
+                dir.join("sub/new.hsp")
+            );
+
+            // リンク先が存在しないシンボリックリンク
+            symlink(dir.join("sub/dangling.hsp"), dir.join("dangling")).unwrap();
+            assert_eq!(
+                canonicalize_path(&dir.join("dangling")),
+                dir.join("sub/dangling.hsp")
+            );
+
+            // 循環しているシンボリックリンク
+            symlink(dir.join("loop2"), dir.join("loop1")).unwrap();
+            symlink(dir.join("loop1"), dir.join("loop2")).unwrap();
+            assert_eq!(canonicalize_path(&dir.join("loop1")), dir.join("loop1"));
+        }
+    }
+}
@@ -1,6 +1,7 @@
+use super::canonical_path::{canonicalize_path, fold_case};
 use lsp_types::Url;
 use std::{
-    env::current_dir,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
 };
 
@@ -8,67 +9,68 @@ use std::{
 //
 // 正規化について:
 // `a/../b` と `b` のように、等価だが異なる表現を統一的な表現に揃える。
-// (シンボリックリンクの展開なども行う。)
+// (シンボリックリンクの展開なども行う。詳しくは `canonical_path` を参照。)
 //
-// ファイルパスの参照先が存在しない場合、
+// ファイルパスの参照先が存在しない場合、存在する部分だけ正規化する。
 //
 // 正規化されていない URL をマップのキーに使ってしまうと、
 // 単一のファイルに対して複数のデータが登録できてしまい、不具合の原因になる。
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+//
+// Windows では大文字・小文字の違いを無視して比較する。
+#[derive(Clone, Debug)]
 pub(crate) struct CanonicalUri {
     uri: Url,
 }
 
 impl CanonicalUri {
     pub(crate) fn from_file_path(path: &Path) -> Option<Self> {
-        let to_uri = |path: &Path| {
-            Url::from_file_path(path)
-                .ok()
-                .map(|uri| CanonicalUri { uri })
-        };
-
-        // canonicalize に成功するなら、これを正規形と信じて使う。
-        if let Ok(canonical_path) = path.canonicalize() {
-            return to_uri(&canonical_path);
-        }
-
-        // 絶対パスでなければ、カレントディレクトリと繋ぐ。
-        // 念のため再び canonicalize を試してから、絶対パスを URI にする。
-        if !path.is_absolute() {
-            if let Some(path) = current_dir().ok().map(|current_dir| current_dir.join(path)) {
-                if let Ok(canonical_path) = path.canonicalize() {
-                    return to_uri(&canonical_path);
-                }
-
-                return to_uri(&path);
-            }
-        }
-
-        // canonicalize できない絶対パスというのはよく分からないが、
-        // 例えばファイルパスを取得した直後にファイルが削除された場合などに発生しうる。
-        // (存在しないファイルパスは canonicalize に失敗するはず。)
-        // 正規形ではないかもしれないが、そのまま URI として使う。
-        to_uri(path)
+        Url::from_file_path(canonicalize_path(path))
+            .ok()
+            .map(|uri| CanonicalUri { uri })
     }
 
+    /// URI を正規化する。
+    ///
+    /// `untitled:` のようにファイルを指さない URI (保存されていないドキュメントなど) は、そのまま使う。
     pub(crate) fn from_url(uri: &Url) -> Self {
-        let uri = match uri
-            .to_file_path()
+        if uri.scheme() != "file" {
+            return CanonicalUri {
+                uri: uri.to_owned(),
+            };
+        }
+
+        uri.to_file_path()
             .ok()
-            .and_then(|file_path| file_path.canonicalize().ok())
-            .and_then(|canonical_path| Url::from_file_path(canonical_path).ok())
-        {
-            Some(uri) => uri,
-            None => uri.to_owned(),
-        };
-        CanonicalUri { uri }
+            .and_then(|path| Self::from_file_path(&path))
+            .unwrap_or_else(|| CanonicalUri {
+                uri: uri.to_owned(),
+            })
     }
 
     pub(crate) fn into_url(self) -> Url {
         self.uri
     }
 
+    /// ファイルを指す URI か？
+    pub(crate) fn is_file(&self) -> bool {
+        self.uri.scheme() == "file"
+    }
+
     pub(crate) fn to_file_path(&self) -> Option<PathBuf> {
         self.uri.to_file_path().ok()
     }
 }
+
+impl PartialEq for CanonicalUri {
+    fn eq(&self, other: &Self) -> bool {
+        fold_case(self.uri.as_str()) == fold_case(other.uri.as_str())
+    }
+}
+
+impl Eq for CanonicalUri {}
+
+impl Hash for CanonicalUri {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fold_case(self.uri.as_str()).hash(state);
+    }
+}
@@ -1,45 +1,84 @@
-use encoding::{DecoderTrap, Encoding};
+use encoding::{DecoderTrap, EncoderTrap, Encoding};
 use std::{fs, path::Path, str};
 
-/// テキストファイルを shift_jis または UTF-8 として読む。
-pub(crate) fn read_file(file_path: &Path, out: &mut String) -> bool {
-    // バイナリで読む。
-    let contents = match fs::read(file_path).ok() {
-        None => return false,
-        Some(it) => it,
-    };
-
-    // 可能ならUTF-8として読む。
-    match str::from_utf8(&contents) {
-        Ok(text) => {
-            *out += text;
-            return true;
+/// `#cmpopt utf8` が書かれているか？ (HSP3.6 の UTF-8 版ランタイムを使うスクリプトにつけるもの)
+pub(crate) fn has_utf8_marker(contents: &[u8]) -> bool {
+    contents.split(|&b| b == b'\n').any(|line| {
+        let line = match str::from_utf8(line) {
+            Ok(it) => it,
+            Err(_) => return false,
+        };
+        let mut words = line.split_ascii_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("#cmpopt"), Some(opt), value) => {
+                opt.eq_ignore_ascii_case("utf8") && value != Some("0")
+            }
+            _ => false,
         }
-        Err(_) => {}
+    })
+}
+
+fn decode_sjis(contents: &[u8], out: &mut String) -> bool {
+    let len = out.len();
+    let ok = encoding::all::WINDOWS_31J
+        .decode_to(contents, DecoderTrap::Strict, out)
+        .is_ok();
+    if !ok {
+        out.truncate(len);
     }
+    ok
+}
 
-    // shift_jisから変換する。
+/// 文字を shift_jis (CP932) で表せるか？ (表せない文字はコンパイル時に `?` に置き換わる。)
+pub(crate) fn sjis_encodable(c: char) -> bool {
+    let mut buf = [0; 4];
     encoding::all::WINDOWS_31J
-        .decode_to(&contents, DecoderTrap::Strict, out)
+        .encode(c.encode_utf8(&mut buf), EncoderTrap::Strict)
         .is_ok()
 }
 
-/// テキストファイルを可能ならshift_jisとして読み、ダメだったらUTF-8として読む。
-pub(crate) fn read_sjis_file(file_path: &Path, out: &mut String) -> bool {
-    debug_assert_eq!(out.len(), 0);
+/// UTF-8 の BOM があれば、それを取り除いた残りを返す。
+pub(crate) fn strip_utf8_bom(contents: &[u8]) -> Option<&[u8]> {
+    contents.strip_prefix(b"\xEF\xBB\xBF")
+}
+
+/// ファイルの先頭付近にあるモードライン (`; ham: encoding=utf-8` のようなコメント) から、
+/// 指定された文字コードの名前を探す。
+pub(crate) fn find_modeline_encoding(contents: &[u8]) -> Option<String> {
+    contents.split(|&b| b == b'\n').take(5).find_map(|line| {
+        let line = str::from_utf8(line).ok()?.trim();
+        let comment = line.strip_prefix(';').or_else(|| line.strip_prefix("//"))?;
+        let options = comment.trim_start().strip_prefix("ham:")?;
+        options
+            .split(|c: char| c.is_ascii_whitespace() || c == ',')
+            .find_map(|option| option.strip_prefix("encoding="))
+            .map(|name| name.to_ascii_lowercase())
+    })
+}
 
-    let contents = match fs::read(file_path).ok() {
-        None => return false,
-        Some(it) => it,
-    };
+/// バイト列を UTF-8 とみなすべきか推測する。
+///
+/// UTF-8 として正しくても、2バイトの文字しか含まないときは shift_jis の半角カナの並びである可能性がある。
+/// そのため、`#cmpopt utf8` があるか、3バイト以上の文字 (日本語など) を含むときだけ UTF-8 とみなす。
+pub(crate) fn guess_utf8(contents: &[u8]) -> bool {
+    match str::from_utf8(contents) {
+        Ok(text) => {
+            text.chars().any(|c| c.len_utf8() >= 3)
+                || has_utf8_marker(contents)
+                || !decode_sjis(contents, &mut String::new())
+        }
+        // `#cmpopt utf8` があるなら、不正なバイトを含んでいても UTF-8 として読む。
+        Err(_) => has_utf8_marker(contents),
+    }
+}
 
-    let result = encoding::all::WINDOWS_31J.decode_to(&contents, DecoderTrap::Strict, out);
-    if result.is_ok() {
+/// バイト列を shift_jis として解釈する。ダメだったら UTF-8 として解釈する。
+pub(crate) fn decode_sjis_or_utf8(contents: &[u8], out: &mut String) -> bool {
+    if decode_sjis(contents, out) {
         return true;
     }
-    out.clear();
 
-    match str::from_utf8(&contents) {
+    match str::from_utf8(contents) {
         Ok(text) => {
             *out += text;
             true
@@ -47,3 +86,99 @@ pub(crate) fn read_sjis_file(file_path: &Path, out: &mut String) -> bool {
         Err(_) => false,
     }
 }
+
+/// バイト列を shift_jis として解釈する。不正なバイトは置換文字になる。
+///
+/// 不正なバイトがなければ true を返す。
+pub(crate) fn decode_sjis_lossy(contents: &[u8], out: &mut String) -> bool {
+    if decode_sjis(contents, out) {
+        return true;
+    }
+
+    let _ = encoding::all::WINDOWS_31J.decode_to(contents, DecoderTrap::Replace, out);
+    false
+}
+
+/// バイト列を UTF-8 として解釈する。不正なバイトは置換文字になる。
+///
+/// 不正なバイトがなければ true を返す。
+pub(crate) fn decode_utf8_lossy(contents: &[u8], out: &mut String) -> bool {
+    let text = String::from_utf8_lossy(contents);
+    let ok = matches!(text, std::borrow::Cow::Borrowed(_));
+    *out += &text;
+    ok
+}
+
+fn read_with(file_path: &Path, out: &mut String, decode: fn(&[u8], &mut String) -> bool) -> bool {
+    // バイナリで読む。
+    match fs::read(file_path).ok() {
+        None => false,
+        Some(contents) => decode(&contents, out),
+    }
+}
+
+/// テキストファイルを可能ならshift_jisとして読み、ダメだったらUTF-8として読む。
+pub(crate) fn read_sjis_file(file_path: &Path, out: &mut String) -> bool {
+    debug_assert_eq!(out.len(), 0);
+    read_with(file_path, out, decode_sjis_or_utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auto(contents: &[u8]) -> String {
+        let mut out = String::new();
+        let ok = if guess_utf8(contents) {
+            decode_utf8_lossy(contents, &mut out)
+        } else {
+            decode_sjis_lossy(contents, &mut out)
+        };
+        assert!(ok);
+        out
+    }
+
+    #[test]
+    fn test_guess_utf8() {
+        // UTF-8 の日本語
+        assert_eq!(auto("mes \"日本語\"".as_bytes()), "mes \"日本語\"");
+
+        // shift_jis の日本語 (UTF-8 として不正)
+        assert_eq!(auto(b"mes \"\x93\xfa\x96\x7b\x8c\xea\""), "mes \"日本語\"");
+
+        // shift_jis の半角カナ「ﾃｽ」は、UTF-8 としても正しいが shift_jis として読む。
+        assert_eq!(auto(b"mes \"\xc3\xbd\""), "mes \"ﾃｽ\"");
+
+        // `#cmpopt utf8` があれば UTF-8 として読む。
+        assert_eq!(
+            auto(b"#cmpopt utf8 1\nmes \"\xc3\xbd\""),
+            "#cmpopt utf8 1\nmes \"ý\""
+        );
+    }
+
+    #[test]
+    fn test_sjis_encodable() {
+        for c in ['a', 'あ', '漢', 'ｱ', '①', '～'] {
+            assert!(sjis_encodable(c), "{:?}", c);
+        }
+        for c in ['😀', '©', '\u{2014}', '们'] {
+            assert!(!sjis_encodable(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn test_find_modeline_encoding() {
+        assert_eq!(
+            find_modeline_encoding(b"; ham: encoding=UTF-8\nmes 1\n").as_deref(),
+            Some("utf-8")
+        );
+        assert_eq!(
+            find_modeline_encoding(b"#include \"a.as\"\n// ham: foo, encoding=sjis\n").as_deref(),
+            Some("sjis")
+        );
+        assert_eq!(
+            find_modeline_encoding(b"mes \"; ham: encoding=utf8\"\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! テストで共通して使う補助関数
+
+use lsp_types::Url;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// 存在しないディレクトリ。ファイルとしては存在しないドキュメントのパスを作るのに使う。
+pub(crate) fn dummy_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(".no_exist")
+}
+
+/// `dummy_root` の中にあるファイルのURI
+pub(crate) fn dummy_url(s: &str) -> Url {
+    Url::from_file_path(dummy_root().join(s)).unwrap()
+}
+
+/// テスト用の一時ディレクトリ。ドロップ時に削除される。
+///
+/// テストは並行に実行されるため、ディレクトリ名にはプロセスIDと連番をつけて衝突を避ける。
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("ham-core-{}-{}-{}", name, process::id(), n));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
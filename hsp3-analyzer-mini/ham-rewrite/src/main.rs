@@ -11,24 +11,49 @@ fn print_help() {
 HSPのスクリプトのコメントのスタイルを変更します。
 スクリプトのエンコーディングはUTF-8である必要があります。
 
+OPTIONS:
+    --replace-in-strings PATTERN REPLACEMENT
+        コメントのスタイルを変更する代わりに、文字列リテラルの中身だけを
+        正規表現 PATTERN で検索して REPLACEMENT に置換します。
+        (識別子やコメントは変更されません。)
+
 EXAMPLE:
 (1)
     {name} a.hsp b.hsp c.hsp
 
 (2)
-    cat input.hsp | {name} - >output.hsp"#,
+    cat input.hsp | {name} - >output.hsp
+
+(3)
+    {name} --replace-in-strings "^img/" "assets/img/" a.hsp b.hsp"#,
         name = env!("CARGO_PKG_NAME"),
         version = env!("CARGO_PKG_VERSION")
     );
 }
 
+/// 文字列リテラルの置換のパラメータ (パターン, 置換後の文字列)
+type ReplaceInStrings = Option<(String, String)>;
+
+fn rewrite(text: String, replace_in_strings: &ReplaceInStrings) -> String {
+    match replace_in_strings {
+        Some((pattern, replacement)) => ham_core::replace_in_strings_fn(text, pattern, replacement)
+            .unwrap_or_else(|err| {
+                eprintln!("正規表現が不正です。{}", err);
+                std::process::exit(1)
+            }),
+        None => ham_core::rewrite_fn(text),
+    }
+}
+
 fn main() {
     let mut count = 0;
-    for arg in std::env::args().skip(1) {
+    let mut replace_in_strings: ReplaceInStrings = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
         if arg == "-" {
             let mut buf = String::with_capacity(4096);
             std::io::stdin().read_to_string(&mut buf).unwrap();
-            let buf = ham_core::rewrite_fn(buf);
+            let buf = rewrite(buf, &replace_in_strings);
             std::io::stdout().write_all(buf.as_bytes()).unwrap();
             count += 1;
             continue;
@@ -44,6 +69,16 @@ fn main() {
                     println!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
                     std::process::exit(0);
                 }
+                "--replace-in-strings" => match (args.next(), args.next()) {
+                    (Some(pattern), Some(replacement)) => {
+                        replace_in_strings = Some((pattern, replacement));
+                        continue;
+                    }
+                    _ => {
+                        eprintln!("--replace-in-strings にはパターンと置換後の文字列が必要です。");
+                        std::process::exit(1);
+                    }
+                },
                 _ => {}
             }
             eprintln!("不明なパラメータです。'{}'", arg);
@@ -53,7 +88,7 @@ fn main() {
         {
             let filename = arg;
             let contents = std::fs::read_to_string(&filename).expect("read file");
-            let contents = ham_core::rewrite_fn(contents);
+            let contents = rewrite(contents, &replace_in_strings);
             std::fs::write(&filename, &contents).expect("write file");
             count += 1;
         }
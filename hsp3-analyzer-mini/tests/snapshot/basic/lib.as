@@ -0,0 +1,13 @@
+#module counter
+
+#deffunc counter_add int n, int step = 1
+	total += n * step
+	return
+
+#defcfunc counter_get
+	return total
+
+#global
+
+#const global MAX_COUNT 10
+#define ctype label_of(%1) ("#" + str(%1))
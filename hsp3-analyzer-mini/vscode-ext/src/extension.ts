@@ -10,8 +10,8 @@
 
 import * as fs from "fs/promises"
 import { watch, FSWatcher } from "fs"
-import { ExtensionContext, workspace, window } from "vscode"
-import { Disposable, LanguageClient, LanguageClientOptions, ServerOptions } from "vscode-languageclient/node"
+import { commands, ExtensionContext, Uri, workspace, window } from "vscode"
+import { Disposable, LanguageClient, LanguageClientOptions, Location, Position, ServerOptions } from "vscode-languageclient/node"
 
 /** 開発モード */
 const DEV = process.env["HSP3_ANALYZER_MINI_DEV"] === "1"
@@ -67,9 +67,21 @@ const getHsp3Root = () => {
 const lintIsEnabled = () =>
   workspace.getConfiguration("hsp3-analyzer-mini").get<boolean>("lint-enabled") ?? true
 
+const lintUnusedEnabled = () =>
+  workspace.getConfiguration("hsp3-analyzer-mini").get<boolean>("lint.unused") ?? true
+
 const documentSymbolEnabled = () =>
   workspace.getConfiguration("hsp3-analyzer-mini").get<boolean>("documentSymbol.enabled") === true
 
+const inlayHintTypesEnabled = () =>
+  workspace.getConfiguration("hsp3-analyzer-mini").get<boolean>("inlayHints.types") === true
+
+const getIncludePaths = () =>
+  workspace.getConfiguration("hsp3-analyzer-mini").get<string[]>("includePaths") ?? []
+
+const getTarget = () =>
+  workspace.getConfiguration("hsp3-analyzer-mini").get<string>("target") || null
+
 // -----------------------------------------------
 // LSPクライアント
 // -----------------------------------------------
@@ -91,19 +103,82 @@ const newLspClient = (lspBin: string): LanguageClient => {
   const clientOptions: LanguageClientOptions = {
     documentSelector: [
       { scheme: "file", language: "hsp3" },
+      // 保存されていないドキュメント
+      { scheme: "untitled", language: "hsp3" },
     ],
     synchronize: {
       // `workspace/didChangeWatchedFiles` のための監視対象
-      fileEvents: workspace.createFileSystemWatcher("**/*.hsp"),
+      fileEvents: workspace.createFileSystemWatcher("**/*.{hsp,as}"),
+      // 設定が変更されたら `workspace/didChangeConfiguration` で通知する
+      configurationSection: "hsp3-analyzer-mini",
     },
     initializationOptions: {
       documentSymbol: { enabled: documentSymbolEnabled() },
+      lint: { unused: { enabled: lintUnusedEnabled() } },
+      inlayHints: { types: inlayHintTypesEnabled() },
+      includePaths: getIncludePaths(),
     },
   }
 
   return new LanguageClient("hsp3-analyzer-mini", "HSP3アナライザー・ミニ", serverOptions, clientOptions)
 }
 
+// -----------------------------------------------
+// コマンド
+// -----------------------------------------------
+
+/**
+ * 参照の一覧を表示するコマンド。(LSPサーバーのコードレンズが使う。ham-core の code_lens.rs を参照。)
+ *
+ * `editor.action.showReferences` は VSCode の Uri や Position を引数にとるので、LSP の値から変換して渡す。
+ */
+const SHOW_REFERENCES_COMMAND = "hsp3-analyzer-mini.showReferences"
+
+/**
+ * スクリプトを実行するランタイムの名前を返すコマンド。(デバッガーの拡張機能が起動時に使う。)
+ *
+ * 引数はエントリーポイントのファイルパス。LSPサーバーに `ham/buildOptions` を要求して、
+ * そのプロジェクトの `#runtime` を調べる。(ham-core の build_options.rs を参照。)
+ * 見つからなければ設定の `target` を返す。
+ */
+const GET_RUNTIME_COMMAND = "hsp3-analyzer-mini.getRuntime"
+
+/** `ham/buildOptions` リクエストの結果 (ham-core の build_options.rs を参照。) */
+interface ProjectBuildOptions {
+  entryPoint: string
+  runtime: string | null
+}
+
+const registerCommands = (context: ExtensionContext, getClient: () => LanguageClient | null): void => {
+  context.subscriptions.push(
+    commands.registerCommand(SHOW_REFERENCES_COMMAND, async (uri: string, position: Position, locations: Location[]) => {
+      const client = getClient()
+      if (client == null) {
+        return
+      }
+
+      const c = client.protocol2CodeConverter
+      await commands.executeCommand(
+        "editor.action.showReferences",
+        c.asUri(uri),
+        c.asPosition(position),
+        locations.map(location => c.asLocation(location)),
+      )
+    }),
+    commands.registerCommand(GET_RUNTIME_COMMAND, async (program: string): Promise<string | null> => {
+      const client = getClient()
+      if (client == null) {
+        return getTarget()
+      }
+
+      const projects = await client.sendRequest<ProjectBuildOptions[]>("ham/buildOptions", null)
+      const entryPoint = Uri.file(program).toString()
+      const project = projects.find(p => Uri.parse(p.entryPoint).toString() === entryPoint)
+      return project?.runtime ?? getTarget()
+    }),
+  )
+}
+
 // -----------------------------------------------
 // 開発者モード
 // -----------------------------------------------
@@ -161,6 +236,7 @@ const dev = (context: ExtensionContext): void => {
 
   const client = newLspClient(lspBackupBin)
   context.subscriptions.push({ dispose: () => client.stop() })
+  registerCommands(context, () => client)
 
   const waitClientStateChange = () => new Promise<void>(resolve => {
     const h = client.onDidChangeState(() => {
@@ -241,6 +317,8 @@ export const activate = async (context: ExtensionContext): Promise<void> => {
     return
   }
 
+  registerCommands(context, () => sClient)
+
   const lspBin = getLspBin(context)
   const client = newLspClient(lspBin)
   sClient = client
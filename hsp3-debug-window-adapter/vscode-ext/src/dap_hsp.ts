@@ -24,6 +24,13 @@ interface LaunchRequestArguments extends DebugProtocol.LaunchRequestArguments {
     /** 設定項目 'utf8Support' の値 */
     utf8Support: string
 
+    /**
+     * スクリプトを実行するランタイムの名前 (`#runtime` で指定されたもの)
+     *
+     * 省略時はビルダーの出力から特定する。
+     */
+    runtime?: string
+
     /**
      * デバッグアダプターのあるディレクトリ (絶対パス)
      *
@@ -129,7 +136,7 @@ const compileBuilder = async (hsp3Root: string, distDir: string): Promise<string
 /**
  * スクリプトをコンパイルして、オブジェクトファイルを生成する。
  */
-const compileHsp = async (program: string, hsp3Root: string, utf8Support: string, distDir: string) => {
+const compileHsp = async (program: string, hsp3Root: string, utf8Support: string, distDir: string, runtime: string | undefined) => {
     const hsp3clExe = path.join(hsp3Root, "hsp3cl.exe")
     const builderAx = await compileBuilder(hsp3Root, distDir)
 
@@ -176,10 +183,12 @@ const compileHsp = async (program: string, hsp3Root: string, utf8Support: string
         output = "[STDOUT]\r\n" + stdout + "\r\n[STDERR]\r\n" + stderr
     }
 
-    // ビルダーの出力から、使うランタイムを特定する。
+    // 使うランタイムを特定する。指定されていなければ、ビルダーの出力から探す。
     const RUNTIME_REGEXP = /#Use runtime "([a-zA-Z_0-9.]+)"/
     let runtimeName = "hsp3.exe"
-    {
+    if (runtime) {
+        runtimeName = runtime.endsWith(".exe") ? runtime : runtime + ".exe"
+    } else {
         const m = output.match(RUNTIME_REGEXP)
         writeTrace("runtime search", m)
         if (m && m.length >= 1) {
@@ -234,12 +243,13 @@ export class Hsp3DebugSession extends LoggingDebugSession {
         writeTrace("launch", args)
 
         // 正しく引数が渡されたか検査する。
-        const { program, hsp3Root, utf8Support, distDir } = args
+        const { program, hsp3Root, utf8Support, distDir, runtime } = args
 
         if (typeof program !== "string"
             || typeof hsp3Root !== "string"
             || typeof utf8Support !== "string"
-            || typeof distDir !== "string") {
+            || typeof distDir !== "string"
+            || (runtime !== undefined && typeof runtime !== "string")) {
             writeTrace("bad arguments")
             return [false, "デバッガーの起動に失敗しました。(launch 引数が不正です。)"]
         }
@@ -253,7 +263,7 @@ export class Hsp3DebugSession extends LoggingDebugSession {
 
         // コンパイルする。
         writeTrace("compile")
-        const compileResult = await compileHsp(program, hsp3Root, utf8Support, distDir)
+        const compileResult = await compileHsp(program, hsp3Root, utf8Support, distDir, runtime)
         writeTrace("compiled", { compileResult })
 
         if (!compileResult.success) {
@@ -1,10 +1,24 @@
 import * as fsP from "fs/promises"
-import { CancellationToken, DebugConfiguration, DebugConfigurationProvider, WorkspaceFolder, window, ProviderResult } from "vscode"
+import { CancellationToken, DebugConfiguration, DebugConfigurationProvider, WorkspaceFolder, commands, window, ProviderResult } from "vscode"
 import { selectHsp3Root } from "./ext_command_select_hsp3_root"
 import { createHsptmp } from "./ext_command_create_hsptmp"
 import { HSP3_LANG_ID } from "./ext_constants"
 import { decode } from "iconv-lite"
 
+/**
+ * HSP3 アナライザー・ミニ (LSP) の拡張機能に、スクリプトを実行するランタイムの名前を問い合わせる。
+ *
+ * (`#runtime` で指定されたもの。拡張機能がインストールされていないときなどは undefined を返す。)
+ */
+const getRuntimeFromAnalyzer = async (program: string): Promise<string | undefined> => {
+    try {
+        const runtime = await commands.executeCommand<string | null>("hsp3-analyzer-mini.getRuntime", program)
+        return runtime || undefined
+    } catch (err) {
+        return undefined
+    }
+}
+
 /**
  * デバッガーの設定を構成する。
  *
@@ -59,6 +73,7 @@ const doResolveDebugConfiguration = async (config: DebugConfiguration, distDir:
     }
 
     config.program = program
+    config.runtime = config.runtime || await getRuntimeFromAnalyzer(program)
     config.hsp3Root = config.hsp3Root || await selectHsp3Root()
     config.utf8Support = utf8Support
     config.distDir = config.distDir || distDir
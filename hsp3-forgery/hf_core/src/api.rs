@@ -253,7 +253,15 @@ mod tests {
     fn test_diagnostics() {
         let root_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let tests_dir = root_dir.join("../tests");
-        let test_names = vec!["assign", "command", "exit_42", "syntax_error", "syntax_pp"];
+        let test_names = vec![
+            "assign",
+            "command",
+            "exit_42",
+            "literal",
+            "syntax_error",
+            "syntax_pp",
+            "unary",
+        ];
 
         for name in test_names {
             let source_path = Rc::new(tests_dir.join(format!("{}/{}.hsp", name, name)));
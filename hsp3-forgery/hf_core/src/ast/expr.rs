@@ -32,13 +32,72 @@ impl Ast for ACallExpr {
     }
 }
 
+pub(crate) struct AUnaryExpr(SyntaxNode);
+
+impl AUnaryExpr {
+    /// 演算子のトークン (`-` または `!`)
+    pub(crate) fn operator(&self) -> Option<Token> {
+        self.syntax()
+            .child_tokens()
+            .map(|token| token.kind())
+            .find(|&token| token.is_unary_operator())
+    }
+
+    pub(crate) fn operand(&self) -> Option<AExpr> {
+        self.syntax()
+            .child_nodes()
+            .find_map(|node| AExpr::cast(&node))
+    }
+}
+
+impl Ast for AUnaryExpr {
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    fn cast(syntax_node: &SyntaxNode) -> Option<Self> {
+        if syntax_node.kind() == NodeKind::UnaryExpr {
+            Some(AUnaryExpr(syntax_node.clone()))
+        } else {
+            None
+        }
+    }
+}
+
 pub(crate) enum AExpr {
     Label(ALabel),
     Str(AStr),
     Int(AInt),
+    Double(ADouble),
+    Char(AChar),
     Name(AName),
     Group(AGroupExpr),
     Call(ACallExpr),
+    Unary(AUnaryExpr),
+}
+
+impl AExpr {
+    /// 整数・文字リテラルと単項演算子だけからなる式 (`-1` や `!0` など) の値を計算する。
+    pub(crate) fn fold_int(&self) -> Option<i32> {
+        match self {
+            AExpr::Int(a) => a.to_int(),
+            AExpr::Char(a) => a.to_int(),
+            AExpr::Group(a) => a
+                .syntax()
+                .child_nodes()
+                .find_map(|node| AExpr::cast(&node))?
+                .fold_int(),
+            AExpr::Unary(a) => {
+                let value = a.operand()?.fold_int()?;
+                match a.operator()? {
+                    Token::Minus => Some(value.wrapping_neg()),
+                    Token::Bang => Some(if value == 0 { 1 } else { 0 }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<ALabel> for AExpr {
@@ -59,6 +118,18 @@ impl From<AInt> for AExpr {
     }
 }
 
+impl From<ADouble> for AExpr {
+    fn from(it: ADouble) -> Self {
+        AExpr::Double(it)
+    }
+}
+
+impl From<AChar> for AExpr {
+    fn from(it: AChar) -> Self {
+        AExpr::Char(it)
+    }
+}
+
 impl From<AName> for AExpr {
     fn from(it: AName) -> Self {
         AExpr::Name(it)
@@ -71,15 +142,24 @@ impl From<ACallExpr> for AExpr {
     }
 }
 
+impl From<AUnaryExpr> for AExpr {
+    fn from(it: AUnaryExpr) -> Self {
+        AExpr::Unary(it)
+    }
+}
+
 impl Ast for AExpr {
     fn syntax(&self) -> &SyntaxNode {
         match self {
             AExpr::Label(a) => a.syntax(),
             AExpr::Str(a) => a.syntax(),
             AExpr::Int(a) => a.syntax(),
+            AExpr::Double(a) => a.syntax(),
+            AExpr::Char(a) => a.syntax(),
             AExpr::Name(a) => a.syntax(),
             AExpr::Group(a) => a.syntax(),
             AExpr::Call(a) => a.syntax(),
+            AExpr::Unary(a) => a.syntax(),
         }
     }
 
@@ -96,6 +176,14 @@ impl Ast for AExpr {
             return Some(AExpr::Int(a));
         }
 
+        if let Some(a) = ADouble::cast(syntax_node) {
+            return Some(AExpr::Double(a));
+        }
+
+        if let Some(a) = AChar::cast(syntax_node) {
+            return Some(AExpr::Char(a));
+        }
+
         if let Some(a) = AName::cast(syntax_node) {
             return Some(AExpr::Name(a));
         }
@@ -108,6 +196,10 @@ impl Ast for AExpr {
             return Some(AExpr::Call(a));
         }
 
+        if let Some(a) = AUnaryExpr::cast(syntax_node) {
+            return Some(AExpr::Unary(a));
+        }
+
         None
     }
 }
@@ -127,3 +219,124 @@ impl Ast for AArg {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_tokens;
+    use crate::token::tokenize::tokenize;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    fn parse(source_code: &str) -> Rc<SyntaxRoot> {
+        let source = TokenSource::from_file(SourceFile {
+            source_path: Rc::new(PathBuf::new()),
+        });
+        let tokens = tokenize(source, Rc::new(source_code.to_string()));
+        parse_tokens(&tokens)
+    }
+
+    /// ソースコードに含まれる代入文の右辺の式を列挙する。
+    fn parse_assigned_exprs(source_code: &str) -> Vec<AExpr> {
+        parse(source_code)
+            .node()
+            .descendant_nodes()
+            .filter(|node| node.kind() == NodeKind::AssignStmt)
+            .filter_map(|stmt| {
+                let arg = stmt.child_nodes().find_map(|node| AArg::cast(&node))?;
+                arg.syntax()
+                    .child_nodes()
+                    .find_map(|node| AExpr::cast(&node))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unary_expr() {
+        let exprs = parse_assigned_exprs("a = -1\nb = !0\nc = !-(2)\nd = - -3\ne = -x(2)\n");
+        let values = exprs.iter().map(|e| e.fold_int()).collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(-1), Some(1), Some(0), Some(3), None]);
+
+        match &exprs[4] {
+            AExpr::Unary(a) => {
+                assert_eq!(a.operator(), Some(Token::Minus));
+                assert!(match a.operand() {
+                    Some(AExpr::Call(_)) => true,
+                    _ => false,
+                });
+            }
+            _ => panic!("単項式になるべき"),
+        }
+    }
+
+    #[test]
+    fn test_literal_values() {
+        let exprs = parse_assigned_exprs(
+            "a = 42\nb = $FF\nc = 0x1F\nd = %1010\ne = 0b1010\nf = $FFFFFFFF\ng = 'A'\nh = '\\''\ni = -'0'\n",
+        );
+        let values = exprs.iter().map(|e| e.fold_int()).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                Some(42),
+                Some(255),
+                Some(31),
+                Some(10),
+                Some(10),
+                Some(-1),
+                Some(65),
+                Some(39),
+                Some(-48),
+            ]
+        );
+
+        let exprs = parse_assigned_exprs("a = 1.5e-3\nb = .5\nc = 2.\nd = 1E+9\n");
+        let values = exprs
+            .iter()
+            .map(|e| match e {
+                AExpr::Double(a) => a.to_double(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(1.5e-3), Some(0.5), Some(2.0), Some(1e9)]);
+    }
+
+    #[test]
+    fn test_multiline_str() {
+        let exprs = parse_assigned_exprs("s = {\"\n\tfirst \"line\"\r\n\t\tsecond\n\"}\nt = 1\n");
+        assert_eq!(exprs.len(), 2);
+
+        match &exprs[0] {
+            AExpr::Str(a) => {
+                assert_eq!(a.to_string(), "\nfirst \"line\"\r\nsecond\n");
+
+                let range = a.syntax().range();
+                assert_eq!(
+                    (range.start, range.end),
+                    (Position::new(0, 4), Position::new(3, 2))
+                );
+            }
+            _ => panic!("文字列リテラルになるべき"),
+        }
+    }
+
+    #[test]
+    fn test_unary_expr_binds_tighter_than_binary_expr() {
+        let root = parse("a = -b * 3\n");
+
+        let binary = root
+            .node()
+            .descendant_nodes()
+            .find(|node| node.kind() == NodeKind::BinaryExpr)
+            .expect("二項式になるべき");
+        let kinds = binary
+            .child_nodes()
+            .map(|node| node.kind())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![NodeKind::UnaryExpr, NodeKind::IntLiteral]);
+        assert!(root
+            .node()
+            .descendant_nodes()
+            .all(|node| node.kind() != NodeKind::Other));
+    }
+}
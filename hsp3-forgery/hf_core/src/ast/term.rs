@@ -61,6 +61,31 @@ impl Ast for AStr {
 
 pub(crate) struct AInt(SyntaxNode);
 
+impl AInt {
+    /// 整数リテラルの値。
+    ///
+    /// 10進数 (`42`)、16進数 (`$FF`, `0x1F`)、2進数 (`%1010`, `0b1010`) を解釈する。
+    /// HSP3 と同様に 32 ビットの符号なし整数として読み、`$FFFFFFFF` は -1 になる。
+    pub(crate) fn to_int(&self) -> Option<i32> {
+        let mut radix = 10;
+
+        for token in self.syntax().child_tokens() {
+            match token.kind() {
+                Token::ZeroX | Token::Dollar => radix = 16,
+                Token::ZeroB | Token::Percent => radix = 2,
+                Token::Digit | Token::Hex | Token::Binary => {
+                    return u32::from_str_radix(token.text(), radix)
+                        .ok()
+                        .map(|value| value as i32);
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
 impl Ast for AInt {
     fn syntax(&self) -> &SyntaxNode {
         &self.0
@@ -75,6 +100,86 @@ impl Ast for AInt {
     }
 }
 
+pub(crate) struct ADouble(SyntaxNode);
+
+impl ADouble {
+    /// 小数リテラルの値。(`1.5`, `.5`, `1e9`, `1.5e-3` など)
+    pub(crate) fn to_double(&self) -> Option<f64> {
+        let mut text = String::new();
+
+        for token in self.syntax().child_tokens() {
+            match token.kind() {
+                Token::FloatInt
+                | Token::FloatPoint
+                | Token::Fraction
+                | Token::ExpChar
+                | Token::ExpSign
+                | Token::ExpDigit => text += token.text(),
+                _ => {}
+            }
+        }
+
+        text.parse().ok()
+    }
+}
+
+impl Ast for ADouble {
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    fn cast(syntax_node: &SyntaxNode) -> Option<Self> {
+        if syntax_node.kind() == NodeKind::DoubleLiteral {
+            Some(ADouble(syntax_node.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+pub(crate) struct AChar(SyntaxNode);
+
+impl AChar {
+    /// 文字リテラルの値 (文字コード)。(`'A'` なら 65)
+    ///
+    /// 非 ASCII 文字は、UTF-8 版の HSP3 と同様に先頭のバイトの値とする。
+    pub(crate) fn to_int(&self) -> Option<i32> {
+        for token in self.syntax().child_tokens() {
+            match token.kind() {
+                Token::StrVerbatim => {
+                    return token.text().bytes().next().map(|b| b as i32);
+                }
+                Token::StrEscape => {
+                    let value = match token.text().chars().nth(1)? {
+                        't' => b'\t',
+                        'n' => b'\n',
+                        'r' => b'\r',
+                        _ => token.text().as_bytes()[1],
+                    };
+                    return Some(value as i32);
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+impl Ast for AChar {
+    fn syntax(&self) -> &SyntaxNode {
+        &self.0
+    }
+
+    fn cast(syntax_node: &SyntaxNode) -> Option<Self> {
+        if syntax_node.kind() == NodeKind::CharLiteral {
+            Some(AChar(syntax_node.clone()))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub(crate) struct AName(SyntaxNode);
 
@@ -36,8 +36,10 @@ mod tests {
             "assign",
             "command",
             "exit_42",
+            "literal",
             "syntax_error",
             "syntax_pp",
+            "unary",
         ];
 
         for name in test_names {
@@ -15,13 +15,18 @@ impl Token {
         }
     }
 
-    fn is_factor_first(self) -> bool {
+    /// 単項演算子か？ (`-`, `!`)
+    pub(crate) fn is_unary_operator(self) -> bool {
         match self {
-            Token::Minus => true,
-            _ => self.is_atom_expr_first(),
+            Token::Minus | Token::Bang => true,
+            _ => false,
         }
     }
 
+    fn is_factor_first(self) -> bool {
+        self.is_unary_operator() || self.is_atom_expr_first()
+    }
+
     /// このトークンが式の先頭になることがあるか？
     /// (= expr の FIRST 集合に含まれるか？)
     pub(crate) fn is_expr_first(self) -> bool {
@@ -75,8 +80,11 @@ pub(crate) fn parse_call_expr(p: &mut Px) {
     p.end_node(NodeKind::CallExpr);
 }
 
+/// 単項式 (`-x` や `!x`) を解析する。
+///
+/// 単項演算子は二項演算子より強く結合する。(`-b * 3` は `(-b) * 3` になる。)
 fn parse_unary_expr(p: &mut Px) {
-    assert_eq!(p.next(), Token::Minus);
+    assert!(p.next().is_unary_operator());
 
     p.start_node();
     p.bump();
@@ -93,7 +101,7 @@ fn parse_factor(p: &mut Px) {
         Token::LeftParen => parse_group_expr(p),
         Token::CharStart => parse_char_literal(p),
         Token::FloatInt => parse_double_literal(p),
-        Token::Minus => parse_unary_expr(p),
+        Token::Minus | Token::Bang => parse_unary_expr(p),
         Token::Star => parse_label_literal(p),
         Token::StrStart => parse_str_literal(p),
         _ if p.next().is_int_literal_first() => parse_int_literal(p),
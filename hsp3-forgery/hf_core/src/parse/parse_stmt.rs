@@ -203,6 +203,15 @@ fn look_ahead_stmt(p: &mut Px) -> StmtKind {
         return StmtKind::Command;
     }
 
+    // `mes !flag` のように `!` の後ろに式が続くなら、否定を引数に渡す命令文とみなす。
+    // (変数の後ろに比較演算子の `!` が続く文は意味をなさない。)
+    if second == Token::Bang {
+        if p.nth(2).is_expr_first() {
+            return StmtKind::Command;
+        }
+        return StmtKind::Assign;
+    }
+
     // `a / ...` のように二項演算子が続く場合は代入文。
     // `a = ...` もこのケースに該当する。
     // ただし `a - ...` のように演算子が式の先頭になりうる場合は除く。
@@ -298,15 +298,56 @@ fn tokenize_str(t: &mut TokenizeContext) -> bool {
     false
 }
 
+/// 複数行文字列リテラル (`{"..."}`) を字句解析する。
+///
+/// 改行や `"` を含むことができ、改行は値に含まれる。
+/// 各行の先頭にあるタブ文字は値に含まれないので、スペースとして扱う。
 fn tokenize_multiline_str(t: &mut TokenizeContext) -> bool {
     if t.eat("{\"") {
         t.commit(Token::StrStart);
 
-        // FIXME: 各行の最初のタブ文字は文字列リテラルの値に含まれないので、Token::Space にする。
+        let mut at_line_head = false;
+
         while !t.at_eof() && !t.is_followed_by("\"}") {
-            t.bump();
+            if at_line_head {
+                at_line_head = false;
+
+                if t.next() == '\t' {
+                    while t.next() == '\t' {
+                        t.bump();
+                    }
+                    t.commit(Token::Space);
+                    continue;
+                }
+            }
+
+            // \ の直後が行末やファイル末尾のときはエスケープとみなさない。
+            if t.next() == '\\' && t.nth(1) != '\0' && !char_is_eol(t.nth(1)) {
+                t.bump();
+                t.bump();
+                t.commit(Token::StrEscape);
+                continue;
+            }
+
+            // 次のエスケープか、行の終わりまでを読む。
+            loop {
+                let c = t.next();
+                t.bump();
+
+                if char_is_eol(c) {
+                    if c == '\r' && t.next() == '\n' {
+                        t.bump();
+                    }
+                    at_line_head = true;
+                    break;
+                }
+
+                if t.at_eof() || t.next() == '\\' || t.is_followed_by("\"}") {
+                    break;
+                }
+            }
+            t.commit(Token::StrVerbatim);
         }
-        t.commit(Token::StrVerbatim);
 
         if t.eat("\"}") {
             t.commit(Token::StrEnd);
@@ -439,4 +480,35 @@ mod tests {
     fn test_script_ending_with_ident() {
         tokenize("a");
     }
+
+    #[test]
+    fn test_multiline_str() {
+        assert_eq!(
+            tokenize("{\"\n\t\ta \"b\"\r\n\\t\n\"}"),
+            vec![
+                Token::StrStart,
+                Token::StrVerbatim,
+                Token::Space,
+                Token::StrVerbatim,
+                Token::StrEscape,
+                Token::StrVerbatim,
+                Token::StrEnd,
+                Token::Semi,
+                Token::Eof,
+            ]
+        );
+
+        // 閉じていない複数行文字列リテラルはファイルの末尾まで続く。
+        assert_eq!(
+            tokenize("{\"a\n\tb"),
+            vec![
+                Token::StrStart,
+                Token::StrVerbatim,
+                Token::Space,
+                Token::StrVerbatim,
+                Token::Semi,
+                Token::Eof,
+            ]
+        );
+    }
 }